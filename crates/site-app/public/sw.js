@@ -0,0 +1,16 @@
+// Shows the push notifications the server sends when a post is published.
+
+self.addEventListener("push", (event) => {
+  const { title, url } = event.data.json();
+  event.waitUntil(
+    self.registration.showNotification("New post: " + title, {
+      icon: "/favicon.png",
+      data: { url },
+    }),
+  );
+});
+
+self.addEventListener("notificationclick", (event) => {
+  event.notification.close();
+  event.waitUntil(self.clients.openWindow(event.notification.data.url));
+});
@@ -0,0 +1,698 @@
+//! A minimal ActivityPub actor for the site, so that it can be followed from
+//! Mastodon and the like as `@username@host`, when
+//! [`ActivityPubConfig`](crate::config::ActivityPubConfig) enables it. The
+//! server answers WebFinger lookups for the actor, serves its actor document,
+//! outbox and followers, and takes follows at its inbox. Each post published
+//! after that is delivered to its followers as an `Article`.
+//!
+//! Requests between servers carry HTTP signatures made with the actor's RSA
+//! key. The ones the server sends are signed here, and the ones its inbox
+//! takes are checked here against their sender's published key.
+
+use std::{
+  io::Write,
+  path::Path,
+  time::{Duration, SystemTime},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hyper::{body::HttpBody, header, Body, Method, Request, Uri};
+use openssl::{
+  hash::MessageDigest,
+  pkey::{PKey, Private},
+  rsa::Rsa,
+  sign::{Signer, Verifier},
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{config::site_config, posts::Post, previews::client};
+
+/// Where WebFinger looks the actor up by its account.
+pub const WEBFINGER_PATH: &str = "/.well-known/webfinger";
+pub const ACTOR_PATH: &str = "/activitypub/actor";
+pub const INBOX_PATH: &str = "/activitypub/inbox";
+pub const OUTBOX_PATH: &str = "/activitypub/outbox";
+pub const FOLLOWERS_PATH: &str = "/activitypub/followers";
+/// Where each post's `Article` is served from, under the post's path.
+pub const ARTICLES_PATH: &str = "/activitypub/posts";
+/// The media type ActivityPub documents are served and sent as.
+pub const ACTIVITY_JSON: &str = "application/activity+json";
+
+const ACTIVITY_STREAMS: &str = "https://www.w3.org/ns/activitystreams";
+/// The audience of everything the actor publishes.
+const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+/// How many of the newest posts the outbox lists.
+const OUTBOX_POSTS: usize = 20;
+/// How long fetching a document or delivering an activity can take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How big a fetched document can be.
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+/// How far from now a signed request can say it was sent, as Mastodon
+/// allows.
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(12 * 60 * 60);
+const USER_AGENT: &str =
+  concat!("site-app/", env!("CARGO_PKG_VERSION"), " (activitypub)");
+
+/// The actor's key pair, which its requests are signed with.
+#[derive(Clone)]
+pub struct ActorKey {
+  private:    PKey<Private>,
+  /// The public key, PEM-encoded, as the actor document gives it.
+  public_pem: String,
+}
+
+impl ActorKey {
+  /// Reads the key from `path`, first generating one there if it doesn't
+  /// exist.
+  pub fn load_or_generate(path: &Path) -> Result<Self, String> {
+    let private = match std::fs::read(path) {
+      Ok(pem) => PKey::private_key_from_pem(&pem)
+        .map_err(|e| format!("invalid `{}`: {e}", path.display()))?,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+        let private = generate_key(path)
+          .map_err(|e| format!("can't write `{}`: {e}", path.display()))?;
+        log::info!("generated an ActivityPub key at `{}`", path.display());
+        private
+      }
+      Err(e) => return Err(format!("can't read `{}`: {e}", path.display())),
+    };
+    let public_pem = private
+      .public_key_to_pem()
+      .map_err(|e| e.to_string())
+      .and_then(|pem| String::from_utf8(pem).map_err(|e| e.to_string()))?;
+    Ok(ActorKey {
+      private,
+      public_pem,
+    })
+  }
+}
+
+fn generate_key(
+  path: &Path,
+) -> Result<PKey<Private>, Box<dyn std::error::Error>> {
+  use std::os::unix::fs::OpenOptionsExt;
+
+  let private = PKey::from_rsa(Rsa::generate(2048)?)?;
+  if let Some(dir) = path.parent() {
+    std::fs::create_dir_all(dir)?;
+  }
+  // only the server should be able to read it
+  std::fs::OpenOptions::new()
+    .write(true)
+    .create_new(true)
+    .mode(0o600)
+    .open(path)?
+    .write_all(&private.private_key_to_pem_pkcs8()?)?;
+  Ok(private)
+}
+
+pub fn actor_id() -> String { site_config().url(ACTOR_PATH) }
+
+fn key_id() -> String { format!("{}#main-key", actor_id()) }
+
+fn followers_id() -> String { site_config().url(FOLLOWERS_PATH) }
+
+/// The ID of a post's `Article`.
+pub fn article_id(path: &str) -> String {
+  site_config().url(&format!("{ARTICLES_PATH}/{path}"))
+}
+
+/// The `acct:` URI WebFinger finds the actor by, on the site's host.
+pub fn account() -> String {
+  let config = site_config();
+  let host = config
+    .base_url
+    .parse::<Uri>()
+    .ok()
+    .and_then(|uri| uri.authority().map(ToString::to_string))
+    .unwrap_or_default();
+  format!("acct:{}@{host}", config.activitypub.username)
+}
+
+/// Text escaped for HTML, in an element or a quoted attribute.
+pub(crate) fn escape_html(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+/// What WebFinger answers for the actor's account.
+pub fn webfinger() -> Value {
+  json!({
+    "subject": account(),
+    "aliases": [actor_id()],
+    "links": [
+      { "rel": "self", "type": ACTIVITY_JSON, "href": actor_id() },
+      {
+        "rel": "http://webfinger.net/rel/profile-page",
+        "type": "text/html",
+        "href": site_config().url(""),
+      },
+    ],
+  })
+}
+
+/// The actor document, naming the site and where to follow it.
+pub fn actor(key: &ActorKey) -> Value {
+  let config = site_config();
+  json!({
+    "@context": [ACTIVITY_STREAMS, "https://w3id.org/security/v1"],
+    "id": actor_id(),
+    "type": "Person",
+    "preferredUsername": config.activitypub.username,
+    "name": config.title,
+    "summary": format!("<p>{}</p>", escape_html(&config.tagline)),
+    "url": config.url(""),
+    "inbox": config.url(INBOX_PATH),
+    "outbox": config.url(OUTBOX_PATH),
+    "followers": followers_id(),
+    "manuallyApprovesFollowers": false,
+    "discoverable": true,
+    "publicKey": {
+      "id": key_id(),
+      "owner": actor_id(),
+      "publicKeyPem": key.public_pem,
+    },
+  })
+}
+
+/// When a post was written, as ActivityPub dates things.
+fn published(post: &Post) -> Option<String> {
+  crate::dates::iso_date(&post.metadata.written_on)
+    .map(|date| format!("{date}T00:00:00Z"))
+}
+
+/// A post as an `Article`, linking to its page.
+pub fn article(post: &Post) -> Value {
+  let mut article = json!({
+    "@context": ACTIVITY_STREAMS,
+    "id": article_id(&post.path),
+    "type": "Article",
+    "attributedTo": actor_id(),
+    "name": post.metadata.title,
+    "url": site_config().url(&format!("/post/{}", post.path)),
+    "mediaType": "text/html",
+    "content": post.html_content,
+    "to": [PUBLIC],
+    "cc": [followers_id()],
+  });
+  if let Some(published) = published(post) {
+    article["published"] = published.into();
+  }
+  article
+}
+
+/// The activity publishing a post.
+pub fn create(post: &Post) -> Value {
+  let mut object = article(post);
+  // the activity's context covers it
+  if let Some(object) = object.as_object_mut() {
+    object.remove("@context");
+  }
+  let mut create = json!({
+    "@context": ACTIVITY_STREAMS,
+    "id": format!("{}#create", article_id(&post.path)),
+    "type": "Create",
+    "actor": actor_id(),
+    "to": [PUBLIC],
+    "cc": [followers_id()],
+    "object": object,
+  });
+  if let Some(published) = published(post) {
+    create["published"] = published.into();
+  }
+  create
+}
+
+/// The outbox, publishing the newest of `posts`, which are newest first.
+pub fn outbox(posts: &[Post]) -> Value {
+  json!({
+    "@context": ACTIVITY_STREAMS,
+    "id": site_config().url(OUTBOX_PATH),
+    "type": "OrderedCollection",
+    "totalItems": posts.len(),
+    "orderedItems": posts
+      .iter()
+      .take(OUTBOX_POSTS)
+      .map(create)
+      .collect::<Vec<_>>(),
+  })
+}
+
+/// The followers collection, which only says how many there are.
+pub fn followers(count: usize) -> Value {
+  json!({
+    "@context": ACTIVITY_STREAMS,
+    "id": followers_id(),
+    "type": "OrderedCollection",
+    "totalItems": count,
+  })
+}
+
+/// The activity accepting a follow.
+pub fn accept(follow: &Value) -> Value {
+  let follow_id = follow["id"].as_str().unwrap_or_default();
+  json!({
+    "@context": ACTIVITY_STREAMS,
+    "id": format!(
+      "{}#accepts/{:x}",
+      actor_id(),
+      Sha256::digest(follow_id.as_bytes())
+    ),
+    "type": "Accept",
+    "actor": actor_id(),
+    "object": follow,
+  })
+}
+
+/// An actor on another server, as far as the site needs to know it.
+#[derive(Clone, Debug)]
+pub struct RemoteActor {
+  pub id:           String,
+  pub inbox:        String,
+  /// The inbox of the actor's whole server, which takes activities for
+  /// everyone there at once.
+  pub shared_inbox: Option<String>,
+  key_id:           String,
+  /// The actor the key says it belongs to.
+  key_owner:        String,
+  public_key_pem:   String,
+}
+
+impl RemoteActor {
+  fn from_document(document: &Value) -> Option<Self> {
+    let string = |value: &Value| value.as_str().map(str::to_string);
+    Some(RemoteActor {
+      id:             string(&document["id"])?,
+      inbox:          string(&document["inbox"])?,
+      shared_inbox:   string(&document["endpoints"]["sharedInbox"]),
+      key_id:         string(&document["publicKey"]["id"])?,
+      key_owner:      string(&document["publicKey"]["owner"])?,
+      public_key_pem: string(&document["publicKey"]["publicKeyPem"])?,
+    })
+  }
+
+  /// Where activities for the actor are best delivered.
+  pub fn delivery_inbox(&self) -> &str {
+    self.shared_inbox.as_deref().unwrap_or(&self.inbox)
+  }
+}
+
+fn digest_header(body: &[u8]) -> String {
+  format!("SHA-256={}", STANDARD.encode(Sha256::digest(body)))
+}
+
+/// The headers signing a request to `uri` with the actor's key: `Host`,
+/// `Date`, `Digest` for a request with a body, and `Signature` over them.
+fn signature_headers(
+  key: &ActorKey,
+  method: &Method,
+  uri: &Uri,
+  body: Option<&[u8]>,
+) -> Result<Vec<(&'static str, String)>, String> {
+  let host = uri.authority().ok_or("has no host")?.to_string();
+  let mut headers = vec![
+    ("host", host),
+    ("date", httpdate::fmt_http_date(SystemTime::now())),
+  ];
+  if let Some(body) = body {
+    headers.push(("digest", digest_header(body)));
+  }
+
+  let request_target = format!(
+    "{} {}",
+    method.as_str().to_lowercase(),
+    uri.path_and_query().map_or("/", |target| target.as_str())
+  );
+  let signed = std::iter::once(format!("(request-target): {request_target}"))
+    .chain(
+      headers
+        .iter()
+        .map(|(name, value)| format!("{name}: {value}")),
+    )
+    .collect::<Vec<_>>()
+    .join("\n");
+  let mut signer = Signer::new(MessageDigest::sha256(), &key.private)
+    .map_err(|e| e.to_string())?;
+  let signature = signer
+    .update(signed.as_bytes())
+    .and_then(|_| signer.sign_to_vec())
+    .map_err(|e| e.to_string())?;
+
+  let names = std::iter::once("(request-target)")
+    .chain(headers.iter().map(|(name, _)| *name))
+    .collect::<Vec<_>>()
+    .join(" ");
+  headers.push((
+    "signature",
+    format!(
+      "keyId=\"{}\",algorithm=\"rsa-sha256\",headers=\"{names}\",signature=\"\
+       {}\"",
+      key_id(),
+      STANDARD.encode(signature)
+    ),
+  ));
+  Ok(headers)
+}
+
+/// Sends a signed request, reading the response's body if it answers with
+/// success.
+async fn signed_request(
+  key: &ActorKey,
+  method: Method,
+  url: &str,
+  body: Option<Vec<u8>>,
+) -> Result<(u16, Vec<u8>), String> {
+  if !crate::webmentions::is_public(url) {
+    return Err(format!("`{url}` isn't public"));
+  }
+  let uri = url.parse::<Uri>().map_err(|e| e.to_string())?;
+  let mut request = Request::builder()
+    .method(method.clone())
+    .uri(uri.clone())
+    .header(header::ACCEPT, ACTIVITY_JSON)
+    .header(header::USER_AGENT, USER_AGENT);
+  for (name, value) in signature_headers(key, &method, &uri, body.as_deref())? {
+    request = request.header(name, value);
+  }
+  if body.is_some() {
+    request = request.header(header::CONTENT_TYPE, ACTIVITY_JSON);
+  }
+  let request = request
+    .body(body.map(Body::from).unwrap_or_else(Body::empty))
+    .map_err(|e| e.to_string())?;
+
+  let send = async {
+    let mut response =
+      client().request(request).await.map_err(|e| e.to_string())?;
+    let status = response.status();
+    let mut body = Vec::new();
+    if status.is_success() {
+      while let Some(chunk) = response.body_mut().data().await {
+        body.extend_from_slice(&chunk.map_err(|e| e.to_string())?);
+        if body.len() > MAX_BODY_BYTES {
+          return Err("answered with too much".to_string());
+        }
+      }
+    }
+    Ok((status.as_u16(), body))
+  };
+  tokio::time::timeout(REQUEST_TIMEOUT, send)
+    .await
+    .unwrap_or_else(|_| Err("timed out".to_string()))
+}
+
+/// Fetches an ActivityPub document. The request is signed, since servers
+/// using Mastodon's authorized fetch turn away ones that aren't.
+pub async fn fetch(key: &ActorKey, url: &str) -> Result<Value, String> {
+  match signed_request(key, Method::GET, url, None).await? {
+    (200..=299, body) => serde_json::from_slice(&body)
+      .map_err(|e| format!("answered with invalid JSON: {e}")),
+    (status, _) => Err(format!("answered {status}")),
+  }
+}
+
+/// Fetches the actor with the ID `url`.
+pub async fn fetch_actor(
+  key: &ActorKey,
+  url: &str,
+) -> Result<RemoteActor, String> {
+  let document = fetch(key, url).await?;
+  RemoteActor::from_document(&document)
+    .ok_or_else(|| format!("`{url}` isn't an actor"))
+}
+
+/// Delivers an activity to an inbox, returning the status it answered with.
+pub async fn deliver(
+  key: &ActorKey,
+  inbox: &str,
+  activity: &Value,
+) -> Result<u16, String> {
+  let body = serde_json::to_vec(activity).map_err(|e| e.to_string())?;
+  signed_request(key, Method::POST, inbox, Some(body))
+    .await
+    .map(|(status, _)| status)
+}
+
+/// The parameters of a `Signature` header, like
+/// `keyId="...",headers="...",signature="..."`.
+fn signature_params(header: &str) -> Vec<(&str, &str)> {
+  header
+    .split(',')
+    .filter_map(|param| param.trim().split_once('='))
+    .map(|(name, value)| (name.trim(), value.trim().trim_matches('"')))
+    .collect()
+}
+
+/// What a request's `Signature` header says about it.
+struct RequestSignature {
+  key_id:    String,
+  signature: Vec<u8>,
+  /// The text the signature is over, as this request gives it.
+  signed:    String,
+}
+
+impl RequestSignature {
+  /// Reads the signature of a request, checking that it covers everything it
+  /// has to and that the request is recent and carries the body it says.
+  fn read(
+    method: &str,
+    target: &str,
+    headers: &http::HeaderMap,
+    body: &[u8],
+  ) -> Result<Self, String> {
+    let header = |name: &str| {
+      headers
+        .get_all(name)
+        .iter()
+        .map(|value| value.to_str().map_err(|_| format!("invalid `{name}`")))
+        .collect::<Result<Vec<_>, _>>()
+        .map(|values| values.join(", "))
+        .and_then(|value| match value.is_empty() {
+          true => Err(format!("missing `{name}`")),
+          false => Ok(value),
+        })
+    };
+
+    let signature = header("signature")?;
+    let params = signature_params(&signature);
+    let param = |name: &str| {
+      params
+        .iter()
+        .find(|(param, _)| *param == name)
+        .map(|(_, value)| *value)
+        .ok_or_else(|| format!("signature without `{name}`"))
+    };
+    let key_id = param("keyId")?.to_string();
+    let signature = STANDARD
+      .decode(param("signature")?)
+      .map_err(|e| e.to_string())?;
+    let signed_headers = param("headers").unwrap_or("date");
+    let signed_headers = signed_headers.split_whitespace().collect::<Vec<_>>();
+    // what has to be signed for the signature to say anything about this
+    // request, and when it was sent
+    for required in ["(request-target)", "host", "date", "digest"] {
+      if !signed_headers.contains(&required) {
+        return Err(format!("`{required}` isn't signed"));
+      }
+    }
+
+    let date = httpdate::parse_http_date(&header("date")?)
+      .map_err(|_| "invalid `date`".to_string())?;
+    let skew = SystemTime::now()
+      .duration_since(date)
+      .or_else(|_| date.duration_since(SystemTime::now()))
+      .unwrap_or_default();
+    if skew > MAX_CLOCK_SKEW {
+      return Err("signed too long ago".to_string());
+    }
+    if header("digest")? != digest_header(body) {
+      return Err("`digest` doesn't match the body".to_string());
+    }
+
+    let signed = signed_headers
+      .iter()
+      .map(|name| match *name {
+        "(request-target)" => Ok(format!(
+          "(request-target): {} {target}",
+          method.to_lowercase()
+        )),
+        name => header(name).map(|value| format!("{name}: {value}")),
+      })
+      .collect::<Result<Vec<_>, _>>()?
+      .join("\n");
+    Ok(RequestSignature {
+      key_id,
+      signature,
+      signed,
+    })
+  }
+
+  /// The URL of the actor the key is published by.
+  fn actor_url(&self) -> &str {
+    self.key_id.split('#').next().unwrap_or_default()
+  }
+
+  /// Reads the actor the signature's key belongs to from the document fetched
+  /// from [`actor_url`](Self::actor_url). The document has to be that actor's
+  /// own, or any server could claim to be anyone by serving their ID with its
+  /// own key.
+  fn signer(&self, document: &Value) -> Result<RemoteActor, String> {
+    let actor_url = self.actor_url();
+    let actor = RemoteActor::from_document(document)
+      .ok_or_else(|| format!("`{actor_url}` isn't an actor"))?;
+    if actor.id != actor_url {
+      return Err(format!("`{actor_url}` claims to be `{}`", actor.id));
+    }
+    if actor.key_id != self.key_id || actor.key_owner != actor.id {
+      return Err(format!("`{}` isn't the key of `{}`", self.key_id, actor.id));
+    }
+    Ok(actor)
+  }
+
+  /// Checks the signature against the key of `actor`.
+  fn verify(&self, actor: &RemoteActor) -> Result<(), String> {
+    let public_key = PKey::public_key_from_pem(actor.public_key_pem.as_bytes())
+      .map_err(|e| e.to_string())?;
+    let mut verifier = Verifier::new(MessageDigest::sha256(), &public_key)
+      .map_err(|e| e.to_string())?;
+    let verified = verifier
+      .update(self.signed.as_bytes())
+      .and_then(|_| verifier.verify(&self.signature))
+      .map_err(|e| e.to_string())?;
+    match verified {
+      true => Ok(()),
+      false => Err("the signature doesn't match".to_string()),
+    }
+  }
+}
+
+/// Checks that a request the inbox took was signed by the actor whose key it
+/// names, returning that actor. `target` is the path and query it was sent
+/// to, and `body` what it carried.
+pub async fn verify_request(
+  key: &ActorKey,
+  method: &str,
+  target: &str,
+  headers: &http::HeaderMap,
+  body: &[u8],
+) -> Result<RemoteActor, String> {
+  let signature = RequestSignature::read(method, target, headers, body)?;
+  let document = fetch(key, signature.actor_url()).await?;
+  let actor = signature.signer(&document)?;
+  signature.verify(&actor)?;
+  Ok(actor)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn generated_key() -> ActorKey {
+    let private = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let public_pem =
+      String::from_utf8(private.public_key_to_pem().unwrap()).unwrap();
+    ActorKey {
+      private,
+      public_pem,
+    }
+  }
+
+  /// The headers of an activity sent to the inbox, signed with `key` as the
+  /// site's actor.
+  fn signed_headers(key: &ActorKey, body: &[u8]) -> http::HeaderMap {
+    let uri = site_config().url(INBOX_PATH).parse::<Uri>().unwrap();
+    signature_headers(key, &Method::POST, &uri, Some(body))
+      .unwrap()
+      .into_iter()
+      .map(|(name, value)| (name.parse().unwrap(), value.parse().unwrap()))
+      .collect()
+  }
+
+  fn actor_document(id: &str, key_id: &str, key: &ActorKey) -> Value {
+    json!({
+      "id": id,
+      "inbox": format!("{id}/inbox"),
+      "publicKey": {
+        "id": key_id,
+        "owner": id,
+        "publicKeyPem": key.public_pem,
+      },
+    })
+  }
+
+  #[test]
+  fn signatures_verify_against_their_actors_key() {
+    let (key, body) = (generated_key(), br#"{"type":"Follow"}"#);
+    let headers = signed_headers(&key, body);
+    let signature =
+      RequestSignature::read("POST", INBOX_PATH, &headers, body).unwrap();
+    assert_eq!(signature.actor_url(), actor_id());
+
+    let document = actor_document(&actor_id(), &key_id(), &key);
+    let actor = signature.signer(&document).unwrap();
+    assert_eq!(actor.id, actor_id());
+    assert!(signature.verify(&actor).is_ok());
+
+    let other = actor_document(&actor_id(), &key_id(), &generated_key());
+    let other = signature.signer(&other).unwrap();
+    assert!(signature.verify(&other).is_err());
+    let moved = RequestSignature::read("POST", "/elsewhere", &headers, body);
+    assert!(moved.unwrap().verify(&actor).is_err());
+  }
+
+  #[test]
+  fn actors_are_only_who_their_document_is_fetched_from() {
+    let (key, body) = (generated_key(), b"{}");
+    let signature = RequestSignature::read(
+      "POST",
+      INBOX_PATH,
+      &signed_headers(&key, body),
+      body,
+    )
+    .unwrap();
+
+    // a server serving someone else's ID with its own key
+    let victim = "https://mastodon.social/users/victim";
+    let forged = actor_document(victim, &key_id(), &key);
+    assert!(signature.signer(&forged).is_err());
+    let mut forged = actor_document(&actor_id(), &key_id(), &key);
+    forged["publicKey"]["owner"] = json!(victim);
+    assert!(signature.signer(&forged).is_err());
+    let unowned = actor_document(&actor_id(), "https://elsewhere/#key", &key);
+    assert!(signature.signer(&unowned).is_err());
+  }
+
+  #[test]
+  fn stale_or_altered_requests_are_refused() {
+    let (key, body) = (generated_key(), br#"{"type":"Follow"}"#);
+    let read = |headers: &http::HeaderMap, body: &[u8]| {
+      RequestSignature::read("POST", INBOX_PATH, headers, body).err()
+    };
+
+    let headers = signed_headers(&key, body);
+    assert_eq!(
+      read(&headers, br#"{"type":"Delete"}"#).as_deref(),
+      Some("`digest` doesn't match the body")
+    );
+
+    let mut stale = headers.clone();
+    let sent = SystemTime::now() - MAX_CLOCK_SKEW - Duration::from_secs(60);
+    stale.insert("date", httpdate::fmt_http_date(sent).parse().unwrap());
+    assert_eq!(read(&stale, body).as_deref(), Some("signed too long ago"));
+
+    let mut unsigned = headers.clone();
+    let signature = unsigned["signature"].to_str().unwrap().replace(
+      "headers=\"(request-target) host date digest\"",
+      "headers=\"(request-target) host date\"",
+    );
+    unsigned.insert("signature", signature.parse().unwrap());
+    assert_eq!(
+      read(&unsigned, body).as_deref(),
+      Some("`digest` isn't signed")
+    );
+  }
+}
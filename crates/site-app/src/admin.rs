@@ -0,0 +1,336 @@
+//! The admin area, under `/admin`, where the author can see every post,
+//! including drafts and private posts, with what's wrong with any that can't
+//! be served. It only exists once a way to log in is configured, see
+//! [`auth`](crate::auth).
+
+use leptos::*;
+use leptos_meta::{Meta, Title};
+use leptos_router::{use_query_map, ActionForm, Outlet};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ssr")]
+use crate::auth::require_admin;
+use crate::{
+  auth::{check_admin, AdminLogIn, AdminLogOut, AuthMethod},
+  error_template::{AppError, ErrorTemplate},
+};
+
+/// How long preview links last unless asked otherwise.
+#[cfg(feature = "ssr")]
+const PREVIEW_DAYS: u64 = 7;
+
+/// A link letting whoever has it read the post at `path`, public or not, for
+/// `days`, and when it expires.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreviewLink {
+  pub title: String,
+  pub url:   String,
+  pub days:  u64,
+}
+
+/// Makes a link for reviewers to read a draft before it's public.
+#[server]
+pub async fn mint_preview_link(
+  path: String,
+  days: Option<u64>,
+) -> Result<PreviewLink, ServerFnError> {
+  require_admin().await?;
+  let key = crate::auth::use_signing_key()?;
+  let index = expect_context::<crate::post_index::PostIndex>();
+  let path = crate::posts::normalize_post_path(&path)
+    .ok_or_else(|| ServerFnError::new(AppError::NotFound))?;
+  let input = index
+    .source(&path)
+    .ok_or_else(|| ServerFnError::new(AppError::NotFound))?;
+  let (metadata, _) = crate::posts::try_parse_frontmatter(&input)
+    .map_err(|_| ServerFnError::new(AppError::InvalidContent))?;
+
+  // a year is plenty for a review, and a zero-day link would be useless
+  let days = days.unwrap_or(PREVIEW_DAYS).clamp(1, 365);
+  let token = crate::auth::preview_token(
+    &key,
+    &path,
+    std::time::Duration::from_secs(days * 24 * 3600),
+  );
+  log::info!("minted a {days}-day preview link for `{path}`");
+  Ok(PreviewLink {
+    title: metadata.title,
+    url: crate::config::site_url(&format!("/preview/{path}?token={token}")),
+    days,
+  })
+}
+
+/// A post as the admin area lists it, whether or not it can be served.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminPost {
+  pub path:       String,
+  /// The post's title, if its frontmatter could be read.
+  pub title:      Option<String>,
+  pub written_on: Option<String>,
+  pub public:     bool,
+  /// What's wrong with the post, if anything.
+  pub problem:    Option<String>,
+}
+
+/// Every post, including drafts, private posts and broken ones.
+#[server]
+pub async fn get_admin_posts() -> Result<Vec<AdminPost>, ServerFnError> {
+  require_admin().await?;
+  let index = expect_context::<crate::post_index::PostIndex>();
+  let posts = index
+    .sources()
+    .into_iter()
+    .map(
+      |(path, input)| match crate::posts::try_parse_frontmatter(&input) {
+        Ok((metadata, _)) => AdminPost {
+          problem: crate::dates::iso_date(&metadata.written_on).is_none().then(
+            || {
+              format!(
+                "`written_on` is `{}`, not a `YYYY.MM.DD` date",
+                metadata.written_on
+              )
+            },
+          ),
+          path,
+          title: Some(metadata.title),
+          written_on: Some(metadata.written_on),
+          public: metadata.public,
+        },
+        Err(e) => AdminPost {
+          path,
+          title: None,
+          written_on: None,
+          public: false,
+          problem: Some(e),
+        },
+      },
+    )
+    .collect();
+  Ok(posts)
+}
+
+/// The admin area's layout, showing the login form until the author logs in.
+#[component]
+pub fn AdminArea() -> impl IntoView {
+  // admin pages are never to be cached or indexed
+  #[cfg(feature = "ssr")]
+  if let Some(response) = use_context::<leptos_axum::ResponseOptions>() {
+    response.insert_header(
+      http::header::CACHE_CONTROL,
+      http::HeaderValue::from_static("no-store"),
+    );
+  }
+  let session_resource = create_blocking_resource(|| (), |_| check_admin());
+
+  view! {
+    <Title text="Admin" />
+    <Meta name="robots" content="noindex" />
+    <Suspense>
+      <ErrorBoundary fallback=|errors| view! { <ErrorTemplate errors /> }>
+        { move || session_resource.get().map(|session| match session.map_err(AppError::from) {
+          Ok(()) => Ok(view! {
+            <div class="flex gap-4 items-center mb-4">
+              <h1 class="text-2xl">"Admin"</h1>
+              <a class="text-periwinkle underline hover:no-underline" href="/admin">"Posts"</a>
+              <a class="text-periwinkle underline hover:no-underline" href="/admin/comments">"Comments"</a>
+              <a class="text-periwinkle underline hover:no-underline" href="/admin/upload">"Upload"</a>
+              <a class="text-periwinkle underline hover:no-underline" href="/admin/webmentions">"Webmentions"</a>
+              <a class="text-periwinkle underline hover:no-underline" href="/admin/analytics">"Analytics"</a>
+              <div class="flex-1" />
+              <LogOutButton />
+            </div>
+            <Outlet />
+          }.into_view()),
+          Err(AppError::Unauthorized) => Ok(view! { <LogInForm /> }.into_view()),
+          Err(e) => Err(e),
+        }) }
+      </ErrorBoundary>
+    </Suspense>
+  }
+}
+
+/// Logs in with each configured way to.
+#[component]
+fn LogInForm() -> impl IntoView {
+  let log_in = create_server_action::<AdminLogIn>();
+  let failed = use_query_map().get_untracked().get("login").cloned();
+  let methods_resource =
+    create_blocking_resource(|| (), |_| crate::auth::get_auth_methods());
+
+  view! {
+    <div class="markdown">
+      <h1>"Log In"</h1>
+      { failed.map(|failed| view! {
+        <p>{ match failed.as_str() {
+          "github" => "GitHub didn't log you in as the author.",
+          _ => "That's not the password.",
+        } }</p>
+      }) }
+    </div>
+    <Suspense>
+      <ErrorBoundary fallback=|errors| view! { <ErrorTemplate errors /> }>
+        { move || methods_resource.get().map(|m| m.map_err(AppError::from).map(|methods| methods.into_iter().map(|method| match method {
+          AuthMethod::Password => view! {
+            <ActionForm action=log_in class="flex gap-2 mb-4">
+              <input
+                type="password" name="password" required autofocus
+                autocomplete="current-password" aria-label="Password"
+                class="bg-neutral-700 px-2 py-1 flex-1"
+              />
+              <button type="submit" class="bg-neutral-700 px-4 py-1 hover:bg-neutral-600">"Log in"</button>
+            </ActionForm>
+          }.into_view(),
+          AuthMethod::GitHub => view! {
+            <a
+              href="/auth/github" rel="external"
+              class="inline-block bg-neutral-700 px-4 py-1 mb-4 hover:bg-neutral-600"
+            >"Log in with GitHub"</a>
+          }.into_view(),
+        }).collect_view())) }
+      </ErrorBoundary>
+    </Suspense>
+  }
+}
+
+#[component]
+fn LogOutButton() -> impl IntoView {
+  let log_out = create_server_action::<AdminLogOut>();
+  view! {
+    <ActionForm action=log_out>
+      <button type="submit" class="text-periwinkle underline hover:no-underline">"Log out"</button>
+    </ActionForm>
+  }
+}
+
+/// Every post, with whether it's public and what's wrong with it.
+#[component]
+pub fn AdminPosts() -> impl IntoView {
+  let posts_resource = create_blocking_resource(|| (), |_| get_admin_posts());
+
+  let post_row = |post: AdminPost| {
+    let status = match (&post.problem, post.public) {
+      (Some(_), _) => "broken",
+      (None, true) => "public",
+      (None, false) => "private",
+    };
+    view! {
+      <tr class="border-t border-neutral-600 align-top">
+        <td class="py-1 pr-4">
+          { match post.title {
+            Some(title) if post.public => view! {
+              <a class="text-periwinkle underline hover:no-underline" href=format!("/post/{}", post.path)>{title}</a>
+            }.into_view(),
+            Some(title) => title.into_view(),
+            None => view! { <code>{post.path.clone()}</code> }.into_view(),
+          } }
+        </td>
+        <td class="py-1 pr-4 whitespace-nowrap">{post.written_on}</td>
+        <td class="py-1 pr-4">{status}</td>
+        <td class="py-1 pr-4 text-neutral-400">{post.problem.clone()}</td>
+        <td class="py-1 whitespace-nowrap">
+          <a class="text-periwinkle underline hover:no-underline" href=format!("/admin/edit/{}", post.path)>"Edit"</a>
+          { post.problem.is_none().then(|| view! {
+            " "
+            <a class="text-periwinkle underline hover:no-underline" href=format!("/admin/preview/{}", post.path)>"Share"</a>
+          }) }
+        </td>
+      </tr>
+    }
+  };
+
+  view! {
+    <Suspense>
+      <ErrorBoundary fallback=|errors| view! { <ErrorTemplate errors /> }>
+        { move || posts_resource.get().map(|p| p.map_err(AppError::from).map(|posts| view! {
+          <table class="w-full text-left">
+            <thead>
+              <tr><th>"Post"</th><th>"Written"</th><th>"Status"</th><th>"Problems"</th><th /></tr>
+            </thead>
+            <tbody>{posts.into_iter().map(post_row).collect_view()}</tbody>
+          </table>
+        })) }
+      </ErrorBoundary>
+    </Suspense>
+  }
+}
+
+/// Makes a preview link for a post, for as many days as asked.
+#[component]
+pub fn AdminPreviewLink() -> impl IntoView {
+  let path = crate::posts::use_post_path();
+  let days = use_query_map()
+    .get_untracked()
+    .get("days")
+    .and_then(|days| days.parse::<u64>().ok());
+  let link_resource = create_blocking_resource(
+    move || (path.clone(), days),
+    |(path, days)| mint_preview_link(path, days),
+  );
+
+  view! {
+    <Suspense>
+      <ErrorBoundary fallback=|errors| view! { <ErrorTemplate errors /> }>
+        { move || link_resource.get().map(|l| l.map_err(AppError::from).map(|link| view! {
+          <div class="markdown">
+            <h2>"Preview of “" {link.title} "”"</h2>
+            <p>"Anyone with this link can read the post for " {link.days} " days:"</p>
+          </div>
+          <input
+            type="text" readonly value=link.url aria-label="Preview link"
+            class="bg-neutral-700 px-2 py-1 w-full my-2"
+          />
+          <form method="get" class="flex gap-2 items-center">
+            <label for="days">"Make one lasting"</label>
+            <input
+              type="number" id="days" name="days" min="1" max="365" value=link.days
+              class="bg-neutral-700 px-2 py-1 w-20"
+            />
+            <span>"days"</span>
+            <button type="submit" class="bg-neutral-700 px-4 py-1 hover:bg-neutral-600">"Make"</button>
+          </form>
+        })) }
+      </ErrorBoundary>
+    </Suspense>
+  }
+}
+
+/// Uploads images, for a post or shared, and shows the markdown for the last
+/// upload. The form is handled by the server's upload route, which comes back
+/// here with the markdown.
+#[component]
+pub fn AdminUpload() -> impl IntoView {
+  let uploaded = use_query_map().get_untracked().get("uploaded").cloned();
+  let posts_resource = create_blocking_resource(|| (), |_| get_admin_posts());
+
+  view! {
+    { uploaded.map(|snippets| view! {
+      <div class="markdown">
+        <p>"Uploaded! Paste this into the post:"</p>
+      </div>
+      <textarea readonly rows="3" aria-label="Markdown" class="bg-neutral-700 px-2 py-1 w-full my-2 font-mono">{snippets}</textarea>
+    }) }
+    <Suspense>
+      <ErrorBoundary fallback=|errors| view! { <ErrorTemplate errors /> }>
+        { move || posts_resource.get().map(|p| p.map_err(AppError::from).map(|posts| view! {
+          <form method="post" action="/upload/images" enctype="multipart/form-data" class="flex flex-col gap-2">
+            <label>
+              "For "
+              <select name="post" class="bg-neutral-700 px-2 py-1">
+                <option value="">"every post"</option>
+                { posts.into_iter().map(|post| view! {
+                  <option value=post.path.clone()>{post.title.unwrap_or(post.path)}</option>
+                }).collect_view() }
+              </select>
+            </label>
+            <input
+              type="text" name="alt" placeholder="Alt text" aria-label="Alt text"
+              class="bg-neutral-700 px-2 py-1"
+            />
+            <input type="file" name="file" multiple required accept="image/png,image/jpeg,image/gif,image/webp,image/avif" />
+            <button type="submit" class="bg-neutral-700 px-4 py-1 hover:bg-neutral-600 self-start">"Upload"</button>
+          </form>
+        })) }
+      </ErrorBoundary>
+    </Suspense>
+  }
+}
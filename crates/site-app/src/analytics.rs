@@ -0,0 +1,280 @@
+//! Visits to the site, recorded without cookies or any third party, see
+//! [`AnalyticsConfig`](crate::config::AnalyticsConfig).
+//!
+//! Once a page has loaded, [`AnalyticsBeacon`] sends its path and referrer to
+//! [`BEACON_PATH`], where the server records the visit. The admin area's
+//! dashboard charts the visits of each day and week, and lists where they
+//! went and came from.
+//!
+//! Sites can also have a third-party service like Plausible count visits,
+//! see [`ExternalAnalytics`](crate::config::ExternalAnalytics).
+
+use leptos::*;
+use serde::{Deserialize, Serialize};
+
+use crate::error_template::{AppError, ErrorTemplate};
+
+/// Where pages send their beacon.
+pub const BEACON_PATH: &str = "/beacon";
+/// How many days the dashboard charts.
+const DAYS_CHARTED: i64 = 30;
+/// How many weeks the dashboard charts.
+#[cfg(feature = "ssr")]
+const WEEKS_CHARTED: i64 = 12;
+/// How many of the most visited pages, top referrers and countries are
+/// listed.
+#[cfg(feature = "ssr")]
+const TOP_LIMIT: i64 = 10;
+
+/// What a page's beacon sends.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Beacon {
+  pub path:     String,
+  /// The page the visitor came from, which is empty if they didn't follow a
+  /// link.
+  pub referrer: String,
+}
+
+/// Whether visits are recorded.
+pub fn is_enabled() -> bool {
+  #[cfg(feature = "ssr")]
+  {
+    crate::config::use_site_config().analytics.enabled
+  }
+  #[cfg(not(feature = "ssr"))]
+  false
+}
+
+/// The day `date` is stored as, like `2024-12-13`.
+#[cfg(feature = "ssr")]
+pub fn day(date: time::Date) -> String { date.to_string() }
+
+/// Sends the page's beacon once it's loaded. Navigations are full page loads,
+/// so each one sends its own.
+#[crate::eager_island]
+pub fn AnalyticsBeacon() -> impl IntoView {
+  #[cfg(feature = "hydrate")]
+  {
+    let beacon = Beacon {
+      path:     window().location().pathname().unwrap_or_default(),
+      referrer: document().referrer(),
+    };
+    if let Ok(body) = serde_json::to_string(&beacon) {
+      let _ = window()
+        .navigator()
+        .send_beacon_with_opt_str(BEACON_PATH, Some(&body));
+    }
+  }
+}
+
+/// The configured third-party analytics script, if there is one.
+#[component]
+pub fn ExternalAnalyticsScript() -> impl IntoView {
+  #[cfg(feature = "ssr")]
+  {
+    use leptos_meta::{Script, ScriptProps};
+
+    let config = crate::config::use_site_config();
+    let analytics = config.external_analytics.as_ref()?;
+    let (name, value) = analytics.attribute(&config.base_url);
+    let attrs = vec![
+      (name, value.into_attribute()),
+      (crate::zero_js::KEEP_SCRIPT_ATTRIBUTE, true.into_attribute()),
+    ];
+    Some(Script(
+      ScriptProps::builder()
+        .defer("")
+        .src(analytics.src().to_string())
+        .attrs(attrs)
+        .build(),
+    ))
+  }
+  #[cfg(not(feature = "ssr"))]
+  None::<View>
+}
+
+/// The visits of a day or week.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PeriodVisits {
+  /// The day, or the first day of the week.
+  pub start:    String,
+  pub visits:   i64,
+  /// How many visitors there were, each counted once a day.
+  pub visitors: i64,
+}
+
+/// What the dashboard shows.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AnalyticsSummary {
+  /// The last [`DAYS_CHARTED`] days, oldest first.
+  pub daily:     Vec<PeriodVisits>,
+  /// The last [`WEEKS_CHARTED`] weeks, oldest first.
+  pub weekly:    Vec<PeriodVisits>,
+  /// The most visited pages, referrers and countries of the charted days,
+  /// with their visits.
+  pub pages:     Vec<(String, i64)>,
+  pub referrers: Vec<(String, i64)>,
+  pub countries: Vec<(String, i64)>,
+}
+
+/// The visits of the last few days and weeks.
+#[server]
+pub async fn get_analytics() -> Result<AnalyticsSummary, ServerFnError> {
+  use std::collections::HashMap;
+
+  use site_db::storage::{AnalyticsStore, VisitField};
+  use time::Duration;
+
+  crate::auth::require_admin().await?;
+  let db = crate::comments::use_database()?;
+  let db_error = |e: site_db::DbError| ServerFnError::new(e.to_string());
+
+  let today = time::OffsetDateTime::now_utc().date();
+  let first_day = today - Duration::days(DAYS_CHARTED - 1);
+  let first_week = today
+    - Duration::days(i64::from(today.weekday().number_days_from_monday()))
+    - Duration::weeks(WEEKS_CHARTED - 1);
+  let days = db
+    .daily_visits(&day(first_day.min(first_week)))
+    .await
+    .map_err(db_error)?
+    .into_iter()
+    .map(|visits| (visits.day, (visits.visits, visits.visitors)))
+    .collect::<HashMap<_, _>>();
+  // days without visits are missing
+  let period = |start: time::Date, length: i64| {
+    let (visits, visitors) = (0..length)
+      .filter_map(|offset| days.get(&day(start + Duration::days(offset))))
+      .fold((0, 0), |(visits, visitors), (v, u)| {
+        (visits + v, visitors + u)
+      });
+    PeriodVisits {
+      start: day(start),
+      visits,
+      visitors,
+    }
+  };
+  let daily = (0..DAYS_CHARTED)
+    .map(|offset| period(first_day + Duration::days(offset), 1))
+    .collect();
+  let weekly = (0..WEEKS_CHARTED)
+    .map(|offset| period(first_week + Duration::weeks(offset), 7))
+    .collect();
+
+  let since = day(first_day);
+  let mut top = Vec::new();
+  for field in [VisitField::Path, VisitField::Referrer, VisitField::Country] {
+    let counts = db
+      .top_visits(field, &since, TOP_LIMIT)
+      .await
+      .map_err(db_error)?;
+    top.push(
+      counts
+        .into_iter()
+        .map(|count| (count.value, count.visits))
+        .collect::<Vec<_>>(),
+    );
+  }
+  let [pages, referrers, countries] =
+    <[_; 3]>::try_from(top).expect("there are three fields");
+
+  Ok(AnalyticsSummary {
+    daily,
+    weekly,
+    pages,
+    referrers,
+    countries,
+  })
+}
+
+/// A bar chart, with each bar's label shown when it's hovered.
+#[component]
+fn BarChart(title: &'static str, bars: Vec<(String, i64)>) -> impl IntoView {
+  let max = bars
+    .iter()
+    .map(|(_, value)| *value)
+    .max()
+    .unwrap_or(0)
+    .max(1);
+  let bars = bars.into_iter().map(|(label, value)| {
+    let height = format!("height: {}%", value * 100 / max);
+    view! {
+      <div class="flex-1 flex flex-col justify-end h-full" title=label>
+        <div class="bg-periwinkle min-h-px" style=height></div>
+      </div>
+    }
+  });
+  view! {
+    <div class="flex items-end gap-px h-32 border-b border-neutral-600" role="img" aria-label=title>
+      {bars.collect_view()}
+    </div>
+  }
+}
+
+/// The most common values of something visits had, with how many had them.
+#[component]
+fn TopList(title: &'static str, counts: Vec<(String, i64)>) -> impl IntoView {
+  let rows = counts.into_iter().map(|(value, visits)| {
+    view! {
+      <tr class="border-t border-neutral-600">
+        <td class="py-1 pr-4 break-all">{value}</td>
+        <td class="py-1 text-right">{visits}</td>
+      </tr>
+    }
+  });
+  view! {
+    <section>
+      <h3 class="text-lg mb-1">{title}</h3>
+      <table class="w-full text-left">
+        <tbody>{rows.collect_view()}</tbody>
+      </table>
+    </section>
+  }
+}
+
+/// The dashboard of recent visits.
+#[component]
+pub fn AdminAnalytics() -> impl IntoView {
+  let analytics_resource = create_blocking_resource(|| (), |_| get_analytics());
+
+  let summary_view = |summary: AnalyticsSummary| {
+    let total = summary.daily.iter().map(|day| day.visits).sum::<i64>();
+    let chart = |title, periods: Vec<PeriodVisits>, unit: &str| {
+      let bars = periods
+        .into_iter()
+        .map(|period| {
+          let label = format!(
+            "{} {unit} {}, {} visitors",
+            period.visits, period.start, period.visitors
+          );
+          (label, period.visits)
+        })
+        .collect::<Vec<_>>();
+      view! {
+        <h3 class="text-lg mt-4 mb-1">{title}</h3>
+        <BarChart title bars />
+      }
+    };
+    view! {
+      { (!is_enabled()).then(|| view! {
+        <p class="text-neutral-400">"Visits aren't being recorded, see the `analytics` config."</p>
+      }) }
+      <p>{format!("{total} visits in the last {DAYS_CHARTED} days.")}</p>
+      {chart("Visits per day", summary.daily, "visits on")}
+      {chart("Visits per week", summary.weekly, "visits in the week of")}
+      <div class="grid md:grid-cols-3 gap-4 mt-4">
+        <TopList title="Pages" counts=summary.pages />
+        <TopList title="Referrers" counts=summary.referrers />
+        <TopList title="Countries" counts=summary.countries />
+      </div>
+    }
+  };
+
+  view! {
+    <Suspense>
+      <ErrorBoundary fallback=|errors| view! { <ErrorTemplate errors /> }>
+        { move || analytics_resource.get().map(|a| a.map_err(AppError::from).map(summary_view)) }
+      </ErrorBoundary>
+    </Suspense>
+  }
+}
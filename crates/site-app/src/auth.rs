@@ -0,0 +1,674 @@
+//! Logging in to what only the author may use, like the admin area. The
+//! author logs in with any of the configured [`AuthMethod`]s, a password or
+//! GitHub restricted to their account, see
+//! [`AdminConfig`](crate::config::AdminConfig), and gets the same session
+//! either way.
+//!
+//! Sessions are stored in the database, so logging out ends them. The cookie
+//! holds the session's ID and when it expires, signed with the session
+//! secret, see [`SiteConfig::session_secret`](crate::config::SiteConfig),
+//! so changing the secret logs every session out too. Server fns guard
+//! themselves with [`require_admin`], and the server guards its own routes
+//! with [`has_admin_session`].
+//!
+//! Passwords are hashed with Argon2id, as `$argon2id$...` PHC strings.
+
+use leptos::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ssr")]
+use crate::error_template::AppError;
+
+/// A way for the author to log in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuthMethod {
+  Password,
+  GitHub,
+}
+
+#[cfg(feature = "ssr")]
+mod tokens {
+  use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+  use argon2::{
+    password_hash::{PasswordHash, PasswordVerifier, SaltString},
+    Argon2, PasswordHasher,
+  };
+  use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+  use hmac::{Hmac, Mac};
+  use sha2::Sha256;
+  use subtle::ConstantTimeEq;
+
+  type HmacSha256 = Hmac<Sha256>;
+
+  /// The cookie holding an admin session.
+  const SESSION_COOKIE: &str = "admin_session";
+
+  /// Hashes a password with a new random salt, as an Argon2id PHC string
+  /// with the parameters OWASP recommends.
+  pub fn hash_password(password: &str) -> String {
+    let salt = SaltString::encode_b64(&rand::random::<[u8; 16]>())
+      .expect("16 bytes make a valid salt");
+    Argon2::default()
+      .hash_password(password.as_bytes(), &salt)
+      .expect("the default parameters are valid")
+      .to_string()
+  }
+
+  /// A configured password hash, describing what's wrong if it isn't an
+  /// Argon2 PHC string.
+  fn parse_hash(hash: &str) -> Result<PasswordHash<'_>, String> {
+    let hash = PasswordHash::new(hash)
+      .map_err(|e| format!("isn't a PHC string: {e}"))?;
+    match hash.algorithm.as_str() {
+      "argon2id" | "argon2i" | "argon2d" => Ok(hash),
+      algorithm => Err(format!(
+        "is a `{algorithm}` hash, not an Argon2 one from `hash-password`"
+      )),
+    }
+  }
+
+  /// Checks that a configured password hash is valid.
+  pub fn validate_hash(hash: &str) -> Result<(), String> {
+    parse_hash(hash).map(|_| ())
+  }
+
+  /// Whether `password` is the one `hash` was made from. This is slow on
+  /// purpose, so call it from blocking code.
+  pub fn verify_password(password: &str, hash: &str) -> bool {
+    parse_hash(hash).is_ok_and(|hash| {
+      Argon2::default()
+        .verify_password(password.as_bytes(), &hash)
+        .is_ok()
+    })
+  }
+
+  /// Whether two secrets are the same, taking as long whether they are or not.
+  pub fn secrets_match(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+  }
+
+  /// A random value, for state that can't be guessed.
+  pub fn random_state() -> String {
+    URL_SAFE_NO_PAD.encode(rand::random::<[u8; 16]>())
+  }
+
+  fn now() -> u64 {
+    SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap_or_default()
+      .as_secs()
+  }
+
+  /// Signs what a token grants until it expires. Separate grants, like
+  /// sessions and previews of each post, must have separate `grant`s.
+  fn sign(key: &str, grant: &str, expires: u64) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes())
+      .expect("HMAC takes keys of any length");
+    mac.update(grant.as_bytes());
+    mac.update(&[0]);
+    mac.update(&expires.to_be_bytes());
+    mac
+  }
+
+  /// A token granting `grant` for `length`, as `<expires>.<signature>`.
+  fn token(key: &str, grant: &str, length: Duration) -> String {
+    let expires = now() + length.as_secs();
+    let signature = sign(key, grant, expires).finalize().into_bytes();
+    format!("{expires}.{}", URL_SAFE_NO_PAD.encode(signature))
+  }
+
+  /// Whether `token` grants `grant` and hasn't expired.
+  fn verify(key: &str, grant: &str, token: &str) -> bool {
+    let Some((expires, signature)) = token.split_once('.') else {
+      return false;
+    };
+    let (Ok(expires), Ok(signature)) =
+      (expires.parse::<u64>(), URL_SAFE_NO_PAD.decode(signature))
+    else {
+      return false;
+    };
+    expires > now()
+      && sign(key, grant, expires).verify_slice(&signature).is_ok()
+  }
+
+  /// A token for previewing the post at `path`.
+  pub fn preview_token(key: &str, path: &str, length: Duration) -> String {
+    token(key, &format!("preview {path}"), length)
+  }
+
+  /// Whether `token` lets its holder preview the post at `path`.
+  pub fn verify_preview_token(key: &str, path: &str, token: &str) -> bool {
+    verify(key, &format!("preview {path}"), token)
+  }
+
+  /// A token for a link in an email from the newsletter, letting its holder
+  /// `action` the subscription of `email`.
+  pub fn newsletter_token(
+    key: &str,
+    action: &str,
+    email: &str,
+    length: Duration,
+  ) -> String {
+    token(key, &format!("newsletter {action} {email}"), length)
+  }
+
+  /// Whether `token` lets its holder `action` the subscription of `email`.
+  pub fn verify_newsletter_token(
+    key: &str,
+    action: &str,
+    email: &str,
+    token: &str,
+  ) -> bool {
+    verify(key, &format!("newsletter {action} {email}"), token)
+  }
+
+  /// When `form` was rendered, now, signed as `<rendered_at>.<signature>`.
+  pub fn form_timestamp(key: &str, form: &str) -> String {
+    let rendered_at = now();
+    let signature = sign(key, &format!("form {form}"), rendered_at)
+      .finalize()
+      .into_bytes();
+    format!("{rendered_at}.{}", URL_SAFE_NO_PAD.encode(signature))
+  }
+
+  /// How many seconds ago `form` was rendered, if `timestamp` is one
+  /// [`form_timestamp`] made for it.
+  pub fn form_age(key: &str, form: &str, timestamp: &str) -> Option<u64> {
+    let (rendered_at, signature) = timestamp.split_once('.')?;
+    let rendered_at = rendered_at.parse::<u64>().ok()?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+    sign(key, &format!("form {form}"), rendered_at)
+      .verify_slice(&signature)
+      .ok()?;
+    Some(now().saturating_sub(rendered_at))
+  }
+
+  /// A `Set-Cookie` value holding the session `id`, which lasts `length`.
+  pub fn session_cookie(
+    key: &str,
+    id: &str,
+    length: Duration,
+    secure: bool,
+  ) -> String {
+    format!(
+      "{SESSION_COOKIE}={id}.{}; Max-Age={}; Path=/; HttpOnly; \
+       SameSite=Strict{}",
+      token(key, &format!("{SESSION_COOKIE} {id}"), length),
+      length.as_secs(),
+      if secure { "; Secure" } else { "" }
+    )
+  }
+
+  /// A `Set-Cookie` value ending the session.
+  pub fn expired_cookie() -> String {
+    format!("{SESSION_COOKIE}=; Max-Age=0; Path=/; HttpOnly; SameSite=Strict")
+  }
+
+  /// The ID of the unexpired session a `Cookie` header holds, if it holds
+  /// one. Whether it's been ended is up to the database.
+  pub fn session_id(key: &str, cookies: &str) -> Option<String> {
+    cookies
+      .split(';')
+      .filter_map(|cookie| cookie.trim().split_once('='))
+      .filter(|(name, _)| *name == SESSION_COOKIE)
+      .filter_map(|(_, session)| session.split_once('.'))
+      .find(|(id, token)| verify(key, &format!("{SESSION_COOKIE} {id}"), token))
+      .map(|(id, _)| id.to_string())
+  }
+
+  #[cfg(test)]
+  mod tests {
+    use super::*;
+
+    #[test]
+    fn passwords_verify_against_their_hash() {
+      let hash = hash_password("hunter2");
+      assert!(hash.starts_with("$argon2id$"));
+      assert!(validate_hash(&hash).is_ok());
+      assert!(verify_password("hunter2", &hash));
+      assert!(!verify_password("hunter3", &hash));
+      assert!(validate_hash("$pbkdf2-sha256$i=600000$c2FsdA$aGFzaA").is_err());
+      assert!(validate_hash("hunter2").is_err());
+    }
+
+    #[test]
+    fn form_timestamps_are_only_read_for_their_form() {
+      let timestamp = form_timestamp("key", "comment");
+      assert_eq!(form_age("key", "comment", &timestamp), Some(0));
+      assert_eq!(form_age("key", "contact", &timestamp), None);
+      assert_eq!(form_age("other key", "comment", &timestamp), None);
+
+      // an earlier time with this one's signature
+      let (rendered_at, signature) = timestamp.split_once('.').unwrap();
+      let earlier = rendered_at.parse::<u64>().unwrap() - 60;
+      let forged = format!("{earlier}.{signature}");
+      assert_eq!(form_age("key", "comment", &forged), None);
+      assert_eq!(form_age("key", "comment", rendered_at), None);
+    }
+
+    #[test]
+    fn sessions_are_only_read_from_signed_cookies() {
+      let length = Duration::from_secs(60);
+      let cookie = session_cookie("key", "id", length, false);
+      let value = cookie.split(';').next().unwrap();
+      assert_eq!(session_id("key", value).as_deref(), Some("id"));
+      assert_eq!(session_id("other key", value), None);
+
+      // another session's ID with this one's signature
+      let forged = value.replacen("=id.", "=other.", 1);
+      assert_eq!(session_id("key", &forged), None);
+      assert_eq!(session_id("key", "admin_session=id"), None);
+    }
+  }
+}
+
+#[cfg(feature = "ssr")]
+pub use tokens::{hash_password, validate_hash};
+#[cfg(feature = "ssr")]
+pub(crate) use tokens::{
+  newsletter_token, preview_token, verify_newsletter_token,
+  verify_preview_token,
+};
+
+/// Logging in with GitHub, through an OAuth app which only lets the
+/// configured account in. The server serves [`START_PATH`] and
+/// [`CALLBACK_PATH`], which is the callback URL to give the app.
+#[cfg(feature = "ssr")]
+pub mod github {
+  use std::time::Duration;
+
+  use hyper::{body::HttpBody, Body, Request};
+  use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+  use serde::Deserialize;
+
+  use crate::config::{GitHubAuthConfig, SiteConfig};
+
+  /// Where logging in with GitHub starts.
+  pub const START_PATH: &str = "/auth/github";
+  /// Where GitHub sends the author back to.
+  pub const CALLBACK_PATH: &str = "/auth/github/callback";
+  /// The cookie holding the state a login was started with, tying GitHub's
+  /// callback to the browser which started it.
+  const STATE_COOKIE: &str = "github_state";
+  /// How long the author has to log in on GitHub.
+  const STATE_SECS: u64 = 10 * 60;
+  /// How long each request to GitHub can take.
+  const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+  /// The most of a response from GitHub that's read.
+  const MAX_BODY_BYTES: usize = 64 * 1024;
+
+  /// Where to send the author to log in, and the `Set-Cookie` value to send
+  /// along with them.
+  pub fn authorize(
+    config: &SiteConfig,
+    github: &GitHubAuthConfig,
+  ) -> (String, String) {
+    let state = super::tokens::random_state();
+    let url = format!(
+      "https://github.com/login/oauth/authorize?client_id={}&redirect_uri={}&state={state}&allow_signup=false",
+      utf8_percent_encode(&github.client_id, NON_ALPHANUMERIC),
+      utf8_percent_encode(&config.url(CALLBACK_PATH), NON_ALPHANUMERIC),
+    );
+    // `Lax`, since GitHub's redirect back is a cross-site navigation
+    let cookie = format!(
+      "{STATE_COOKIE}={state}; Max-Age={STATE_SECS}; Path={CALLBACK_PATH}; \
+       HttpOnly; SameSite=Lax{}",
+      if config.base_url.starts_with("https:") {
+        "; Secure"
+      } else {
+        ""
+      }
+    );
+    (url, cookie)
+  }
+
+  /// A `Set-Cookie` value forgetting the state, once it's been used.
+  pub fn expired_state_cookie() -> String {
+    format!(
+      "{STATE_COOKIE}=; Max-Age=0; Path={CALLBACK_PATH}; HttpOnly; \
+       SameSite=Lax"
+    )
+  }
+
+  #[derive(Deserialize)]
+  struct AccessToken {
+    access_token: Option<String>,
+    error:        Option<String>,
+  }
+
+  #[derive(Deserialize)]
+  struct User {
+    login: String,
+  }
+
+  /// Sends a request to GitHub and parses its JSON response.
+  async fn fetch<T: serde::de::DeserializeOwned>(
+    request: Request<Body>,
+  ) -> Result<T, String> {
+    let fetch = async {
+      let mut response = crate::previews::client()
+        .request(request)
+        .await
+        .map_err(|e| e.to_string())?;
+      if !response.status().is_success() {
+        return Err(format!("GitHub responded with {}", response.status()));
+      }
+      let mut body = Vec::new();
+      while let Some(chunk) = response.body_mut().data().await {
+        body.extend_from_slice(&chunk.map_err(|e| e.to_string())?);
+        if body.len() > MAX_BODY_BYTES {
+          return Err("GitHub's response is too big".to_string());
+        }
+      }
+      serde_json::from_slice(&body)
+        .map_err(|e| format!("GitHub's response is invalid: {e}"))
+    };
+    tokio::time::timeout(REQUEST_TIMEOUT, fetch)
+      .await
+      .unwrap_or_else(|_| Err("GitHub took too long".to_string()))
+  }
+
+  /// Finishes logging in with the `code` and `state` GitHub sent back,
+  /// describing what went wrong unless the configured account logged in.
+  pub async fn log_in(
+    config: &SiteConfig,
+    github: &GitHubAuthConfig,
+    code: &str,
+    state: &str,
+    cookies: &str,
+  ) -> Result<(), String> {
+    let started_here = cookies
+      .split(';')
+      .filter_map(|cookie| cookie.trim().split_once('='))
+      .any(|(name, value)| {
+        name == STATE_COOKIE && super::tokens::secrets_match(value, state)
+      });
+    if !started_here {
+      return Err("the login wasn't started from this browser".to_string());
+    }
+
+    let form = format!(
+      "client_id={}&client_secret={}&code={}&redirect_uri={}",
+      utf8_percent_encode(&github.client_id, NON_ALPHANUMERIC),
+      utf8_percent_encode(&github.client_secret, NON_ALPHANUMERIC),
+      utf8_percent_encode(code, NON_ALPHANUMERIC),
+      utf8_percent_encode(&config.url(CALLBACK_PATH), NON_ALPHANUMERIC),
+    );
+    let request = Request::post("https://github.com/login/oauth/access_token")
+      .header(hyper::header::ACCEPT, "application/json")
+      .header(
+        hyper::header::CONTENT_TYPE,
+        "application/x-www-form-urlencoded",
+      )
+      .body(Body::from(form))
+      .map_err(|e| e.to_string())?;
+    let token = match fetch::<AccessToken>(request).await? {
+      AccessToken {
+        access_token: Some(token),
+        ..
+      } => token,
+      AccessToken { error, .. } => {
+        return Err(format!(
+          "GitHub didn't give an access token: {}",
+          error.unwrap_or_default()
+        ))
+      }
+    };
+
+    let request = Request::get("https://api.github.com/user")
+      .header(hyper::header::ACCEPT, "application/vnd.github+json")
+      .header(hyper::header::AUTHORIZATION, format!("Bearer {token}"))
+      // GitHub's API turns away requests without one
+      .header(
+        hyper::header::USER_AGENT,
+        concat!("site-app/", env!("CARGO_PKG_VERSION"), " (admin login)"),
+      )
+      .body(Body::empty())
+      .map_err(|e| e.to_string())?;
+    let user = fetch::<User>(request).await?;
+    // GitHub logins are case-insensitive
+    if !user.login.eq_ignore_ascii_case(&github.user) {
+      return Err(format!("`{}` isn't the author's account", user.login));
+    }
+    Ok(())
+  }
+}
+
+/// The GitHub app to log in with, unless it's missing its secret, without
+/// which nobody could log in with it.
+#[cfg(feature = "ssr")]
+pub fn github_config(
+  config: &crate::config::SiteConfig,
+) -> Option<&crate::config::GitHubAuthConfig> {
+  config
+    .admin
+    .github
+    .as_ref()
+    .filter(|github| !github.client_secret.is_empty())
+    .filter(|_| config.session_secret.is_some())
+}
+
+/// The ways to log in that are configured. There are none without the
+/// session secret, since there'd be nothing to sign sessions with.
+#[cfg(feature = "ssr")]
+pub fn auth_methods(config: &crate::config::SiteConfig) -> Vec<AuthMethod> {
+  let mut methods = Vec::new();
+  if config.session_secret.is_none() {
+    return methods;
+  }
+  if config.admin.password_hash.is_some() {
+    methods.push(AuthMethod::Password);
+  }
+  if github_config(config).is_some() {
+    methods.push(AuthMethod::GitHub);
+  }
+  methods
+}
+
+/// The key sessions, preview links, the newsletter's links and the forms'
+/// timestamps are signed with, which is the session secret alone, so that
+/// rotating any other secret leaves them be. `None` without one.
+#[cfg(feature = "ssr")]
+pub fn signing_key(config: &crate::config::SiteConfig) -> Option<String> {
+  config.session_secret.clone()
+}
+
+/// The signing key, or a `404` without one.
+#[cfg(feature = "ssr")]
+pub(crate) fn use_signing_key() -> Result<String, ServerFnError> {
+  signing_key(&crate::config::use_site_config())
+    .ok_or_else(|| ServerFnError::new(AppError::NotFound))
+}
+
+/// The key forms' timestamps are signed with: the signing key, or without
+/// one, a random key made when the server starts, which does for forms
+/// filled in soon after they're rendered.
+#[cfg(feature = "ssr")]
+fn form_key() -> String {
+  static FALLBACK: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+  signing_key(&crate::config::use_site_config())
+    .unwrap_or_else(|| FALLBACK.get_or_init(tokens::random_state).clone())
+}
+
+/// The value of the hidden `rendered_at` field of `form`, so that its
+/// submission can tell when it was rendered.
+#[cfg(feature = "ssr")]
+pub(crate) fn form_timestamp(form: &str) -> String {
+  tokens::form_timestamp(&form_key(), form)
+}
+
+/// How many seconds ago `form` was rendered, from its `rendered_at` field, or
+/// `None` if the field wasn't one [`form_timestamp`] gave.
+#[cfg(feature = "ssr")]
+pub(crate) fn form_age(form: &str, rendered_at: &str) -> Option<u64> {
+  tokens::form_age(&form_key(), form, rendered_at)
+}
+
+/// The signing key, if there's an admin area to sign sessions for.
+#[cfg(feature = "ssr")]
+fn session_key(config: &crate::config::SiteConfig) -> Option<String> {
+  signing_key(config).filter(|_| !auth_methods(config).is_empty())
+}
+
+/// A `404` unless there's an admin area.
+#[cfg(feature = "ssr")]
+fn require_admin_area() -> Result<(), ServerFnError> {
+  if auth_methods(&crate::config::use_site_config()).is_empty() {
+    Err(ServerFnError::new(AppError::NotFound))
+  } else {
+    Ok(())
+  }
+}
+
+/// Starts a session, however the author logged in, returning the
+/// `Set-Cookie` value for it, or `None` if there's no way to log in.
+#[cfg(feature = "ssr")]
+pub async fn start_session(
+  config: &crate::config::SiteConfig,
+  db: &site_db::Database,
+) -> Result<Option<String>, site_db::DbError> {
+  use site_db::storage::SessionStore;
+
+  let Some(key) = session_key(config) else {
+    return Ok(None);
+  };
+  let length =
+    std::time::Duration::from_secs(config.admin.session_hours * 3600);
+  let id = tokens::random_state();
+  let expires_at =
+    time::OffsetDateTime::now_utc().unix_timestamp() + length.as_secs() as i64;
+  db.start_session(&id, expires_at).await?;
+  Ok(Some(tokens::session_cookie(
+    &key,
+    &id,
+    length,
+    config.base_url.starts_with("https:"),
+  )))
+}
+
+/// The ID of the session whose cookie a request's headers hold, if it's
+/// signed and unexpired.
+#[cfg(feature = "ssr")]
+fn session_id(
+  config: &crate::config::SiteConfig,
+  headers: &http::HeaderMap,
+) -> Option<String> {
+  let key = session_key(config)?;
+  headers
+    .get_all(http::header::COOKIE)
+    .iter()
+    .filter_map(|cookies| cookies.to_str().ok())
+    .find_map(|cookies| tokens::session_id(&key, cookies))
+}
+
+/// Whether a request's headers hold an admin session which hasn't been
+/// logged out of.
+#[cfg(feature = "ssr")]
+pub async fn has_admin_session(
+  config: &crate::config::SiteConfig,
+  db: &site_db::Database,
+  headers: &http::HeaderMap,
+) -> bool {
+  use site_db::storage::SessionStore;
+
+  let Some(id) = session_id(config, headers) else {
+    return false;
+  };
+  db.has_session(&id).await.unwrap_or_else(|e| {
+    log::error!("couldn't look up an admin session: {e}");
+    false
+  })
+}
+
+/// Fails with [`AppError::Unauthorized`] unless the request has an admin
+/// session. Every server fn only the author may call starts with this.
+#[cfg(feature = "ssr")]
+pub async fn require_admin() -> Result<(), ServerFnError> {
+  require_admin_area()?;
+  let db = crate::comments::use_database()?;
+  let has_session = match use_context::<http::request::Parts>() {
+    Some(parts) => {
+      has_admin_session(&crate::config::use_site_config(), &db, &parts.headers)
+        .await
+    }
+    None => false,
+  };
+  if has_session {
+    Ok(())
+  } else {
+    Err(ServerFnError::new(AppError::Unauthorized))
+  }
+}
+
+/// Checks that the request has an admin session.
+#[server]
+pub async fn check_admin() -> Result<(), ServerFnError> {
+  require_admin().await
+}
+
+/// The ways to log in, or a `404` if there are none.
+#[server]
+pub async fn get_auth_methods() -> Result<Vec<AuthMethod>, ServerFnError> {
+  require_admin_area()?;
+  Ok(auth_methods(&crate::config::use_site_config()))
+}
+
+/// Logs in with the admin password, going back to the admin area either way.
+#[server]
+pub async fn admin_log_in(password: String) -> Result<(), ServerFnError> {
+  let config = crate::config::use_site_config();
+  let password_hash = config
+    .admin
+    .password_hash
+    .clone()
+    .filter(|_| auth_methods(&config).contains(&AuthMethod::Password))
+    .ok_or_else(|| ServerFnError::new(AppError::NotFound))?;
+  let verified = crate::posts::blocking(move || {
+    tokens::verify_password(&password, &password_hash)
+  })
+  .await?;
+  if !verified {
+    log::warn!("failed admin login");
+    leptos_axum::redirect("/admin?login=failed");
+    return Ok(());
+  }
+
+  let db = crate::comments::use_database()?;
+  let cookie = start_session(&config, &db)
+    .await
+    .map_err(|e| ServerFnError::new(e.to_string()))?
+    .expect("a password is configured");
+  let response = expect_context::<leptos_axum::ResponseOptions>();
+  response.append_header(
+    http::header::SET_COOKIE,
+    http::HeaderValue::from_str(&cookie).expect("cookies are valid headers"),
+  );
+  log::info!("admin logged in with the password");
+  leptos_axum::redirect("/admin");
+  Ok(())
+}
+
+/// Ends the admin session, so its cookie no longer logs anyone in.
+#[server]
+pub async fn admin_log_out() -> Result<(), ServerFnError> {
+  use site_db::storage::SessionStore;
+
+  let config = crate::config::use_site_config();
+  let id = use_context::<http::request::Parts>()
+    .and_then(|parts| session_id(&config, &parts.headers));
+  if let Some(id) = id {
+    crate::comments::use_database()?
+      .end_session(&id)
+      .await
+      .map_err(|e| ServerFnError::new(e.to_string()))?;
+  }
+  let response = expect_context::<leptos_axum::ResponseOptions>();
+  response.append_header(
+    http::header::SET_COOKIE,
+    http::HeaderValue::from_str(&tokens::expired_cookie())
+      .expect("cookies are valid headers"),
+  );
+  leptos_axum::redirect("/admin");
+  Ok(())
+}
@@ -0,0 +1,423 @@
+//! Comments on posts, kept in the site's own database rather than left to a
+//! third-party embed. Comments are plain text, shown under the post once
+//! they're approved, and go through the spam classifier in
+//! [`spam`](crate::spam) as they're submitted. Unless
+//! [`auto_approve`](crate::config::CommentsConfig::auto_approve) is on, every
+//! comment then waits in the admin area's moderation queue, see
+//! [`moderation`](crate::moderation).
+//!
+//! Sites without a database of comments can have giscus or utterances keep
+//! them on GitHub instead, see [`CommentsEmbed`]. Their widget is only loaded
+//! once the reader scrolls down to it.
+//!
+//! The form works without scripts. Before a comment gets to the classifier,
+//! two cheap checks turn away most bots: a field hidden from readers, which
+//! bots fill in anyway, and when the form was rendered, since nobody writes a
+//! comment within a few seconds of the page loading. Bots are told their
+//! comment is waiting for moderation, so that they learn nothing.
+
+use leptos::*;
+use leptos_router::{use_query_map, ActionForm};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::CommentsEmbed, error_template::AppError};
+
+/// The longest name a commenter can give, in characters.
+const MAX_AUTHOR_CHARS: usize = 64;
+/// The longest comment, in characters.
+const MAX_BODY_CHARS: usize = 4000;
+/// How soon after the form is rendered a comment can be submitted by a
+/// person.
+#[cfg(feature = "ssr")]
+pub(crate) const MIN_FILL_SECS: u64 = 3;
+/// How long after the form is rendered a comment can still be submitted, so
+/// that a bot can't keep reusing one form's timestamp.
+#[cfg(feature = "ssr")]
+pub(crate) const MAX_FILL_SECS: u64 = 24 * 60 * 60;
+/// The name the comment form's timestamp is signed for.
+#[cfg(feature = "ssr")]
+const FORM_NAME: &str = "comment";
+
+/// An approved comment, as it's shown under a post.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PostComment {
+  pub id:         i64,
+  pub author:     String,
+  pub body:       String,
+  /// When the comment was made, as `YYYY.MM.DD` like post dates.
+  pub written_on: String,
+}
+
+/// The path of the public post at `path`, which is the only kind that can be
+/// commented on or reacted to.
+#[cfg(feature = "ssr")]
+pub(crate) fn public_post(path: &str) -> Result<String, ServerFnError> {
+  let not_found = || ServerFnError::new(AppError::NotFound);
+  let path = crate::posts::normalize_post_path(path).ok_or_else(not_found)?;
+  let index = expect_context::<crate::post_index::PostIndex>();
+  if index.is_public(&path) {
+    Ok(path)
+  } else {
+    Err(not_found())
+  }
+}
+
+#[cfg(feature = "ssr")]
+pub(crate) fn use_database() -> Result<site_db::Database, ServerFnError> {
+  use_context::<site_db::Database>()
+    .ok_or_else(|| ServerFnError::new("database unavailable"))
+}
+
+/// When a comment was made, as `YYYY.MM.DD` like post dates.
+#[cfg(feature = "ssr")]
+pub(crate) fn written_on(created_at: i64) -> String {
+  let date = time::OffsetDateTime::from_unix_timestamp(created_at)
+    .unwrap_or(time::OffsetDateTime::UNIX_EPOCH)
+    .date();
+  format!(
+    "{}.{:02}.{:02}",
+    date.year(),
+    u8::from(date.month()),
+    date.day()
+  )
+}
+
+/// The approved comments on a post, oldest first.
+#[server]
+pub async fn get_comments(
+  path: String,
+) -> Result<Vec<PostComment>, ServerFnError> {
+  use site_db::storage::CommentStore;
+
+  let path = public_post(&path)?;
+  let comments = use_database()?
+    .comments_for_post(&path)
+    .await
+    .map_err(|e| ServerFnError::new(e.to_string()))?;
+  Ok(
+    comments
+      .into_iter()
+      .map(|comment| PostComment {
+        id:         comment.id,
+        author:     comment.author,
+        body:       comment.body,
+        written_on: written_on(comment.created_at),
+      })
+      .collect(),
+  )
+}
+
+/// Submits a comment on a post, going back to the post's comments with
+/// whether it was posted. `website` is the field hidden from readers, and
+/// `rendered_at` the signed time the form was rendered, see
+/// [`form_timestamp`](crate::auth::form_timestamp).
+#[server]
+pub async fn submit_comment(
+  path: String,
+  author: String,
+  body: String,
+  website: String,
+  rendered_at: String,
+) -> Result<(), ServerFnError> {
+  use site_db::storage::{CommentStatus, CommentStore, NewComment};
+
+  let path = public_post(&path)?;
+  let back = |outcome: &str| {
+    leptos_axum::redirect(&format!("/post/{path}?comment={outcome}#comments"));
+    Ok(())
+  };
+
+  let Some(filled_in_secs) = crate::auth::form_age(FORM_NAME, &rendered_at)
+  else {
+    log::info!("dropped a comment on `{path}` with a forged timestamp");
+    return back("pending");
+  };
+  if !website.is_empty() || filled_in_secs < MIN_FILL_SECS {
+    log::info!(
+      "dropped a bot's comment on `{path}`, filled in after {filled_in_secs}s"
+    );
+    return back("pending");
+  }
+  if filled_in_secs > MAX_FILL_SECS {
+    return back("expired");
+  }
+  let (author, body) = (author.trim(), body.trim().replace("\r\n", "\n"));
+  let is_valid = |text: &str, max_chars: usize| {
+    !text.is_empty() && text.chars().count() <= max_chars
+  };
+  if !is_valid(author, MAX_AUTHOR_CHARS) || !is_valid(&body, MAX_BODY_CHARS) {
+    return back("invalid");
+  }
+
+  let db = use_database()?;
+  let verdict = crate::spam::classify(
+    &db,
+    &path,
+    &body,
+    crate::spam::Thresholds::default(),
+  )
+  .await
+  .map_err(|e| ServerFnError::new(e.to_string()))?;
+  let status = match verdict.status {
+    CommentStatus::Approved
+      if !crate::config::use_site_config().comments.auto_approve =>
+    {
+      CommentStatus::Pending
+    }
+    status => status,
+  };
+  let comment = db
+    .add_comment(NewComment {
+      post_path: path.clone(),
+      author: author.to_string(),
+      body,
+      status,
+      spam_score: verdict.score,
+      body_hash: verdict.body_hash,
+    })
+    .await
+    .map_err(|e| ServerFnError::new(e.to_string()))?;
+  log::info!(
+    "comment #{} on `{path}` is {} with a score of {:.1}",
+    comment.id,
+    comment.status,
+    comment.spam_score
+  );
+
+  match comment.status {
+    CommentStatus::Approved => back("posted"),
+    CommentStatus::Pending | CommentStatus::Rejected => back("pending"),
+  }
+}
+
+/// What the comments section says about the reader's last comment.
+fn outcome_message(outcome: &str) -> Option<&'static str> {
+  Some(match outcome {
+    "posted" => "Thanks for your comment!",
+    "pending" => {
+      "Thanks for your comment! It'll show up once it's been approved."
+    }
+    "invalid" => "Comments need a name and some text, and can't be too long.",
+    "expired" => {
+      "The page was open too long to post from. Reload it and try again?"
+    }
+    _ => return None,
+  })
+}
+
+/// A post's comments, from the site's database or the configured widget.
+#[component]
+pub fn Comments(path: String) -> impl IntoView {
+  match crate::config::use_site_config().comments.embed {
+    Some(embed) => view! { <EmbeddedComments path embed /> },
+    None => view! { <SiteComments path /> },
+  }
+}
+
+/// A post's comments, and the form for leaving one.
+#[component]
+fn SiteComments(path: String) -> impl IntoView {
+  let outcome = use_query_map()
+    .get_untracked()
+    .get("comment")
+    .and_then(|outcome| outcome_message(outcome));
+  let submit = create_server_action::<SubmitComment>();
+  // the page isn't an island, so this only ever runs on the server
+  #[cfg(feature = "ssr")]
+  let rendered_at = crate::auth::form_timestamp(FORM_NAME);
+  #[cfg(not(feature = "ssr"))]
+  let rendered_at = String::new();
+  let rendered_at = store_value(rendered_at);
+  let path = store_value(path);
+  let comments_resource =
+    create_resource(move || path.get_value(), get_comments);
+
+  let comment_view = |comment: PostComment| {
+    view! {
+      <li id=format!("comment-{}", comment.id) class="border-t border-neutral-600 py-2">
+        <p class="text-neutral-400">
+          {comment.author} " on " <crate::dates::PostDate written_on=comment.written_on />
+        </p>
+        <p class="whitespace-pre-line">{comment.body}</p>
+      </li>
+    }
+  };
+
+  // like the post, the section is left out when the post is missing
+  view! {
+    <Suspense>
+      <ErrorBoundary fallback=|_| ()>
+        { move || comments_resource.get().map(|c| c.map_err(AppError::from).map(|comments| view! {
+          <section id="comments" class="mt-8">
+            <div class="markdown">
+              <h2>"Comments"</h2>
+            </div>
+            { comments.is_empty().then(|| view! { <p class="text-neutral-400">"No comments yet."</p> }) }
+            <ul>{comments.into_iter().map(comment_view).collect_view()}</ul>
+            { outcome.map(|outcome| view! { <p class="my-2">{outcome}</p> }) }
+            <ActionForm action=submit class="flex flex-col gap-2 mt-4">
+              <input type="hidden" name="path" value=path.get_value() />
+              <input type="hidden" name="rendered_at" value=rendered_at.get_value() />
+              // left empty by people, who can't see it
+              <div class="hidden" aria-hidden="true">
+                <input type="text" name="website" tabindex="-1" autocomplete="off" />
+              </div>
+              <input
+                type="text" name="author" required maxlength=MAX_AUTHOR_CHARS.to_string()
+                placeholder="Name" aria-label="Name" autocomplete="name"
+                class="bg-neutral-700 px-2 py-1"
+              />
+              <textarea
+                name="body" required rows="4" maxlength=MAX_BODY_CHARS.to_string()
+                placeholder="Leave a comment" aria-label="Comment"
+                class="bg-neutral-700 px-2 py-1"
+              />
+              <button type="submit" class="bg-neutral-700 px-4 py-1 hover:bg-neutral-600 self-start">"Comment"</button>
+            </ActionForm>
+          </section>
+        })) }
+      </ErrorBoundary>
+    </Suspense>
+  }
+}
+
+/// How far below the viewport the comments widget starts loading, as an
+/// `IntersectionObserver` root margin, so that it's usually ready by the time
+/// the reader gets to it.
+#[cfg(feature = "hydrate")]
+const WIDGET_LOAD_MARGIN: &str = "0px 0px 600px 0px";
+
+/// The attributes of the widget's script, which tell it which thread is the
+/// post's.
+fn widget_attributes(
+  embed: &CommentsEmbed,
+  path: &str,
+) -> Vec<(String, String)> {
+  let attributes: Vec<(&str, &str)> = match embed {
+    CommentsEmbed::Giscus {
+      repo,
+      repo_id,
+      category,
+      category_id,
+      theme,
+    } => vec![
+      ("data-repo", repo),
+      ("data-repo-id", repo_id),
+      ("data-category", category),
+      ("data-category-id", category_id),
+      // posts keep their path through a change of base URL or title
+      ("data-mapping", "specific"),
+      ("data-term", path),
+      ("data-reactions-enabled", "1"),
+      ("data-emit-metadata", "0"),
+      ("data-input-position", "top"),
+      ("data-theme", theme),
+      ("data-lang", "en"),
+    ],
+    CommentsEmbed::Utterances { repo, label, theme } => {
+      let mut attributes = vec![
+        ("repo", repo.as_str()),
+        ("issue-term", path),
+        ("theme", theme),
+      ];
+      attributes.extend(label.as_deref().map(|label| ("label", label)));
+      attributes
+    }
+  };
+  attributes
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .collect()
+}
+
+/// A post's comments in the configured widget, left out when the post is
+/// missing like the site's own.
+#[component]
+fn EmbeddedComments(path: String, embed: CommentsEmbed) -> impl IntoView {
+  let script = store_value(format!("{}/client.js", embed.origin()));
+  let attributes = store_value(widget_attributes(&embed, &path));
+  let header_resource =
+    create_resource(move || path.clone(), crate::posts::get_post_header);
+
+  view! {
+    <Suspense>
+      <ErrorBoundary fallback=|_| ()>
+        { move || header_resource.get().map(|h| h.map_err(AppError::from).map(|_| view! {
+          <CommentsWidget script=script.get_value() attributes=attributes.get_value() />
+        })) }
+      </ErrorBoundary>
+    </Suspense>
+  }
+}
+
+/// Loads a comments widget's script, with `attributes`, once the reader
+/// scrolls near it.
+#[crate::eager_island]
+fn CommentsWidget(
+  script: String,
+  attributes: Vec<(String, String)>,
+) -> impl IntoView {
+  let container = create_node_ref::<html::Section>();
+
+  #[cfg(feature = "hydrate")]
+  container.on_load(move |container| {
+    use js_sys::{Array, Object, Reflect};
+    use leptos::web_sys::{
+      IntersectionObserver, IntersectionObserverEntry, IntersectionObserverInit,
+    };
+    use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+
+    let target = container.clone();
+    let callback = Closure::<dyn Fn(Array, IntersectionObserver)>::new(
+      move |observed: Array, observer: IntersectionObserver| {
+        let is_near = observed.iter().any(|entry| {
+          entry
+            .unchecked_into::<IntersectionObserverEntry>()
+            .is_intersecting()
+        });
+        if !is_near {
+          return;
+        }
+        observer.disconnect();
+        let Ok(tag) = document().create_element("script") else {
+          return;
+        };
+        let _ = tag.set_attribute("src", &script);
+        let _ = tag.set_attribute("crossorigin", "anonymous");
+        let _ = tag.set_attribute("async", "");
+        for (name, value) in &attributes {
+          let _ = tag.set_attribute(name, value);
+        }
+        let _ = target.append_child(&tag);
+      },
+    );
+
+    // set through `Reflect` like the table of contents' observer
+    let options = Object::new();
+    let _ = Reflect::set(
+      &options,
+      &JsValue::from_str("rootMargin"),
+      &JsValue::from_str(WIDGET_LOAD_MARGIN),
+    );
+    if let Ok(observer) = IntersectionObserver::new_with_options(
+      callback.as_ref().unchecked_ref(),
+      options.unchecked_ref::<IntersectionObserverInit>(),
+    ) {
+      observer.observe(&container);
+      on_cleanup(move || {
+        observer.disconnect();
+        drop(callback);
+      });
+    }
+  });
+  #[cfg(not(feature = "hydrate"))]
+  let _ = (script, attributes, container);
+
+  view! {
+    <section id="comments" class="mt-8" node_ref=container>
+      <noscript>
+        <p class="text-neutral-400">"Comments are loaded from GitHub, which needs JavaScript."</p>
+      </noscript>
+    </section>
+  }
+}
@@ -0,0 +1,918 @@
+//! The site's configuration: what it's called, who writes it, where it's
+//! hosted and where its content lives.
+//!
+//! It's read from `site.toml` in the working directory, or the file named by
+//! `SITE_CONFIG`, and any field left out keeps its default. Most fields can
+//! then be overridden by an environment variable, e.g. `SITE_BASE_URL` or
+//! `SITE_TLS_CERT`, which is handier for deployments than editing the file.
+//!
+//! The secret sessions and the site's links are signed with is only ever read
+//! from `SITE_SESSION_SECRET`, since `site.toml` is often checked in or
+//! readable by others, see [`SiteConfig::session_secret`].
+//!
+//! ```toml
+//! title = "John Lewis' Blog"
+//! tagline = "Rust, Games, Musings"
+//! base_url = "https://jlewis.sh"
+//! content_dir = "content"
+//! redirects = "redirects.toml"
+//! bind_address = "0.0.0.0:8080"
+//!
+//! # optional, for serving HTTPS without a reverse proxy
+//! [tls]
+//! cert = "/etc/site/cert.pem"
+//! key = "/etc/site/key.pem"
+//! redirect_address = "0.0.0.0:80"
+//!
+//! # the security headers, see `SecurityConfig`
+//! [security]
+//! frame_ancestors = ["'self'"]
+//!
+//! # how often one client may call server fns, see `RateLimitConfig`
+//! [rate_limit]
+//! burst = 30
+//! window_secs = 60
+//! client_ip_header = "Fly-Client-IP"
+//!
+//! # how long requests can take and how big they can be, see `LimitsConfig`
+//! [limits]
+//! request_timeout_secs = 30
+//! body_bytes = 65536
+//!
+//! # the admin area, see `AdminConfig`, logged in to with a password made by
+//! # `site-server hash-password`, GitHub, or both
+//! [admin]
+//! password_hash = "$argon2id$v=19$m=19456,t=2,p=1$..."
+//!
+//! [admin.github]
+//! client_id = "Iv1.0123456789abcdef"
+//! client_secret = "..."
+//! user = "johnbchron"
+//!
+//! # how posts saved from the admin editor are committed, see `GitConfig`
+//! [admin.git]
+//! message = "Edit {path}"
+//! push = true
+//!
+//! # posts that can't be trusted with raw HTML, see `SanitizeConfig`
+//! [sanitize]
+//! posts = ["guest-*"]
+//!
+//! # post view counts, see `ViewsConfig`
+//! [views]
+//! show = true
+//!
+//! # visits, for the admin area's dashboard, see `AnalyticsConfig`
+//! [analytics]
+//! enabled = true
+//! country_header = "CF-IPCountry"
+//!
+//! # optional, a third-party analytics service, see `ExternalAnalytics`
+//! [external_analytics]
+//! provider = "plausible"
+//!
+//! # optional, for the newsletter and the contact form, see `SmtpConfig`
+//! [smtp]
+//! host = "smtp.example.com"
+//! username = "blog@jlewis.sh"
+//! from = "John Lewis <blog@jlewis.sh>"
+//!
+//! [author]
+//! name = "John Lewis"
+//! email = "contact@jlewis.sh"
+//!
+//! [[social]]
+//! name = "Mastodon"
+//! url = "https://social.treehouse.systems/@johnbchron"
+//! ```
+
+use std::{net::SocketAddr, path::PathBuf};
+
+use leptos::*;
+use serde::{Deserialize, Serialize};
+
+/// The environment variable naming the configuration file.
+#[cfg(feature = "ssr")]
+pub const CONFIG_FILE_VAR: &str = "SITE_CONFIG";
+/// The configuration file read when `SITE_CONFIG` isn't set. Unlike a file
+/// named by `SITE_CONFIG`, it's fine for it not to exist.
+#[cfg(feature = "ssr")]
+pub const DEFAULT_CONFIG_FILE: &str = "site.toml";
+
+/// How long `SITE_SESSION_SECRET` must be at least, so it can't be guessed.
+#[cfg(feature = "ssr")]
+const MIN_SESSION_SECRET_LEN: usize = 32;
+
+/// The site's configuration, provided to every request.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SiteConfig {
+  /// The site's name, shown in the header and the document title.
+  pub title:              String,
+  /// A few words on what the site is about, shown in the header.
+  pub tagline:            String,
+  /// The URL the site is hosted at, which absolute links are made from with
+  /// [`site_url`]. It can include a path, for a site served under one.
+  pub base_url:           String,
+  pub author:             Author,
+  /// Where else the author can be found, linked from the home page.
+  pub social:             Vec<SocialLink>,
+  /// The directory holding the site's content, with the posts in `posts`
+  /// inside it.
+  pub content_dir:        PathBuf,
+  /// The file mapping old paths to where they've moved. It's fine for it not
+  /// to exist.
+  pub redirects:          PathBuf,
+  /// The address to listen on. Without one, the server listens on the
+  /// address `cargo-leptos` configures.
+  pub bind_address:       Option<SocketAddr>,
+  /// Serves HTTPS on the bind address, rather than leaving it to a proxy in
+  /// front of the server.
+  pub tls:                Option<TlsConfig>,
+  pub security:           SecurityConfig,
+  pub rate_limit:         RateLimitConfig,
+  pub limits:             LimitsConfig,
+  pub admin:              AdminConfig,
+  pub comments:           CommentsConfig,
+  pub sanitize:           SanitizeConfig,
+  pub webmentions:        WebmentionsConfig,
+  pub activitypub:        ActivityPubConfig,
+  pub views:              ViewsConfig,
+  pub analytics:          AnalyticsConfig,
+  /// A third-party analytics service whose script is added to every page,
+  /// for sites that would rather use one.
+  pub external_analytics: Option<ExternalAnalytics>,
+  /// The mail server the site sends email through. Without one, the
+  /// newsletter signup and the contact form aren't offered.
+  pub smtp:               Option<SmtpConfig>,
+  /// The secret admin sessions, preview links, the newsletter's links and
+  /// the forms' timestamps are signed with, from `SITE_SESSION_SECRET` and
+  /// never the file. It should be random, e.g. from `openssl rand -base64
+  /// 32`. Without it, there's no admin area or newsletter.
+  #[serde(skip)]
+  pub session_secret:     Option<String>,
+}
+
+/// The certificate the server serves HTTPS with.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+  /// A PEM file holding the certificate chain.
+  pub cert:             PathBuf,
+  /// A PEM file holding the certificate's private key, as PKCS #8, PKCS #1 or
+  /// SEC1.
+  pub key:              PathBuf,
+  /// An address to listen for plain HTTP on, redirecting every request to
+  /// HTTPS. Usually port 80.
+  pub redirect_address: Option<SocketAddr>,
+}
+
+/// Who writes the site.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Author {
+  pub name:  String,
+  /// Where readers can get in touch, which is also given to push services.
+  pub email: String,
+}
+
+/// A profile of the author's elsewhere. Each is linked with `rel="me"`, so
+/// that sites which verify links back, like Mastodon, can.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SocialLink {
+  pub name: String,
+  pub url:  String,
+}
+
+impl Default for SiteConfig {
+  fn default() -> Self {
+    SiteConfig {
+      title:              "John Lewis' Blog".to_string(),
+      tagline:            "Rust, Games, Musings".to_string(),
+      base_url:           "https://jlewis.sh".to_string(),
+      author:             Author {
+        name:  "John Lewis".to_string(),
+        email: "contact@jlewis.sh".to_string(),
+      },
+      social:             vec![SocialLink {
+        name: "Mastodon".to_string(),
+        url:  "https://social.treehouse.systems/@johnbchron".to_string(),
+      }],
+      content_dir:        PathBuf::from("content"),
+      redirects:          PathBuf::from("redirects.toml"),
+      bind_address:       None,
+      tls:                None,
+      security:           SecurityConfig::default(),
+      rate_limit:         RateLimitConfig::default(),
+      limits:             LimitsConfig::default(),
+      admin:              AdminConfig::default(),
+      comments:           CommentsConfig::default(),
+      sanitize:           SanitizeConfig::default(),
+      webmentions:        WebmentionsConfig::default(),
+      activitypub:        ActivityPubConfig::default(),
+      views:              ViewsConfig::default(),
+      analytics:          AnalyticsConfig::default(),
+      external_analytics: None,
+      smtp:               None,
+      session_secret:     None,
+    }
+  }
+}
+
+/// The security headers sent with every response. The sources are in
+/// Content Security Policy syntax, e.g. `'self'` or `https:`, and replace the
+/// defaults rather than adding to them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecurityConfig {
+  /// Whether to send a Content Security Policy at all.
+  pub content_security_policy: bool,
+  /// Which sites may frame the site's pages.
+  pub frame_ancestors:         Vec<String>,
+  /// Where embeds may be loaded from. Mastodon posts can be embedded from any
+  /// instance, so by default it's anywhere over HTTPS.
+  pub frame_src:               Vec<String>,
+  /// Where images may be loaded from. Posts link to images anywhere.
+  pub img_src:                 Vec<String>,
+  /// Where scripts may connect to.
+  pub connect_src:             Vec<String>,
+  /// The `Referrer-Policy`.
+  pub referrer_policy:         String,
+}
+
+impl Default for SecurityConfig {
+  fn default() -> Self {
+    let sources = |sources: &[&str]| {
+      sources.iter().map(|s| s.to_string()).collect::<Vec<_>>()
+    };
+    SecurityConfig {
+      content_security_policy: true,
+      frame_ancestors:         sources(&["'none'"]),
+      frame_src:               sources(&["https:"]),
+      img_src:                 sources(&["'self'", "data:", "https:"]),
+      connect_src:             sources(&["'self'"]),
+      referrer_policy:         "strict-origin-when-cross-origin".to_string(),
+    }
+  }
+}
+
+/// How often each client may call server fns or submit forms. A client can
+/// make `burst` calls at once, and then gets them back over `window_secs`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+  pub enabled:          bool,
+  pub burst:            u32,
+  pub window_secs:      u64,
+  /// The header a proxy in front of the server puts the client's address in.
+  /// If it holds a list, like `X-Forwarded-For`, the last address is taken,
+  /// since the ones before it are whatever the client sent. Without one,
+  /// clients are told apart by the address they connect from, which behind a
+  /// proxy is the proxy's.
+  pub client_ip_header: Option<String>,
+}
+
+impl Default for RateLimitConfig {
+  fn default() -> Self {
+    RateLimitConfig {
+      enabled:          true,
+      burst:            30,
+      window_secs:      60,
+      client_ip_header: None,
+    }
+  }
+}
+
+#[cfg(feature = "ssr")]
+impl RateLimitConfig {
+  /// The address of the client making a request, told apart as
+  /// [`client_ip_header`](Self::client_ip_header) says, if it's known.
+  pub fn client_ip(
+    &self,
+    headers: &http::HeaderMap,
+    extensions: &http::Extensions,
+  ) -> Option<std::net::IpAddr> {
+    use axum::extract::ConnectInfo;
+
+    // proxies append the address they were connected from to any list the
+    // client sent, so only the last one is the proxy's word
+    let forwarded = self
+      .client_ip_header
+      .as_ref()
+      .and_then(|name| headers.get_all(name).iter().last())
+      .and_then(|v| v.to_str().ok())
+      .and_then(|v| v.rsplit(',').next()?.trim().parse().ok());
+    forwarded.or_else(|| {
+      let ConnectInfo(addr) = extensions.get::<ConnectInfo<SocketAddr>>()?;
+      Some(addr.ip())
+    })
+  }
+}
+
+/// How long requests can take and how big their bodies can be. Uploads get
+/// limits of their own, since they're far bigger than anything else sent to
+/// the server.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+  /// How long a client has to send a request's headers.
+  pub header_read_timeout_secs:    u64,
+  /// How long a request has to be handled, including reading its body.
+  pub request_timeout_secs:        u64,
+  pub body_bytes:                  u64,
+  pub upload_request_timeout_secs: u64,
+  pub upload_body_bytes:           u64,
+}
+
+impl Default for LimitsConfig {
+  fn default() -> Self {
+    LimitsConfig {
+      header_read_timeout_secs:    10,
+      request_timeout_secs:        30,
+      // server fn arguments are small
+      body_bytes:                  64 * 1024,
+      upload_request_timeout_secs: 5 * 60,
+      upload_body_bytes:           16 * 1024 * 1024,
+    }
+  }
+}
+
+/// Who can log in to the admin area, under `/admin`, and for how long.
+/// Without a password or GitHub, or without
+/// [`SiteConfig::session_secret`], there's no admin area at all.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+  /// The hash of the admin password, from `site-server hash-password`.
+  pub password_hash: Option<String>,
+  /// Logging in with GitHub, as the author's account only.
+  pub github:        Option<GitHubAuthConfig>,
+  /// How long a login lasts.
+  pub session_hours: u64,
+  pub git:           GitConfig,
+}
+
+impl Default for AdminConfig {
+  fn default() -> Self {
+    AdminConfig {
+      password_hash: None,
+      github:        None,
+      session_hours: 7 * 24,
+      git:           GitConfig::default(),
+    }
+  }
+}
+
+/// A GitHub OAuth app for logging in to the admin area, whose callback URL
+/// is the site's `/auth/github/callback`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GitHubAuthConfig {
+  pub client_id:     String,
+  /// Usually left to `SITE_ADMIN_GITHUB_CLIENT_SECRET`, with the rest of the
+  /// deployment's secrets.
+  #[serde(default)]
+  pub client_secret: String,
+  /// The only account which may log in.
+  pub user:          String,
+}
+
+/// How posts saved from the admin editor are committed to the git repository
+/// holding the content directory, so that edits made on the server survive
+/// redeploys and stay in the history.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GitConfig {
+  /// Whether saves are committed at all, rather than only written.
+  pub commit:       bool,
+  /// Who commits are by, the site's author unless given.
+  pub author_name:  Option<String>,
+  pub author_email: Option<String>,
+  /// The commit message when the editor isn't given one, with `{path}`
+  /// replaced by the post's path.
+  pub message:      String,
+  /// Whether each commit is pushed to the branch's upstream.
+  pub push:         bool,
+}
+
+impl Default for GitConfig {
+  fn default() -> Self {
+    GitConfig {
+      commit:       true,
+      author_name:  None,
+      author_email: None,
+      message:      "Edit {path}".to_string(),
+      push:         false,
+    }
+  }
+}
+
+/// Which posts can't be trusted with raw HTML, like guest-authored or
+/// imported ones. Their rendered HTML is passed through an allowlist-based
+/// sanitizer, which a post's frontmatter can ask for with `sanitize: true`
+/// but can't turn off.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SanitizeConfig {
+  /// Sanitizes every post.
+  pub all:   bool,
+  /// The paths of the posts to sanitize. A trailing `*` matches any ending,
+  /// e.g. `guest-*`.
+  pub posts: Vec<String>,
+}
+
+impl SanitizeConfig {
+  /// Whether the post at `path` is sanitized.
+  pub fn applies_to(&self, path: &str) -> bool {
+    self.all
+      || self
+        .posts
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+          Some(prefix) => path.starts_with(prefix),
+          None => path == pattern,
+        })
+  }
+}
+
+/// How comments on posts are moderated, or where they're hosted instead of
+/// the site's database.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CommentsConfig {
+  /// Whether comments the spam classifier has no doubts about are shown
+  /// straight away. Otherwise every comment waits in the admin area's
+  /// moderation queue until it's approved.
+  pub auto_approve: bool,
+  /// Comments kept on GitHub by giscus or utterances, shown under posts in
+  /// place of the site's own.
+  pub embed:        Option<CommentsEmbed>,
+}
+
+/// A third-party comments widget, whose threads are each found by the path
+/// of the post they're under.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "provider", rename_all = "lowercase", deny_unknown_fields)]
+pub enum CommentsEmbed {
+  /// Comments as GitHub Discussions, in `category`. The IDs are the ones
+  /// giscus.app gives for the repository and category.
+  Giscus {
+    repo:        String,
+    repo_id:     String,
+    category:    String,
+    category_id: String,
+    #[serde(default = "default_giscus_theme")]
+    theme:       String,
+  },
+  /// Comments as GitHub issues, labelled with `label` if there is one.
+  Utterances {
+    repo:  String,
+    label: Option<String>,
+    #[serde(default = "default_utterances_theme")]
+    theme: String,
+  },
+}
+
+fn default_giscus_theme() -> String { "dark".to_string() }
+
+fn default_utterances_theme() -> String { "github-dark".to_string() }
+
+impl CommentsEmbed {
+  /// Where the widget's script is loaded from.
+  pub fn origin(&self) -> &'static str {
+    match self {
+      CommentsEmbed::Giscus { .. } => "https://giscus.app",
+      CommentsEmbed::Utterances { .. } => "https://utteranc.es",
+    }
+  }
+}
+
+/// Webmentions sent to the pages posts link to, telling them they've been
+/// linked to.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebmentionsConfig {
+  /// Whether webmentions are sent when a post is published or changed. It's
+  /// off unless asked for, so that a server run locally doesn't send them
+  /// for the deployed site.
+  pub send: bool,
+}
+
+/// The site's ActivityPub actor, which Mastodon and the like can follow to
+/// be sent new posts, see `site_app::activitypub`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActivityPubConfig {
+  /// Whether the actor is served and new posts are delivered to its
+  /// followers. Like sending webmentions, it's off unless asked for.
+  pub enabled:  bool,
+  /// The actor's username, which it's followed by as `@username@host`.
+  pub username: String,
+  /// A PEM file holding the actor's RSA private key, which is generated if
+  /// it doesn't exist. Followers' servers remember the key, so the file
+  /// should outlive deployments.
+  pub key_file: PathBuf,
+}
+
+/// How many times each post has been read. Views are counted once per reader
+/// every so often, telling readers apart by a hash of their address and
+/// browser which is forgotten soon after, and only the counts are stored.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ViewsConfig {
+  /// Whether post views are counted.
+  pub count: bool,
+  /// Whether the counts are shown to readers, on each post and on a page of
+  /// the most read posts.
+  pub show:  bool,
+}
+
+impl Default for ViewsConfig {
+  fn default() -> Self {
+    ViewsConfig {
+      count: true,
+      show:  false,
+    }
+  }
+}
+
+/// Visits to the site's pages, recorded by a beacon the pages send once
+/// they've loaded and shown on the admin area's dashboard. No cookies are
+/// set: a visitor is told apart by a hash of their address and browser with a
+/// salt that's replaced every day, so they can't be followed from one day to
+/// the next. Readers without scripts aren't recorded.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnalyticsConfig {
+  /// Whether visits are recorded.
+  pub enabled:        bool,
+  /// A header the proxy in front of the site puts the visitor's country in,
+  /// like Cloudflare's `CF-IPCountry`. Without one, countries aren't
+  /// recorded.
+  pub country_header: Option<String>,
+  /// How many days visits are kept for.
+  pub retention_days: u32,
+}
+
+impl Default for AnalyticsConfig {
+  fn default() -> Self {
+    AnalyticsConfig {
+      enabled:        false,
+      country_header: None,
+      retention_days: 365,
+    }
+  }
+}
+
+/// A third-party analytics service, whose script counts visits to every
+/// page, including those served without the site's own scripts.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "provider", rename_all = "lowercase", deny_unknown_fields)]
+pub enum ExternalAnalytics {
+  /// Plausible, counting visits under `domain`, which is the host of
+  /// `base_url` unless it's given. `src` can point to a self-hosted instance,
+  /// or to one of the script's extensions.
+  Plausible {
+    domain: Option<String>,
+    #[serde(default = "default_plausible_src")]
+    src:    String,
+  },
+  /// Umami, counting visits for `website_id`, which Umami gives each site.
+  Umami {
+    website_id: String,
+    #[serde(default = "default_umami_src")]
+    src:        String,
+  },
+}
+
+fn default_plausible_src() -> String {
+  "https://plausible.io/js/script.js".to_string()
+}
+
+fn default_umami_src() -> String {
+  "https://cloud.umami.is/script.js".to_string()
+}
+
+impl ExternalAnalytics {
+  /// Where the script is loaded from.
+  pub fn src(&self) -> &str {
+    match self {
+      ExternalAnalytics::Plausible { src, .. } => src,
+      ExternalAnalytics::Umami { src, .. } => src,
+    }
+  }
+
+  /// The origin of the script, which it also sends its events to.
+  pub fn origin(&self) -> &str {
+    let src = self.src();
+    let host_start = src.find("://").map_or(0, |scheme| scheme + 3);
+    match src[host_start..].find('/') {
+      Some(path) => &src[..host_start + path],
+      None => src,
+    }
+  }
+
+  /// The attribute the script is configured by, and its value.
+  pub fn attribute(&self, base_url: &str) -> (&'static str, String) {
+    match self {
+      ExternalAnalytics::Plausible { domain, .. } => {
+        let host = base_url
+          .split_once("://")
+          .map_or(base_url, |(_, rest)| rest)
+          .split('/')
+          .next()
+          .unwrap_or_default();
+        (
+          "data-domain",
+          domain.clone().unwrap_or_else(|| host.to_string()),
+        )
+      }
+      ExternalAnalytics::Umami { website_id, .. } => {
+        ("data-website-id", website_id.clone())
+      }
+    }
+  }
+}
+
+impl Default for ActivityPubConfig {
+  fn default() -> Self {
+    ActivityPubConfig {
+      enabled:  false,
+      username: "blog".to_string(),
+      key_file: PathBuf::from("data/activitypub.pem"),
+    }
+  }
+}
+
+/// The SMTP server email is sent through, like the newsletter's and the
+/// contact form's.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SmtpConfig {
+  pub host:     String,
+  /// Defaults to the usual port for `security`.
+  pub port:     Option<u16>,
+  #[serde(default)]
+  pub security: SmtpSecurity,
+  /// Who to log in as, if the server needs it.
+  pub username: Option<String>,
+  /// Better left to `SITE_SMTP_PASSWORD`, so that it's kept with the
+  /// deployment's secrets.
+  pub password: Option<String>,
+  /// Who email is from, like `John Lewis <blog@jlewis.sh>`.
+  pub from:     String,
+}
+
+/// How the connection to the SMTP server is secured.
+#[derive(
+  Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+  /// Upgraded to TLS with `STARTTLS`, usually on port 587.
+  #[default]
+  StartTls,
+  /// TLS from the start, usually on port 465.
+  Tls,
+  /// Not at all, for a relay on the same machine.
+  None,
+}
+
+impl SmtpSecurity {
+  /// The port servers usually listen on for connections secured this way.
+  pub fn default_port(&self) -> u16 {
+    match self {
+      SmtpSecurity::StartTls => 587,
+      SmtpSecurity::Tls => 465,
+      SmtpSecurity::None => 25,
+    }
+  }
+}
+
+#[cfg(feature = "ssr")]
+impl SiteConfig {
+  /// Reads the configuration file and applies the environment's overrides,
+  /// describing what's wrong if either is invalid.
+  pub fn load() -> Result<Self, String> {
+    let (file, required) = match std::env::var(CONFIG_FILE_VAR) {
+      Ok(file) => (PathBuf::from(file), true),
+      Err(_) => (PathBuf::from(DEFAULT_CONFIG_FILE), false),
+    };
+    let mut config = match std::fs::read_to_string(&file) {
+      Ok(input) => toml::from_str(&input)
+        .map_err(|e| format!("invalid `{}`: {e}", file.display()))?,
+      Err(e) if required || e.kind() != std::io::ErrorKind::NotFound => {
+        return Err(format!("can't read `{}`: {e}", file.display()));
+      }
+      Err(_) => SiteConfig::default(),
+    };
+    config.apply_env_overrides()?;
+    config.base_url = config.base_url.trim_end_matches('/').to_string();
+    Ok(config)
+  }
+
+  fn apply_env_overrides(&mut self) -> Result<(), String> {
+    let var = |name: &str| std::env::var(name).ok();
+    if let Some(title) = var("SITE_TITLE") {
+      self.title = title;
+    }
+    if let Some(tagline) = var("SITE_TAGLINE") {
+      self.tagline = tagline;
+    }
+    if let Some(base_url) = var("SITE_BASE_URL") {
+      self.base_url = base_url;
+    }
+    if let Some(name) = var("SITE_AUTHOR_NAME") {
+      self.author.name = name;
+    }
+    if let Some(email) = var("SITE_AUTHOR_EMAIL") {
+      self.author.email = email;
+    }
+    if let Some(content_dir) = var("SITE_CONTENT_DIR") {
+      self.content_dir = PathBuf::from(content_dir);
+    }
+    if let Some(bind_address) = var("SITE_BIND_ADDRESS") {
+      self.bind_address = Some(
+        bind_address
+          .parse()
+          .map_err(|e| format!("invalid `SITE_BIND_ADDRESS`: {e}"))?,
+      );
+    }
+    if let Some(header) = var("SITE_CLIENT_IP_HEADER") {
+      self.rate_limit.client_ip_header = Some(header);
+    }
+    if let Some(secret) = var("SITE_SESSION_SECRET") {
+      if secret.len() < MIN_SESSION_SECRET_LEN {
+        return Err(format!(
+          "`SITE_SESSION_SECRET` must be at least {MIN_SESSION_SECRET_LEN} \
+           characters"
+        ));
+      }
+      self.session_secret = Some(secret);
+    }
+    // so that the hash can be kept with the deployment's secrets
+    if let Some(hash) = var("SITE_ADMIN_PASSWORD_HASH") {
+      self.admin.password_hash = Some(hash);
+    }
+    if let Some(secret) = var("SITE_ADMIN_GITHUB_CLIENT_SECRET") {
+      let Some(github) = &mut self.admin.github else {
+        return Err(
+          "`SITE_ADMIN_GITHUB_CLIENT_SECRET` needs an `[admin.github]` section"
+            .to_string(),
+        );
+      };
+      github.client_secret = secret;
+    }
+    if let Some(password) = var("SITE_SMTP_PASSWORD") {
+      let Some(smtp) = &mut self.smtp else {
+        return Err(
+          "`SITE_SMTP_PASSWORD` needs an `[smtp]` section".to_string(),
+        );
+      };
+      smtp.password = Some(password);
+    }
+    if let Some(auto_approve) = var("SITE_COMMENTS_AUTO_APPROVE") {
+      self.comments.auto_approve = auto_approve
+        .parse()
+        .map_err(|e| format!("invalid `SITE_COMMENTS_AUTO_APPROVE`: {e}"))?;
+    }
+    if let Some(all) = var("SITE_SANITIZE_ALL") {
+      self.sanitize.all = all
+        .parse()
+        .map_err(|e| format!("invalid `SITE_SANITIZE_ALL`: {e}"))?;
+    }
+    if let Some(send) = var("SITE_WEBMENTIONS_SEND") {
+      self.webmentions.send = send
+        .parse()
+        .map_err(|e| format!("invalid `SITE_WEBMENTIONS_SEND`: {e}"))?;
+    }
+    if let Some(enabled) = var("SITE_ACTIVITYPUB_ENABLED") {
+      self.activitypub.enabled = enabled
+        .parse()
+        .map_err(|e| format!("invalid `SITE_ACTIVITYPUB_ENABLED`: {e}"))?;
+    }
+    if let Some(key_file) = var("SITE_ACTIVITYPUB_KEY_FILE") {
+      self.activitypub.key_file = PathBuf::from(key_file);
+    }
+    if let Some(count) = var("SITE_VIEWS_COUNT") {
+      self.views.count = count
+        .parse()
+        .map_err(|e| format!("invalid `SITE_VIEWS_COUNT`: {e}"))?;
+    }
+    if let Some(show) = var("SITE_VIEWS_SHOW") {
+      self.views.show = show
+        .parse()
+        .map_err(|e| format!("invalid `SITE_VIEWS_SHOW`: {e}"))?;
+    }
+    if let Some(enabled) = var("SITE_ANALYTICS_ENABLED") {
+      self.analytics.enabled = enabled
+        .parse()
+        .map_err(|e| format!("invalid `SITE_ANALYTICS_ENABLED`: {e}"))?;
+    }
+    match (var("SITE_TLS_CERT"), var("SITE_TLS_KEY")) {
+      (Some(cert), Some(key)) => {
+        let tls = self.tls.get_or_insert(TlsConfig {
+          cert:             PathBuf::new(),
+          key:              PathBuf::new(),
+          redirect_address: None,
+        });
+        tls.cert = PathBuf::from(cert);
+        tls.key = PathBuf::from(key);
+      }
+      (None, None) => {}
+      _ => {
+        return Err(
+          "`SITE_TLS_CERT` and `SITE_TLS_KEY` must be set together".to_string(),
+        )
+      }
+    }
+    Ok(())
+  }
+
+  /// The directory post markdown files are read from.
+  pub fn posts_dir(&self) -> PathBuf { self.content_dir.join("posts") }
+
+  /// Makes this the configuration that code outside of requests, like the
+  /// post index, reads with [`site_config`]. Only the first call counts.
+  pub fn install(self) {
+    if INSTALLED.set(self).is_err() {
+      log::warn!("the site configuration was installed twice");
+    }
+  }
+}
+
+#[cfg(feature = "ssr")]
+static INSTALLED: std::sync::OnceLock<SiteConfig> = std::sync::OnceLock::new();
+
+/// The configuration installed at startup, or the defaults if none was.
+#[cfg(feature = "ssr")]
+pub fn site_config() -> &'static SiteConfig {
+  INSTALLED.get_or_init(SiteConfig::default)
+}
+
+/// The configuration provided to the current request, falling back to the
+/// defaults outside of one.
+pub fn use_site_config() -> SiteConfig {
+  use_context::<SiteConfig>().unwrap_or_default()
+}
+
+impl SiteConfig {
+  /// The absolute URL of a path on the site. The base URL may have a path of
+  /// its own, for a site served under a subpath, which `path` is joined onto.
+  pub fn url(&self, path: &str) -> String {
+    let base_url = self.base_url.trim_end_matches('/');
+    format!("{base_url}/{}", path.trim_start_matches('/'))
+  }
+}
+
+/// The absolute URL of a path on the site, for anything read away from the
+/// site, like link previews, where a relative link means nothing.
+pub fn site_url(path: &str) -> String { use_site_config().url(path) }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[cfg(feature = "ssr")]
+  #[test]
+  fn client_ips_are_the_ones_the_proxy_added() {
+    let config = RateLimitConfig {
+      client_ip_header: Some("x-forwarded-for".to_string()),
+      ..RateLimitConfig::default()
+    };
+    let client_ip = |values: &[&str]| {
+      let mut headers = http::HeaderMap::new();
+      for value in values {
+        headers.append("x-forwarded-for", value.parse().unwrap());
+      }
+      config.client_ip(&headers, &http::Extensions::new())
+    };
+    let proxied = Some("203.0.113.7".parse().unwrap());
+    assert_eq!(client_ip(&["203.0.113.7"]), proxied);
+    assert_eq!(client_ip(&["10.0.0.1, 198.51.100.2,203.0.113.7"]), proxied);
+    assert_eq!(client_ip(&["10.0.0.1", "203.0.113.7"]), proxied);
+    assert_eq!(client_ip(&["203.0.113.7, nonsense"]), None);
+    assert_eq!(client_ip(&[]), None);
+  }
+
+  #[test]
+  fn sanitize_config_matches_paths_and_prefixes() {
+    let config = SanitizeConfig {
+      all:   false,
+      posts: vec!["imported".to_string(), "guest-*".to_string()],
+    };
+    assert!(config.applies_to("imported"));
+    assert!(config.applies_to("guest-"));
+    assert!(config.applies_to("guest-post-by-someone"));
+    assert!(!config.applies_to("imported-later"));
+    assert!(!config.applies_to("my-guest-post"));
+    assert!(!SanitizeConfig::default().applies_to("imported"));
+
+    let all = SanitizeConfig {
+      all:   true,
+      posts: Vec::new(),
+    };
+    assert!(all.applies_to("anything"));
+  }
+}
@@ -0,0 +1,209 @@
+//! The contact form, which emails its messages to the author through the
+//! configured SMTP server, so that their address isn't on the site for
+//! spambots to find. Without SMTP, the site links to the address as before.
+//!
+//! Like the comment form, it turns away bots with a field hidden from readers
+//! and the signed time the form was rendered, see
+//! [`comments`](crate::comments), and bots
+//! are told their message was sent. Each client can only send a few messages
+//! an hour, on top of the site's own rate limit.
+
+use leptos::*;
+use leptos_router::{use_query_map, ActionForm};
+
+use crate::error_template::{AppError, ErrorTemplate};
+
+/// Where the contact form is.
+pub const CONTACT_PATH: &str = "/contact";
+/// The longest name a sender can give, in characters.
+const MAX_NAME_CHARS: usize = 64;
+/// The longest message, in characters.
+const MAX_MESSAGE_CHARS: usize = 4000;
+/// How many messages one client can send in [`SEND_WINDOW`].
+#[cfg(feature = "ssr")]
+const MAX_SENDS: usize = 3;
+/// How far back a client's messages count against it.
+#[cfg(feature = "ssr")]
+const SEND_WINDOW: std::time::Duration =
+  std::time::Duration::from_secs(60 * 60);
+/// The name the contact form's timestamp is signed for.
+#[cfg(feature = "ssr")]
+const FORM_NAME: &str = "contact";
+
+/// Whether the form is offered.
+pub fn is_offered() -> bool {
+  #[cfg(feature = "ssr")]
+  {
+    crate::config::use_site_config().smtp.is_some()
+  }
+  #[cfg(not(feature = "ssr"))]
+  false
+}
+
+/// Counts a message against the client sending it, or says it's sent too
+/// many lately. Clients the server can't tell apart aren't counted.
+#[cfg(feature = "ssr")]
+fn take_send() -> bool {
+  use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Mutex, OnceLock},
+    time::Instant,
+  };
+
+  static SENT: OnceLock<Mutex<HashMap<IpAddr, Vec<Instant>>>> = OnceLock::new();
+
+  let config = crate::config::use_site_config();
+  let Some(client) = use_context::<http::request::Parts>().and_then(|parts| {
+    config
+      .rate_limit
+      .client_ip(&parts.headers, &parts.extensions)
+  }) else {
+    return true;
+  };
+
+  let now = Instant::now();
+  let mut sent = SENT.get_or_init(Default::default).lock().unwrap();
+  sent.retain(|_, sends| {
+    sends.retain(|sent_at| now.duration_since(*sent_at) < SEND_WINDOW);
+    !sends.is_empty()
+  });
+  let sends = sent.entry(client).or_default();
+  if sends.len() >= MAX_SENDS {
+    return false;
+  }
+  sends.push(now);
+  true
+}
+
+/// Emails a message to the author, going back to the form with whether it
+/// was sent. `website` is the field hidden from readers, and `rendered_at`
+/// the signed time the form was rendered, like the comment form's.
+#[server]
+pub async fn send_message(
+  name: String,
+  email: String,
+  message: String,
+  website: String,
+  rendered_at: String,
+) -> Result<(), ServerFnError> {
+  use crate::comments::{MAX_FILL_SECS, MIN_FILL_SECS};
+
+  let config = crate::config::use_site_config();
+  let Some(smtp) = config.smtp.as_ref() else {
+    return Err(ServerFnError::new(AppError::NotFound));
+  };
+  let back = |status: &str| {
+    leptos_axum::redirect(&format!("{CONTACT_PATH}?status={status}"));
+    Ok(())
+  };
+
+  let Some(filled_in_secs) = crate::auth::form_age(FORM_NAME, &rendered_at)
+  else {
+    log::info!("dropped a message with a forged timestamp");
+    return back("sent");
+  };
+  if !website.is_empty() || filled_in_secs < MIN_FILL_SECS {
+    log::info!("dropped a bot's message, filled in after {filled_in_secs}s");
+    return back("sent");
+  }
+  if filled_in_secs > MAX_FILL_SECS {
+    return back("expired");
+  }
+  let (name, message) = (name.trim(), message.trim().replace("\r\n", "\n"));
+  let email = crate::newsletter::normalize_email(&email);
+  let is_valid = |text: &str, max_chars: usize| {
+    !text.is_empty() && text.chars().count() <= max_chars
+  };
+  let Some(email) = email.filter(|_| {
+    is_valid(name, MAX_NAME_CHARS) && is_valid(&message, MAX_MESSAGE_CHARS)
+  }) else {
+    return back("invalid");
+  };
+  if !take_send() {
+    return back("limited");
+  }
+
+  let message = crate::mail::Email {
+    to:      config.author.email.clone(),
+    subject: format!("{name} via {}", config.title),
+    text:    format!("From {name} <{email}>:\n\n{message}\n"),
+    html:    None,
+    headers: vec![("Reply-To".to_string(), email)],
+  };
+  match crate::mail::send(smtp, &message).await {
+    Ok(()) => back("sent"),
+    Err(e) => {
+      log::error!("failed to send a message from the contact form: {e}");
+      back("failed")
+    }
+  }
+}
+
+/// What the contact page says about how `status` went.
+fn status_message(status: &str) -> Option<&'static str> {
+  Some(match status {
+    "sent" => "Thanks for your message! I'll get back to you soon.",
+    "invalid" => {
+      "Messages need a name, an email address to reply to, and some text, and \
+       can't be too long."
+    }
+    "limited" => "You've sent a few messages already. Try again later?",
+    "failed" => "Your message couldn't be sent. Try again later?",
+    "expired" => {
+      "The page was open too long to send from. Reload it and try again?"
+    }
+    _ => return None,
+  })
+}
+
+/// The contact page, or a `404` without SMTP.
+#[component]
+pub fn ContactPage() -> impl IntoView {
+  if !is_offered() {
+    let mut outside_errors = Errors::default();
+    outside_errors.insert_with_default_key(AppError::NotFound);
+    return view! { <ErrorTemplate outside_errors /> }.into_view();
+  }
+  let status = use_query_map()
+    .get_untracked()
+    .get("status")
+    .and_then(|status| status_message(status));
+  let send = create_server_action::<SendMessage>();
+  // the page isn't an island, so this only ever runs on the server
+  #[cfg(feature = "ssr")]
+  let rendered_at = crate::auth::form_timestamp(FORM_NAME);
+  #[cfg(not(feature = "ssr"))]
+  let rendered_at = String::new();
+
+  view! {
+    <div class="markdown">
+      <h2>"Contact"</h2>
+      { status.map(|status| view! { <p>{status}</p> }) }
+    </div>
+    <ActionForm action=send class="flex flex-col gap-2 mt-4">
+      <input type="hidden" name="rendered_at" value=rendered_at />
+      // left empty by people, who can't see it
+      <div class="hidden" aria-hidden="true">
+        <input type="text" name="website" tabindex="-1" autocomplete="off" />
+      </div>
+      <input
+        type="text" name="name" required maxlength=MAX_NAME_CHARS.to_string()
+        placeholder="Name" aria-label="Name" autocomplete="name"
+        class="bg-neutral-700 px-2 py-1"
+      />
+      <input
+        type="email" name="email" required placeholder="you@example.com"
+        aria-label="Email address" autocomplete="email"
+        class="bg-neutral-700 px-2 py-1"
+      />
+      <textarea
+        name="message" required rows="8" maxlength=MAX_MESSAGE_CHARS.to_string()
+        placeholder="Your message" aria-label="Message"
+        class="bg-neutral-700 px-2 py-1"
+      />
+      <button type="submit" class="bg-neutral-700 px-4 py-1 hover:bg-neutral-600 self-start">"Send"</button>
+    </ActionForm>
+  }
+  .into_view()
+}
@@ -0,0 +1,94 @@
+//! The Content Security Policy. Scripts are only run from the site itself or
+//! when they carry the page's nonce, which Leptos adds to each inline script it
+//! renders. Styles can't be held to a nonce, since highlighted code is coloured
+//! with `style` attributes.
+//!
+//! Each page is rendered with a fresh nonce, so pages set their own policy.
+//! The server gives everything else, like static files, a policy without one.
+
+use crate::config::SiteConfig;
+
+/// The policy for a response, allowing inline scripts with `nonce`, or `None`
+/// if the policy is turned off.
+pub fn policy(site_config: &SiteConfig, nonce: Option<&str>) -> Option<String> {
+  let config = &site_config.security;
+  if !config.content_security_policy {
+    return None;
+  }
+
+  let mut script_src = vec![
+    "'self'".to_string(),
+    // the site is hydrated by a wasm module
+    "'wasm-unsafe-eval'".to_string(),
+  ];
+  script_src.extend(nonce.map(|nonce| format!("'nonce-{nonce}'")));
+  // the comments widget, whose script isn't inline and so has no nonce
+  script_src.extend(
+    site_config
+      .comments
+      .embed
+      .as_ref()
+      .map(|embed| embed.origin().to_string()),
+  );
+  // the external analytics script, which also reports back to where it's
+  // from
+  let analytics_origin = site_config
+    .external_analytics
+    .as_ref()
+    .map(|analytics| analytics.origin().to_string());
+  script_src.extend(analytics_origin.clone());
+  let mut connect_src = config.connect_src.clone();
+  connect_src.extend(analytics_origin);
+  let directives = [
+    ("default-src", vec!["'self'".to_string()]),
+    ("script-src", script_src),
+    ("style-src", vec![
+      "'self'".to_string(),
+      "'unsafe-inline'".to_string(),
+    ]),
+    ("img-src", config.img_src.clone()),
+    ("frame-src", config.frame_src.clone()),
+    ("connect-src", connect_src),
+    ("frame-ancestors", config.frame_ancestors.clone()),
+    ("object-src", vec!["'none'".to_string()]),
+    ("base-uri", vec!["'self'".to_string()]),
+    ("form-action", vec!["'self'".to_string()]),
+  ];
+  Some(
+    directives
+      .into_iter()
+      // an empty directive would block everything, not fall back to the
+      // default
+      .filter(|(_, sources)| !sources.is_empty())
+      .map(|(directive, sources)| format!("{directive} {}", sources.join(" ")))
+      .collect::<Vec<_>>()
+      .join("; "),
+  )
+}
+
+/// Sets the policy of the page being rendered, with its nonce. This does
+/// nothing outside of server rendering.
+pub fn set_page_policy() {
+  #[cfg(feature = "ssr")]
+  {
+    use http::{header, HeaderValue};
+    use leptos::*;
+
+    let (Some(nonce), Some(response)) = (
+      nonce::use_nonce(),
+      use_context::<leptos_axum::ResponseOptions>(),
+    ) else {
+      return;
+    };
+    let config = crate::config::use_site_config();
+    let Some(policy) = policy(&config, Some(&nonce)) else {
+      return;
+    };
+    match HeaderValue::from_str(&policy) {
+      Ok(policy) => {
+        response.insert_header(header::CONTENT_SECURITY_POLICY, policy)
+      }
+      Err(_) => log::error!("the configured security policy isn't a header"),
+    }
+  }
+}
@@ -0,0 +1,148 @@
+//! Post dates, which are rendered as written on the server and localized in
+//! the browser.
+//!
+//! The server can't know the reader's locale, so it renders the date as it's
+//! written in the frontmatter. [`LocalDates`] then rewrites the dates in place
+//! once the page has loaded. The dates aren't inside any island, so rewriting
+//! them can't cause a hydration mismatch.
+
+use leptos::*;
+
+/// The class of the `<time>` elements that get localized.
+const DATE_CLASS: &str = "post-date";
+/// The class added to dates once they've been localized.
+#[cfg(feature = "hydrate")]
+const LOCALIZED_CLASS: &str = "post-date-local";
+
+/// Converts a frontmatter date like `2024.09.07` to an ISO 8601 date, as used
+/// by `<time datetime>`.
+pub fn iso_date(written_on: &str) -> Option<String> {
+  let parts = written_on.split('.').collect::<Vec<_>>();
+  let [year, month, day] = parts.as_slice() else {
+    return None;
+  };
+  let is_numeric = |part: &str, len: usize| {
+    part.len() == len && part.bytes().all(|b| b.is_ascii_digit())
+  };
+  (is_numeric(year, 4) && is_numeric(month, 2) && is_numeric(day, 2))
+    .then(|| format!("{year}-{month}-{day}"))
+}
+
+/// A post's date, as written in its frontmatter. A `published` date is also
+/// the `dt-published` of the h-entry it's in, for microformats parsers.
+#[component]
+pub fn PostDate(
+  #[prop(into)] written_on: String,
+  #[prop(optional)] published: bool,
+) -> impl IntoView {
+  let class = match published {
+    true => format!("{DATE_CLASS} dt-published"),
+    false => DATE_CLASS.to_string(),
+  };
+  view! {
+    <time class=class datetime=iso_date(&written_on)>{written_on}</time>
+  }
+}
+
+/// Describes how long ago a date was, like "3 weeks ago", in the reader's
+/// locale.
+#[cfg(feature = "hydrate")]
+fn relative_hint(date: &js_sys::Date) -> Option<String> {
+  use js_sys::{Array, Intl::RelativeTimeFormat, Object, Reflect};
+
+  const DAY_MS: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+  let days = ((js_sys::Date::now() - date.get_time()) / DAY_MS).floor();
+  let (value, unit) = match days {
+    days if days < 0.0 => return None,
+    days if days < 7.0 => (days, "day"),
+    days if days < 30.0 => ((days / 7.0).floor(), "week"),
+    days if days < 365.0 => ((days / 30.0).floor(), "month"),
+    days => ((days / 365.0).floor(), "year"),
+  };
+
+  // renders "yesterday" rather than "1 day ago"
+  let options = Object::new();
+  Reflect::set(&options, &"numeric".into(), &"auto".into()).ok()?;
+  let format = RelativeTimeFormat::new(&Array::new(), &options);
+  Some(format.format(-value, unit).into())
+}
+
+/// Rewrites every post date on the page in the reader's locale, followed by
+/// how long ago it was.
+#[cfg(feature = "hydrate")]
+fn localize_dates() {
+  use js_sys::{Array, Function, Intl::DateTimeFormat, Object, Reflect};
+  use leptos::web_sys::Element;
+  use wasm_bindgen::{JsCast, JsValue};
+
+  let options = Object::new();
+  for (key, value) in
+    [("year", "numeric"), ("month", "long"), ("day", "numeric")]
+  {
+    let _ = Reflect::set(&options, &key.into(), &value.into());
+  }
+  let format = DateTimeFormat::new(&Array::new(), &options).format();
+  let format = format.unchecked_ref::<Function>();
+
+  let Ok(dates) = document().query_selector_all(&format!(
+    "time.{DATE_CLASS}[datetime]:not(.{LOCALIZED_CLASS})"
+  )) else {
+    return;
+  };
+  for i in 0..dates.length() {
+    let Some(time) = dates.item(i).and_then(|t| t.dyn_into::<Element>().ok())
+    else {
+      continue;
+    };
+    let Some([year, month, day]) =
+      time.get_attribute("datetime").and_then(|d| {
+        let parts = d
+          .split('-')
+          .map(str::parse)
+          .collect::<Result<Vec<i32>, _>>()
+          .ok()?;
+        <[i32; 3]>::try_from(parts).ok()
+      })
+    else {
+      continue;
+    };
+
+    // a date without a time is midnight in the reader's timezone, not UTC
+    let date =
+      js_sys::Date::new_with_year_month_day(year as u32, month - 1, day);
+    let Some(localized) = format
+      .call1(&JsValue::NULL, &date)
+      .ok()
+      .and_then(|s| s.as_string())
+    else {
+      continue;
+    };
+
+    time.set_text_content(Some(&localized));
+    if let Some(hint) = relative_hint(&date) {
+      if let Ok(span) = document().create_element("span") {
+        let _ = span.class_list().add_1("date-relative");
+        span.set_text_content(Some(&format!(" ({hint})")));
+        let _ = time.after_with_node_1(&span);
+      }
+    }
+    let _ = time.class_list().add_1(LOCALIZED_CLASS);
+  }
+}
+
+/// Localizes the post dates on the page, once all of it has arrived.
+#[crate::eager_island]
+pub fn LocalDates() -> impl IntoView {
+  #[cfg(feature = "hydrate")]
+  {
+    // the home page's list of posts may still be streaming in
+    if document().ready_state() == "loading" {
+      let handle =
+        window_event_listener_untyped("DOMContentLoaded", |_| localize_dates());
+      on_cleanup(move || handle.remove());
+    } else {
+      localize_dates();
+    }
+  }
+}
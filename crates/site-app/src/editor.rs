@@ -0,0 +1,265 @@
+//! The admin area's editor, under `/admin/edit/:path`, for changing a post's
+//! markdown from the browser. Saving writes the post's file in the content
+//! directory, which the server picks up like any other change to it, and
+//! commits it as [`GitConfig`](crate::config::GitConfig) says, so that the
+//! edit isn't lost when the server is redeployed.
+
+use leptos::*;
+use leptos_router::{use_query_map, ActionForm};
+use serde::{Deserialize, Serialize};
+
+use crate::error_template::{AppError, ErrorTemplate};
+
+#[cfg(feature = "ssr")]
+mod git {
+  use std::{path::Path, process::Command};
+
+  use crate::config::SiteConfig;
+
+  /// Runs git in `dir`, describing what went wrong if it fails.
+  fn git(dir: &Path, args: &[&str]) -> Result<(), String> {
+    let output = Command::new("git")
+      .arg("-C")
+      .arg(dir)
+      .args(args)
+      // nobody is there to type in credentials
+      .env("GIT_TERMINAL_PROMPT", "0")
+      .output()
+      .map_err(|e| format!("can't run git: {e}"))?;
+    if output.status.success() {
+      Ok(())
+    } else {
+      Err(format!(
+        "`git {}` failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr).trim()
+      ))
+    }
+  }
+
+  /// Commits `file`, and nothing else that might be staged, with `message`.
+  pub fn commit(
+    config: &SiteConfig,
+    file: &Path,
+    message: &str,
+  ) -> Result<(), String> {
+    let git_config = &config.admin.git;
+    let name = git_config
+      .author_name
+      .as_deref()
+      .unwrap_or(&config.author.name);
+    let email = git_config
+      .author_email
+      .as_deref()
+      .unwrap_or(&config.author.email);
+    let (Some(dir), Some(file_name)) = (
+      file.parent(),
+      file.file_name().and_then(|name| name.to_str()),
+    ) else {
+      return Err(format!("`{}` isn't a file", file.display()));
+    };
+
+    git(dir, &["add", "--", file_name])?;
+    // servers rarely have an identity of their own, so the author commits
+    // the edit too
+    git(dir, &[
+      "-c",
+      &format!("user.name={name}"),
+      "-c",
+      &format!("user.email={email}"),
+      "commit",
+      "--quiet",
+      "--message",
+      message,
+      "--only",
+      "--",
+      file_name,
+    ])
+  }
+
+  /// Pushes the commits in `dir` to the branch's upstream.
+  pub fn push(dir: &Path) -> Result<(), String> {
+    git(dir, &["push", "--quiet"])
+  }
+}
+
+/// A post's markdown, as its file holds it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostSource {
+  pub path:   String,
+  pub source: String,
+}
+
+/// The markdown of the post at `path`, frontmatter and all, whether or not
+/// it's public or can be read.
+#[server]
+pub async fn get_post_source(
+  path: String,
+) -> Result<PostSource, ServerFnError> {
+  crate::auth::require_admin().await?;
+  let not_found = || ServerFnError::new(AppError::NotFound);
+  let path = crate::posts::normalize_post_path(&path).ok_or_else(not_found)?;
+
+  crate::posts::blocking(move || {
+    let file = crate::posts::post_file(&path).ok_or_else(not_found)?;
+    match std::fs::read_to_string(&file) {
+      Ok(source) => Ok(PostSource { path, source }),
+      Err(e) => {
+        log::error!("can't read `{}`: {e}", file.display());
+        Err(ServerFnError::new(AppError::InvalidContent))
+      }
+    }
+  })
+  .await?
+}
+
+/// Writes a post's file and commits it, returning what was done, as the
+/// editor's `saved` query parameter.
+#[cfg(feature = "ssr")]
+fn save(
+  config: &crate::config::SiteConfig,
+  path: &str,
+  source: &str,
+  message: &str,
+) -> Result<&'static str, ServerFnError> {
+  let file = crate::posts::post_file(path)
+    .ok_or_else(|| ServerFnError::new(AppError::NotFound))?;
+  if std::fs::read_to_string(&file).is_ok_and(|old| old == source) {
+    return Ok("unchanged");
+  }
+
+  // written beside the post and moved over it, so that it's never read half
+  // written, and hidden so that it's never read as a post of its own
+  let file_name = file.file_name().unwrap_or_default().to_string_lossy();
+  let temp = file.with_file_name(format!(".{file_name}.saving"));
+  std::fs::write(&temp, source)
+    .and_then(|_| std::fs::rename(&temp, &file))
+    .map_err(|e| {
+      log::error!("can't write `{}`: {e}", file.display());
+      ServerFnError::new(format!("can't write `{}`", file.display()))
+    })?;
+  log::info!("saved `{}` from the admin editor", file.display());
+
+  let git_config = &config.admin.git;
+  if !git_config.commit {
+    return Ok("written");
+  }
+  let message = match message.trim() {
+    "" => git_config.message.replace("{path}", path),
+    message => message.to_string(),
+  };
+  if let Err(e) = git::commit(config, &file, &message) {
+    log::error!("can't commit `{}`: {e}", file.display());
+    return Ok("uncommitted");
+  }
+  if !git_config.push {
+    return Ok("committed");
+  }
+  match git::push(file.parent().unwrap_or(&file)) {
+    Ok(()) => Ok("pushed"),
+    Err(e) => {
+      log::error!("can't push `{}`: {e}", file.display());
+      Ok("unpushed")
+    }
+  }
+}
+
+/// Saves a post's markdown, committing it with `message`, or the configured
+/// message if it's blank, and goes back to the editor. Markdown whose
+/// frontmatter can't be read isn't saved, so that a typo can't take a public
+/// post down.
+#[server]
+pub async fn save_post(
+  path: String,
+  source: String,
+  message: String,
+) -> Result<(), ServerFnError> {
+  crate::auth::require_admin().await?;
+  let path = crate::posts::normalize_post_path(&path)
+    .ok_or_else(|| ServerFnError::new(AppError::NotFound))?;
+  // browsers send textareas with CRLF line endings
+  let source = source.replace("\r\n", "\n");
+
+  if let Err(problem) = crate::posts::try_parse_frontmatter(&source) {
+    let problem = percent_encoding::utf8_percent_encode(
+      &problem,
+      percent_encoding::NON_ALPHANUMERIC,
+    );
+    leptos_axum::redirect(&format!(
+      "/admin/edit/{path}?saved=invalid&problem={problem}"
+    ));
+    return Ok(());
+  }
+
+  let config = crate::config::use_site_config();
+  let saved = {
+    let path = path.clone();
+    crate::posts::blocking(move || save(&config, &path, &source, &message))
+      .await??
+  };
+  leptos_axum::redirect(&format!("/admin/edit/{path}?saved={saved}"));
+  Ok(())
+}
+
+/// What the editor says about the last save.
+fn saved_message(saved: &str) -> Option<&'static str> {
+  Some(match saved {
+    "unchanged" => "Nothing changed, so nothing was saved.",
+    "written" => "Saved.",
+    "committed" => "Saved and committed.",
+    "pushed" => "Saved, committed and pushed.",
+    "uncommitted" => "Saved, but not committed. The server's logs say why.",
+    "unpushed" => {
+      "Saved and committed, but not pushed. The server's logs say why."
+    }
+    "invalid" => "Not saved, since the frontmatter can't be read:",
+    _ => return None,
+  })
+}
+
+/// Edits a post's markdown.
+#[component]
+pub fn AdminEditor() -> impl IntoView {
+  let path = crate::posts::use_post_path();
+  let query = use_query_map().get_untracked();
+  let saved = query.get("saved").and_then(|saved| saved_message(saved));
+  let problem = query.get("problem").cloned();
+  let save = create_server_action::<SavePost>();
+  let source_resource =
+    create_blocking_resource(move || path.clone(), get_post_source);
+  let default_message =
+    store_value(crate::config::use_site_config().admin.git.message);
+
+  view! {
+    { saved.map(|saved| view! {
+      <div class="markdown">
+        <p>{saved} " " { problem.map(|problem| view! { <code>{problem}</code> }) }</p>
+      </div>
+    }) }
+    <Suspense>
+      <ErrorBoundary fallback=|errors| view! { <ErrorTemplate errors /> }>
+        { move || source_resource.get().map(|s| s.map_err(AppError::from).map(|post| {
+          let placeholder = default_message
+            .with_value(|message| message.replace("{path}", &post.path));
+          view! {
+          <div class="markdown">
+            <h2>"Editing " <code>{post.path.clone()}</code></h2>
+          </div>
+          <ActionForm action=save class="flex flex-col gap-2">
+            <input type="hidden" name="path" value=post.path.clone() />
+            <textarea
+              name="source" rows="30" required aria-label="Markdown"
+              class="bg-neutral-700 px-2 py-1 w-full font-mono"
+            >{post.source}</textarea>
+            <input
+              type="text" name="message" aria-label="Commit message"
+              placeholder=placeholder
+              class="bg-neutral-700 px-2 py-1"
+            />
+            <button type="submit" class="bg-neutral-700 px-4 py-1 hover:bg-neutral-600 self-start">"Save"</button>
+          </ActionForm>
+        }})) }
+      </ErrorBoundary>
+    </Suspense>
+  }
+}
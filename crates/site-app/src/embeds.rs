@@ -0,0 +1,95 @@
+//! Interactive components which can be embedded in posts with the
+//! `{{< component "Name" >}}` shortcode, and the facades which stand in for
+//! third-party embeds until they're clicked.
+//!
+//! The markdown pipeline replaces the shortcode with a marker comment (see
+//! [`site_markdown::embeds`]), and
+//! [`Post::full_post`](crate::posts::Post::full_post) swaps each marker for
+//! the registered island, so it's rendered on the server and hydrated in the
+//! browser.
+
+use leptos::*;
+pub use site_markdown::embeds::{
+  is_allowed_embed_src, FACADE_BUTTON_CLASS, FACADE_CLASS, MARKER_CLOSE,
+  MARKER_OPEN,
+};
+
+/// The names of the components which can be embedded in posts.
+pub const EMBEDDABLE_COMPONENTS: &[&str] = &["ColorPickerDemo"];
+
+/// Renders an embeddable component by name.
+pub fn render_embedded_component(name: &str) -> Option<View> {
+  match name {
+    "ColorPickerDemo" => Some(view! { <ColorPickerDemo /> }.into_view()),
+    _ => None,
+  }
+}
+
+/// A colour picker which shows the chosen colour and its hex code.
+#[crate::eager_island]
+fn ColorPickerDemo() -> impl IntoView {
+  let (color, set_color) = create_signal("#9c9cf4".to_string());
+
+  view! {
+    <div class="flex items-center gap-4 my-6 p-3 rounded border border-zinc-600 bg-zinc-800">
+      <input
+        type="color"
+        value=color
+        on:input=move |ev| set_color(event_target_value(&ev))
+      />
+      <div class="h-8 flex-1 rounded" style:background-color=color />
+      <code>{color}</code>
+    </div>
+  }
+}
+
+/// Swaps embed facades for the embeds they stand in for when they're clicked,
+/// so third-party content isn't loaded until the reader asks for it.
+#[crate::eager_island]
+pub fn EmbedFacades() -> impl IntoView {
+  #[cfg(feature = "hydrate")]
+  {
+    use leptos::web_sys::Element;
+    use wasm_bindgen::JsCast;
+
+    let handle = window_event_listener(ev::click, |ev| {
+      let Some(facade) = ev
+        .target()
+        .and_then(|t| t.dyn_into::<Element>().ok())
+        .and_then(|el| {
+          el.closest(&format!(".{FACADE_BUTTON_CLASS}"))
+            .ok()
+            .flatten()
+        })
+        .and_then(|el| el.closest(&format!(".{FACADE_CLASS}")).ok().flatten())
+      else {
+        return;
+      };
+      let Some(src) = facade
+        .get_attribute("data-embed-src")
+        .filter(|src| is_allowed_embed_src(src))
+      else {
+        return;
+      };
+      let Ok(iframe) = document().create_element("iframe") else {
+        return;
+      };
+
+      ev.prevent_default();
+      let _ = iframe.set_attribute("src", &src);
+      let _ = iframe.set_attribute(
+        "title",
+        &facade.get_attribute("data-embed-title").unwrap_or_default(),
+      );
+      let _ = iframe.set_attribute(
+        "allow",
+        "autoplay; encrypted-media; fullscreen; picture-in-picture",
+      );
+      let _ = iframe.set_attribute("allowfullscreen", "");
+      facade.set_inner_html("");
+      let _ = facade.append_child(&iframe);
+      let _ = facade.class_list().remove_1(FACADE_CLASS);
+    });
+    on_cleanup(move || handle.remove());
+  }
+}
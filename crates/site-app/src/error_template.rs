@@ -3,18 +3,121 @@ use http::status::StatusCode;
 use leptos::*;
 #[cfg(feature = "ssr")]
 use leptos_axum::ResponseOptions;
+use leptos_router::use_location;
 use thiserror::Error;
 
+use crate::posts::RelatedPosts;
+
+/// Everything that can go wrong while rendering a page. Server fns report
+/// these by returning them as their error message, e.g.
+/// `ServerFnError::new(AppError::NotFound)`, and the page's error boundary
+/// shows them with [`ErrorTemplate`].
 #[derive(Clone, Debug, Error)]
 pub enum AppError {
   #[error("Not Found")]
   NotFound,
+  /// The page is only for the author, who isn't logged in.
+  #[error("Unauthorized")]
+  Unauthorized,
+  /// A post exists but its file can't be read, e.g. its frontmatter is
+  /// malformed. What's wrong is logged on the server rather than shown.
+  #[error("Invalid Content")]
+  InvalidContent,
+  /// A server fn couldn't be called, e.g. its arguments or result didn't
+  /// survive the trip between the browser and the server.
+  #[error("Bad Request")]
+  ServerFn(String),
+  /// The server couldn't be reached, e.g. while the reader is offline.
+  #[error("Network Error")]
+  Network,
+  /// The server failed to handle the request.
+  #[error("Internal Server Error")]
+  Internal(String),
 }
 
 impl AppError {
   pub fn status_code(&self) -> StatusCode {
     match self {
       AppError::NotFound => StatusCode::NOT_FOUND,
+      AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+      AppError::InvalidContent => StatusCode::UNPROCESSABLE_ENTITY,
+      AppError::ServerFn(_) => StatusCode::BAD_REQUEST,
+      AppError::Network => StatusCode::SERVICE_UNAVAILABLE,
+      AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+  }
+
+  /// The heading of the error's page.
+  pub fn title(&self) -> &'static str {
+    match self {
+      AppError::NotFound => "Page Not Found",
+      AppError::Unauthorized => "Log In Required",
+      AppError::InvalidContent => "Broken Post",
+      AppError::ServerFn(_) => "Bad Request",
+      AppError::Network => "Offline",
+      AppError::Internal(_) => "Server Error",
+    }
+  }
+
+  /// An explanation of the error for readers.
+  pub fn message(&self) -> &'static str {
+    match self {
+      AppError::NotFound => "There's nothing here.",
+      AppError::Unauthorized => "You need to log in to see this page.",
+      AppError::InvalidContent => {
+        "This post couldn't be read, so it can't be shown until it's fixed."
+      }
+      AppError::ServerFn(_) => {
+        "The page asked the server for something it didn't understand. \
+         Reloading might help."
+      }
+      AppError::Network => {
+        "The server couldn't be reached. Check your connection and try again."
+      }
+      AppError::Internal(_) => {
+        "Something went wrong on our end. Trying again might help."
+      }
+    }
+  }
+
+  /// What went wrong, for the logs.
+  fn detail(&self) -> Option<&str> {
+    match self {
+      AppError::ServerFn(detail) | AppError::Internal(detail) => Some(detail),
+      _ => None,
+    }
+  }
+
+  /// Whether trying the request again could succeed.
+  pub fn is_retryable(&self) -> bool {
+    !matches!(
+      self,
+      AppError::NotFound | AppError::Unauthorized | AppError::InvalidContent
+    )
+  }
+}
+
+impl From<ServerFnError> for AppError {
+  fn from(error: ServerFnError) -> Self {
+    match error {
+      ServerFnError::ServerError(message) => [
+        AppError::NotFound,
+        AppError::Unauthorized,
+        AppError::InvalidContent,
+      ]
+      .into_iter()
+      .find(|error| error.to_string() == message)
+      .unwrap_or(AppError::Internal(message)),
+      ServerFnError::Request(_) | ServerFnError::Response(_) => {
+        AppError::Network
+      }
+      ServerFnError::Args(_)
+      | ServerFnError::MissingArg(_)
+      | ServerFnError::Serialization(_)
+      | ServerFnError::Deserialization(_) => {
+        AppError::ServerFn(error.to_string())
+      }
+      error => AppError::Internal(error.to_string()),
     }
   }
 }
@@ -52,9 +155,17 @@ pub fn ErrorTemplate(
       }
   }}
 
+  let not_found = errors.iter().any(|e| matches!(e, AppError::NotFound));
+  let retryable = errors.iter().any(AppError::is_retryable);
+  for error in errors.iter() {
+    if let Some(detail) = error.detail() {
+      logging::error!("{error}: {detail}");
+    }
+  }
+
   view! {
     <div class="markdown">
-      <h1>{if errors.len() > 1 { "Server Errors" } else { "Server Error" }}</h1>
+      <h1>{errors.first().map(AppError::title)}</h1>
       <For
         each=move || { errors.clone().into_iter().enumerate() }
         key=|(index, _error)| *index
@@ -62,11 +173,16 @@ pub fn ErrorTemplate(
           let error_string = error.1.to_string();
           let error_code = error.1.status_code();
           view! {
-            <h2>{error_code.to_string()}</h2>
-            <p>"Error: " {error_string}</p>
+            <h2>{error_code.as_u16()} " " {error_string}</h2>
+            <p>{error.1.message()}</p>
           }
         }
       />
+      // a full page load, since the resources are loaded on the server
+      {retryable.then(|| view! {
+        <p><a href=use_location().pathname.get_untracked()>"Try again"</a></p>
+      })}
+      {not_found.then(|| view! { <RelatedPosts /> })}
     </div>
   }
 }
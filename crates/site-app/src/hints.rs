@@ -0,0 +1,116 @@
+//! Decides which resource hints each page gets, and emits them both as `Link`
+//! response headers and as `<link>` tags, so that browsers and CDNs which only
+//! look at one of them still pick them up.
+
+use leptos::*;
+
+use crate::posts::PostHeader;
+
+/// A hint to the browser about a resource it's going to need.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ResourceHint {
+  /// Fetches a font before the stylesheet asks for it.
+  Font {
+    href:  &'static str,
+    type_: &'static str,
+  },
+  /// Fetches a page the reader is likely to visit next.
+  Prefetch { href: String },
+}
+
+impl ResourceHint {
+  /// The hint as the value of a `Link` header.
+  pub fn link_header(&self) -> String {
+    match self {
+      ResourceHint::Font { href, type_ } => {
+        format!("<{href}>; rel=preload; as=font; type=\"{type_}\"; crossorigin")
+      }
+      ResourceHint::Prefetch { href } => format!("<{href}>; rel=prefetch"),
+    }
+  }
+}
+
+/// A font declared in [`FONTS_CSS`](crate::FONTS_CSS).
+struct FontAsset {
+  href:    &'static str,
+  type_:   &'static str,
+  /// Whether the font is used on most pages, and so is worth preloading.
+  preload: bool,
+}
+
+/// The fonts the site uses.
+const FONTS: &[FontAsset] = &[
+  FontAsset {
+    href:    "/fonts/Firava.woff2",
+    type_:   "font/woff2",
+    preload: true,
+  },
+  FontAsset {
+    href:    "/fonts/IosevkaCustom-Regular.ttf",
+    type_:   "font/ttf",
+    preload: true,
+  },
+  FontAsset {
+    href:    "/fonts/IosevkaCustom-Bold.ttf",
+    type_:   "font/ttf",
+    preload: false,
+  },
+  FontAsset {
+    href:    "/fonts/IosevkaCustom-Italic.ttf",
+    type_:   "font/ttf",
+    preload: true,
+  },
+];
+
+/// The hints every page gets.
+pub fn site_hints() -> Vec<ResourceHint> {
+  FONTS
+    .iter()
+    .filter(|font| font.preload)
+    .map(|font| ResourceHint::Font {
+      href:  font.href,
+      type_: font.type_,
+    })
+    .collect()
+}
+
+/// The hints a post's page gets. Embeds aren't preconnected to, since their
+/// facades keep third parties from seeing readers until they're clicked.
+pub fn post_hints(header: &PostHeader) -> Vec<ResourceHint> {
+  header
+    .likely_next
+    .iter()
+    .map(|path| ResourceHint::Prefetch {
+      href: format!("/post/{path}"),
+    })
+    .collect()
+}
+
+/// Emits resource hints as `<link>` tags in the document head, and as `Link`
+/// headers if the response headers haven't been sent yet.
+#[component]
+pub fn ResourceHints(hints: Vec<ResourceHint>) -> impl IntoView {
+  #[cfg(feature = "ssr")]
+  if let Some(response) = use_context::<leptos_axum::ResponseOptions>() {
+    for hint in hints.iter() {
+      if let Ok(value) = http::HeaderValue::from_str(&hint.link_header()) {
+        response.append_header(http::header::LINK, value);
+      }
+    }
+  }
+
+  hints
+    .into_iter()
+    .map(|hint| match hint {
+      ResourceHint::Font { href, type_ } => view! {
+        <leptos_meta::Link
+          rel="preload" href=href as_="font" type_=type_
+          crossorigin="anonymous"
+        />
+      },
+      ResourceHint::Prefetch { href } => view! {
+        <leptos_meta::Link rel="prefetch" href=href />
+      },
+    })
+    .collect_view()
+}
@@ -1,6 +1,44 @@
 #[cfg(feature = "ssr")]
-mod markdown;
-mod posts;
+pub mod activitypub;
+pub mod admin;
+pub mod analytics;
+pub mod auth;
+pub mod comments;
+pub mod config;
+pub mod contact;
+pub mod csp;
+pub mod dates;
+pub mod editor;
+pub mod embeds;
+pub mod hints;
+#[cfg(feature = "ssr")]
+pub mod links;
+pub mod live;
+#[cfg(feature = "ssr")]
+pub mod mail;
+pub mod moderation;
+pub mod newsletter;
+pub mod palette;
+#[cfg(feature = "ssr")]
+pub mod post_index;
+pub mod posts;
+pub mod prefetch;
+pub mod preview;
+#[cfg(feature = "ssr")]
+pub mod previews;
+pub mod push;
+pub mod reactions;
+#[cfg(feature = "ssr")]
+pub mod render_cache;
+pub mod revisions;
+pub mod search;
+#[cfg(feature = "ssr")]
+pub mod spam;
+pub mod theme;
+pub mod toc;
+pub mod views;
+pub mod webmentions;
+pub mod zero_js;
 
 use leptos::*;
 use leptos_meta::*;
@@ -8,64 +46,114 @@ use leptos_router::*;
 
 use crate::error_template::{AppError, ErrorTemplate};
 
+// Islands are hydrated by one of two bundles: the site's, which every page
+// loads, or the lazy one, which is only loaded once a reader reaches for the
+// search box or the command palette. Each bundle is built with the other's
+// islands as plain components, so it never ships code it doesn't hydrate.
+
+/// The name the lazily loaded bundle is built under, in the site's `pkg`
+/// directory.
+pub const LAZY_BUNDLE_NAME: &str = "site-lazy";
+
+#[cfg(not(any(feature = "ssr", not(feature = "lazy-islands"))))]
+pub(crate) use leptos::component as eager_island;
+#[cfg(not(any(feature = "ssr", feature = "lazy-islands")))]
+pub(crate) use leptos::component as lazy_island;
+/// Marks an island hydrated by the site's bundle.
+#[cfg(any(feature = "ssr", not(feature = "lazy-islands")))]
+pub(crate) use leptos::island as eager_island;
+/// Marks an island hydrated by the lazily loaded bundle, see
+/// `site-frontend-lazy`.
+#[cfg(any(feature = "ssr", feature = "lazy-islands"))]
+pub(crate) use leptos::island as lazy_island;
+
 pub mod error_template;
 
+/// The `@font-face` declarations for the site's fonts.
+pub const FONTS_CSS: &str = include_str!("../style/fonts.css");
+
 #[component]
 pub fn App() -> impl IntoView {
   // Provides context that manages stylesheets, titles, meta tags, etc.
   provide_meta_context();
+  csp::set_page_policy();
+  let config = config::use_site_config();
+  let theme = theme::chosen_theme();
 
   view! {
+    { theme.map(|theme| view! { <Html class=theme.name() /> }) }
     <div class="bg-neutral-800 min-h-screen">
       <Stylesheet href="/pkg/site.css"/>
-      <Style>{include_str!("../style/fonts.css")}</Style>
-
-      // preloads the fonts
-      <leptos_meta::Link
-        rel="preload" href="/fonts/Firava.woff2"
-        as_="font" type_="font/woff2" crossorigin="anonymous"
-      />
-      <leptos_meta::Link
-        rel="preload" href="/fonts/IosevkaCustom-Regular.ttf"
-        as_="font" type_="font/ttf" crossorigin="anonymous"
-      />
-      // <leptos_meta::Link
-      //   rel="preload" href="/fonts/IosevkaCustom-Bold.ttf"
-      //   as_="font" type_="font/ttf" crossorigin="anonymous"
-      // />
-      <leptos_meta::Link
-        rel="preload" href="/fonts/IosevkaCustom-Italic.ttf"
-        as_="font" type_="font/ttf" crossorigin="anonymous"
-      />
-
-      <leptos_meta::Link rel="icon" href="/favicon.png" type_="image/png" />
+      <Style>{FONTS_CSS}</Style>
+
+      <hints::ResourceHints hints=hints::site_hints() />
+
+      <theme::ThemeMeta theme />
+      <analytics::ExternalAnalyticsScript />
 
       // sets the document title
-      <Title text="John Lewis' Blog" />
+      <Title text=config.title.clone() />
 
       <Router fallback=|| {
         let mut outside_errors = Errors::default();
         outside_errors.insert_with_default_key(AppError::NotFound);
         view! { <ErrorTemplate outside_errors/> }.into_view()
       }>
+        <SiteMeta title=config.title.clone() />
         <div class="font-mono px-4 md:px-0 md:mx-auto md:w-[48rem] py-4 text-neutral-100 text-lg">
-          // header
-          <div class="flex gap-2 w-full">
-            <StyledLink href="/">"John Lewis\' Blog"</StyledLink>
+          // header, which is also the author's representative h-card
+          <div class="flex gap-2 w-full h-card">
+            <StyledLink class="u-url u-uid" href="/">{config.title}</StyledLink>
+            <data class="p-name hidden" value=config.author.name.clone()></data>
             <div class="flex-1" />
-            <p class="items-center font-light">"Rust, Games, Musings"</p>
+            <p class="items-center font-light p-note">{config.tagline}</p>
+            <theme::ThemeToggle theme />
           </div>
           <Separator />
           <Routes>
-            <Route path="" view=HomePage />
-            <Route path="post/:path" view=posts::PostPage />
+            <Route path="" view=HomePage ssr=zero_js::ssr_mode() />
+            <Route path="post/:path" view=posts::PostPage ssr=zero_js::ssr_mode() />
+            <Route path="post/:path/changes" view=revisions::PostChangesPage ssr=zero_js::ssr_mode() />
+            <Route path="preview/:path" view=preview::PreviewPage ssr=zero_js::ssr_mode() />
+            <Route path="admin" view=admin::AdminArea ssr=zero_js::ssr_mode()>
+              <Route path="" view=admin::AdminPosts />
+              <Route path="analytics" view=analytics::AdminAnalytics />
+              <Route path="comments" view=moderation::AdminComments />
+              <Route path="edit/:path" view=editor::AdminEditor />
+              <Route path="preview/:path" view=admin::AdminPreviewLink />
+              <Route path="upload" view=admin::AdminUpload />
+              <Route path="webmentions" view=webmentions::AdminWebmentions />
+            </Route>
+            <Route path="contact" view=contact::ContactPage ssr=zero_js::ssr_mode() />
+            <Route path="newsletter" view=newsletter::NewsletterPage ssr=zero_js::ssr_mode() />
+            <Route path="newsletter/confirm" view=newsletter::NewsletterConfirmPage ssr=zero_js::ssr_mode() />
+            <Route path="newsletter/unsubscribe" view=newsletter::NewsletterUnsubscribePage ssr=zero_js::ssr_mode() />
+            <Route path="popular" view=views::PopularPage ssr=zero_js::ssr_mode() />
+            <Route path="search" view=search::SearchPage ssr=zero_js::ssr_mode() />
           </Routes>
+          <newsletter::NewsletterFooter />
+          <prefetch::PostPrefetcher />
+          <dates::LocalDates />
+          <palette::CommandPalette />
+          { analytics::is_enabled().then(|| view! { <analytics::AnalyticsBeacon /> }) }
         </div>
       </Router>
     </div>
   }
 }
 
+/// The page's canonical URL, and the Open Graph tags which link previews on
+/// other sites are built from. Pages can add their own, like a post's title.
+#[component]
+fn SiteMeta(title: String) -> impl IntoView {
+  let url = config::site_url(&use_location().pathname.get_untracked());
+  view! {
+    <Link rel="canonical" href=url.clone() />
+    <Meta property="og:site_name" content=title />
+    <Meta property="og:url" content=url />
+  }
+}
+
 /// A styled hyperlink.
 #[component]
 fn StyledLink(
@@ -78,6 +166,16 @@ fn StyledLink(
   }
 }
 
+/// The author of an h-entry, as microformats parsers read it. It's not shown,
+/// since the author of every post is the same.
+#[component]
+pub(crate) fn AuthorCard() -> impl IntoView {
+  let config = config::use_site_config();
+  view! {
+    <a class="p-author h-card hidden" href=config.url("")>{config.author.name}</a>
+  }
+}
+
 /// A full-width separator.
 #[component]
 fn Separator() -> impl IntoView {
@@ -87,25 +185,30 @@ fn Separator() -> impl IntoView {
 /// Renders the home page of your application.
 #[component]
 fn HomePage() -> impl IntoView {
+  let config = config::use_site_config();
   let posts_resource = create_resource(|| (), |_| posts::get_all_posts());
 
   let post_list_item = |p: posts::Post| {
     view! {
-      <li>
-        <a href={format!("/post/{}", p.path)}>
+      <li class="h-entry">
+        <a class="u-url p-name" href={format!("/post/{}", p.path)}>
           {p.metadata.title}
         </a>
-        " - " {p.metadata.written_on}
+        " - " <dates::PostDate written_on=p.metadata.written_on published=true />
+        <AuthorCard />
       </li>
     }
   };
 
   let post_elements = view! {
-    <Suspense>
-      { move || posts_resource.get().map(|p| match p {
-        Ok(posts) => posts.clone().into_iter().map(post_list_item).collect_view(),
-        _ => view! { <p>"This should never happen"</p> }.into_view()
-      })}
+    <Suspense fallback=|| view! { <posts::PostListSkeleton /> }>
+      <ErrorBoundary fallback=|errors| view! { <ErrorTemplate errors /> }>
+        { move || posts_resource.get().map(|p| p.map_err(AppError::from).map(|posts| view! {
+          <ul class="h-feed">
+            {posts.into_iter().map(post_list_item).collect_view()}
+          </ul>
+        }))}
+      </ErrorBoundary>
     </Suspense>
   };
 
@@ -114,15 +217,40 @@ fn HomePage() -> impl IntoView {
       <h2>"Hey, John here!"</h2>
       <p>
         "Welcome to my blog. I write about my findings and thoughts, mostly regarding Rust, Nix, and game development. If you'd like to hire me, I'm available to hire! Contact me "
-        <a href="mailto:contact@jlewis.sh">"here"</a>
-        ", or find me on "
-        <a rel="me" href="https://social.treehouse.systems/@johnbchron">Mastodon</a>
+        // the form keeps the author's address away from spambots
+        { match contact::is_offered() {
+          true => view! { <a href=contact::CONTACT_PATH>"here"</a> },
+          false => view! { <a href=format!("mailto:{}", config.author.email)>"here"</a> },
+        } }
+        <SocialLinks links=config.social />
         "."
       </p>
       <h3>"Recent Posts"</h3>
-      <ul>
-        {post_elements}
-      </ul>
+      <search::SearchForm />
+      {post_elements}
+      { views::is_shown().then(|| view! {
+        <p><a href=views::POPULAR_PATH>"See the most read posts"</a></p>
+      }) }
+      { push::push_public_key().map(|public_key| view! {
+        <push::PushOptIn public_key />
+      }) }
+      <live::NewPostToast />
     </div>
   }
 }
+
+/// The end of the home page's introduction, listing where else the author can
+/// be found, if anywhere.
+#[component]
+fn SocialLinks(links: Vec<config::SocialLink>) -> impl IntoView {
+  let count = links.len();
+  let links = links.into_iter().enumerate().map(|(i, link)| {
+    let separator = match i {
+      0 => ", or find me on ",
+      i if i + 1 == count => " or ",
+      _ => ", ",
+    };
+    view! { {separator} <a rel="me" href=link.url>{link.name}</a> }
+  });
+  links.collect_view()
+}
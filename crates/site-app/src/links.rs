@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use crate::posts::{
+  link_context, read_post_sources, render_post, try_parse_frontmatter,
+};
+
+/// An internal link that doesn't resolve to an existing post or heading.
+#[derive(Debug, Clone)]
+pub struct DeadLink {
+  /// The path of the post containing the link.
+  pub source: String,
+  /// The link destination as written in the post.
+  pub target: String,
+  /// Why the link doesn't resolve.
+  pub reason: DeadLinkReason,
+}
+
+#[derive(Debug, Clone)]
+pub enum DeadLinkReason {
+  /// No post exists at the linked path.
+  MissingPost,
+  /// The linked post exists but isn't public, so readers would get a 404.
+  PrivatePost,
+  /// The linked post exists but has no heading with the linked ID.
+  MissingHeading,
+}
+
+impl std::fmt::Display for DeadLinkReason {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(match self {
+      DeadLinkReason::MissingPost => "no such post",
+      DeadLinkReason::PrivatePost => "post is not public",
+      DeadLinkReason::MissingHeading => "no such heading",
+    })
+  }
+}
+
+impl std::fmt::Display for DeadLink {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "dead link in post `{}` to `{}`: {}",
+      self.source, self.target, self.reason
+    )
+  }
+}
+
+struct LinkablePost {
+  public:      bool,
+  heading_ids: Vec<String>,
+  links:       Vec<String>,
+}
+
+/// Renders every post and returns the internal links (`/post/...` paths and
+/// heading anchors) which don't resolve. Relative links to other markdown
+/// files have already been rewritten to `/post/...` paths by the pipeline, so
+/// they're validated here too.
+pub fn find_dead_links() -> Vec<DeadLink> {
+  let sources = read_post_sources();
+  let context = link_context(&sources);
+  let posts = sources
+    .into_iter()
+    .filter_map(|(path, input)| {
+      // posts which can't be read are reported by `check-content`
+      let (metadata, content) = try_parse_frontmatter(&input).ok()?;
+      let rendered = render_post(&path, &content, &metadata, &context);
+      Some((path, LinkablePost {
+        public:      metadata.public,
+        heading_ids: rendered.toc.into_iter().map(|h| h.id).collect(),
+        links:       rendered.links,
+      }))
+    })
+    .collect::<HashMap<_, _>>();
+
+  let mut dead_links = Vec::new();
+  for (source, post) in posts.iter() {
+    for link in post.links.iter() {
+      let (target_path, anchor) = match link.split_once('#') {
+        Some((path, anchor)) => (path, Some(anchor)),
+        None => (link.as_str(), None),
+      };
+
+      let target = if target_path.is_empty() {
+        post
+      } else if let Some(target_path) = target_path.strip_prefix("/post/") {
+        match posts.get(target_path.trim_end_matches('/')) {
+          Some(target) => target,
+          None => {
+            dead_links.push(DeadLink {
+              source: source.clone(),
+              target: link.clone(),
+              reason: DeadLinkReason::MissingPost,
+            });
+            continue;
+          }
+        }
+      } else {
+        // not an internal link
+        continue;
+      };
+
+      if post.public && !target.public {
+        dead_links.push(DeadLink {
+          source: source.clone(),
+          target: link.clone(),
+          reason: DeadLinkReason::PrivatePost,
+        });
+      } else if let Some(anchor) = anchor {
+        if !target.heading_ids.iter().any(|id| id == anchor) {
+          dead_links.push(DeadLink {
+            source: source.clone(),
+            target: link.clone(),
+            reason: DeadLinkReason::MissingHeading,
+          });
+        }
+      }
+    }
+  }
+
+  dead_links
+    .sort_by(|a, b| (&a.source, &a.target).cmp(&(&b.source, &b.target)));
+  dead_links
+}
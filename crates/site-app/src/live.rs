@@ -0,0 +1,108 @@
+//! Live updates pushed from the server over server-sent events.
+//!
+//! The server broadcasts a [`LiveEvent`] whenever a post is published or
+//! edited, or a comment is left, to every client subscribed to
+//! [`EVENTS_PATH`]. Each event is sent with its [`name`](LiveEvent::name) as
+//! the SSE event type and its JSON encoding as the data, so external
+//! integrations can subscribe with any SSE client.
+
+use leptos::*;
+use serde::{Deserialize, Serialize};
+
+/// The path of the server-sent events endpoint.
+pub const EVENTS_PATH: &str = "/events";
+
+/// An update broadcast to subscribed clients.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum LiveEvent {
+  /// A public post appeared.
+  NewPost { path: String, title: String },
+  /// The source of a post changed.
+  PostUpdated { path: String },
+  /// A comment was left on a post.
+  NewComment { post_path: String, comment_id: i64 },
+}
+
+impl LiveEvent {
+  /// The SSE event type this event is sent as.
+  pub fn name(&self) -> &'static str {
+    match self {
+      LiveEvent::NewPost { .. } => "new-post",
+      LiveEvent::PostUpdated { .. } => "post-updated",
+      LiveEvent::NewComment { .. } => "new-comment",
+    }
+  }
+}
+
+/// Subscribes to events of type `name`, calling `callback` with each one. The
+/// subscription is closed when the calling island is cleaned up.
+#[cfg(feature = "hydrate")]
+fn subscribe(name: &str, callback: impl Fn(LiveEvent) + 'static) {
+  use leptos::web_sys::MessageEvent;
+  use wasm_bindgen::{closure::Closure, JsCast};
+
+  let Ok(source) = web_sys::EventSource::new(EVENTS_PATH) else {
+    return;
+  };
+  let listener =
+    Closure::<dyn Fn(MessageEvent)>::new(move |ev: MessageEvent| {
+      if let Some(event) = ev
+        .data()
+        .as_string()
+        .and_then(|data| serde_json::from_str(&data).ok())
+      {
+        callback(event);
+      }
+    });
+  let _ = source
+    .add_event_listener_with_callback(name, listener.as_ref().unchecked_ref());
+
+  on_cleanup(move || {
+    source.close();
+    drop(listener);
+  });
+}
+
+/// Shows a dismissable notice when a new post is published while the reader
+/// is on the page.
+#[crate::eager_island]
+pub fn NewPostToast() -> impl IntoView {
+  let (new_post, set_new_post) = create_signal(None::<(String, String)>);
+
+  #[cfg(feature = "hydrate")]
+  subscribe("new-post", move |event| {
+    if let LiveEvent::NewPost { path, title } = event {
+      set_new_post(Some((path, title)));
+    }
+  });
+
+  move || {
+    new_post().map(|(path, title)| {
+      view! {
+        <div
+          role="status"
+          class="fixed bottom-4 right-4 flex gap-4 items-center p-3 rounded border border-zinc-600 bg-zinc-800"
+        >
+          <span>"New post: "<a class="text-periwinkle underline hover:no-underline" href=format!("/post/{path}")>{title}</a></span>
+          <button aria-label="Dismiss" on:click=move |_| set_new_post(None)>"×"</button>
+        </div>
+      }
+    })
+  }
+}
+
+/// Reloads the page when the source of the post at `path` changes. Only used
+/// in debug builds.
+#[crate::eager_island]
+pub fn LiveReload(path: String) -> impl IntoView {
+  #[cfg(feature = "hydrate")]
+  subscribe("post-updated", move |event| {
+    if matches!(event, LiveEvent::PostUpdated { path: ref updated } if *updated == path)
+    {
+      let _ = window().location().reload();
+    }
+  });
+  #[cfg(not(feature = "hydrate"))]
+  let _ = path;
+}
@@ -0,0 +1,284 @@
+//! Sends email through the configured SMTP server, see
+//! [`SmtpConfig`](crate::config::SmtpConfig). It's just enough SMTP for the
+//! site's own mail, like the newsletter's: one message to one recipient per
+//! connection, logging in with `AUTH PLAIN` if there's a username.
+
+use std::time::Duration;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use tokio::{
+  io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+  net::TcpStream,
+};
+use tokio_native_tls::{native_tls, TlsStream};
+
+use crate::config::{SmtpConfig, SmtpSecurity};
+
+/// How long sending a message can take, from connecting to hanging up.
+const SEND_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long each line of a base64-encoded body is.
+const BASE64_LINE_LEN: usize = 76;
+
+/// A message to one recipient.
+#[derive(Clone, Debug)]
+pub struct Email {
+  pub to:      String,
+  pub subject: String,
+  /// The plain text body.
+  pub text:    String,
+  /// An HTML body, sent alongside the plain text for clients that show it.
+  pub html:    Option<String>,
+  /// Any other headers, like `List-Unsubscribe`.
+  pub headers: Vec<(String, String)>,
+}
+
+/// The address of a mailbox like `John Lewis <blog@jlewis.sh>`.
+fn address(mailbox: &str) -> &str {
+  match mailbox.rsplit_once('<') {
+    Some((_, address)) => address.trim_end().trim_end_matches('>'),
+    None => mailbox.trim(),
+  }
+}
+
+/// A header's value, kept to one line and encoded if it isn't ASCII.
+fn header_value(value: &str) -> String {
+  let value = value.replace(['\r', '\n'], " ");
+  match value.is_ascii() {
+    true => value,
+    false => format!("=?utf-8?B?{}?=", STANDARD.encode(value)),
+  }
+}
+
+/// A body in base64, so that it survives servers without `8BITMIME`.
+fn base64_body(body: &str) -> String {
+  let encoded =
+    STANDARD.encode(body.replace("\r\n", "\n").replace('\n', "\r\n"));
+  encoded
+    .as_bytes()
+    .chunks(BASE64_LINE_LEN)
+    .map(|line| String::from_utf8_lossy(line).into_owned())
+    .collect::<Vec<_>>()
+    .join("\r\n")
+}
+
+/// The message as it's sent after `DATA`, without the final `.`.
+fn message(smtp: &SmtpConfig, email: &Email) -> String {
+  let domain = address(&smtp.from)
+    .rsplit_once('@')
+    .map_or("localhost", |(_, domain)| domain);
+  let date = time::OffsetDateTime::now_utc()
+    .format(&time::format_description::well_known::Rfc2822)
+    .unwrap_or_default();
+  let mut headers = vec![
+    ("From".to_string(), smtp.from.clone()),
+    ("To".to_string(), email.to.clone()),
+    ("Subject".to_string(), email.subject.clone()),
+    ("Date".to_string(), date),
+    (
+      "Message-ID".to_string(),
+      format!("<{:032x}@{domain}>", rand::random::<u128>()),
+    ),
+    ("MIME-Version".to_string(), "1.0".to_string()),
+  ];
+  headers.extend(email.headers.iter().cloned());
+
+  let part = |content_type: &str, body: &str| {
+    format!(
+      "Content-Type: {content_type}; \
+       charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}\r\n",
+      base64_body(body)
+    )
+  };
+  let body = match &email.html {
+    None => part("text/plain", &email.text),
+    Some(html) => {
+      let boundary = format!("{:032x}", rand::random::<u128>());
+      let mut body = format!(
+        "Content-Type: multipart/alternative; boundary=\"{boundary}\"\r\n"
+      );
+      for part in [part("text/plain", &email.text), part("text/html", html)] {
+        body.push_str(&format!("\r\n--{boundary}\r\n{part}"));
+      }
+      body.push_str(&format!("\r\n--{boundary}--\r\n"));
+      body
+    }
+  };
+
+  let mut message = String::new();
+  for (name, value) in &headers {
+    message.push_str(&format!("{name}: {}\r\n", header_value(value)));
+  }
+  message.push_str(&body);
+  message
+}
+
+/// A connection to the SMTP server, plain or over TLS.
+struct Connection<S> {
+  stream: BufReader<S>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
+  fn new(stream: S) -> Self {
+    Connection {
+      stream: BufReader::new(stream),
+    }
+  }
+
+  /// Reads a reply, failing unless its code is in the same hundred as `ok`,
+  /// like any `2xx` for `250`.
+  async fn reply(&mut self, ok: u16) -> Result<(), String> {
+    let mut reply = String::new();
+    loop {
+      let mut line = String::new();
+      let read = self
+        .stream
+        .read_line(&mut line)
+        .await
+        .map_err(|e| e.to_string())?;
+      if read == 0 {
+        return Err("the server hung up".to_string());
+      }
+      reply.push_str(&line);
+      // every line but a reply's last has a dash after its code
+      if line.as_bytes().get(3) != Some(&b'-') {
+        break;
+      }
+    }
+    match reply.get(..3).and_then(|code| code.parse::<u16>().ok()) {
+      Some(code) if code / 100 == ok / 100 => Ok(()),
+      _ => Err(format!("answered `{}`", reply.trim_end())),
+    }
+  }
+
+  async fn write(&mut self, data: &str) -> Result<(), String> {
+    let stream = self.stream.get_mut();
+    stream
+      .write_all(data.as_bytes())
+      .await
+      .map_err(|e| e.to_string())?;
+    stream.flush().await.map_err(|e| e.to_string())
+  }
+
+  /// Sends a command, reading its reply as [`Connection::reply`] does.
+  async fn command(&mut self, command: &str, ok: u16) -> Result<(), String> {
+    self.write(&format!("{command}\r\n")).await?;
+    self.reply(ok).await
+  }
+
+  async fn hello(&mut self, smtp: &SmtpConfig) -> Result<(), String> {
+    let domain = address(&smtp.from)
+      .rsplit_once('@')
+      .map_or("localhost", |(_, domain)| domain);
+    self.command(&format!("EHLO {domain}"), 250).await
+  }
+
+  /// Logs in, if there's a username.
+  async fn log_in(&mut self, smtp: &SmtpConfig) -> Result<(), String> {
+    if let Some(username) = &smtp.username {
+      let password = smtp.password.as_deref().unwrap_or_default();
+      let credentials = STANDARD.encode(format!("\0{username}\0{password}"));
+      self
+        .command(&format!("AUTH PLAIN {credentials}"), 235)
+        .await
+        .map_err(|e| format!("couldn't log in: {e}"))?;
+    }
+    Ok(())
+  }
+
+  /// Logs in, then sends `email` and hangs up, or without one, just hangs
+  /// up.
+  async fn finish(
+    &mut self,
+    smtp: &SmtpConfig,
+    email: Option<&Email>,
+  ) -> Result<(), String> {
+    self.log_in(smtp).await?;
+    let Some(email) = email else {
+      return self.command("QUIT", 221).await;
+    };
+    self
+      .command(&format!("MAIL FROM:<{}>", address(&smtp.from)), 250)
+      .await?;
+    self
+      .command(&format!("RCPT TO:<{}>", address(&email.to)), 250)
+      .await?;
+    self.command("DATA", 354).await?;
+    // a line that's just a dot would end the message early
+    let message = message(smtp, email)
+      .split("\r\n")
+      .map(|line| match line.starts_with('.') {
+        true => format!(".{line}"),
+        false => line.to_string(),
+      })
+      .collect::<Vec<_>>()
+      .join("\r\n");
+    self.write(&message).await?;
+    self.command(".", 250).await?;
+    self.command("QUIT", 221).await
+  }
+}
+
+async fn tls(
+  smtp: &SmtpConfig,
+  stream: TcpStream,
+) -> Result<TlsStream<TcpStream>, String> {
+  let connector = native_tls::TlsConnector::new().map_err(|e| e.to_string())?;
+  tokio_native_tls::TlsConnector::from(connector)
+    .connect(&smtp.host, stream)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Greets the server, securing the connection as configured, and then
+/// finishes as [`Connection::finish`] does.
+async fn session(
+  smtp: &SmtpConfig,
+  email: Option<&Email>,
+) -> Result<(), String> {
+  let port = smtp.port.unwrap_or(smtp.security.default_port());
+  let stream = TcpStream::connect((smtp.host.as_str(), port))
+    .await
+    .map_err(|e| e.to_string())?;
+
+  match smtp.security {
+    SmtpSecurity::StartTls => {
+      let mut connection = Connection::new(stream);
+      connection.reply(220).await?;
+      connection.hello(smtp).await?;
+      connection.command("STARTTLS", 220).await?;
+      let stream = tls(smtp, connection.stream.into_inner()).await?;
+      let mut connection = Connection::new(stream);
+      connection.hello(smtp).await?;
+      connection.finish(smtp, email).await
+    }
+    SmtpSecurity::Tls => {
+      let mut connection = Connection::new(tls(smtp, stream).await?);
+      connection.reply(220).await?;
+      connection.hello(smtp).await?;
+      connection.finish(smtp, email).await
+    }
+    SmtpSecurity::None => {
+      let mut connection = Connection::new(stream);
+      connection.reply(220).await?;
+      connection.hello(smtp).await?;
+      connection.finish(smtp, email).await
+    }
+  }
+}
+
+/// Sends a message through the SMTP server, describing what went wrong if
+/// the server didn't take it.
+pub async fn send(smtp: &SmtpConfig, email: &Email) -> Result<(), String> {
+  tokio::time::timeout(SEND_TIMEOUT, session(smtp, Some(email)))
+    .await
+    .unwrap_or_else(|_| Err("timed out".to_string()))
+}
+
+/// Connects to the SMTP server, greets it and logs in as sending would, but
+/// hangs up without sending anything, describing what went wrong if that
+/// didn't work.
+pub async fn check(smtp: &SmtpConfig) -> Result<(), String> {
+  tokio::time::timeout(SEND_TIMEOUT, session(smtp, None))
+    .await
+    .unwrap_or_else(|_| Err("timed out".to_string()))
+}
@@ -0,0 +1,183 @@
+//! The admin area's moderation queue, under `/admin/comments`, listing the
+//! comments waiting to be shown under their posts. Each can be approved,
+//! marked as spam or deleted. Approving and marking as spam both train the
+//! classifier, see [`mark_comment`](crate::spam::mark_comment), while deleting
+//! a comment only gets rid of it, e.g. for one that's neither but shouldn't
+//! be shown.
+
+use leptos::*;
+use leptos_router::{use_query_map, ActionForm};
+use serde::{Deserialize, Serialize};
+
+use crate::error_template::{AppError, ErrorTemplate};
+
+/// A comment waiting in the moderation queue.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QueuedComment {
+  pub id:         i64,
+  pub post_path:  String,
+  /// The title of the post it's on, if the post still exists.
+  pub post_title: Option<String>,
+  pub author:     String,
+  pub body:       String,
+  pub spam_score: f64,
+  pub written_on: String,
+}
+
+/// What the author decides about a queued comment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Moderation {
+  Approve,
+  Spam,
+  Delete,
+}
+
+/// The comments waiting in the moderation queue, oldest first.
+#[server]
+pub async fn get_moderation_queue() -> Result<Vec<QueuedComment>, ServerFnError>
+{
+  use site_db::storage::{CommentStatus, CommentStore};
+
+  crate::auth::require_admin().await?;
+  let index = expect_context::<crate::post_index::PostIndex>();
+  let comments = crate::comments::use_database()?
+    .comments_with_status(CommentStatus::Pending)
+    .await
+    .map_err(|e| ServerFnError::new(e.to_string()))?;
+  Ok(
+    comments
+      .into_iter()
+      .map(|comment| QueuedComment {
+        post_title: index
+          .source(&comment.post_path)
+          .and_then(|input| crate::posts::try_parse_frontmatter(&input).ok())
+          .map(|(metadata, _)| metadata.title),
+        id:         comment.id,
+        post_path:  comment.post_path,
+        author:     comment.author,
+        body:       comment.body,
+        spam_score: comment.spam_score,
+        written_on: crate::comments::written_on(comment.created_at),
+      })
+      .collect(),
+  )
+}
+
+/// Approves, marks as spam or deletes a queued comment, going back to the
+/// queue. Only queued comments can be moderated, so that a form submitted
+/// twice doesn't delete a comment that's since been approved.
+#[server]
+pub async fn moderate_comment(
+  id: i64,
+  action: Moderation,
+) -> Result<(), ServerFnError> {
+  use site_db::storage::{CommentStatus, CommentStore};
+
+  crate::auth::require_admin().await?;
+  let db = crate::comments::use_database()?;
+  let db_error = |e: site_db::DbError| ServerFnError::new(e.to_string());
+
+  let queued = db
+    .comment(id)
+    .await
+    .map_err(db_error)?
+    .is_some_and(|comment| comment.status == CommentStatus::Pending);
+  let moderated = if !queued {
+    "gone"
+  } else {
+    match action {
+      Moderation::Approve => {
+        crate::spam::mark_comment(&db, id, false)
+          .await
+          .map_err(db_error)?;
+        "approved"
+      }
+      Moderation::Spam => {
+        crate::spam::mark_comment(&db, id, true)
+          .await
+          .map_err(db_error)?;
+        "spam"
+      }
+      Moderation::Delete => {
+        db.delete_comment(id).await.map_err(db_error)?;
+        "deleted"
+      }
+    }
+  };
+  log::info!("moderated comment #{id}: {moderated}");
+
+  leptos_axum::redirect(&format!("/admin/comments?moderated={moderated}"));
+  Ok(())
+}
+
+/// What the queue says about the last comment moderated.
+fn moderated_message(moderated: &str) -> Option<&'static str> {
+  Some(match moderated {
+    "approved" => "Approved, and shown under its post.",
+    "spam" => "Marked as spam.",
+    "deleted" => "Deleted.",
+    "gone" => "That comment isn't in the queue anymore.",
+    _ => return None,
+  })
+}
+
+/// The moderation queue.
+#[component]
+pub fn AdminComments() -> impl IntoView {
+  let moderated = use_query_map()
+    .get_untracked()
+    .get("moderated")
+    .and_then(|moderated| moderated_message(moderated));
+  let moderate = create_server_action::<ModerateComment>();
+  let queue_resource =
+    create_blocking_resource(|| (), |_| get_moderation_queue());
+
+  let comment_row = move |comment: QueuedComment| {
+    let button = |action: &'static str, label: &'static str| {
+      view! {
+        <button
+          type="submit" name="action" value=action
+          class="bg-neutral-700 px-4 py-1 hover:bg-neutral-600"
+        >{label}</button>
+      }
+    };
+    view! {
+      <li class="border-t border-neutral-600 py-2">
+        <p class="text-neutral-400">
+          {comment.author} " on "
+          <a class="text-periwinkle underline hover:no-underline" href=format!("/post/{}", comment.post_path)>
+            {comment.post_title.unwrap_or(comment.post_path)}
+          </a>
+          ", " {comment.written_on} ", scoring " {format!("{:.1}", comment.spam_score)}
+        </p>
+        <p class="whitespace-pre-line my-2">{comment.body}</p>
+        <ActionForm action=moderate class="flex gap-2">
+          <input type="hidden" name="id" value=comment.id />
+          {button("approve", "Approve")}
+          {button("spam", "Spam")}
+          {button("delete", "Delete")}
+        </ActionForm>
+      </li>
+    }
+  };
+
+  view! {
+    { moderated.map(|moderated| view! {
+      <div class="markdown">
+        <p>{moderated}</p>
+      </div>
+    }) }
+    <Suspense>
+      <ErrorBoundary fallback=|errors| view! { <ErrorTemplate errors /> }>
+        { move || queue_resource.get().map(|q| q.map_err(AppError::from).map(|queue| view! {
+          <div class="markdown">
+            <h2>"Moderation Queue"</h2>
+          </div>
+          { queue.is_empty().then(|| view! { <p class="text-neutral-400">"No comments are waiting."</p> }) }
+          <ul>{queue.into_iter().map(comment_row).collect_view()}</ul>
+        })) }
+      </ErrorBoundary>
+    </Suspense>
+  }
+}
@@ -0,0 +1,397 @@
+//! The newsletter, which readers subscribe to with their email address. It's
+//! double opt-in: signing up only sends a link to the address, and it's
+//! subscribed once the link is followed, so nobody can be signed up by
+//! someone else. The server emails each new post to the subscribers, as
+//! [`post_email`] has it, and every email from the newsletter links to a page
+//! for unsubscribing.
+//!
+//! The links are signed with the session secret, like every token the site
+//! hands out, see [`signing_key`](crate::auth::signing_key), so the signup is
+//! only offered with SMTP and the secret configured. It needn't have an admin
+//! area, and rotating other secrets, like GitHub's, leaves links in emails
+//! already sent working.
+
+use leptos::*;
+use leptos_router::{use_location, use_query_map, ActionForm};
+
+use crate::error_template::{AppError, ErrorTemplate};
+
+/// Where a confirmation link goes.
+pub const CONFIRM_PATH: &str = "/newsletter/confirm";
+/// Where an unsubscribe link goes.
+pub const UNSUBSCRIBE_PATH: &str = "/newsletter/unsubscribe";
+/// How long a confirmation link works for.
+#[cfg(feature = "ssr")]
+const CONFIRM_LINK_LENGTH: std::time::Duration =
+  std::time::Duration::from_secs(2 * 24 * 60 * 60);
+/// How long an unsubscribe link works for, which is long enough that the one
+/// in an old email still does.
+#[cfg(feature = "ssr")]
+const UNSUBSCRIBE_LINK_LENGTH: std::time::Duration =
+  std::time::Duration::from_secs(10 * 365 * 24 * 60 * 60);
+/// The longest email address there can be.
+const MAX_EMAIL_CHARS: usize = 254;
+
+/// Whether the signup is offered.
+fn is_offered() -> bool {
+  #[cfg(feature = "ssr")]
+  {
+    let config = crate::config::use_site_config();
+    config.smtp.is_some() && crate::auth::signing_key(&config).is_some()
+  }
+  #[cfg(not(feature = "ssr"))]
+  false
+}
+
+/// An email address as it's stored, or `None` if it doesn't look like one.
+/// It's only checked for what would make a mess of an email's headers, since
+/// the confirmation link tells whether it works.
+#[cfg(feature = "ssr")]
+pub(crate) fn normalize_email(email: &str) -> Option<String> {
+  let email = email.trim().to_lowercase();
+  let (local, domain) = email.split_once('@')?;
+  let is_valid = email.chars().count() <= MAX_EMAIL_CHARS
+    && !local.is_empty()
+    && !domain.contains('@')
+    && domain.contains('.')
+    && !domain.starts_with('.')
+    && !domain.ends_with('.')
+    && !email.chars().any(|c| {
+      c.is_whitespace() || c.is_control() || "<>,;:\"()[]\\".contains(c)
+    });
+  is_valid.then_some(email)
+}
+
+/// A link to `path` for the subscription of `email`, signed to let its
+/// holder `action` it.
+#[cfg(feature = "ssr")]
+fn signed_link(
+  key: &str,
+  path: &str,
+  action: &str,
+  email: &str,
+  length: std::time::Duration,
+) -> String {
+  use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+
+  let token = crate::auth::newsletter_token(key, action, email, length);
+  crate::config::site_config().url(&format!(
+    "{path}?email={}&token={token}",
+    utf8_percent_encode(email, NON_ALPHANUMERIC)
+  ))
+}
+
+/// The link for unsubscribing `email`, for the emails the newsletter sends.
+#[cfg(feature = "ssr")]
+pub fn unsubscribe_link(key: &str, email: &str) -> String {
+  signed_link(
+    key,
+    UNSUBSCRIBE_PATH,
+    "unsubscribe",
+    email,
+    UNSUBSCRIBE_LINK_LENGTH,
+  )
+}
+
+/// Goes to the newsletter's page, which says how `status` went.
+#[cfg(feature = "ssr")]
+fn back(status: &str) -> Result<(), ServerFnError> {
+  leptos_axum::redirect(&format!("/newsletter?status={status}"));
+  Ok(())
+}
+
+/// Sends a link for confirming the subscription to `email`. `website` is the
+/// field hidden from readers. Whether the address is already subscribed isn't
+/// let on, so the signup can't be used to find out.
+#[server]
+pub async fn subscribe(
+  email: String,
+  website: String,
+) -> Result<(), ServerFnError> {
+  let config = crate::config::use_site_config();
+  let Some(smtp) = config.smtp.as_ref() else {
+    return Err(ServerFnError::new(AppError::NotFound));
+  };
+  let key = crate::auth::use_signing_key()?;
+  if !website.is_empty() {
+    log::info!("dropped a bot's newsletter signup");
+    return back("sent");
+  }
+  let Some(email) = normalize_email(&email) else {
+    return back("invalid");
+  };
+
+  let link =
+    signed_link(&key, CONFIRM_PATH, "subscribe", &email, CONFIRM_LINK_LENGTH);
+  let message = crate::mail::Email {
+    to:      email,
+    subject: format!("Confirm your subscription to {}", config.title),
+    text:    format!(
+      "Someone, hopefully you, asked for new posts on {} to be sent to this \
+       address. To confirm, follow this link in the next two \
+       days:\n\n{link}\n\nIf it wasn't you, you can ignore this email, and \
+       you won't get any more.\n",
+      config.title
+    ),
+    html:    None,
+    headers: Vec::new(),
+  };
+  match crate::mail::send(smtp, &message).await {
+    Ok(()) => back("sent"),
+    Err(e) => {
+      log::error!("failed to send a newsletter confirmation: {e}");
+      back("failed")
+    }
+  }
+}
+
+/// Subscribes `email`, if `token` was sent to it to confirm.
+#[server]
+pub async fn confirm_subscription(
+  email: String,
+  token: String,
+) -> Result<(), ServerFnError> {
+  use site_db::storage::SubscriberStore;
+
+  let key = crate::auth::use_signing_key()?;
+  if !crate::auth::verify_newsletter_token(&key, "subscribe", &email, &token) {
+    return back("expired");
+  }
+  let added = crate::comments::use_database()?
+    .add_subscriber(&email)
+    .await
+    .map_err(|e| ServerFnError::new(e.to_string()))?;
+  if added {
+    log::info!("a newsletter subscription was confirmed");
+  }
+  back("confirmed")
+}
+
+/// Unsubscribes `email`, if `token` was sent to it to let it.
+#[server]
+pub async fn unsubscribe(
+  email: String,
+  token: String,
+) -> Result<(), ServerFnError> {
+  use site_db::storage::SubscriberStore;
+
+  let key = crate::auth::use_signing_key()?;
+  if !crate::auth::verify_newsletter_token(&key, "unsubscribe", &email, &token)
+  {
+    return back("expired");
+  }
+  let removed = crate::comments::use_database()?
+    .remove_subscriber(&email)
+    .await
+    .map_err(|e| ServerFnError::new(e.to_string()))?;
+  if removed {
+    log::info!("a newsletter subscription was ended");
+  }
+  back("unsubscribed")
+}
+
+/// What the newsletter's page says about how `status` went.
+fn status_message(status: &str) -> Option<&'static str> {
+  Some(match status {
+    "sent" => {
+      "Thanks! Check your email for a link to confirm your subscription."
+    }
+    "invalid" => "That doesn't look like an email address.",
+    "failed" => "The confirmation email couldn't be sent. Try again later?",
+    "confirmed" => "You're subscribed, and you'll get new posts by email.",
+    "unsubscribed" => "You're unsubscribed, and won't get any more email.",
+    "expired" => "That link has expired. You can sign up again below.",
+    _ => return None,
+  })
+}
+
+/// The form for signing up to the newsletter, unless it isn't offered.
+#[component]
+pub fn NewsletterSignup(#[prop(into)] intro: String) -> impl IntoView {
+  let subscribe = create_server_action::<Subscribe>();
+
+  is_offered().then(|| {
+    view! {
+      <section class="my-8">
+        <p>{intro}</p>
+        <ActionForm action=subscribe class="flex flex-wrap gap-2 mt-2">
+          // left empty by people, who can't see it
+          <div class="hidden" aria-hidden="true">
+            <input type="text" name="website" tabindex="-1" autocomplete="off" />
+          </div>
+          <input
+            type="email" name="email" required maxlength=MAX_EMAIL_CHARS.to_string()
+            placeholder="you@example.com" aria-label="Email address" autocomplete="email"
+            class="bg-neutral-700 px-2 py-1 flex-1 min-w-0"
+          />
+          <button type="submit" class="bg-neutral-700 px-4 py-1 hover:bg-neutral-600">"Subscribe"</button>
+        </ActionForm>
+      </section>
+    }
+  })
+}
+
+/// The signup at the bottom of each page, except the ones with their own and
+/// the admin area's.
+#[component]
+pub fn NewsletterFooter() -> impl IntoView {
+  let path = use_location().pathname.get_untracked();
+  let is_shown = !["/post/", "/newsletter", "/admin", "/preview/"]
+    .iter()
+    .any(|prefix| path.starts_with(prefix));
+  is_shown.then(|| {
+    view! {
+      <footer class="border-t-2 border-neutral-400/50 mt-8">
+        <NewsletterSignup intro="Get new posts by email." />
+      </footer>
+    }
+  })
+}
+
+/// The newsletter's page, which says how signing up, confirming or
+/// unsubscribing went.
+#[component]
+pub fn NewsletterPage() -> impl IntoView {
+  if !is_offered() {
+    let mut outside_errors = Errors::default();
+    outside_errors.insert_with_default_key(AppError::NotFound);
+    return view! { <ErrorTemplate outside_errors /> }.into_view();
+  }
+  let status = use_query_map()
+    .get_untracked()
+    .get("status")
+    .and_then(|status| status_message(status));
+
+  view! {
+    <div class="markdown">
+      <h2>"Newsletter"</h2>
+      { status.map(|status| view! { <p>{status}</p> }) }
+    </div>
+    <NewsletterSignup intro="Get new posts by email, and nothing else." />
+  }
+  .into_view()
+}
+
+/// The address and token of a link from an email, for the page it goes to.
+fn link_query() -> (String, String) {
+  let query = use_query_map().get_untracked();
+  let param = |name: &str| query.get(name).cloned().unwrap_or_default();
+  (param("email"), param("token"))
+}
+
+/// Asks for the subscription a confirmation link is for to be confirmed. It
+/// takes a click, so that mail scanners following the link don't confirm it
+/// for the reader.
+#[component]
+pub fn NewsletterConfirmPage() -> impl IntoView {
+  let confirm = create_server_action::<ConfirmSubscription>();
+  let (email, token) = link_query();
+
+  view! {
+    <div class="markdown">
+      <h2>"Confirm your subscription"</h2>
+      <p>"New posts will be sent to " {email.clone()} "."</p>
+    </div>
+    <ActionForm action=confirm>
+      <input type="hidden" name="email" value=email />
+      <input type="hidden" name="token" value=token />
+      <button type="submit" class="bg-neutral-700 px-4 py-1 hover:bg-neutral-600">"Confirm"</button>
+    </ActionForm>
+  }
+}
+
+/// Asks for the subscription an unsubscribe link is for to be ended.
+#[component]
+pub fn NewsletterUnsubscribePage() -> impl IntoView {
+  let unsubscribe = create_server_action::<Unsubscribe>();
+  let (email, token) = link_query();
+
+  view! {
+    <div class="markdown">
+      <h2>"Unsubscribe"</h2>
+      <p>"New posts won't be sent to " {email.clone()} " anymore."</p>
+    </div>
+    <ActionForm action=unsubscribe>
+      <input type="hidden" name="email" value=email />
+      <input type="hidden" name="token" value=token />
+      <button type="submit" class="bg-neutral-700 px-4 py-1 hover:bg-neutral-600">"Unsubscribe"</button>
+    </ActionForm>
+  }
+}
+
+/// A post's HTML as it's sent by email. Links and images on the site are
+/// made absolute, and embedded components, which can't run in an email, are
+/// swapped for a link to the post.
+#[cfg(feature = "ssr")]
+fn email_html(post: &crate::posts::Post, post_url: &str) -> String {
+  use site_markdown::embeds::{MARKER_CLOSE, MARKER_OPEN};
+
+  let mut html = String::new();
+  let mut rest = post.html_content.as_str();
+  while let Some(start) = rest.find(MARKER_OPEN) {
+    html.push_str(&rest[..start]);
+    rest = &rest[start + MARKER_OPEN.len()..];
+    rest = rest
+      .find(MARKER_CLOSE)
+      .map_or("", |end| &rest[end + MARKER_CLOSE.len()..]);
+    html.push_str(&format!(
+      "<p><a href=\"{post_url}\">This part of the post is interactive, so \
+       it's only on the site.</a></p>"
+    ));
+  }
+  html.push_str(rest);
+
+  let base_url = crate::config::site_config().url("");
+  html
+    .replace("href=\"#", &format!("href=\"{post_url}#"))
+    .replace("href=\"/", &format!("href=\"{base_url}"))
+    .replace("src=\"/", &format!("src=\"{base_url}"))
+}
+
+/// The email sending a new post to `email`, with the link for unsubscribing
+/// it.
+#[cfg(feature = "ssr")]
+pub fn post_email(
+  key: &str,
+  post: &crate::posts::Post,
+  email: &str,
+) -> crate::mail::Email {
+  use crate::activitypub::escape_html;
+
+  let config = crate::config::site_config();
+  let post_url = config.url(&format!("/post/{}", post.path));
+  let unsubscribe = unsubscribe_link(key, email);
+  let title = &post.metadata.title;
+
+  let text = format!(
+    "{title}\n{}\n\n{}\n\n{post_url}\n\n-- \nYou're getting this because you \
+     subscribed to {}. To unsubscribe, follow this link:\n{unsubscribe}\n",
+    post.metadata.written_on,
+    post.plaintext.trim(),
+    config.title,
+  );
+  // code is highlighted with classes, which the light theme's stylesheet
+  // colours, since emails are usually shown light
+  let html = format!(
+    "<!DOCTYPE html><html><head><style>{}</style></head><body \
+     style=\"font-family: sans-serif; max-width: 40rem; margin: auto; \
+     line-height: 1.5\"><h1><a href=\"{}\">{}</a></h1><p>{}</p>{}<hr><p \
+     style=\"font-size: small\">You're getting this because you subscribed to \
+     {}. <a href=\"{}\">Unsubscribe</a>.</p></body></html>",
+    crate::theme::ColorTheme::Light.code_css(),
+    escape_html(&post_url),
+    escape_html(title),
+    escape_html(&post.metadata.written_on),
+    email_html(post, &escape_html(&post_url)),
+    escape_html(&config.title),
+    escape_html(&unsubscribe),
+  );
+
+  crate::mail::Email {
+    to: email.to_string(),
+    subject: title.clone(),
+    text,
+    html: Some(html),
+    headers: vec![("List-Unsubscribe".to_string(), format!("<{unsubscribe}>"))],
+  }
+}
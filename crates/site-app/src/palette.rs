@@ -0,0 +1,258 @@
+//! A command palette for getting around the site from the keyboard, opened
+//! with ⌘K or Ctrl-K. It searches the posts like the search box does, and
+//! lists the site's pages and a few things to do on the current one.
+
+use leptos::*;
+use serde::{Deserialize, Serialize};
+
+use crate::search::{highlighted, instant_results, lazy_index, InstantResult};
+
+/// One of the site's pages, as the palette lists it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PalettePage {
+  pub label: String,
+  pub href:  String,
+}
+
+/// The pages the palette lists, which depend on what's turned on.
+fn pages() -> Vec<PalettePage> {
+  let page = |label: &str, href: &str| PalettePage {
+    label: label.to_string(),
+    href:  href.to_string(),
+  };
+  let mut pages = vec![
+    page("Home", "/"),
+    page("Search", crate::search::SEARCH_PATH),
+  ];
+  if crate::views::is_shown() {
+    pages.push(page("Most read posts", crate::views::POPULAR_PATH));
+  }
+  if crate::contact::is_offered() {
+    pages.push(page("Contact", crate::contact::CONTACT_PATH));
+  }
+  pages.push(page("Newsletter", "/newsletter"));
+  pages
+}
+
+/// Something the palette can do.
+#[derive(Clone, Debug, PartialEq)]
+enum Command {
+  Page(PalettePage),
+  Post(InstantResult),
+  BackToTop,
+  CopyLink,
+}
+
+impl Command {
+  /// What the command is called, to match against what's typed.
+  fn label(&self) -> &str {
+    match self {
+      Command::Page(page) => &page.label,
+      Command::Post(_) => "",
+      Command::BackToTop => "Back to top",
+      Command::CopyLink => "Copy link to this page",
+    }
+  }
+
+  /// What kind of command it is, shown beside it.
+  fn kind(&self) -> &'static str {
+    match self {
+      Command::Page(_) => "Page",
+      Command::Post(_) => "Post",
+      Command::BackToTop | Command::CopyLink => "Action",
+    }
+  }
+
+  fn run(&self) {
+    let location = window().location();
+    match self {
+      Command::Page(page) => {
+        let _ = location.set_href(&page.href);
+      }
+      Command::Post(post) => {
+        let _ = location.set_href(&format!("/post/{}", post.path));
+      }
+      Command::BackToTop => window().scroll_to_with_x_and_y(0.0, 0.0),
+      #[cfg(feature = "hydrate")]
+      Command::CopyLink => {
+        if let Ok(href) = location.href() {
+          let _ = crate::posts::write_clipboard_text(&href);
+        }
+      }
+      #[cfg(not(feature = "hydrate"))]
+      Command::CopyLink => {}
+    }
+  }
+}
+
+/// Whether every word of `query` starts a word of `label`.
+fn label_matches(label: &str, query: &str) -> bool {
+  let label = label.to_lowercase();
+  let words = label
+    .split(|c: char| !c.is_alphanumeric())
+    .collect::<Vec<_>>();
+  query
+    .to_lowercase()
+    .split_whitespace()
+    .all(|term| words.iter().any(|word| word.starts_with(term)))
+}
+
+/// The command palette, which is hidden until it's opened.
+#[component]
+pub fn CommandPalette() -> impl IntoView {
+  view! { <Palette pages=pages() /> }
+}
+
+#[crate::lazy_island]
+fn Palette(pages: Vec<PalettePage>) -> impl IntoView {
+  let pages = store_value(pages);
+  let (open, set_open) = create_signal(false);
+  let (query, set_query) = create_signal(String::new());
+  // which command is chosen with the arrow keys
+  let (active, set_active) = create_signal(0_usize);
+  let (index, load_index) = lazy_index();
+  let input_ref = create_node_ref::<html::Input>();
+
+  let commands = create_memo(move |_| {
+    let query = query();
+    let mut commands = pages
+      .get_value()
+      .into_iter()
+      .map(Command::Page)
+      .chain([Command::BackToTop, Command::CopyLink])
+      .filter(|command| label_matches(command.label(), &query))
+      .collect::<Vec<_>>();
+    // posts only once something's typed, and before everything else
+    if !query.trim().is_empty() {
+      let posts = index.with(|index| {
+        index
+          .as_deref()
+          .map(|index| instant_results(index, &query))
+          .unwrap_or_default()
+      });
+      commands.splice(0..0, posts.into_iter().map(Command::Post));
+    }
+    commands
+  });
+
+  let close = move || {
+    set_open(false);
+    set_query(String::new());
+    set_active(0);
+  };
+  let run = move |command: &Command| {
+    close();
+    command.run();
+  };
+
+  #[cfg(feature = "hydrate")]
+  {
+    let handle = window_event_listener(ev::keydown, move |ev| {
+      if ev.key().eq_ignore_ascii_case("k") && (ev.meta_key() || ev.ctrl_key())
+      {
+        ev.prevent_default();
+        match open.get_untracked() {
+          true => close(),
+          false => {
+            load_index();
+            set_open(true);
+          }
+        }
+      }
+    });
+    on_cleanup(move || handle.remove());
+
+    // once it's shown, since hidden inputs can't be focused
+    create_effect(move |_| {
+      if open() {
+        request_animation_frame(move || {
+          if let Some(input) = input_ref.get_untracked() {
+            let _ = input.focus();
+          }
+        });
+      }
+    });
+  }
+  #[cfg(not(feature = "hydrate"))]
+  let _ = (load_index, input_ref);
+
+  let on_keydown = move |ev: ev::KeyboardEvent| {
+    let count = commands.with_untracked(Vec::len);
+    match ev.key().as_str() {
+      "ArrowDown" if count > 0 => {
+        ev.prevent_default();
+        set_active((active.get_untracked() + 1) % count);
+      }
+      "ArrowUp" if count > 0 => {
+        ev.prevent_default();
+        set_active((active.get_untracked() + count - 1) % count);
+      }
+      "Enter" => {
+        ev.prevent_default();
+        let command = commands.with_untracked(|commands| {
+          commands.get(active.get_untracked()).cloned()
+        });
+        if let Some(command) = command {
+          run(&command);
+        }
+      }
+      "Escape" => close(),
+      _ => {}
+    }
+  };
+
+  let command_item = move |(i, command): (usize, Command)| {
+    let is_active = move || active() == i;
+    let kind = command.kind();
+    let label = match &command {
+      Command::Post(post) => highlighted(post.title.clone()).into_view(),
+      command => command.label().to_string().into_view(),
+    };
+    view! {
+      <li
+        id=format!("palette-command-{i}") role="option"
+        aria-selected=move || is_active().to_string()
+        class="flex gap-2 px-3 py-1 cursor-pointer" class:bg-neutral-700=is_active
+        on:mousemove=move |_| set_active(i)
+        on:click=move |_| run(&command)
+      >
+        <span class="flex-1 truncate">{label}</span>
+        <span class="text-sm text-neutral-400">{kind}</span>
+      </li>
+    }
+  };
+
+  view! {
+    <div
+      class="fixed inset-0 z-50 flex items-start justify-center pt-24 px-4 bg-black/60"
+      class:hidden=move || !open()
+      on:click=move |_| close()
+    >
+      <div
+        role="dialog" aria-modal="true" aria-label="Command palette"
+        class="w-full max-w-lg bg-neutral-800 border border-neutral-600 rounded"
+        on:click=|ev| ev.stop_propagation()
+      >
+        <input
+          type="text" node_ref=input_ref placeholder="Search posts or jump to…"
+          aria-label="Command" role="combobox" aria-expanded="true"
+          aria-controls="palette-commands" autocomplete="off"
+          aria-activedescendant=move || format!("palette-command-{}", active())
+          class="w-full bg-neutral-700 px-3 py-2"
+          prop:value=query
+          on:input=move |ev| {
+            set_query(event_target_value(&ev));
+            set_active(0);
+          }
+          on:keydown=on_keydown
+        />
+        <ul id="palette-commands" role="listbox" aria-label="Commands" class="max-h-80 overflow-y-auto py-1">
+          { move || commands().into_iter().enumerate().map(command_item).collect_view() }
+        </ul>
+        <p class="px-3 py-1 text-sm text-neutral-400 border-t border-neutral-600">
+          "↑↓ to choose, enter to go, esc to close"
+        </p>
+      </div>
+    </div>
+  }
+}
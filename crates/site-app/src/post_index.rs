@@ -0,0 +1,293 @@
+//! An in-memory index of the posts, read and rendered once at startup and
+//! then kept up to date by the content watcher, so that serving a page
+//! doesn't read or render every post.
+//!
+//! A post is rendered again when its file changes. Every post is rendered
+//! again when a post appears, disappears or is retitled, since posts render
+//! links to each other with their titles, and when a link preview is
+//! fetched, since any post might show it. Each rendered post is also indexed
+//! for [`search`](crate::search).
+
+use std::{
+  collections::HashMap,
+  sync::{Arc, RwLock},
+  time::Instant,
+};
+
+use crate::{
+  posts::{
+    extract_post, link_context, post_file, read_post_sources,
+    try_parse_frontmatter, Post, PostMetadata,
+  },
+  search::{IndexedPost, SearchIndex, SearchResult},
+};
+
+/// A change to a post's file, found by [`PostIndex::refresh`].
+#[derive(Clone, Debug)]
+pub enum SourceChange {
+  /// A post appeared.
+  Added {
+    path:     String,
+    /// The post's metadata, if its frontmatter is valid.
+    metadata: Option<PostMetadata>,
+  },
+  /// A post's file changed.
+  Updated { path: String },
+  /// A post's file was removed.
+  Removed { path: String },
+}
+
+#[derive(Default)]
+struct IndexState {
+  /// Every post's path and raw file contents, in path order.
+  sources:             Vec<(String, String)>,
+  /// Every post, rendered, keyed by path, or what's wrong with its file.
+  posts:               HashMap<String, Result<Post, String>>,
+  /// The link previews generation the posts are rendered with.
+  previews_generation: u64,
+  /// Every post which rendered, indexed for searching.
+  search:              SearchIndex,
+}
+
+impl IndexState {
+  /// Renders every post, spread across a thread per core since highlighting
+  /// is slow.
+  fn render_all(&mut self) {
+    let _span =
+      tracing::debug_span!("render_all", posts = self.sources.len()).entered();
+    let started = Instant::now();
+    self.previews_generation = crate::previews::generation();
+    let context = &link_context(&self.sources);
+
+    let threads = std::thread::available_parallelism().map_or(1, usize::from);
+    let chunk_size = self.sources.len().div_ceil(threads).max(1);
+    self.posts = std::thread::scope(|scope| {
+      let renders = self
+        .sources
+        .chunks(chunk_size)
+        .map(|chunk| {
+          scope.spawn(move || {
+            chunk
+              .iter()
+              .map(|(path, input)| {
+                (path.clone(), extract_post(path, input, context))
+              })
+              .collect::<Vec<_>>()
+          })
+        })
+        .collect::<Vec<_>>();
+      renders
+        .into_iter()
+        .flat_map(|render| render.join().expect("rendering a post panicked"))
+        .collect()
+    });
+    self.search.replace_all(
+      self
+        .posts
+        .iter()
+        .filter_map(|(path, post)| Some((path, post.as_ref().ok()?))),
+    );
+
+    log::info!(
+      "rendered {} posts in {:.2?}",
+      self.posts.len(),
+      started.elapsed()
+    );
+  }
+
+  fn titles(&self) -> HashMap<&str, &str> {
+    self
+      .posts
+      .iter()
+      .filter_map(|(path, post)| {
+        Some((path.as_str(), post.as_ref().ok()?.metadata.title.as_str()))
+      })
+      .collect()
+  }
+}
+
+/// Warns if the post at `path` is in a file whose name isn't its path, since
+/// links to the file name won't work.
+fn warn_if_renamed(path: &str) {
+  let Some(file) = post_file(path) else {
+    return;
+  };
+  if file.file_stem().is_some_and(|stem| stem != path) {
+    log::warn!(
+      "`{}` is served at `/post/{path}`; name it `{path}.md` to match",
+      file.display()
+    );
+  }
+}
+
+/// The posts, shared between requests. Cloning it gives another handle to the
+/// same index.
+#[derive(Clone, Default)]
+pub struct PostIndex {
+  state: Arc<RwLock<IndexState>>,
+}
+
+impl PostIndex {
+  /// Reads and renders every post.
+  pub fn load() -> Self {
+    let index = PostIndex::default();
+    index.refresh();
+    index
+  }
+
+  /// Indexes the given posts, as paths and raw file contents, rather than the
+  /// posts on disk. [`refresh`](PostIndex::refresh) still reads from disk.
+  pub fn from_sources(mut sources: Vec<(String, String)>) -> Self {
+    sources.sort();
+    let mut state = IndexState {
+      sources,
+      ..Default::default()
+    };
+    state.render_all();
+    PostIndex {
+      state: Arc::new(RwLock::new(state)),
+    }
+  }
+
+  /// Reads the posts again, rendering the ones which changed, and returns
+  /// what changed.
+  pub fn refresh(&self) -> Vec<SourceChange> {
+    let _span = tracing::trace_span!("refresh_index").entered();
+    let mut sources = read_post_sources();
+    sources.sort();
+
+    let mut state = self.state.write().unwrap();
+    let old_sources = state
+      .sources
+      .iter()
+      .map(|(path, input)| (path.as_str(), input.as_str()))
+      .collect::<HashMap<_, _>>();
+    let mut changes = Vec::new();
+    for (path, input) in sources.iter() {
+      match old_sources.get(path.as_str()) {
+        Some(old) if old == input => {}
+        Some(_) => changes.push(SourceChange::Updated { path: path.clone() }),
+        None => {
+          warn_if_renamed(path);
+          changes.push(SourceChange::Added {
+            path:     path.clone(),
+            metadata: try_parse_frontmatter(input).ok().map(|(m, _)| m),
+          });
+        }
+      }
+    }
+    for path in old_sources.keys() {
+      if !sources.iter().any(|(p, _)| p == path) {
+        changes.push(SourceChange::Removed {
+          path: path.to_string(),
+        });
+      }
+    }
+    if changes.is_empty() {
+      return changes;
+    }
+
+    let old_titles = state
+      .titles()
+      .into_iter()
+      .map(|(path, title)| (path.to_string(), title.to_string()))
+      .collect::<HashMap<_, _>>();
+    state.sources = sources;
+    let context = link_context(&state.sources);
+    // only the posts linking to a retitled post miss the render cache
+    if context.post_titles != old_titles {
+      state.render_all();
+      return changes;
+    }
+
+    for change in changes.iter() {
+      let SourceChange::Updated { path } = change else {
+        continue;
+      };
+      let state = &mut *state;
+      if let Some((_, input)) = state.sources.iter().find(|(p, _)| p == path) {
+        let post = extract_post(path, input, &context);
+        match &post {
+          Ok(post) => state.search.insert(path, post),
+          Err(_) => state.search.remove(path),
+        }
+        state.posts.insert(path.clone(), post);
+      }
+    }
+    changes
+  }
+
+  /// Calls `f` with the index, first rendering every post again if a link
+  /// preview has been fetched since they were rendered. Only the posts showing
+  /// it miss the render cache.
+  fn with_state<T>(&self, f: impl FnOnce(&IndexState) -> T) -> T {
+    let generation = crate::previews::generation();
+    if self.state.read().unwrap().previews_generation != generation {
+      self.state.write().unwrap().render_all();
+    }
+    f(&self.state.read().unwrap())
+  }
+
+  /// A post, whether it's public or not, or what's wrong with its file. This
+  /// may render every post, so call it from blocking code.
+  pub fn post(&self, path: &str) -> Option<Result<Post, String>> {
+    self.with_state(|state| state.posts.get(path).cloned())
+  }
+
+  /// The raw file contents of a post. Unlike the rendered posts, this never
+  /// renders anything, so it's cheap to call from async code.
+  pub fn source(&self, path: &str) -> Option<String> {
+    self
+      .state
+      .read()
+      .unwrap()
+      .sources
+      .iter()
+      .find(|(p, _)| p == path)
+      .map(|(_, input)| input.clone())
+  }
+
+  /// Whether there's a public post at `path`, which is as cheap to find out
+  /// as [`source`](Self::source).
+  pub fn is_public(&self, path: &str) -> bool {
+    self
+      .source(path)
+      .and_then(|input| try_parse_frontmatter(&input).ok())
+      .is_some_and(|(metadata, _)| metadata.public)
+  }
+
+  /// Every post's path and raw file contents.
+  pub fn sources(&self) -> Vec<(String, String)> {
+    self.state.read().unwrap().sources.clone()
+  }
+
+  /// Every public post, newest first. This may render every post, so call it
+  /// from blocking code.
+  pub fn public_posts(&self) -> Vec<Post> {
+    let mut posts = self.with_state(|state| {
+      state
+        .posts
+        .values()
+        .filter_map(|post| post.as_ref().ok())
+        .filter(|post| post.metadata.public)
+        .cloned()
+        .collect::<Vec<_>>()
+    });
+    posts.sort_by(|a, b| {
+      (&b.metadata.written_on, &b.path).cmp(&(&a.metadata.written_on, &a.path))
+    });
+    posts
+  }
+
+  /// The public posts matching `query`, best first. This may render every
+  /// post, so call it from blocking code.
+  pub fn search(&self, query: &str) -> Vec<SearchResult> {
+    self.with_state(|state| state.search.search(query))
+  }
+
+  /// Every public post's words, for the search box to search as you type.
+  /// This may render every post, so call it from blocking code.
+  pub fn search_index(&self) -> Vec<IndexedPost> {
+    self.with_state(|state| state.search.indexed_posts())
+  }
+}
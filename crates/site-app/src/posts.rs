@@ -4,22 +4,62 @@ use std::io::Read;
 #[cfg(feature = "ssr")]
 use gray_matter::{engine::YAML, Matter};
 use leptos::*;
-use leptos_meta::Title;
-use leptos_router::use_params_map;
+use leptos_meta::{Meta, Title};
+use leptos_router::{use_location, use_params_map};
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "ssr")]
+use site_markdown::{LinkContext, RenderedPost};
+
+use crate::{
+  embeds::{render_embedded_component, MARKER_CLOSE, MARKER_OPEN},
+  error_template::{AppError, ErrorTemplate},
+  toc::{TableOfContents, TocEntry},
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Post {
-  pub html_content: String,
-  pub path:         String,
-  pub metadata:     PostMetadata,
+  pub html_content:  String,
+  /// The post's text content without markup, for consumers that don't want
+  /// HTML. Only available on the server.
+  #[serde(skip)]
+  pub plaintext:     String,
+  pub path:          String,
+  pub metadata:      PostMetadata,
+  /// The post's headings, for its table of contents.
+  pub toc:           Vec<TocEntry>,
+  /// The path of the post a reader is most likely to read next, if known.
+  pub likely_next:   Option<String>,
+  /// When the previous published revision of the post was committed, if it's
+  /// been changed since.
+  pub changed_since: Option<String>,
 }
 
 impl Post {
   pub fn full_post(&self) -> impl IntoView {
-    leptos::leptos_dom::html::div()
-      .attr("class", "markdown")
-      .inner_html(self.html_content.clone())
+    let html_section = |html: &str| {
+      leptos::leptos_dom::html::div()
+        .attr("class", "markdown")
+        .inner_html(html.to_string())
+        .into_view()
+    };
+
+    // split the post around embedded component markers
+    let mut sections = Vec::new();
+    let mut rest = self.html_content.as_str();
+    while let Some(start) = rest.find(MARKER_OPEN) {
+      let name_start = start + MARKER_OPEN.len();
+      let Some(len) = rest[name_start..].find(MARKER_CLOSE) else {
+        break;
+      };
+      let name = &rest[name_start..name_start + len];
+
+      sections.push(html_section(&rest[..start]));
+      sections.extend(render_embedded_component(name));
+      rest = &rest[name_start + len + MARKER_CLOSE.len()..];
+    }
+    sections.push(html_section(rest));
+
+    sections.collect_view()
   }
 }
 
@@ -28,93 +68,781 @@ pub struct PostMetadata {
   pub title:      String,
   pub written_on: String,
   pub public:     bool,
+  /// What the post is about, which searches match as well as its title.
+  #[serde(default)]
+  pub tags:       Vec<String>,
+  /// The markdown extensions the post is rendered with. These are given at the
+  /// top level of the frontmatter. `sanitize: true` can only add a post to
+  /// the ones the [`SanitizeConfig`](crate::config::SanitizeConfig) sanitizes,
+  /// see [`markdown_options`].
+  #[serde(flatten)]
+  pub markdown:   site_markdown::MarkdownOptions,
+}
+
+/// The directory that post markdown files are read from, from the
+/// [`SiteConfig`](crate::config::SiteConfig).
+#[cfg(feature = "ssr")]
+pub fn posts_dir() -> std::path::PathBuf {
+  crate::config::site_config().posts_dir()
 }
 
+/// Splits a post file into its metadata and its markdown body, describing
+/// what's wrong if the frontmatter is missing or malformed.
 #[cfg(feature = "ssr")]
-pub fn extract_post(path: &str, input: &str) -> Post {
+pub fn try_parse_frontmatter(
+  input: &str,
+) -> Result<(PostMetadata, String), String> {
   let matter = Matter::<YAML>::new().parse(input);
-  let metadata = matter.data.unwrap();
-
-  Post {
-    html_content: crate::markdown::markdown_to_html(&matter.content),
-    path:         path.to_string(),
-    metadata:     PostMetadata {
-      title:      metadata["title"].as_string().unwrap(),
-      written_on: metadata["written_on"].as_string().unwrap(),
-      public:     metadata["public"].as_bool().unwrap(),
-    },
-  }
+  let metadata = matter
+    .data
+    .ok_or_else(|| "missing frontmatter".to_string())?
+    .deserialize()
+    .map_err(|e| format!("invalid frontmatter: {e}"))?;
+
+  Ok((metadata, matter.content))
 }
 
-#[server]
-pub async fn get_all_posts() -> Result<Vec<Post>, ServerFnError> {
-  let mut posts = Vec::new();
+/// The markdown options the post at `path` is rendered with: its own, but
+/// sanitized if the site's configuration says so, whatever its frontmatter
+/// says.
+#[cfg(feature = "ssr")]
+pub fn markdown_options(
+  path: &str,
+  metadata: &PostMetadata,
+) -> site_markdown::MarkdownOptions {
+  let mut options = metadata.markdown.clone();
+  options.sanitize |= crate::config::site_config().sanitize.applies_to(path);
+  options
+}
 
-  for entry in std::fs::read_dir("./content/posts").unwrap() {
+/// Renders the markdown body of the post at `path` through the pipeline,
+/// unless an unchanged render is cached.
+#[cfg(feature = "ssr")]
+pub fn render_post(
+  path: &str,
+  content: &str,
+  metadata: &PostMetadata,
+  context: &LinkContext,
+) -> RenderedPost {
+  crate::render_cache::render(content, &site_markdown::Options {
+    links:      context,
+    components: crate::embeds::EMBEDDABLE_COMPONENTS,
+    markdown:   &markdown_options(path, metadata),
+  })
+}
+
+/// Reads and renders a post, describing what's wrong if its frontmatter is
+/// missing or malformed.
+#[cfg(feature = "ssr")]
+pub fn extract_post(
+  path: &str,
+  input: &str,
+  context: &LinkContext,
+) -> Result<Post, String> {
+  let _span = tracing::debug_span!("render_post", path).entered();
+  let (metadata, content) = try_parse_frontmatter(input)?;
+  let rendered = render_post(path, &content, &metadata, context);
+
+  Ok(Post {
+    html_content: rendered.html,
+    plaintext: rendered.plaintext,
+    path: path.to_string(),
+    metadata,
+    toc: rendered.toc,
+    likely_next: None,
+    changed_since: None,
+  })
+}
+
+/// The path a post is served at, from its file name: lowercase ASCII, with
+/// runs of anything but letters and digits made into single dashes, so that
+/// `Über Caching.md` is served at `/post/uber-caching`.
+#[cfg(feature = "ssr")]
+pub fn slug_of(file_stem: &str) -> String { slug::slugify(file_stem) }
+
+/// Every post file in [`posts_dir`], alongside the path its post is served
+/// at. Several files may have the same path, in which case the one named
+/// exactly that comes first.
+#[cfg(feature = "ssr")]
+pub fn post_files() -> Vec<(String, std::path::PathBuf)> {
+  let mut files = Vec::new();
+
+  for entry in std::fs::read_dir(posts_dir()).unwrap() {
     let entry = entry.unwrap();
     let path = entry.path();
 
-    if path.is_file() {
-      let mut file = std::fs::File::open(&path).expect("failed to open file");
-      let mut input = String::new();
-      file
-        .read_to_string(&mut input)
-        .expect("failed to read file");
-
-      posts.push(extract_post(
-        path.file_stem().unwrap().to_str().unwrap(),
-        &input,
-      ));
+    // skip editor swap files and any other hidden or non-markdown files
+    let is_hidden = path
+      .file_name()
+      .and_then(|name| name.to_str())
+      .map_or(true, |name| name.starts_with('.'));
+    let is_markdown = path.extension().is_some_and(|ext| ext == "md");
+
+    if path.is_file() && is_markdown && !is_hidden {
+      let slug = slug_of(path.file_stem().unwrap().to_str().unwrap());
+      files.push((slug, path));
+    }
+  }
+
+  // files already named for their path come first, so that they're the ones
+  // served when others have the same path
+  files.sort_by_key(|(slug, file)| {
+    (
+      file.file_stem().is_some_and(|stem| stem != slug.as_str()),
+      file.clone(),
+    )
+  });
+  files
+}
+
+/// The file of the post served at `path`, if there is one.
+#[cfg(feature = "ssr")]
+pub fn post_file(path: &str) -> Option<std::path::PathBuf> {
+  // most file names are already their post's path
+  let file = posts_dir().join(format!("{path}.md"));
+  if file.is_file() {
+    return Some(file);
+  }
+  post_files()
+    .into_iter()
+    .find(|(slug, _)| slug == path)
+    .map(|(_, file)| file)
+}
+
+/// Reads every post file in [`posts_dir`], returning each post's path
+/// alongside the raw file contents. Files whose name has no letters or digits,
+/// or whose path another file already has, are skipped.
+#[cfg(feature = "ssr")]
+pub fn read_post_sources() -> Vec<(String, String)> {
+  let mut sources = Vec::<(String, String)>::new();
+
+  for (path, file) in post_files() {
+    if path.is_empty() {
+      log::warn!("skipping `{}`, which has no path", file.display());
+      continue;
+    }
+    if sources.iter().any(|(p, _)| *p == path) {
+      log::warn!(
+        "skipping `{}`, since another post is already at `/post/{path}`",
+        file.display()
+      );
+      continue;
     }
+
+    let mut file = std::fs::File::open(&file).expect("failed to open file");
+    let mut input = String::new();
+    file
+      .read_to_string(&mut input)
+      .expect("failed to read file");
+
+    sources.push((path, input));
+  }
+
+  sources
+}
+
+/// Builds the context that posts' links are resolved against. Posts which
+/// can't be read can't be linked to.
+#[cfg(feature = "ssr")]
+pub fn link_context(sources: &[(String, String)]) -> LinkContext {
+  LinkContext {
+    post_titles:   sources
+      .iter()
+      .filter_map(|(path, input)| {
+        let (metadata, _) = try_parse_frontmatter(input).ok()?;
+        Some((path.clone(), metadata.title))
+      })
+      .collect(),
+    link_previews: crate::previews::cached_previews(),
   }
+}
+
+/// Renders every post, returning the path of each post alongside each warning
+/// the markdown pipeline produced for it.
+#[cfg(feature = "ssr")]
+pub fn find_render_warnings() -> Vec<(String, String)> {
+  let sources = read_post_sources();
+  let context = link_context(&sources);
+
+  sources
+    .iter()
+    .flat_map(|(path, input)| {
+      let warnings = match try_parse_frontmatter(input) {
+        Ok((metadata, content)) => {
+          render_post(path, &content, &metadata, &context)
+            .warnings
+            .into_iter()
+            .map(|warning| warning.message)
+            .collect()
+        }
+        Err(e) => vec![e],
+      };
+      warnings.into_iter().map(|warning| (path.clone(), warning))
+    })
+    .collect()
+}
+
+/// Runs blocking work, like rendering or running git, on a thread where it
+/// won't hold up the async runtime.
+#[cfg(feature = "ssr")]
+pub async fn blocking<T: Send + 'static>(
+  f: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, ServerFnError> {
+  tokio::task::spawn_blocking(f)
+    .await
+    .map_err(ServerFnError::new)
+}
+
+#[server]
+pub async fn get_all_posts() -> Result<Vec<Post>, ServerFnError> {
+  let index = expect_context::<crate::post_index::PostIndex>();
+  blocking(move || index.public_posts()).await
+}
+
+/// Picks the post a reader of `path` is most likely to read next: the public
+/// post written after it, or the one before it if it's the latest.
+#[cfg(feature = "ssr")]
+fn likely_next_post(
+  path: &str,
+  sources: &[(String, String)],
+) -> Option<String> {
+  let mut public_posts = sources
+    .iter()
+    .filter_map(|(path, input)| {
+      let (metadata, _) = try_parse_frontmatter(input).ok()?;
+      metadata.public.then_some((metadata.written_on, path))
+    })
+    .collect::<Vec<_>>();
+  public_posts.sort();
+
+  let index = public_posts.iter().position(|(_, p)| *p == path)?;
+  public_posts
+    .get(index + 1)
+    .or_else(|| index.checked_sub(1).and_then(|i| public_posts.get(i)))
+    .map(|(_, path)| path.to_string())
+}
+
+/// The path of the post that `path` refers to, slugified as file names are,
+/// so that `Über Caching` is `uber-caching`. Slugs are single path segments
+/// that can safely be joined onto [`posts_dir`].
+#[cfg(feature = "ssr")]
+pub fn normalize_post_path(path: &str) -> Option<String> {
+  Some(slug_of(path)).filter(|slug| !slug.is_empty())
+}
+
+/// A post page's `path` param, which the router leaves percent-encoded.
+pub fn use_post_path() -> String {
+  let path = use_params_map()().get("path").cloned().unwrap_or_default();
+  percent_encoding::percent_decode_str(&path)
+    .decode_utf8_lossy()
+    .into_owned()
+}
+
+/// What a post's page shows before the post itself, which is known without
+/// rendering it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostHeader {
+  pub path:        String,
+  pub metadata:    PostMetadata,
+  /// The path of the post a reader is most likely to read next, if known.
+  pub likely_next: Option<String>,
+  /// How many times the post has been read, if that's shown.
+  pub views:       Option<i64>,
+}
 
-  posts.retain(|p| p.metadata.public);
-  posts.sort_by(|a, b| a.metadata.written_on.cmp(&b.metadata.written_on));
-  posts.reverse();
+/// Gets a post's header from its frontmatter, so that it can be sent before
+/// the post is rendered.
+#[server]
+pub async fn get_post_header(
+  path: String,
+) -> Result<PostHeader, ServerFnError> {
+  let index = expect_context::<crate::post_index::PostIndex>();
+  let Some(path) = normalize_post_path(&path) else {
+    return Err(ServerFnError::new(AppError::NotFound));
+  };
+  let Some(input) = index.source(&path) else {
+    return Err(ServerFnError::new(AppError::NotFound));
+  };
+  let metadata = match try_parse_frontmatter(&input) {
+    Ok((metadata, _)) if metadata.public => metadata,
+    Ok(_) => return Err(ServerFnError::new(AppError::NotFound)),
+    Err(e) => {
+      log::error!("can't read post `{path}`: {e}");
+      return Err(ServerFnError::new(AppError::InvalidContent));
+    }
+  };
 
-  Ok(posts)
+  let views = crate::views::shown_views(&path).await;
+  blocking(move || PostHeader {
+    likely_next: likely_next_post(&path, &index.sources()),
+    path,
+    metadata,
+    views,
+  })
+  .await
 }
 
 #[server]
 pub async fn get_post_by_path(path: String) -> Result<Post, ServerFnError> {
-  let mut file = std::fs::File::open(format!("./content/posts/{}.md", path))
-    .expect("failed to open file");
-  let mut input = String::new();
-  file
-    .read_to_string(&mut input)
-    .expect("failed to read file");
+  let index = expect_context::<crate::post_index::PostIndex>();
+  let Some(path) = normalize_post_path(&path) else {
+    return Err(ServerFnError::new(AppError::NotFound));
+  };
+  let Some(input) = index.source(&path) else {
+    return Err(ServerFnError::new(AppError::NotFound));
+  };
 
-  let post = extract_post(&path, &input);
+  let (metadata, content) = match try_parse_frontmatter(&input) {
+    Ok(parsed) => parsed,
+    Err(e) => {
+      log::error!("can't read post `{path}`: {e}");
+      return Err(ServerFnError::new(AppError::InvalidContent));
+    }
+  };
+  if !metadata.public {
+    return Err(ServerFnError::new(AppError::NotFound));
+  }
+  // untrusted posts don't get to make the server fetch arbitrary URLs
+  let options = markdown_options(&path, &metadata);
+  if !options.sanitize {
+    let urls = site_markdown::standalone_urls(&content, &options);
+    crate::previews::fetch_previews(&urls).await;
+  }
 
-  if post.metadata.public {
+  blocking(move || {
+    let mut post = index
+      .post(&path)
+      .and_then(Result::ok)
+      .ok_or_else(|| ServerFnError::new(AppError::NotFound))?;
+    post.likely_next = likely_next_post(&path, &index.sources());
+    post.changed_since =
+      crate::revisions::previous_revision(&path, &content).map(|r| r.date);
     Ok(post)
-  } else {
-    Err(ServerFnError::new("Post not found"))
+  })
+  .await?
+}
+
+/// The number of single-character insertions, deletions and substitutions
+/// which turn `a` into `b`.
+#[cfg(feature = "ssr")]
+fn edit_distance(a: &str, b: &str) -> usize {
+  let b = b.chars().collect::<Vec<_>>();
+  let mut previous = (0..=b.len()).collect::<Vec<_>>();
+  for (i, a) in a.chars().enumerate() {
+    let mut current = vec![i + 1];
+    for (j, b) in b.iter().enumerate() {
+      let substitution = previous[j] + usize::from(a != *b);
+      current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+    }
+    previous = current;
   }
+  previous[b.len()]
 }
 
+/// Suggests the public posts whose paths are closest to a `/post/:path` that
+/// didn't resolve, for typos and truncated links.
+#[server]
+pub async fn get_slug_suggestions(
+  path: String,
+) -> Result<Vec<PostHeader>, ServerFnError> {
+  const MAX_RESULTS: usize = 3;
+  const MIN_PREFIX: usize = 4;
+
+  let Some(slug) = path
+    .strip_prefix("/post/")
+    .and_then(|rest| rest.split('/').next())
+    .map(str::to_lowercase)
+    .filter(|slug| !slug.is_empty())
+  else {
+    return Ok(Vec::new());
+  };
+  // far enough to catch a few typos, but not to suggest unrelated posts
+  let max_distance = (slug.chars().count() / 3).max(2);
+
+  let index = expect_context::<crate::post_index::PostIndex>();
+  let mut suggestions = index
+    .sources()
+    .into_iter()
+    .filter_map(|(path, input)| {
+      let (metadata, _) = try_parse_frontmatter(&input).ok()?;
+      if !metadata.public {
+        return None;
+      }
+      // a truncated link, or one with junk after it
+      let is_prefix = (slug.len() >= MIN_PREFIX && path.starts_with(&slug))
+        || slug.starts_with(&path);
+      let distance = if is_prefix {
+        0
+      } else {
+        edit_distance(&slug, &path)
+      };
+      (distance <= max_distance).then_some((distance, path, metadata))
+    })
+    .collect::<Vec<_>>();
+  suggestions
+    .sort_by(|(a, a_path, _), (b, b_path, _)| (a, a_path).cmp(&(b, b_path)));
+
+  Ok(
+    suggestions
+      .into_iter()
+      .take(MAX_RESULTS)
+      .map(|(_, path, metadata)| PostHeader {
+        path,
+        metadata,
+        likely_next: None,
+        views: None,
+      })
+      .collect(),
+  )
+}
+
+/// Splits a path into lowercase search terms, ignoring short words and the
+/// `post` route prefix.
+#[cfg(feature = "ssr")]
+fn path_search_terms(path: &str) -> Vec<String> {
+  path
+    .split(|c: char| !c.is_alphanumeric())
+    .map(str::to_lowercase)
+    .filter(|term| term.len() >= 3 && term != "post")
+    .collect()
+}
+
+/// Finds public posts related to a path that didn't resolve, by matching the
+/// words in the path against post titles and contents.
+#[server]
+pub async fn get_related_posts(
+  path: String,
+) -> Result<Vec<Post>, ServerFnError> {
+  const MAX_RESULTS: usize = 3;
+
+  let terms = path_search_terms(&path);
+  if terms.is_empty() {
+    return Ok(Vec::new());
+  }
+
+  let mut scored_posts = get_all_posts()
+    .await?
+    .into_iter()
+    .map(|post| {
+      let title = post.metadata.title.to_lowercase();
+      let plaintext = post.plaintext.to_lowercase();
+      let score = terms
+        .iter()
+        .map(|term| {
+          (if title.contains(term) { 3 } else { 0 })
+            + (if plaintext.contains(term) { 1 } else { 0 })
+        })
+        .sum::<usize>();
+      (score, post)
+    })
+    .filter(|(score, _)| *score > 0)
+    .collect::<Vec<_>>();
+
+  // stable sort keeps the most recent post first among equal scores
+  scored_posts.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+  Ok(
+    scored_posts
+      .into_iter()
+      .take(MAX_RESULTS)
+      .map(|(_, post)| post)
+      .collect(),
+  )
+}
+
+/// Suggests posts related to the current path, for use on "not found" pages:
+/// the posts with the closest paths if the path looks like a mistyped post
+/// link, or else posts which mention the words in it.
 #[component]
-pub fn PostPage() -> impl IntoView {
-  let params = use_params_map();
-  let path = params().get("path").unwrap().clone();
+pub fn RelatedPosts() -> impl IntoView {
+  let pathname = use_location().pathname;
+  let suggestions_resource = create_resource(pathname, get_slug_suggestions);
+  let related_resource = create_resource(pathname, get_related_posts);
 
-  let post_resource =
-    create_blocking_resource(move || path.clone(), get_post_by_path);
+  let post_list = |intro: &'static str, posts: Vec<(String, String)>| {
+    view! {
+      <p>{intro}</p>
+      <ul>
+        { posts.into_iter().map(|(path, title)| view! {
+          <li><a href={format!("/post/{path}")}>{title}</a></li>
+        }).collect_view() }
+      </ul>
+    }
+    .into_view()
+  };
 
   view! {
     <Suspense>
-      { move || post_resource.get().map(|p| match p {
-        Ok(post) => view! {
-          <Title text={post.metadata.title.clone()} />
-          <div class="markdown">
-            <h1>{post.metadata.title.clone()}</h1>
-            <p>Written on {post.metadata.written_on.clone()}</p>
-            <hr />
-          </div>
-          { post.full_post() }
-        }.into_view(),
-        _ => view! { <p>"Loading..."</p> }.into_view()
-      })}
+      { move || match (suggestions_resource.get(), related_resource.get()) {
+        (Some(Ok(suggestions)), _) if !suggestions.is_empty() => post_list(
+          "Did you mean one of these?",
+          suggestions.into_iter().map(|h| (h.path, h.metadata.title)).collect(),
+        ),
+        (Some(_), Some(Ok(posts))) if !posts.is_empty() => post_list(
+          "Maybe you were looking for one of these?",
+          posts.into_iter().map(|p| (p.path, p.metadata.title)).collect(),
+        ),
+        _ => ().into_view(),
+      }}
     </Suspense>
+    <p>"You can also " <a href="/">"browse every post"</a> "."</p>
+  }
+}
+
+/// Stands in for a post's body while it loads, with the same layout as the
+/// rendered post so that the page doesn't jump when it arrives.
+#[component]
+fn PostSkeleton() -> impl IntoView {
+  view! {
+    <div class="markdown" aria-busy="true" aria-label="Loading post">
+      { [3, 4, 2].into_iter().map(|lines| view! {
+        <div class="my-4">
+          { (0..lines).map(|_| view! { <div class="skeleton h-5 my-2" /> }).collect_view() }
+          <div class="skeleton h-5 my-2 w-3/4" />
+        </div>
+      }).collect_view() }
+    </div>
+  }
+}
+
+/// Stands in for the list of posts on the home page while it loads.
+#[component]
+pub(crate) fn PostListSkeleton() -> impl IntoView {
+  view! {
+    <ul aria-busy="true" aria-label="Loading posts">
+      { (0..4).map(|_| view! {
+        <li><div class="skeleton inline-block align-middle h-5 w-80 max-w-full" /></li>
+      }).collect_view() }
+    </ul>
+  }
+}
+
+/// A post's page. The header is sent as soon as the frontmatter is read, and
+/// the rendered post is streamed in after it.
+#[component]
+pub fn PostPage() -> impl IntoView {
+  let path = use_post_path();
+
+  let header_resource = create_blocking_resource(
+    {
+      let path = path.clone();
+      move || path.clone()
+    },
+    get_post_header,
+  );
+  let post_resource = {
+    let path = path.clone();
+    create_resource(move || path.clone(), get_post_by_path)
+  };
+
+  // the header and the post are siblings rather than nested, since nested
+  // suspense is lost when streaming in order. Together they're the post's
+  // h-entry, for microformats parsers like webmention receivers.
+  view! {
+    <article class="relative h-entry">
+      <Suspense>
+        <ErrorBoundary fallback=|errors| view! { <ErrorTemplate errors /> }>
+          { move || header_resource.get().map(|h| h.map_err(AppError::from).map(|header| view! {
+              <Title text={header.metadata.title.clone()} />
+              <Meta property="og:type" content="article" />
+              <Meta property="og:title" content=header.metadata.title.clone() />
+              <crate::hints::ResourceHints hints=crate::hints::post_hints(&header) />
+              <div class="markdown">
+                <h1 class="p-name">{header.metadata.title.clone()}</h1>
+                <p>
+                  "Written on " <crate::dates::PostDate written_on=header.metadata.written_on.clone() published=true />
+                  { header.views.map(|views| view! { " · " <crate::views::ViewCount views /> }) }
+                </p>
+                <data class="u-url" value=crate::config::site_url(&format!("/post/{}", header.path))></data>
+                <crate::AuthorCard />
+                <hr />
+              </div>
+            }))}
+        </ErrorBoundary>
+      </Suspense>
+      <Suspense fallback=|| view! { <PostSkeleton /> }>
+        // a missing post's error is shown in place of its header
+        <ErrorBoundary fallback=|_| ()>
+          { move || post_resource.get().map(|p| p.map_err(AppError::from).map(|post| view! {
+              { post.changed_since.clone().map(|since| view! {
+                <div class="markdown">
+                  <p>
+                    <a href=format!("/post/{}/changes", post.path)>"What changed since " {since}</a>
+                  </p>
+                </div>
+              }) }
+              <div class="e-content">{ post.full_post() }</div>
+              { (post.toc.len() > 1).then(|| view! {
+                <aside class="hidden xl:block absolute top-0 left-full h-full ml-8 w-56">
+                  <div class="sticky top-8">
+                    <TableOfContents entries=post.toc.clone() />
+                  </div>
+                </aside>
+              }) }
+              <HeadingAnchorCopier />
+              <FootnotePopovers />
+              <SpoilerRevealer />
+              <crate::embeds::EmbedFacades />
+              { cfg!(debug_assertions).then(|| view! {
+                <crate::live::LiveReload path=post.path.clone() />
+              }) }
+            }))}
+        </ErrorBoundary>
+      </Suspense>
+      <crate::reactions::Reactions path=path.clone() />
+      <crate::newsletter::NewsletterSignup intro="Liked this? Get new posts by email." />
+      <crate::comments::Comments path=path.clone() />
+    </article>
+  }
+}
+
+#[cfg(feature = "hydrate")]
+#[wasm_bindgen::prelude::wasm_bindgen]
+extern "C" {
+  #[wasm_bindgen(catch, js_namespace = ["navigator", "clipboard"], js_name = writeText)]
+  pub(crate) fn write_clipboard_text(
+    text: &str,
+  ) -> Result<wasm_bindgen::JsValue, wasm_bindgen::JsValue>;
+}
+
+/// Copies the absolute URL of a heading to the clipboard when its anchor is
+/// clicked. The click isn't prevented, so the anchor still jumps to the
+/// heading, and deep links keep working without JS.
+#[crate::eager_island]
+fn HeadingAnchorCopier() -> impl IntoView {
+  #[cfg(feature = "hydrate")]
+  {
+    use leptos::web_sys::{Element, HtmlAnchorElement};
+    use wasm_bindgen::JsCast;
+
+    let handle = window_event_listener(ev::click, |ev| {
+      let Some(anchor) = ev
+        .target()
+        .and_then(|t| t.dyn_into::<Element>().ok())
+        .and_then(|el| el.closest(".heading-anchor").ok().flatten())
+        .and_then(|el| el.dyn_into::<HtmlAnchorElement>().ok())
+      else {
+        return;
+      };
+
+      if write_clipboard_text(&anchor.href()).is_ok() {
+        let _ = anchor.class_list().add_1("copied");
+        set_timeout(
+          move || {
+            let _ = anchor.class_list().remove_1("copied");
+          },
+          std::time::Duration::from_secs(2),
+        );
+      }
+    });
+    on_cleanup(move || handle.remove());
+  }
+}
+
+/// Keeps spoilers revealed once they've been focused. Without it, spoilers are
+/// only revealed while they're focused.
+#[crate::eager_island]
+fn SpoilerRevealer() -> impl IntoView {
+  #[cfg(feature = "hydrate")]
+  {
+    use leptos::web_sys::Element;
+    use wasm_bindgen::JsCast;
+
+    let handle = window_event_listener(ev::focusin, |ev| {
+      if let Some(spoiler) = ev
+        .target()
+        .and_then(|t| t.dyn_into::<Element>().ok())
+        .filter(|el| el.class_list().contains("spoiler"))
+      {
+        let _ = spoiler.class_list().add_1("spoiler-revealed");
+      }
+    });
+    on_cleanup(move || handle.remove());
+  }
+}
+
+/// Shows a footnote's content in a popover while its reference is hovered or
+/// focused, so readers don't have to jump to the bottom of the page.
+#[crate::eager_island]
+fn FootnotePopovers() -> impl IntoView {
+  let (popover, set_popover) = create_signal(None::<(String, f64, f64)>);
+
+  #[cfg(feature = "hydrate")]
+  {
+    use leptos::web_sys::{Element, Event, HtmlAnchorElement};
+    use wasm_bindgen::JsCast;
+
+    let reference_of = |ev: &Event| {
+      ev.target()
+        .and_then(|t| t.dyn_into::<Element>().ok())
+        .and_then(|el| el.closest(".footnote-reference a").ok().flatten())
+        .and_then(|el| el.dyn_into::<HtmlAnchorElement>().ok())
+    };
+
+    let show = move |ev: Event| {
+      let Some(reference) = reference_of(&ev) else {
+        return;
+      };
+      let Some(definition) = reference
+        .hash()
+        .strip_prefix('#')
+        .and_then(|id| document().get_element_by_id(id))
+      else {
+        return;
+      };
+
+      // the label and back-link only make sense at the bottom of the page
+      let Ok(Some(content)) = definition
+        .clone_node_with_deep(true)
+        .map(|node| node.dyn_into::<Element>().ok())
+      else {
+        return;
+      };
+      if let Ok(extras) = content
+        .query_selector_all(".footnote-definition-label, .footnote-backref")
+      {
+        for i in 0..extras.length() {
+          if let Some(extra) = extras.item(i) {
+            if let Some(parent) = extra.parent_node() {
+              let _ = parent.remove_child(&extra);
+            }
+          }
+        }
+      }
+
+      let rect = reference.get_bounding_client_rect();
+      set_popover(Some((content.inner_html(), rect.bottom(), rect.left())));
+    };
+    let hide = move |ev: Event| {
+      if reference_of(&ev).is_some() {
+        set_popover(None);
+      }
+    };
+
+    let handles = [
+      window_event_listener(ev::mouseover, move |ev| show(ev.into())),
+      window_event_listener(ev::focusin, move |ev| show(ev.into())),
+      window_event_listener(ev::mouseout, move |ev| hide(ev.into())),
+      window_event_listener(ev::focusout, move |ev| hide(ev.into())),
+      // the popover is fixed in place, so it would drift from its reference
+      window_event_listener(ev::scroll, move |_| set_popover(None)),
+    ];
+    on_cleanup(move || handles.into_iter().for_each(|h| h.remove()));
+  }
+  #[cfg(not(feature = "hydrate"))]
+  let _ = set_popover;
+
+  move || {
+    popover().map(|(html, top, left)| {
+      view! {
+        <div
+          role="tooltip"
+          class="footnote-popover markdown"
+          style:top=format!("{}px", top + 4.0)
+          style:left=format!("{left}px")
+          inner_html=html
+        />
+      }
+    })
   }
 }
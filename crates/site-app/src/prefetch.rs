@@ -0,0 +1,139 @@
+//! Speculatively prefetches posts that the reader is likely to open next,
+//! so that navigating between posts feels instant.
+
+use leptos::*;
+
+/// The links that get prefetched.
+#[cfg(feature = "hydrate")]
+const POST_LINK_SELECTOR: &str = "a[href^='/post/']";
+
+/// Whether the reader has asked to save data, through the `Save-Data`
+/// preference, a slow connection, or `prefers-reduced-data`.
+#[cfg(feature = "hydrate")]
+fn should_save_data() -> bool {
+  use js_sys::Reflect;
+  use wasm_bindgen::JsValue;
+
+  // `navigator.connection` isn't in `web-sys`'s stable API
+  let connection =
+    Reflect::get(&window().navigator(), &JsValue::from_str("connection"))
+      .unwrap_or(JsValue::UNDEFINED);
+  let save_data = Reflect::get(&connection, &JsValue::from_str("saveData"))
+    .ok()
+    .and_then(|v| v.as_bool())
+    .unwrap_or(false);
+  let slow_connection =
+    Reflect::get(&connection, &JsValue::from_str("effectiveType"))
+      .ok()
+      .and_then(|v| v.as_string())
+      .is_some_and(|t| t.ends_with("2g"));
+  let reduced_data = window()
+    .match_media("(prefers-reduced-data: reduce)")
+    .ok()
+    .flatten()
+    .is_some_and(|query| query.matches());
+
+  save_data || slow_connection || reduced_data
+}
+
+/// Prefetches post links as they scroll into view or are hovered or focused,
+/// unless the reader has asked to save data.
+#[crate::eager_island]
+pub fn PostPrefetcher() -> impl IntoView {
+  #[cfg(feature = "hydrate")]
+  {
+    use std::collections::HashSet;
+
+    use js_sys::Array;
+    use leptos::web_sys::{
+      Element, Event, HtmlAnchorElement, IntersectionObserver,
+      IntersectionObserverEntry,
+    };
+    use wasm_bindgen::{closure::Closure, JsCast};
+
+    if should_save_data() {
+      return;
+    }
+
+    let prefetched = store_value(HashSet::<String>::new());
+    let prefetch = move |anchor: &HtmlAnchorElement| {
+      let location = window().location();
+      let current = format!(
+        "{}{}",
+        location.origin().unwrap_or_default(),
+        location.pathname().unwrap_or_default()
+      );
+      // ignore the fragment, so links within the current page are skipped
+      let href = anchor.href();
+      let href = href.split('#').next().unwrap_or_default().to_string();
+      if href == current
+        || !prefetched
+          .try_update_value(|set| set.insert(href.clone()))
+          .unwrap_or(false)
+      {
+        return;
+      }
+
+      let Ok(link) = document().create_element("link") else {
+        return;
+      };
+      let _ = link.set_attribute("rel", "prefetch");
+      let _ = link.set_attribute("href", &href);
+      if let Some(head) = document().head() {
+        let _ = head.append_child(&link);
+      }
+    };
+
+    let anchor_of = |ev: &Event| {
+      ev.target()
+        .and_then(|t| t.dyn_into::<Element>().ok())
+        .and_then(|el| el.closest(POST_LINK_SELECTOR).ok().flatten())
+        .and_then(|el| el.dyn_into::<HtmlAnchorElement>().ok())
+    };
+    let on_intent = move |ev: Event| {
+      if let Some(anchor) = anchor_of(&ev) {
+        prefetch(&anchor);
+      }
+    };
+    let hover_handle =
+      window_event_listener(ev::mouseover, move |ev| on_intent(ev.into()));
+    let focus_handle =
+      window_event_listener(ev::focusin, move |ev| on_intent(ev.into()));
+
+    let callback = Closure::<dyn Fn(Array, IntersectionObserver)>::new(
+      move |observed: Array, observer: IntersectionObserver| {
+        for entry in observed.iter() {
+          let entry = entry.unchecked_into::<IntersectionObserverEntry>();
+          if !entry.is_intersecting() {
+            continue;
+          }
+          let target = entry.target();
+          observer.unobserve(&target);
+          if let Ok(anchor) = target.dyn_into::<HtmlAnchorElement>() {
+            prefetch(&anchor);
+          }
+        }
+      },
+    );
+    let observer =
+      IntersectionObserver::new(callback.as_ref().unchecked_ref()).ok();
+    if let (Some(observer), Ok(links)) =
+      (&observer, document().query_selector_all(POST_LINK_SELECTOR))
+    {
+      for i in 0..links.length() {
+        if let Some(link) = links.item(i).and_then(|l| l.dyn_into().ok()) {
+          observer.observe(&link);
+        }
+      }
+    }
+
+    on_cleanup(move || {
+      hover_handle.remove();
+      focus_handle.remove();
+      if let Some(observer) = observer {
+        observer.disconnect();
+      }
+      drop(callback);
+    });
+  }
+}
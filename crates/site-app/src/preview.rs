@@ -0,0 +1,99 @@
+//! Previews of posts which aren't public yet, at `/preview/:path?token=...`,
+//! for sending drafts to reviewers. The token comes from a link the admin area
+//! makes, see [`mint_preview_link`](crate::admin::mint_preview_link), and only
+//! opens the one post it was made for until it expires.
+
+use leptos::*;
+use leptos_meta::{Meta, Title};
+use leptos_router::use_query_map;
+
+use crate::{
+  error_template::{AppError, ErrorTemplate},
+  posts::Post,
+  toc::TableOfContents,
+};
+
+/// A post, public or not, if `token` lets its holder preview it. Posts which
+/// can't be previewed are missing, so that bad tokens don't give away which
+/// drafts exist.
+#[server]
+pub async fn get_post_preview(
+  path: String,
+  token: String,
+) -> Result<Post, ServerFnError> {
+  let not_found = || ServerFnError::new(AppError::NotFound);
+  let key = crate::auth::use_signing_key()?;
+  let path = crate::posts::normalize_post_path(&path).ok_or_else(not_found)?;
+  if !crate::auth::verify_preview_token(&key, &path, &token) {
+    return Err(not_found());
+  }
+
+  let index = expect_context::<crate::post_index::PostIndex>();
+  crate::posts::blocking(move || match index.post(&path) {
+    Some(Ok(post)) => Ok(post),
+    Some(Err(e)) => {
+      log::error!("can't read post `{path}`: {e}");
+      Err(ServerFnError::new(AppError::InvalidContent))
+    }
+    None => Err(not_found()),
+  })
+  .await?
+}
+
+/// A preview of a post, marked as one.
+#[component]
+pub fn PreviewPage() -> impl IntoView {
+  // the token is as good as a password, so the page isn't kept anywhere
+  #[cfg(feature = "ssr")]
+  if let Some(response) = use_context::<leptos_axum::ResponseOptions>() {
+    response.insert_header(
+      http::header::CACHE_CONTROL,
+      http::HeaderValue::from_static("no-store"),
+    );
+  }
+  let path = crate::posts::use_post_path();
+  let token = use_query_map()
+    .get_untracked()
+    .get("token")
+    .cloned()
+    .unwrap_or_default();
+  let post_resource = create_blocking_resource(
+    move || (path.clone(), token.clone()),
+    |(path, token)| get_post_preview(path, token),
+  );
+
+  view! {
+    <Meta name="robots" content="noindex" />
+    <div class="relative">
+      <Suspense>
+        <ErrorBoundary fallback=|errors| view! { <ErrorTemplate errors /> }>
+          { move || post_resource.get().map(|p| p.map_err(AppError::from).map(|post| view! {
+              <Title text=format!("Preview: {}", post.metadata.title) />
+              <p class="border-2 border-periwinkle/50 px-4 py-2 mb-4">
+                { if post.metadata.public {
+                  "This post has since been published."
+                } else {
+                  "This is a preview of a post that isn't published yet. Please don't share it."
+                } }
+              </p>
+              <div class="markdown">
+                <h1>{post.metadata.title.clone()}</h1>
+                <p>
+                  "Written on " <crate::dates::PostDate written_on=post.metadata.written_on.clone() />
+                </p>
+                <hr />
+              </div>
+              { post.full_post() }
+              { (post.toc.len() > 1).then(|| view! {
+                <aside class="hidden xl:block absolute top-0 left-full h-full ml-8 w-56">
+                  <div class="sticky top-8">
+                    <TableOfContents entries=post.toc.clone() />
+                  </div>
+                </aside>
+              }) }
+            }))}
+        </ErrorBoundary>
+      </Suspense>
+    </div>
+  }
+}
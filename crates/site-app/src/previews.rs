@@ -0,0 +1,465 @@
+//! Fetches and caches the metadata shown in link preview cards, which replace
+//! paragraphs consisting solely of a URL.
+//!
+//! Previews are fetched before a post is rendered and kept in memory, so the
+//! markdown pipeline can stay synchronous. Fetches run concurrently in the
+//! background, and a page waits only [`FETCH_BUDGET`] for them; previews
+//! arriving later are in the renders after. Failed fetches are cached too,
+//! for a shorter time, and render as plain links, or as the preview fetched
+//! before, if there was one.
+
+use std::{
+  collections::{HashMap, HashSet},
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex, OnceLock,
+  },
+  time::{Duration, Instant},
+};
+
+use futures::StreamExt;
+use hyper::{body::HttpBody, client::HttpConnector, Body, Client, Uri};
+use hyper_tls::HttpsConnector;
+pub use site_markdown::LinkPreview;
+
+/// How long fetching a page's metadata can take, including redirects.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(3);
+/// How long a page waits for its previews, however many there are.
+const FETCH_BUDGET: Duration = Duration::from_secs(2);
+/// How many pages' metadata is fetched at once.
+const MAX_CONCURRENT_FETCHES: usize = 8;
+/// How much of a page is read looking for its metadata.
+const MAX_BODY_BYTES: usize = 512 * 1024;
+/// How many redirects are followed.
+const MAX_REDIRECTS: usize = 3;
+/// How long a fetched preview is used before it's refetched.
+const PREVIEW_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// How long to wait before retrying a page whose metadata couldn't be fetched.
+const FAILURE_TTL: Duration = Duration::from_secs(60 * 60);
+
+struct CacheEntry {
+  /// The last preview fetched, even if fetching it again has since failed.
+  preview:    Option<LinkPreview>,
+  fetched_at: Instant,
+  /// Whether the last fetch failed.
+  failed:     bool,
+}
+
+impl CacheEntry {
+  fn is_fresh(&self) -> bool {
+    let ttl = if self.failed {
+      FAILURE_TTL
+    } else {
+      PREVIEW_TTL
+    };
+    self.fetched_at.elapsed() < ttl
+  }
+}
+
+fn cache() -> &'static Mutex<HashMap<String, CacheEntry>> {
+  static CACHE: OnceLock<Mutex<HashMap<String, CacheEntry>>> = OnceLock::new();
+  CACHE.get_or_init(Default::default)
+}
+
+/// The URLs being fetched, so that pages rendered at once don't fetch them
+/// twice.
+fn in_flight() -> &'static Mutex<HashSet<String>> {
+  static IN_FLIGHT: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+  IN_FLIGHT.get_or_init(Default::default)
+}
+
+/// Counts the previews fetched, so that renders can tell when they're stale.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// The client for everything the server fetches from elsewhere.
+pub(crate) fn client() -> &'static Client<HttpsConnector<HttpConnector>> {
+  static CLIENT: OnceLock<Client<HttpsConnector<HttpConnector>>> =
+    OnceLock::new();
+  CLIENT.get_or_init(|| Client::builder().build(HttpsConnector::new()))
+}
+
+/// Every preview that's been fetched, keyed by URL. Expired previews are
+/// still returned, since they're better than nothing until they're refetched.
+pub fn cached_previews() -> HashMap<String, LinkPreview> {
+  cache()
+    .lock()
+    .unwrap()
+    .iter()
+    .filter_map(|(url, entry)| Some((url.clone(), entry.preview.clone()?)))
+    .collect()
+}
+
+/// Changes whenever a preview is fetched, and so whenever
+/// [`cached_previews`] might return something different.
+pub fn generation() -> u64 { GENERATION.load(Ordering::Acquire) }
+
+/// Fetches the previews for any of `urls` which aren't cached or have expired,
+/// waiting at most [`FETCH_BUDGET`]. Those still being fetched then carry on
+/// in the background.
+pub async fn fetch_previews(urls: &[String]) {
+  let stale = {
+    let cache = cache().lock().unwrap();
+    let mut in_flight = in_flight().lock().unwrap();
+    urls
+      .iter()
+      .filter(|url| !cache.get(*url).is_some_and(CacheEntry::is_fresh))
+      .filter(|url| in_flight.insert(url.to_string()))
+      .cloned()
+      .collect::<Vec<_>>()
+  };
+  if stale.is_empty() {
+    return;
+  }
+
+  let fetches = tokio::spawn(
+    futures::stream::iter(stale)
+      .for_each_concurrent(MAX_CONCURRENT_FETCHES, refresh_preview),
+  );
+  let _ = tokio::time::timeout(FETCH_BUDGET, fetches).await;
+}
+
+/// Fetches the preview for `url` into the cache.
+async fn refresh_preview(url: String) {
+  let preview = match tokio::time::timeout(FETCH_TIMEOUT, fetch_preview(&url))
+    .await
+    .unwrap_or_else(|_| Err("timed out".to_string()))
+  {
+    Ok(preview) => Some(preview),
+    Err(e) => {
+      log::warn!("couldn't fetch a link preview for `{url}`: {e}");
+      None
+    }
+  };
+
+  let mut cache = cache().lock().unwrap();
+  let failed = preview.is_none();
+  // keeps a stale preview rather than replacing it with a failure
+  let preview =
+    preview.or_else(|| cache.get(&url).and_then(|e| e.preview.clone()));
+  in_flight().lock().unwrap().remove(&url);
+  cache.insert(url, CacheEntry {
+    preview,
+    fetched_at: Instant::now(),
+    failed,
+  });
+  if !failed {
+    GENERATION.fetch_add(1, Ordering::Release);
+  }
+}
+
+/// Resolves a possibly relative `link` against the page at `base`.
+pub(crate) fn resolve_url(base: &Uri, link: &str) -> Option<String> {
+  let scheme = base.scheme_str()?;
+  let authority = base.authority()?;
+
+  let url = if link.starts_with("http://") || link.starts_with("https://") {
+    link.to_string()
+  } else if let Some(rest) = link.strip_prefix("//") {
+    format!("{scheme}://{rest}")
+  } else if link.starts_with('/') {
+    format!("{scheme}://{authority}{link}")
+  } else {
+    let dir = base.path().rsplit_once('/').map_or("", |(dir, _)| dir);
+    format!("{scheme}://{authority}{dir}/{link}")
+  };
+  Some(url)
+}
+
+async fn fetch_preview(url: &str) -> Result<LinkPreview, String> {
+  let mut uri = url.parse::<Uri>().map_err(|e| e.to_string())?;
+
+  for _ in 0..=MAX_REDIRECTS {
+    let request = hyper::Request::get(uri.clone())
+      .header(hyper::header::ACCEPT, "text/html")
+      .header(
+        hyper::header::USER_AGENT,
+        concat!("site-app/", env!("CARGO_PKG_VERSION"), " (link previews)"),
+      )
+      .body(Body::empty())
+      .map_err(|e| e.to_string())?;
+    let mut response =
+      client().request(request).await.map_err(|e| e.to_string())?;
+
+    if response.status().is_redirection() {
+      let location = response
+        .headers()
+        .get(hyper::header::LOCATION)
+        .and_then(|l| l.to_str().ok())
+        .and_then(|l| resolve_url(&uri, l))
+        .ok_or("redirected without a location")?;
+      uri = location
+        .parse()
+        .map_err(|e: hyper::http::uri::InvalidUri| e.to_string())?;
+      continue;
+    }
+    if !response.status().is_success() {
+      return Err(format!("responded with {}", response.status()));
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.body_mut().data().await {
+      body.extend_from_slice(&chunk.map_err(|e| e.to_string())?);
+      if body.len() >= MAX_BODY_BYTES {
+        break;
+      }
+    }
+    return parse_preview(&uri, &String::from_utf8_lossy(&body))
+      .ok_or_else(|| "no title found".to_string());
+  }
+
+  Err("too many redirects".to_string())
+}
+
+/// Decodes the handful of HTML entities that show up in page titles.
+pub(crate) fn decode_entities(text: &str) -> String {
+  text
+    .replace("&quot;", "\"")
+    .replace("&#39;", "'")
+    .replace("&#x27;", "'")
+    .replace("&lt;", "<")
+    .replace("&gt;", ">")
+    .replace("&nbsp;", " ")
+    .replace("&amp;", "&")
+}
+
+/// Finds the value of an attribute in the source of a single tag. Attributes
+/// are read in order, so a name inside another attribute's value isn't taken
+/// for an attribute.
+pub(crate) fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+  let ends_name = |c: char| c.is_whitespace() || matches!(c, '=' | '/' | '>');
+  let mut rest = tag.strip_prefix('<')?;
+  // the tag's own name
+  rest = &rest[rest.find(ends_name).unwrap_or(rest.len())..];
+
+  loop {
+    rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+    let name_len = rest.find(ends_name).unwrap_or(rest.len());
+    if name_len == 0 {
+      return None;
+    }
+    let (attribute_name, after_name) = rest.split_at(name_len);
+    // attributes without values have nothing to find
+    let Some(value) = after_name.trim_start().strip_prefix('=') else {
+      rest = after_name;
+      continue;
+    };
+
+    let value = value.trim_start();
+    let (value, after_value) = match value.chars().next() {
+      Some(quote @ ('"' | '\'')) => {
+        let value = &value[1..];
+        let len = value.find(quote).unwrap_or(value.len());
+        (&value[..len], value.get(len + 1..).unwrap_or_default())
+      }
+      _ => {
+        let len = value
+          .find(|c: char| c.is_whitespace() || c == '>')
+          .unwrap_or(value.len());
+        value.split_at(len)
+      }
+    };
+    if attribute_name.eq_ignore_ascii_case(name) {
+      return Some(value);
+    }
+    rest = after_value;
+  }
+}
+
+/// The length of the tag at the start of `html`, up to the `>` that closes it
+/// outside of any quoted attribute value.
+fn tag_len(html: &str) -> Option<usize> {
+  let mut quote = None;
+  for (i, c) in html.char_indices() {
+    match (quote, c) {
+      (None, '"' | '\'') => quote = Some(c),
+      (Some(q), _) if c == q => quote = None,
+      (None, '>') => return Some(i),
+      _ => {}
+    }
+  }
+  None
+}
+
+/// The source of every `<name ...>` tag in a document, whatever the
+/// whitespace after its name.
+pub(crate) fn tags<'a>(html: &'a str, name: &str) -> Vec<&'a str> {
+  let lowercase = html.to_ascii_lowercase();
+  let open = format!("<{name}");
+  lowercase
+    .match_indices(&open)
+    .filter(|(start, _)| {
+      lowercase[start + open.len()..]
+        .starts_with(|c: char| c.is_ascii_whitespace())
+    })
+    .filter_map(|(start, _)| {
+      let len = tag_len(&html[start..])?;
+      Some(&html[start..start + len])
+    })
+    .collect()
+}
+
+fn parse_preview(url: &Uri, html: &str) -> Option<LinkPreview> {
+  // metadata belongs in the head, and the body may be truncated anyway
+  let lowercase = html.to_ascii_lowercase();
+  let head = &html[..lowercase.find("</head>").unwrap_or(html.len())];
+
+  let metas = tags(head, "meta");
+  let meta = |key: &str| {
+    metas.iter().find_map(|tag| {
+      let name =
+        attribute(tag, "property").or_else(|| attribute(tag, "name"))?;
+      (name.eq_ignore_ascii_case(key))
+        .then(|| attribute(tag, "content"))
+        .flatten()
+        .map(|content| decode_entities(content.trim()))
+        .filter(|content| !content.is_empty())
+    })
+  };
+
+  let title_tag = || {
+    let lowercase = head.to_ascii_lowercase();
+    let start = lowercase.find("<title")?;
+    let start = start + lowercase[start..].find('>')? + 1;
+    let len = lowercase[start..].find("</title>")?;
+    Some(decode_entities(head[start..start + len].trim()))
+      .filter(|title| !title.is_empty())
+  };
+  let title = meta("og:title")
+    .or_else(|| meta("twitter:title"))
+    .or_else(title_tag)?;
+  let description = meta("og:description").or_else(|| meta("description"));
+
+  let icon = tags(head, "link")
+    .into_iter()
+    .find(|tag| {
+      attribute(tag, "rel").is_some_and(|rel| {
+        rel
+          .split_whitespace()
+          .any(|r| r.eq_ignore_ascii_case("icon"))
+      })
+    })
+    .and_then(|tag| attribute(tag, "href"))
+    .map(decode_entities)
+    .or_else(|| Some("/favicon.ico".to_string()))
+    .and_then(|href| resolve_url(url, &href))
+    // icons are loaded by readers' browsers, so insecure ones would be blocked
+    .filter(|icon| icon.starts_with("https://"));
+
+  Some(LinkPreview {
+    title,
+    description,
+    icon,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn attributes_are_read_however_theyre_quoted() {
+    let tag = "<meta property=\"og:title\" content='It\"s here' data-x=1>";
+    assert_eq!(attribute(tag, "property"), Some("og:title"));
+    assert_eq!(attribute(tag, "content"), Some("It\"s here"));
+    assert_eq!(attribute(tag, "data-x"), Some("1"));
+    assert_eq!(attribute(tag, "missing"), None);
+
+    let tag = "<META\n  NAME = \"Description\"\tContent=\"about\" async>";
+    assert_eq!(attribute(tag, "name"), Some("Description"));
+    assert_eq!(attribute(tag, "content"), Some("about"));
+    assert_eq!(attribute(tag, "async"), None);
+    assert_eq!(
+      attribute("<link href=/a/b.ico/>", "href"),
+      Some("/a/b.ico/")
+    );
+  }
+
+  #[test]
+  fn attributes_are_only_found_by_their_whole_names() {
+    let tag = "<meta content=\"a name='x'\" data-name=\"y\" name=\"z\">";
+    assert_eq!(attribute(tag, "name"), Some("z"));
+    assert_eq!(attribute("<meta content=\"name=x\">", "name"), None);
+    assert_eq!(
+      attribute("<meta name=\"unterminated", "name"),
+      Some("unterminated")
+    );
+  }
+
+  #[test]
+  fn tags_are_found_whatever_follows_their_name() {
+    let html = "<meta name=a><META\nname=b><meta\tname=c><metadata \
+                name=d><meta name=\"e>f\"><meta>";
+    assert_eq!(tags(html, "meta"), [
+      "<meta name=a",
+      "<META\nname=b",
+      "<meta\tname=c",
+      "<meta name=\"e>f\"",
+    ]);
+    assert_eq!(tags("<meta name=unclosed", "meta"), Vec::<&str>::new());
+  }
+
+  #[test]
+  fn tags_end_at_the_first_unquoted_bracket() {
+    assert_eq!(tag_len("<a href=\"x>y\" title='>'>z"), Some(23));
+    assert_eq!(tag_len("<a>"), Some(2));
+    assert_eq!(tag_len("<a href=\">"), None);
+  }
+
+  #[test]
+  fn entities_are_decoded_once() {
+    assert_eq!(
+      decode_entities("&quot;Tom&#39;s&#x27; &lt;b&gt;&nbsp;&amp;lt;"),
+      "\"Tom's' <b> &lt;"
+    );
+  }
+
+  #[test]
+  fn urls_are_resolved_against_the_page() {
+    let base = "https://example.com/blog/post?x=1".parse::<Uri>().unwrap();
+    let resolve = |link| resolve_url(&base, link).unwrap();
+    assert_eq!(resolve("http://other.example/a"), "http://other.example/a");
+    assert_eq!(resolve("//cdn.example/a.png"), "https://cdn.example/a.png");
+    assert_eq!(resolve("/favicon.ico"), "https://example.com/favicon.ico");
+    assert_eq!(resolve("icon.png"), "https://example.com/blog/icon.png");
+    let root = "https://example.com".parse::<Uri>().unwrap();
+    assert_eq!(
+      resolve_url(&root, "icon.png").unwrap(),
+      "https://example.com/icon.png"
+    );
+    assert_eq!(resolve_url(&"/relative".parse().unwrap(), "icon.png"), None);
+  }
+
+  #[test]
+  fn previews_are_read_from_the_head() {
+    let base = "https://example.com/blog/post".parse::<Uri>().unwrap();
+    let preview = parse_preview(
+      &base,
+      "<html><head><title> Fallback </title><meta\nproperty=\"og:title\" \
+       content=\"Tom &amp; Jerry\"><meta name=\"description\" content=\"  \
+       About them \"><link rel=\"shortcut icon\" \
+       href=\"icons/fav.png\"></head><body><meta property=\"og:title\" \
+       content=\"Body\"></body>",
+    )
+    .unwrap();
+    assert_eq!(preview, LinkPreview {
+      title:       "Tom & Jerry".to_string(),
+      description: Some("About them".to_string()),
+      icon:        Some("https://example.com/blog/icons/fav.png".to_string()),
+    });
+
+    let preview = parse_preview(&base, "<title>Only a title</title>").unwrap();
+    assert_eq!(preview.title, "Only a title");
+    assert_eq!(preview.description, None);
+    assert_eq!(
+      preview.icon.as_deref(),
+      Some("https://example.com/favicon.ico")
+    );
+
+    let insecure = "http://example.com/".parse::<Uri>().unwrap();
+    assert_eq!(
+      parse_preview(&insecure, "<title>T</title>").unwrap().icon,
+      None
+    );
+    assert_eq!(parse_preview(&base, "<p>no title</p>"), None);
+  }
+}
@@ -0,0 +1,180 @@
+//! Opt-in Web Push notifications for new posts.
+//!
+//! Readers subscribe through [`PushOptIn`], which registers the service worker
+//! at [`SERVICE_WORKER_PATH`] and hands the browser's subscription to
+//! [`subscribe_to_push`]. The server sends a notification to every stored
+//! subscription when a post is published. Push is only offered when the
+//! server has been given a VAPID key, in which case it provides a
+//! [`PushConfig`] to every request.
+
+use leptos::*;
+
+/// The path the service worker which shows notifications is served from.
+pub const SERVICE_WORKER_PATH: &str = "/sw.js";
+
+/// The server's Web Push configuration.
+#[cfg(feature = "ssr")]
+#[derive(Clone, Debug)]
+pub struct PushConfig {
+  /// The VAPID public key, base64url-encoded, which browsers need in order to
+  /// subscribe.
+  pub public_key: String,
+}
+
+/// Whether the server can send push notifications.
+pub fn push_public_key() -> Option<String> {
+  #[cfg(feature = "ssr")]
+  return use_context::<PushConfig>().map(|config| config.public_key);
+  #[cfg(not(feature = "ssr"))]
+  None
+}
+
+/// Stores a browser's push subscription.
+#[server]
+pub async fn subscribe_to_push(
+  endpoint: String,
+  p256dh: String,
+  auth: String,
+) -> Result<(), ServerFnError> {
+  use site_db::storage::{PushSubscription, PushSubscriptionStore};
+
+  if use_context::<PushConfig>().is_none() {
+    return Err(ServerFnError::new("push notifications aren't enabled"));
+  }
+  // push services are always reached over HTTPS from the public internet, and
+  // anything else could be used to make the server send requests to hosts on
+  // its own network
+  if !endpoint.starts_with("https://")
+    || !crate::webmentions::is_public(&endpoint)
+  {
+    return Err(ServerFnError::new("invalid push endpoint"));
+  }
+
+  let db = use_context::<site_db::Database>()
+    .ok_or_else(|| ServerFnError::new("database unavailable"))?;
+  db.add_push_subscription(&PushSubscription {
+    endpoint,
+    p256dh,
+    auth,
+  })
+  .await
+  .map_err(|e| ServerFnError::new(e.to_string()))
+}
+
+/// The state of the reader's opt-in.
+// only the browser moves past `Subscribing`
+#[cfg_attr(not(feature = "hydrate"), allow(dead_code))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum OptInState {
+  Idle,
+  Subscribing,
+  Subscribed,
+  Failed,
+}
+
+/// The parts of a `PushSubscription`'s JSON encoding the server needs.
+#[cfg(feature = "hydrate")]
+#[derive(serde::Deserialize)]
+struct BrowserSubscription {
+  endpoint: String,
+  keys:     BrowserSubscriptionKeys,
+}
+
+#[cfg(feature = "hydrate")]
+#[derive(serde::Deserialize)]
+struct BrowserSubscriptionKeys {
+  p256dh: String,
+  auth:   String,
+}
+
+/// Registers the service worker and subscribes the browser to push.
+#[cfg(feature = "hydrate")]
+async fn subscribe_browser(
+  public_key: &str,
+) -> Result<BrowserSubscription, wasm_bindgen::JsValue> {
+  use js_sys::{Object, Reflect, JSON};
+  use leptos::web_sys::{PushSubscription, ServiceWorkerRegistration};
+  use wasm_bindgen::{JsCast, JsValue};
+  use wasm_bindgen_futures::JsFuture;
+
+  let container = window().navigator().service_worker();
+  JsFuture::from(container.register(SERVICE_WORKER_PATH)).await?;
+  let registration = JsFuture::from(container.ready()?)
+    .await?
+    .unchecked_into::<ServiceWorkerRegistration>();
+
+  // set through `Reflect` because the options setters differ between
+  // `web-sys` versions
+  let options = Object::new();
+  Reflect::set(&options, &"userVisibleOnly".into(), &JsValue::TRUE)?;
+  Reflect::set(&options, &"applicationServerKey".into(), &public_key.into())?;
+
+  let subscription = JsFuture::from(
+    registration
+      .push_manager()?
+      .subscribe_with_options(options.unchecked_ref())?,
+  )
+  .await?
+  .unchecked_into::<PushSubscription>();
+
+  let json = JSON::stringify(&subscription)?
+    .as_string()
+    .unwrap_or_default();
+  serde_json::from_str(&json).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// A button which subscribes the reader to notifications about new posts.
+#[crate::eager_island]
+pub fn PushOptIn(public_key: String) -> impl IntoView {
+  let (state, set_state) = create_signal(OptInState::Idle);
+  let public_key = store_value(public_key);
+
+  let subscribe = move |_| {
+    set_state(OptInState::Subscribing);
+    #[cfg(feature = "hydrate")]
+    {
+      let public_key = public_key.get_value();
+      spawn_local(async move {
+        let result = match subscribe_browser(&public_key).await {
+          Ok(sub) => {
+            subscribe_to_push(sub.endpoint, sub.keys.p256dh, sub.keys.auth)
+              .await
+              .map_err(|e| logging::error!("failed to store subscription: {e}"))
+          }
+          Err(e) => {
+            logging::error!("failed to subscribe to push: {e:?}");
+            Err(())
+          }
+        };
+        set_state(match result {
+          Ok(()) => OptInState::Subscribed,
+          Err(()) => OptInState::Failed,
+        });
+      });
+    }
+  };
+  #[cfg(not(feature = "hydrate"))]
+  let _ = public_key;
+
+  move || {
+    match state() {
+    OptInState::Idle | OptInState::Subscribing => view! {
+      <button
+        class="text-periwinkle underline hover:no-underline"
+        disabled=move || state() == OptInState::Subscribing
+        on:click=subscribe
+      >
+        "Notify me about new posts"
+      </button>
+    }
+    .into_view(),
+    OptInState::Subscribed => {
+      view! { <p>"You'll be notified about new posts."</p> }.into_view()
+    }
+    OptInState::Failed => view! {
+      <p>"Couldn't turn on notifications. Check that this site is allowed to send them."</p>
+    }
+    .into_view(),
+  }
+  }
+}
@@ -0,0 +1,273 @@
+//! Anonymous reactions to posts, for readers who want to leave some feedback
+//! without writing a comment. The counts are kept in the database, see
+//! [`ReactionStore`](site_db::storage::ReactionStore).
+//!
+//! Which posts a reader has reacted to is remembered in a cookie, so that
+//! nobody reacts twice by accident. It's easily cleared, so it doesn't stop
+//! anyone determined to, but nothing rides on the counts anyway. Like the
+//! comment form, the bar works without scripts: each reaction is a form, and
+//! the hydrated bar sends it without leaving the page.
+
+use leptos::*;
+use serde::{Deserialize, Serialize};
+
+use crate::error_template::AppError;
+
+/// The reactions readers can leave.
+pub const REACTIONS: &[Reaction] = &[
+  Reaction {
+    name:  "like",
+    emoji: "👍",
+    label: "Like",
+  },
+  Reaction {
+    name:  "love",
+    emoji: "❤️",
+    label: "Love",
+  },
+  Reaction {
+    name:  "rocket",
+    emoji: "🚀",
+    label: "Rocket",
+  },
+];
+
+/// The cookie remembering which posts a reader has reacted to, as
+/// `<post>:<reaction>` pairs separated by slashes.
+#[cfg(feature = "ssr")]
+const REACTED_COOKIE: &str = "reacted";
+/// How many reactions the cookie remembers, dropping the oldest, so that it
+/// stays under the size browsers allow.
+#[cfg(feature = "ssr")]
+const MAX_REMEMBERED: usize = 64;
+/// How long the cookie lasts.
+#[cfg(feature = "ssr")]
+const REACTED_COOKIE_SECS: u64 = 365 * 24 * 60 * 60;
+
+/// A kind of reaction.
+#[derive(Clone, Copy, Debug)]
+pub struct Reaction {
+  /// What the reaction is stored as.
+  pub name:  &'static str,
+  pub emoji: &'static str,
+  /// What the reaction is called for screen readers.
+  pub label: &'static str,
+}
+
+/// How many times a post has had a reaction, and whether this reader left
+/// one of them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReactionTally {
+  pub name:    String,
+  pub count:   i64,
+  pub reacted: bool,
+}
+
+/// The reactions a request's cookie remembers.
+#[cfg(feature = "ssr")]
+fn remembered() -> Vec<String> {
+  let Some(parts) = use_context::<http::request::Parts>() else {
+    return Vec::new();
+  };
+  parts
+    .headers
+    .get_all(http::header::COOKIE)
+    .iter()
+    .filter_map(|cookies| cookies.to_str().ok())
+    .flat_map(|cookies| cookies.split(';'))
+    .filter_map(|cookie| cookie.trim().split_once('='))
+    .filter(|(name, _)| *name == REACTED_COOKIE)
+    .flat_map(|(_, value)| value.split('/'))
+    .filter(|entry| !entry.is_empty())
+    .map(str::to_string)
+    .collect()
+}
+
+/// Remembers the reactions in `entries` for the reader.
+#[cfg(feature = "ssr")]
+fn remember(entries: &[String]) {
+  let config = crate::config::use_site_config();
+  let entries = &entries[entries.len().saturating_sub(MAX_REMEMBERED)..];
+  let cookie = format!(
+    "{REACTED_COOKIE}={}; Max-Age={REACTED_COOKIE_SECS}; Path=/; HttpOnly; \
+     SameSite=Lax{}",
+    entries.join("/"),
+    if config.base_url.starts_with("https:") {
+      "; Secure"
+    } else {
+      ""
+    }
+  );
+  let response = expect_context::<leptos_axum::ResponseOptions>();
+  response.append_header(
+    http::header::SET_COOKIE,
+    http::HeaderValue::from_str(&cookie).expect("cookies are valid headers"),
+  );
+}
+
+/// The tallies of every reaction on the post at `path`, according to the
+/// database and the reader's cookie.
+#[cfg(feature = "ssr")]
+async fn tallies(
+  path: &str,
+  remembered: &[String],
+) -> Result<Vec<ReactionTally>, ServerFnError> {
+  use site_db::storage::ReactionStore;
+
+  let counts = crate::comments::use_database()?
+    .reaction_counts(path)
+    .await
+    .map_err(|e| ServerFnError::new(e.to_string()))?;
+  Ok(
+    REACTIONS
+      .iter()
+      .map(|reaction| ReactionTally {
+        name:    reaction.name.to_string(),
+        count:   counts
+          .iter()
+          .find(|count| count.reaction == reaction.name)
+          .map_or(0, |count| count.count),
+        reacted: remembered.contains(&format!("{path}:{}", reaction.name)),
+      })
+      .collect(),
+  )
+}
+
+/// The reactions on a public post.
+#[server]
+pub async fn get_reactions(
+  path: String,
+) -> Result<Vec<ReactionTally>, ServerFnError> {
+  let path = crate::comments::public_post(&path)?;
+  tallies(&path, &remembered()).await
+}
+
+/// Reacts to a public post, unless the reader already has, returning the
+/// post's reactions. A form sent without scripts goes back to the post.
+#[server]
+pub async fn react(
+  path: String,
+  reaction: String,
+) -> Result<Vec<ReactionTally>, ServerFnError> {
+  use site_db::storage::ReactionStore;
+
+  let path = crate::comments::public_post(&path)?;
+  if !REACTIONS.iter().any(|r| r.name == reaction) {
+    return Err(ServerFnError::new(AppError::NotFound));
+  }
+  let accepts_html =
+    use_context::<http::request::Parts>().is_some_and(|parts| {
+      parts
+        .headers
+        .get(http::header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/html"))
+    });
+  if accepts_html {
+    leptos_axum::redirect(&format!("/post/{path}#reactions"));
+  }
+
+  let mut remembered = remembered();
+  let entry = format!("{path}:{reaction}");
+  if !remembered.contains(&entry) {
+    crate::comments::use_database()?
+      .add_reaction(&path, &reaction)
+      .await
+      .map_err(|e| ServerFnError::new(e.to_string()))?;
+    remembered.push(entry);
+    remember(&remembered);
+  }
+  tallies(&path, &remembered).await
+}
+
+/// A post's reactions, or nothing if they can't be fetched.
+#[component]
+pub fn Reactions(path: String) -> impl IntoView {
+  let reactions_resource = create_resource(
+    move || path.clone(),
+    |path| async {
+      get_reactions(path.clone())
+        .await
+        .map(|tallies| (path, tallies))
+    },
+  );
+
+  view! {
+    <Suspense>
+      <ErrorBoundary fallback=|_| ()>
+        { move || reactions_resource.get().map(|r| r.map_err(AppError::from).map(|(path, tallies)| view! {
+          <ReactionsBar path tallies />
+        })) }
+      </ErrorBoundary>
+    </Suspense>
+  }
+}
+
+/// A button for each reaction, with how many times it's been left. Each one
+/// is a form, which once hydrated is sent without leaving the page.
+#[crate::eager_island]
+fn ReactionsBar(path: String, tallies: Vec<ReactionTally>) -> impl IntoView {
+  let (tallies, set_tallies) = create_signal(tallies);
+  let path = store_value(path);
+
+  let reaction_form = move |reaction: &'static Reaction| {
+    let tally = move || {
+      tallies()
+        .into_iter()
+        .find(|tally| tally.name == reaction.name)
+    };
+    let count = move || tally().map_or(0, |tally| tally.count);
+    let reacted = move || tally().is_some_and(|tally| tally.reacted);
+
+    let on_submit = move |ev: ev::SubmitEvent| {
+      ev.prevent_default();
+      if reacted() {
+        return;
+      }
+      // shown straight away, and put back if the server doesn't take it
+      let before = tallies.get_untracked();
+      set_tallies.update(|tallies| {
+        if let Some(tally) =
+          tallies.iter_mut().find(|tally| tally.name == reaction.name)
+        {
+          tally.count += 1;
+          tally.reacted = true;
+        }
+      });
+      #[cfg(feature = "hydrate")]
+      spawn_local(async move {
+        match react(path.get_value(), reaction.name.to_string()).await {
+          Ok(tallies) => set_tallies(tallies),
+          Err(e) => {
+            logging::error!("failed to react: {e}");
+            set_tallies(before);
+          }
+        }
+      });
+      #[cfg(not(feature = "hydrate"))]
+      let _ = before;
+    };
+
+    view! {
+      <form method="post" action=React::url() on:submit=on_submit>
+        <input type="hidden" name="path" value=path.get_value() />
+        <input type="hidden" name="reaction" value=reaction.name />
+        <button
+          type="submit"
+          aria-label=move || format!("{} ({})", reaction.label, count())
+          aria-pressed=move || reacted().to_string()
+          class="px-2 py-1 bg-neutral-800 hover:bg-neutral-700"
+          class:bg-neutral-700=reacted
+        >
+          {reaction.emoji} " " {count}
+        </button>
+      </form>
+    }
+  };
+
+  view! {
+    <section id="reactions" class="flex gap-2 mt-8" aria-label="Reactions">
+      {REACTIONS.iter().map(reaction_form).collect_view()}
+    </section>
+  }
+}
@@ -0,0 +1,258 @@
+//! A cache of rendered posts, so that only posts which changed are put
+//! through the markdown pipeline again.
+//!
+//! Renders are keyed by a hash of the markdown, the options and the build of
+//! the server. They also record what they depended on outside the markdown:
+//! the hashes of any files they include, and the titles and previews of the
+//! posts and URLs they link to. They're rendered again if any of those change,
+//! so a post is only rendered again for the titles and previews it uses.
+//! Entries are kept in memory and in [`RENDER_CACHE_DIR`], so that restarts and
+//! exports pick up where the last run left off.
+
+use std::{
+  collections::HashMap,
+  path::PathBuf,
+  sync::{Mutex, OnceLock},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use site_markdown::{LinkContext, LinkPreview, Options, RenderedPost};
+
+/// The directory rendered posts are cached in.
+pub const RENDER_CACHE_DIR: &str = "./data/render-cache";
+
+/// A cached render, with the hashes of the files it depended on and the
+/// titles and previews it was rendered with.
+#[derive(Clone, Serialize, Deserialize)]
+struct Entry {
+  files:    Vec<(String, String)>,
+  titles:   Vec<(String, Option<String>)>,
+  previews: Vec<(String, Option<LinkPreview>)>,
+  rendered: RenderedPost,
+}
+
+impl Entry {
+  fn new(rendered: RenderedPost, links: &LinkContext) -> Self {
+    Entry {
+      files: rendered
+        .files
+        .iter()
+        .map(|path| (path.clone(), file_hash(path)))
+        .collect(),
+      titles: rendered
+        .titles
+        .iter()
+        .map(|path| (path.clone(), links.post_titles.get(path).cloned()))
+        .collect(),
+      previews: rendered
+        .previews
+        .iter()
+        .map(|url| (url.clone(), links.link_previews.get(url).cloned()))
+        .collect(),
+      rendered,
+    }
+  }
+
+  /// Whether the render is still what rendering again would give.
+  fn is_fresh(&self, links: &LinkContext) -> bool {
+    self
+      .files
+      .iter()
+      .all(|(path, hash)| file_hash(path) == *hash)
+      && self
+        .titles
+        .iter()
+        .all(|(path, title)| links.post_titles.get(path) == title.as_ref())
+      && self
+        .previews
+        .iter()
+        .all(|(url, preview)| links.link_previews.get(url) == preview.as_ref())
+  }
+}
+
+/// The entries used by this process, keyed by cache key.
+fn entries() -> &'static Mutex<HashMap<String, Entry>> {
+  static ENTRIES: OnceLock<Mutex<HashMap<String, Entry>>> = OnceLock::new();
+  ENTRIES.get_or_init(Default::default)
+}
+
+/// Identifies the build of the server, since a change to the pipeline changes
+/// every render. Without it, renders aren't cached on disk.
+fn build_id() -> Option<&'static str> {
+  static BUILD_ID: OnceLock<Option<String>> = OnceLock::new();
+  BUILD_ID
+    .get_or_init(|| {
+      let exe = std::env::current_exe().ok()?;
+      let modified = std::fs::metadata(&exe).ok()?.modified().ok()?;
+      Some(format!("{}:{modified:?}", exe.display()))
+    })
+    .as_deref()
+}
+
+fn hex_digest(bytes: &[u8]) -> String { format!("{:x}", Sha256::digest(bytes)) }
+
+/// Hashes a file the render depended on. A missing file has a hash too, since
+/// creating it would change the render.
+fn file_hash(path: &str) -> String {
+  std::fs::read(path).map_or_else(|_| "missing".to_string(), |f| hex_digest(&f))
+}
+
+/// Hashes everything that goes into a render, except the link context, which
+/// entries check against instead.
+fn cache_key(markdown: &str, options: &Options) -> String {
+  let mut hasher = Sha256::new();
+  // each part is length-prefixed so that parts can't run into each other
+  let mut update = |part: &str| {
+    hasher.update((part.len() as u64).to_le_bytes());
+    hasher.update(part);
+  };
+
+  update(build_id().unwrap_or_default());
+  update(markdown);
+  update(&format!("{:?}", options.markdown));
+  for component in options.components {
+    update(component);
+  }
+
+  format!("{:x}", hasher.finalize())
+}
+
+fn entry_path(key: &str) -> PathBuf {
+  PathBuf::from(RENDER_CACHE_DIR).join(format!("{key}.json"))
+}
+
+fn read_entry(key: &str) -> Option<Entry> {
+  build_id()?;
+  let entry = std::fs::read(entry_path(key)).ok()?;
+  serde_json::from_slice(&entry).ok()
+}
+
+fn write_entry(key: &str, entry: &Entry) -> std::io::Result<()> {
+  if build_id().is_none() {
+    return Ok(());
+  }
+  let json = serde_json::to_vec(entry).map_err(std::io::Error::other)?;
+  std::fs::create_dir_all(RENDER_CACHE_DIR)?;
+  // written alongside and moved into place, so a concurrent reader never sees
+  // half an entry
+  let path = entry_path(key);
+  let partial = path.with_extension(format!("{}.tmp", std::process::id()));
+  std::fs::write(&partial, json)?;
+  std::fs::rename(partial, path)
+}
+
+/// Renders a markdown document, or returns the cached render if nothing that
+/// goes into it has changed.
+pub fn render(markdown: &str, options: &Options) -> RenderedPost {
+  let key = cache_key(markdown, options);
+
+  let cached = entries().lock().unwrap().get(&key).cloned();
+  if let Some(entry) = cached.or_else(|| read_entry(&key)) {
+    if entry.is_fresh(options.links) {
+      let rendered = entry.rendered.clone();
+      entries().lock().unwrap().insert(key, entry);
+      return rendered;
+    }
+  }
+
+  let rendered = site_markdown::render(markdown, options);
+  let entry = Entry::new(rendered.clone(), options.links);
+  if let Err(e) = write_entry(&key, &entry) {
+    log::warn!("failed to cache a rendered post: {e}");
+  }
+  entries().lock().unwrap().insert(key, entry);
+  rendered
+}
+
+/// Removes the cached renders on disk which this process hasn't used. Once
+/// every post has been rendered, those are the renders of old versions.
+pub fn prune() {
+  let Ok(files) = std::fs::read_dir(RENDER_CACHE_DIR) else {
+    return;
+  };
+  let entries = entries().lock().unwrap();
+  let mut removed = 0;
+  for file in files.flatten() {
+    let path = file.path();
+    let is_used = path
+      .file_stem()
+      .and_then(|stem| stem.to_str())
+      .is_some_and(|key| entries.contains_key(key));
+    if !is_used && std::fs::remove_file(&path).is_ok() {
+      removed += 1;
+    }
+  }
+  if removed > 0 {
+    log::info!("pruned {removed} stale rendered posts from the cache");
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn rendered(titles: &[&str], previews: &[&str]) -> RenderedPost {
+    RenderedPost {
+      html:       String::new(),
+      toc:        Vec::new(),
+      links:      Vec::new(),
+      plaintext:  String::new(),
+      word_count: 0,
+      warnings:   Vec::new(),
+      files:      Vec::new(),
+      titles:     titles.iter().map(ToString::to_string).collect(),
+      previews:   previews.iter().map(ToString::to_string).collect(),
+    }
+  }
+
+  fn preview(title: &str) -> LinkPreview {
+    LinkPreview {
+      title:       title.to_string(),
+      description: None,
+      icon:        None,
+    }
+  }
+
+  #[test]
+  fn renders_only_go_stale_for_the_titles_and_previews_they_use() {
+    let mut links = LinkContext::default();
+    links.post_titles.insert("used".into(), "Used".into());
+    links.post_titles.insert("other".into(), "Other".into());
+    links
+      .link_previews
+      .insert("https://used.example/".into(), preview("Used"));
+    let entry = Entry::new(
+      rendered(&["used", "missing"], &["https://used.example/"]),
+      &links,
+    );
+    assert!(entry.is_fresh(&links));
+
+    links.post_titles.insert("other".into(), "Renamed".into());
+    links
+      .link_previews
+      .insert("https://other.example/".into(), preview("Other"));
+    assert!(entry.is_fresh(&links), "unrelated changes");
+
+    let mut retitled = LinkContext {
+      post_titles:   links.post_titles.clone(),
+      link_previews: links.link_previews.clone(),
+    };
+    retitled.post_titles.insert("used".into(), "Renamed".into());
+    assert!(!entry.is_fresh(&retitled), "a linked post was retitled");
+
+    let mut created = LinkContext {
+      post_titles:   links.post_titles.clone(),
+      link_previews: links.link_previews.clone(),
+    };
+    created
+      .post_titles
+      .insert("missing".into(), "Missing".into());
+    assert!(!entry.is_fresh(&created), "a linked post was created");
+
+    links
+      .link_previews
+      .insert("https://used.example/".into(), preview("Refetched"));
+    assert!(!entry.is_fresh(&links), "a used preview changed");
+  }
+}
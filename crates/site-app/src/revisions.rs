@@ -0,0 +1,463 @@
+//! The "what changed" view, which shows returning readers a word-level diff
+//! between a post and its previous published revision.
+//!
+//! Revisions come from the git history of the post's file, so posts served
+//! from a checkout without history (like the container image) just don't get
+//! the view.
+
+use leptos::*;
+use leptos_meta::Title;
+use serde::{Deserialize, Serialize};
+
+use crate::error_template::{AppError, ErrorTemplate};
+
+/// Whether a span of a diff was kept, added or removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Change {
+  Unchanged,
+  Added,
+  Removed,
+}
+
+/// A run of text in a diff.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffSpan {
+  pub change: Change,
+  pub text:   String,
+}
+
+/// The changes to a post since its previous published revision.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PostChanges {
+  pub title:      String,
+  /// The date the previous revision was committed, as `YYYY-MM-DD`.
+  pub since:      String,
+  /// Each paragraph that changed, as a diff of its words.
+  pub paragraphs: Vec<Vec<DiffSpan>>,
+}
+
+/// The most commits searched for a previous revision.
+#[cfg(feature = "ssr")]
+const MAX_REVISIONS: usize = 20;
+/// The largest diff table that's computed. Bigger inputs are shown as
+/// entirely removed and added instead.
+#[cfg(feature = "ssr")]
+const MAX_DIFF_CELLS: usize = 1_000_000;
+
+/// A previous revision of a post.
+#[cfg(feature = "ssr")]
+#[derive(Clone, Debug)]
+pub struct Revision {
+  /// The date the revision was committed, as `YYYY-MM-DD`.
+  pub date: String,
+  /// The revision's markdown body.
+  pub body: String,
+}
+
+/// Runs git in `dir`, returning its output if it succeeded.
+#[cfg(feature = "ssr")]
+fn git(dir: &std::path::Path, args: &[&str]) -> Option<String> {
+  let output = std::process::Command::new("git")
+    .arg("-C")
+    .arg(dir)
+    .args(args)
+    .output()
+    .ok()?;
+  output
+    .status
+    .success()
+    .then(|| String::from_utf8(output.stdout).ok())
+    .flatten()
+}
+
+/// Finds the most recent public revision of a post file in git history whose
+/// body differs from `current_body`. Git runs in the file's own directory, so
+/// this works whatever the working directory and wherever the content is
+/// checked out.
+#[cfg(feature = "ssr")]
+fn find_previous_revision(
+  file: &std::path::Path,
+  current_body: &str,
+) -> Option<Revision> {
+  let dir = file.parent()?;
+  let name = file.file_name()?.to_str()?;
+  let log = git(dir, &[
+    "log",
+    "--format=%H %cs",
+    "-n",
+    &MAX_REVISIONS.to_string(),
+    "--",
+    name,
+  ])?;
+  // `git show` wants the path from the root of the repository
+  let prefix = git(dir, &["rev-parse", "--show-prefix"])?;
+  let file = format!("{}{name}", prefix.trim_end());
+
+  log.lines().find_map(|line| {
+    let (commit, date) = line.split_once(' ')?;
+    let input = git(dir, &["show", &format!("{commit}:{file}")])?;
+    let (metadata, body) = crate::posts::try_parse_frontmatter(&input).ok()?;
+    (metadata.public && body.trim() != current_body.trim()).then(|| Revision {
+      date: date.to_string(),
+      body,
+    })
+  })
+}
+
+/// Returns the previous revision of a post with the given body, caching the
+/// answer until the body changes.
+#[cfg(feature = "ssr")]
+pub fn previous_revision(path: &str, current_body: &str) -> Option<Revision> {
+  use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+  };
+  let _span = tracing::debug_span!("previous_revision", path).entered();
+
+  type Cache = Mutex<HashMap<String, (String, Option<Revision>)>>;
+  static CACHE: OnceLock<Cache> = OnceLock::new();
+  let cache = CACHE.get_or_init(Default::default);
+
+  if let Some((body, revision)) = cache.lock().unwrap().get(path) {
+    if body == current_body {
+      return revision.clone();
+    }
+  }
+  let revision = crate::posts::post_file(path)
+    .and_then(|file| find_previous_revision(&file, current_body));
+  cache.lock().unwrap().insert(
+    path.to_string(),
+    (current_body.to_string(), revision.clone()),
+  );
+  revision
+}
+
+/// An edit turning one sequence into another.
+#[cfg(feature = "ssr")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Edit {
+  Keep(usize),
+  Remove(usize),
+  Add(usize),
+}
+
+/// Computes the edits turning `old` into `new` from their longest common
+/// subsequence, with indices into whichever of the two the edit applies to.
+#[cfg(feature = "ssr")]
+fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+  let (n, m) = (old.len(), new.len());
+  if n.saturating_mul(m) > MAX_DIFF_CELLS {
+    return (0..n)
+      .map(Edit::Remove)
+      .chain((0..m).map(Edit::Add))
+      .collect();
+  }
+
+  // lengths[i][j] is the length of the LCS of old[i..] and new[j..]
+  let mut lengths = vec![0u32; (n + 1) * (m + 1)];
+  let at = |i: usize, j: usize| i * (m + 1) + j;
+  for i in (0..n).rev() {
+    for j in (0..m).rev() {
+      lengths[at(i, j)] = if old[i] == new[j] {
+        lengths[at(i + 1, j + 1)] + 1
+      } else {
+        lengths[at(i + 1, j)].max(lengths[at(i, j + 1)])
+      };
+    }
+  }
+
+  let mut edits = Vec::with_capacity(n.max(m));
+  let (mut i, mut j) = (0, 0);
+  while i < n && j < m {
+    if old[i] == new[j] {
+      edits.push(Edit::Keep(i));
+      i += 1;
+      j += 1;
+    } else if lengths[at(i + 1, j)] >= lengths[at(i, j + 1)] {
+      edits.push(Edit::Remove(i));
+      i += 1;
+    } else {
+      edits.push(Edit::Add(j));
+      j += 1;
+    }
+  }
+  edits.extend((i..n).map(Edit::Remove));
+  edits.extend((j..m).map(Edit::Add));
+  edits
+}
+
+/// Appends text to a list of spans, merging it into the last span if that
+/// has the same kind of change.
+#[cfg(feature = "ssr")]
+fn push_span(spans: &mut Vec<DiffSpan>, change: Change, text: &str) {
+  match spans.last_mut() {
+    Some(last) if last.change == change => last.text.push_str(text),
+    _ => spans.push(DiffSpan {
+      change,
+      text: text.to_string(),
+    }),
+  }
+}
+
+/// Diffs two versions of a paragraph word by word.
+#[cfg(feature = "ssr")]
+fn diff_words(old: &str, new: &str) -> Vec<DiffSpan> {
+  let old_words = old.split_inclusive(' ').collect::<Vec<_>>();
+  let new_words = new.split_inclusive(' ').collect::<Vec<_>>();
+
+  let mut spans = Vec::new();
+  for edit in diff(&old_words, &new_words) {
+    match edit {
+      Edit::Keep(i) => push_span(&mut spans, Change::Unchanged, old_words[i]),
+      Edit::Remove(i) => push_span(&mut spans, Change::Removed, old_words[i]),
+      Edit::Add(j) => push_span(&mut spans, Change::Added, new_words[j]),
+    }
+  }
+  spans
+}
+
+/// Diffs two versions of a post's plaintext, one paragraph per line,
+/// returning only the paragraphs that changed. Paragraphs that were rewritten
+/// are diffed word by word against the paragraph they replaced.
+#[cfg(feature = "ssr")]
+pub fn diff_paragraphs(old: &str, new: &str) -> Vec<Vec<DiffSpan>> {
+  let old = old.lines().collect::<Vec<_>>();
+  let new = new.lines().collect::<Vec<_>>();
+
+  let mut paragraphs = Vec::new();
+  let (mut removed, mut added) = (Vec::new(), Vec::new());
+  let mut flush = |removed: &mut Vec<&str>, added: &mut Vec<&str>| {
+    let mut added_rest = added.drain(..);
+    for old in removed.drain(..) {
+      paragraphs.push(match added_rest.next() {
+        Some(new) => diff_words(old, new),
+        None => vec![DiffSpan {
+          change: Change::Removed,
+          text:   old.to_string(),
+        }],
+      });
+    }
+    paragraphs.extend(added_rest.map(|new| {
+      vec![DiffSpan {
+        change: Change::Added,
+        text:   new.to_string(),
+      }]
+    }));
+  };
+
+  for edit in diff(&old, &new) {
+    match edit {
+      Edit::Keep(_) => flush(&mut removed, &mut added),
+      Edit::Remove(i) => removed.push(old[i]),
+      Edit::Add(j) => added.push(new[j]),
+    }
+  }
+  flush(&mut removed, &mut added);
+  paragraphs
+}
+
+#[server]
+pub async fn get_post_changes(
+  path: String,
+) -> Result<PostChanges, ServerFnError> {
+  use crate::{
+    post_index::PostIndex,
+    posts::{
+      blocking, get_post_by_path, link_context, render_post,
+      try_parse_frontmatter,
+    },
+  };
+
+  // resolves the post the same way its page does, including the 404s
+  let post = get_post_by_path(path).await?;
+  let path = post.path.clone();
+  let index = expect_context::<PostIndex>();
+  let Some(input) = index.source(&path) else {
+    return Err(ServerFnError::new(AppError::NotFound));
+  };
+  let (metadata, body) = try_parse_frontmatter(&input)
+    .map_err(|_| ServerFnError::new(AppError::InvalidContent))?;
+
+  // running git, rendering and diffing all block
+  blocking(move || {
+    let revision = previous_revision(&path, &body)?;
+    let context = link_context(&index.sources());
+    let previous = render_post(&path, &revision.body, &metadata, &context);
+
+    Some(PostChanges {
+      title:      post.metadata.title,
+      since:      revision.date,
+      paragraphs: diff_paragraphs(&previous.plaintext, &post.plaintext),
+    })
+  })
+  .await?
+  .ok_or_else(|| ServerFnError::new(AppError::NotFound))
+}
+
+fn render_span(span: DiffSpan) -> View {
+  match span.change {
+    Change::Unchanged => span.text.into_view(),
+    Change::Added => view! { <ins>{span.text}</ins> }.into_view(),
+    Change::Removed => view! { <del>{span.text}</del> }.into_view(),
+  }
+}
+
+/// Shows what changed in a post since its previous published revision.
+#[component]
+pub fn PostChangesPage() -> impl IntoView {
+  let path = crate::posts::use_post_path();
+
+  let post_href = store_value(format!("/post/{path}"));
+
+  let changes_resource =
+    create_blocking_resource(move || path.clone(), get_post_changes);
+
+  view! {
+    <Suspense>
+      <ErrorBoundary fallback=|errors| view! { <ErrorTemplate errors /> }>
+        { move || changes_resource.get().map(|c| c.map_err(AppError::from).map(|changes| view! {
+          <Title text=format!("What changed in {}", changes.title) />
+          <div class="markdown">
+            <h1>"What changed in "<a href=post_href.get_value()>{changes.title}</a></h1>
+            <p>"Changes since the revision of " {changes.since} "."</p>
+            <hr />
+            { changes.paragraphs.into_iter().map(|spans| view! {
+              <p class="diff-paragraph">
+                { spans.into_iter().map(render_span).collect_view() }
+              </p>
+            }).collect_view() }
+          </div>
+        }))}
+      </ErrorBoundary>
+    </Suspense>
+  }
+}
+
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+  use super::*;
+
+  fn span(change: Change, text: &str) -> DiffSpan {
+    DiffSpan {
+      change,
+      text: text.to_string(),
+    }
+  }
+
+  #[test]
+  fn diff_follows_the_longest_common_subsequence() {
+    assert_eq!(diff(&["a", "b", "c"], &["a", "c", "d"]), vec![
+      Edit::Keep(0),
+      Edit::Remove(1),
+      Edit::Keep(2),
+      Edit::Add(2),
+    ]);
+    assert_eq!(diff(&["a", "b"], &["b", "a", "b"]), vec![
+      Edit::Add(0),
+      Edit::Keep(0),
+      Edit::Keep(1),
+    ]);
+    assert_eq!(diff::<&str>(&[], &["a"]), vec![Edit::Add(0)]);
+    assert_eq!(diff(&["a"], &[]), vec![Edit::Remove(0)]);
+  }
+
+  #[test]
+  fn diffs_too_big_to_compute_replace_everything() {
+    let old = vec![0; 1001];
+    let new = vec![0; 1000];
+    assert!(old.len() * new.len() > MAX_DIFF_CELLS);
+
+    let edits = diff(&old, &new);
+    assert_eq!(edits.len(), 2001);
+    assert!(edits[..1001].iter().all(|e| matches!(e, Edit::Remove(_))));
+    assert!(edits[1001..].iter().all(|e| matches!(e, Edit::Add(_))));
+  }
+
+  #[test]
+  fn words_are_diffed_with_their_spacing() {
+    assert_eq!(
+      diff_words("the quick brown fox", "the slow brown fox jumps"),
+      vec![
+        span(Change::Unchanged, "the "),
+        span(Change::Removed, "quick "),
+        span(Change::Added, "slow "),
+        span(Change::Unchanged, "brown "),
+        span(Change::Removed, "fox"),
+        span(Change::Added, "fox jumps"),
+      ]
+    );
+    assert_eq!(diff_words("same words", "same words"), vec![span(
+      Change::Unchanged,
+      "same words"
+    )]);
+  }
+
+  #[test]
+  fn only_changed_paragraphs_are_returned() {
+    let old = "kept\nrewritten once\nremoved";
+    let new = "kept\nrewritten twice\nadded";
+    assert_eq!(diff_paragraphs(old, new), vec![
+      vec![
+        span(Change::Unchanged, "rewritten "),
+        span(Change::Removed, "once"),
+        span(Change::Added, "twice"),
+      ],
+      vec![
+        span(Change::Removed, "removed"),
+        span(Change::Added, "added")
+      ],
+    ]);
+    assert_eq!(diff_paragraphs("kept", "kept\nadded"), vec![vec![span(
+      Change::Added,
+      "added"
+    )]]);
+  }
+
+  #[test]
+  fn previous_revisions_are_read_from_the_posts_repository() {
+    let root = std::env::temp_dir()
+      .join(format!("revisions-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    let posts = root.join("content/posts");
+    std::fs::create_dir_all(&posts).unwrap();
+    let file = posts.join("hello.md");
+
+    let commit = |public: bool, body: &str| {
+      std::fs::write(
+        &file,
+        format!(
+          "---\ntitle: Hello\nwritten_on: \"2024.03.01\"\npublic: \
+           {public}\n---\n{body}\n"
+        ),
+      )
+      .unwrap();
+      git(&root, &["add", "."]).unwrap();
+      git(&root, &[
+        "-c",
+        "user.name=Someone",
+        "-c",
+        "user.email=someone@example.com",
+        "-c",
+        "commit.gpgsign=false",
+        "commit",
+        "-q",
+        "-m",
+        body,
+      ])
+      .unwrap();
+    };
+    git(&root, &["init", "-q"]).unwrap();
+    commit(true, "first");
+    commit(false, "draft");
+    commit(true, "second");
+
+    // the test runs from the crate, inside another repository entirely
+    let revision = find_previous_revision(&file, "second").unwrap();
+    assert_eq!(revision.body.trim(), "first");
+    // an edit not yet committed is compared with the last commit
+    let revision = find_previous_revision(&file, "third").unwrap();
+    assert_eq!(revision.body.trim(), "second");
+
+    std::fs::remove_dir_all(&root).unwrap();
+  }
+}
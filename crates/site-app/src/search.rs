@@ -0,0 +1,1164 @@
+//! Full-text search of the public posts, at [`SEARCH_PATH`].
+//!
+//! The public posts are indexed with [tantivy](https://docs.rs/tantivy) as
+//! they're rendered, see [`PostIndex`](crate::post_index::PostIndex), in
+//! memory since every post is read at startup anyway. Every word of a search
+//! has to be in a post's title, tags or body for the post to match, where any
+//! word the index has starting with it counts.
+//! Words in quotes also have to be in that order, and `tag:<tag>` only matches
+//! posts with the tag. A word nothing matches is taken to be misspelled, and
+//! matches words a typo or two away, so that "lepots" still finds "Leptos".
+//! The posts are ranked by tantivy's BM25, boosting words in the title and
+//! tags to count as many in the body, and each result has a snippet of the
+//! body around its first match.
+//!
+//! The search box also searches as you type once it's hydrated, without
+//! asking the server each time: on first use it fetches an index of every
+//! public post's words from [`SEARCH_INDEX_PATH`], and matches them the same
+//! way, apart from the order of quoted words. The index has no bodies, so
+//! those results are ranked more simply and have no snippets.
+
+use leptos::*;
+use leptos_meta::{Meta, Title};
+use leptos_router::use_query_map;
+use serde::{Deserialize, Serialize};
+
+use crate::error_template::{AppError, ErrorTemplate};
+
+/// Where the search page is.
+pub const SEARCH_PATH: &str = "/search";
+/// Where the index the search box searches as you type is served.
+pub const SEARCH_INDEX_PATH: &str = "/search-index.json";
+/// The longest search, in characters. Anything past it is ignored.
+const MAX_QUERY_CHARS: usize = 200;
+/// The most results a search returns.
+#[cfg(feature = "ssr")]
+const MAX_RESULTS: usize = 20;
+/// The most results the search box shows as you type.
+const MAX_INSTANT_RESULTS: usize = 8;
+/// The most words of a post's body the search box shows it matched.
+const MAX_BODY_MATCHES: usize = 3;
+/// About how many characters of the body a snippet shows on either side of
+/// its match.
+#[cfg(feature = "ssr")]
+const SNIPPET_CONTEXT_CHARS: usize = 80;
+/// How much a match in the title outweighs one in the body.
+const TITLE_WEIGHT: f64 = 10.0;
+/// How much a match in the tags outweighs one in the body.
+const TAG_WEIGHT: f64 = 5.0;
+/// How much memory the search index is written with, which is the least
+/// tantivy allows. The posts are far smaller.
+#[cfg(feature = "ssr")]
+const WRITER_MEMORY_BYTES: usize = 15_000_000;
+
+/// Splits text into lowercase words, with where each starts and ends.
+fn words(text: &str) -> Vec<(usize, usize, String)> {
+  let mut words = Vec::new();
+  let mut start = None;
+  for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+    match (c.is_alphanumeric(), start) {
+      (true, None) => start = Some(i),
+      (false, Some(word_start)) => {
+        words.push((word_start, i, text[word_start..i].to_lowercase()));
+        start = None;
+      }
+      _ => {}
+    }
+  }
+  words
+}
+
+/// A tag as searches match it: its words, joined by dashes, so that
+/// `tag:game-dev` finds posts tagged "Game Dev".
+pub fn tag_key(tag: &str) -> String {
+  words(tag)
+    .into_iter()
+    .map(|(_, _, word)| word)
+    .collect::<Vec<_>>()
+    .join("-")
+}
+
+/// How many typos a word of a search may have, so that short words, which
+/// are a typo away from too many others, have to be spelled right.
+fn allowed_typos(length: usize) -> usize {
+  match length {
+    0..=3 => 0,
+    4..=7 => 1,
+    _ => 2,
+  }
+}
+
+/// Whether `word` starts with something at most [`allowed_typos`] typos away
+/// from `term`, where a letter missing, extra, wrong or swapped with the next
+/// is a typo.
+fn starts_with_typos(word: &str, term: &str) -> bool {
+  let term = term.chars().collect::<Vec<_>>();
+  let allowed = allowed_typos(term.len());
+  if allowed == 0 {
+    return false;
+  }
+  let word = word.chars().take(term.len() + allowed).collect::<Vec<_>>();
+  // `typos[i][j]` is how many typos the first `i` letters of the term are from
+  // the first `j` of the word
+  let mut typos = vec![vec![0; word.len() + 1]; term.len() + 1];
+  for (i, row) in typos.iter_mut().enumerate() {
+    row[0] = i;
+  }
+  for (j, typo) in typos[0].iter_mut().enumerate() {
+    *typo = j;
+  }
+  for i in 1..=term.len() {
+    for j in 1..=word.len() {
+      let substitution =
+        typos[i - 1][j - 1] + usize::from(term[i - 1] != word[j - 1]);
+      let mut fewest = substitution
+        .min(typos[i - 1][j] + 1)
+        .min(typos[i][j - 1] + 1);
+      if i > 1
+        && j > 1
+        && term[i - 1] == word[j - 2]
+        && term[i - 2] == word[j - 1]
+      {
+        fewest = fewest.min(typos[i - 2][j - 2] + 1);
+      }
+      typos[i][j] = fewest;
+    }
+  }
+  typos[term.len()].iter().any(|&typos| typos <= allowed)
+}
+
+/// A word of a search, which matches the words starting with it.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Term {
+  text:  String,
+  /// Whether the word may be misspelled, so that it also matches the words
+  /// starting with something a typo or two away from it. It's only thought to
+  /// be when nothing matches it as it is.
+  fuzzy: bool,
+}
+
+impl Term {
+  fn matches(&self, word: &str) -> bool {
+    word.starts_with(self.text.as_str())
+      || (self.fuzzy && starts_with_typos(word, &self.text))
+  }
+}
+
+/// A search, like `"static site" nix tag:rust`.
+#[derive(Clone, Debug, Default)]
+struct Query {
+  /// Every word of the search, in quotes or not.
+  terms:   Vec<Term>,
+  /// The runs of words in quotes, which have to be in that order.
+  phrases: Vec<Vec<String>>,
+  /// The tags posts have to have, as [`tag_key`]s.
+  tags:    Vec<String>,
+}
+
+impl Query {
+  fn parse(query: &str) -> Self {
+    let query = query.chars().take(MAX_QUERY_CHARS).collect::<String>();
+    let words_of = |text: &str| {
+      words(text)
+        .into_iter()
+        .map(|(_, _, word)| word)
+        .collect::<Vec<_>>()
+    };
+    let terms_of = |text: &str| {
+      words_of(text)
+        .into_iter()
+        .map(|text| Term { text, fuzzy: false })
+    };
+    let mut parsed = Query::default();
+    // every other part is in quotes, and a quote left open runs to the end
+    for (i, part) in query.split('"').enumerate() {
+      if i % 2 == 1 {
+        parsed.terms.extend(terms_of(part));
+        let phrase = words_of(part);
+        if phrase.len() > 1 {
+          parsed.phrases.push(phrase);
+        }
+        continue;
+      }
+      for token in part.split_whitespace() {
+        match token.strip_prefix("tag:").map(tag_key) {
+          Some(tag) if !tag.is_empty() => parsed.tags.push(tag),
+          Some(_) => {}
+          None => parsed.terms.extend(terms_of(token)),
+        }
+      }
+    }
+    parsed.terms.sort();
+    parsed.terms.dedup();
+    parsed
+  }
+
+  fn is_empty(&self) -> bool { self.terms.is_empty() && self.tags.is_empty() }
+
+  /// Takes the terms which aren't `matched` to be misspelled.
+  fn allow_typos(&mut self, matched: impl Fn(&Term) -> bool) {
+    for term in self.terms.iter_mut() {
+      term.fuzzy = !matched(term);
+    }
+  }
+}
+
+/// Whether `word` matches any of `terms`.
+fn matches(word: &str, terms: &[Term]) -> bool {
+  terms.iter().any(|term| term.matches(word))
+}
+
+/// How many of `words` match `term`.
+fn count_matches(words: &[String], term: &Term) -> usize {
+  words.iter().filter(|word| term.matches(word)).count()
+}
+
+/// A post, split into words for its results' snippets and the search box's
+/// index.
+#[cfg(feature = "ssr")]
+#[derive(Clone, Debug)]
+struct SearchDocument {
+  title:       String,
+  written_on:  String,
+  public:      bool,
+  /// The post's tags, as [`tag_key`]s.
+  tags:        Vec<String>,
+  body:        String,
+  /// Where each word of the body starts, in order.
+  word_starts: Vec<usize>,
+  /// Each word in the body, with where it is among
+  /// [`word_starts`](Self::word_starts).
+  body_words:  std::collections::HashMap<String, Vec<usize>>,
+}
+
+#[cfg(feature = "ssr")]
+impl SearchDocument {
+  fn new(post: &crate::posts::Post) -> Self {
+    let mut word_starts = Vec::new();
+    let mut body_words = std::collections::HashMap::<_, Vec<_>>::new();
+    for (position, (start, _, word)) in
+      words(&post.plaintext).into_iter().enumerate()
+    {
+      word_starts.push(start);
+      body_words.entry(word).or_default().push(position);
+    }
+    SearchDocument {
+      title: post.metadata.title.clone(),
+      written_on: post.metadata.written_on.clone(),
+      public: post.metadata.public,
+      tags: post.metadata.tags.iter().map(|tag| tag_key(tag)).collect(),
+      body: post.plaintext.clone(),
+      word_starts,
+      body_words,
+    }
+  }
+
+  /// Where in the body `term` is first, if it is.
+  fn first_match(&self, term: &Term) -> Option<usize> {
+    self
+      .body_words
+      .iter()
+      .filter(|(word, _)| term.matches(word))
+      .filter_map(|(_, positions)| positions.first())
+      .min()
+      .map(|&position| self.word_starts[position])
+  }
+
+  /// Where in the body the words of `phrase` first are in that order, if
+  /// they are.
+  fn find_phrase(&self, phrase: &[String]) -> Option<usize> {
+    let positions = self.body_words.get(&phrase[0])?;
+    positions
+      .iter()
+      .copied()
+      .find(|&start| {
+        phrase.iter().enumerate().skip(1).all(|(offset, word)| {
+          self.body_words.get(word).is_some_and(|positions| {
+            positions.binary_search(&(start + offset)).is_ok()
+          })
+        })
+      })
+      .map(|position| self.word_starts[position])
+  }
+}
+
+/// The fields of the posts in the search index.
+#[cfg(feature = "ssr")]
+#[derive(Clone, Copy)]
+struct Fields {
+  path:      tantivy::schema::Field,
+  title:     tantivy::schema::Field,
+  /// The post's tags, as [`tag_key`]s, which are matched whole.
+  tags:      tantivy::schema::Field,
+  /// The words of the post's tags, which searches' words match.
+  tag_words: tantivy::schema::Field,
+  body:      tantivy::schema::Field,
+}
+
+/// The words indexed in `field` which `term` matches.
+#[cfg(feature = "ssr")]
+fn indexed_words(
+  searcher: &tantivy::Searcher,
+  field: tantivy::schema::Field,
+  term: &Term,
+) -> Vec<String> {
+  let mut words = Vec::new();
+  for segment in searcher.segment_readers() {
+    let Ok(inverted_index) = segment.inverted_index(field) else {
+      continue;
+    };
+    let terms = inverted_index.terms();
+    // the words starting with the term follow the spot it would be in
+    let stream = match term.fuzzy {
+      false => terms.range().ge(term.text.as_bytes()).into_stream(),
+      true => terms.stream(),
+    };
+    let Ok(mut stream) = stream else {
+      continue;
+    };
+    while stream.advance() {
+      let Ok(word) = std::str::from_utf8(stream.key()) else {
+        continue;
+      };
+      if term.matches(word) {
+        words.push(word.to_string());
+      } else if !term.fuzzy {
+        break;
+      }
+    }
+  }
+  words.sort();
+  words.dedup();
+  words
+}
+
+/// Every post which rendered, with the public ones in a tantivy index which
+/// matches and ranks searches.
+#[cfg(feature = "ssr")]
+pub(crate) struct SearchIndex {
+  documents: std::collections::HashMap<String, SearchDocument>,
+  fields:    Fields,
+  writer:    tantivy::IndexWriter,
+  reader:    tantivy::IndexReader,
+}
+
+#[cfg(feature = "ssr")]
+impl Default for SearchIndex {
+  fn default() -> Self {
+    use tantivy::schema::{Schema, STORED, STRING, TEXT};
+
+    let mut schema = Schema::builder();
+    let fields = Fields {
+      path:      schema.add_text_field("path", STRING | STORED),
+      title:     schema.add_text_field("title", TEXT),
+      tags:      schema.add_text_field("tags", STRING),
+      tag_words: schema.add_text_field("tag_words", TEXT),
+      body:      schema.add_text_field("body", TEXT),
+    };
+    let index = tantivy::Index::create_in_ram(schema.build());
+    let writer = index
+      .writer_with_num_threads(1, WRITER_MEMORY_BYTES)
+      .expect("an index in memory can be written");
+    let reader = index
+      .reader_builder()
+      .reload_policy(tantivy::ReloadPolicy::Manual)
+      .try_into()
+      .expect("an index in memory can be read");
+    SearchIndex {
+      documents: Default::default(),
+      fields,
+      writer,
+      reader,
+    }
+  }
+}
+
+#[cfg(feature = "ssr")]
+impl SearchIndex {
+  /// Indexes `posts`, keyed by path, in place of every post indexed before.
+  pub(crate) fn replace_all<'a>(
+    &mut self,
+    posts: impl IntoIterator<Item = (&'a String, &'a crate::posts::Post)>,
+  ) {
+    self.documents.clear();
+    if let Err(e) = self.writer.delete_all_documents() {
+      log::error!("failed to clear the search index: {e}");
+    }
+    for (path, post) in posts {
+      self.add(path, post);
+    }
+    self.commit();
+  }
+
+  /// Indexes the post at `path`, in place of the one indexed there before.
+  pub(crate) fn insert(&mut self, path: &str, post: &crate::posts::Post) {
+    self.delete(path);
+    self.add(path, post);
+    self.commit();
+  }
+
+  /// Takes the post at `path` out of the index.
+  pub(crate) fn remove(&mut self, path: &str) {
+    self.delete(path);
+    self.documents.remove(path);
+    self.commit();
+  }
+
+  fn add(&mut self, path: &str, post: &crate::posts::Post) {
+    let document = SearchDocument::new(post);
+    if document.public {
+      let mut indexed = tantivy::TantivyDocument::new();
+      indexed.add_text(self.fields.path, path);
+      indexed.add_text(self.fields.title, &document.title);
+      for tag in post.metadata.tags.iter() {
+        indexed.add_text(self.fields.tag_words, tag);
+      }
+      for tag in document.tags.iter() {
+        indexed.add_text(self.fields.tags, tag);
+      }
+      indexed.add_text(self.fields.body, &document.body);
+      if let Err(e) = self.writer.add_document(indexed) {
+        log::error!("failed to index `{path}` for searching: {e}");
+      }
+    }
+    self.documents.insert(path.to_string(), document);
+  }
+
+  fn delete(&mut self, path: &str) {
+    self
+      .writer
+      .delete_term(tantivy::Term::from_field_text(self.fields.path, path));
+  }
+
+  /// Makes the changes to the index visible to searches.
+  fn commit(&mut self) {
+    let committed = self.writer.commit().and_then(|_| self.reader.reload());
+    if let Err(e) = committed {
+      log::error!("failed to update the search index: {e}");
+    }
+  }
+
+  /// The paths of the posts `query` matches, with tantivy's score for each.
+  fn rank(
+    &self,
+    searcher: &tantivy::Searcher,
+    query: &Query,
+  ) -> tantivy::Result<Vec<(f32, String)>> {
+    use tantivy::{
+      collector::TopDocs,
+      query::{
+        BooleanQuery, BoostQuery, ConstScoreQuery, Occur, PhraseQuery,
+        Query as TantivyQuery, TermQuery,
+      },
+      schema::{IndexRecordOption, Value},
+    };
+
+    let fields = self.fields;
+    let word_of =
+      |field, word: &str| tantivy::Term::from_field_text(field, word);
+    let mut clauses = Vec::<(Occur, Box<dyn TantivyQuery>)>::new();
+    for term in query.terms.iter() {
+      let weighted = [
+        (fields.title, TITLE_WEIGHT),
+        (fields.tag_words, TAG_WEIGHT),
+        (fields.body, 1.0),
+      ];
+      let matches = weighted
+        .into_iter()
+        .flat_map(|(field, weight)| {
+          indexed_words(searcher, field, term)
+            .into_iter()
+            .map(move |word| {
+              let word = TermQuery::new(
+                word_of(field, &word),
+                IndexRecordOption::WithFreqs,
+              );
+              let word = BoostQuery::new(Box::new(word), weight as f32);
+              (Occur::Should, Box::new(word) as Box<dyn TantivyQuery>)
+            })
+        })
+        .collect::<Vec<_>>();
+      if matches.is_empty() {
+        return Ok(Vec::new());
+      }
+      clauses.push((Occur::Must, Box::new(BooleanQuery::new(matches))));
+    }
+    for phrase in query.phrases.iter() {
+      let in_field = |field| {
+        let words = phrase.iter().map(|word| word_of(field, word)).collect();
+        (
+          Occur::Should,
+          Box::new(PhraseQuery::new(words)) as Box<dyn TantivyQuery>,
+        )
+      };
+      let in_title_or_body =
+        BooleanQuery::new(vec![in_field(fields.title), in_field(fields.body)]);
+      clauses.push((Occur::Must, Box::new(in_title_or_body)));
+    }
+    for tag in query.tags.iter() {
+      let tag =
+        TermQuery::new(word_of(fields.tags, tag), IndexRecordOption::Basic);
+      // only a filter, so it doesn't change the posts' ranks
+      let tag = ConstScoreQuery::new(Box::new(tag), 0.0);
+      clauses.push((Occur::Must, Box::new(tag)));
+    }
+
+    // every match, since equals are ordered by date rather than tantivy's way
+    let limit = usize::try_from(searcher.num_docs()).unwrap_or(usize::MAX);
+    let matches = searcher.search(
+      &BooleanQuery::new(clauses),
+      &TopDocs::with_limit(limit.max(1)),
+    )?;
+    matches
+      .into_iter()
+      .map(|(score, address)| {
+        let document = searcher.doc::<tantivy::TantivyDocument>(address)?;
+        let path = document
+          .get_first(fields.path)
+          .and_then(|path| path.as_str())
+          .unwrap_or_default();
+        Ok((score, path.to_string()))
+      })
+      .collect()
+  }
+
+  /// The public posts matching `query`, best first, and newest first among
+  /// equals.
+  pub(crate) fn search(&self, query: &str) -> Vec<SearchResult> {
+    let mut query = Query::parse(query);
+    if query.is_empty() {
+      return Vec::new();
+    }
+    let searcher = self.reader.searcher();
+    let fields = [self.fields.title, self.fields.tag_words, self.fields.body];
+    query.allow_typos(|term| {
+      fields
+        .iter()
+        .any(|&field| !indexed_words(&searcher, field, term).is_empty())
+    });
+    let ranked = match self.rank(&searcher, &query) {
+      Ok(ranked) => ranked,
+      Err(e) => {
+        log::error!("failed to search the posts: {e}");
+        return Vec::new();
+      }
+    };
+
+    let mut ranked = ranked
+      .into_iter()
+      .filter_map(|(score, path)| {
+        let (path, document) = self.documents.get_key_value(&path)?;
+        Some((score, path, document))
+      })
+      .collect::<Vec<_>>();
+    ranked.sort_by(|a, b| {
+      b.0
+        .total_cmp(&a.0)
+        .then_with(|| b.2.written_on.cmp(&a.2.written_on))
+        .then_with(|| a.1.cmp(b.1))
+    });
+    ranked
+      .into_iter()
+      .take(MAX_RESULTS)
+      .map(|(_, path, document)| {
+        // the snippet is of the first phrase, or else the first word
+        let first_match = query
+          .phrases
+          .iter()
+          .find_map(|phrase| document.find_phrase(phrase))
+          .or_else(|| {
+            query
+              .terms
+              .iter()
+              .filter_map(|term| document.first_match(term))
+              .min()
+          });
+        SearchResult {
+          path:       path.clone(),
+          title:      highlight(&document.title, &query.terms),
+          written_on: document.written_on.clone(),
+          snippet:    snippet(
+            &document.body,
+            first_match.unwrap_or(0),
+            &query.terms,
+          ),
+        }
+      })
+      .collect()
+  }
+
+  /// The public posts, for the search box, newest first.
+  pub(crate) fn indexed_posts(&self) -> Vec<IndexedPost> {
+    let mut posts = self
+      .documents
+      .iter()
+      .filter(|(_, document)| document.public)
+      .map(|(path, document)| {
+        let mut words = document.body_words.keys().cloned().collect::<Vec<_>>();
+        words.sort();
+        IndexedPost {
+          path: path.clone(),
+          title: document.title.clone(),
+          written_on: document.written_on.clone(),
+          tags: document.tags.clone(),
+          words,
+        }
+      })
+      .collect::<Vec<_>>();
+    posts
+      .sort_by(|a, b| (&b.written_on, &b.path).cmp(&(&a.written_on, &a.path)));
+    posts
+  }
+}
+
+/// Part of a result's title or snippet, which may be a match. It's plain
+/// text, escaped like any other when it's rendered, so nothing in a post can
+/// add markup to its results.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Highlighted {
+  pub text:     String,
+  pub is_match: bool,
+}
+
+/// Splits `text` into the words matching `terms` and what's between them.
+fn highlight(text: &str, terms: &[Term]) -> Vec<Highlighted> {
+  let mut parts = Vec::new();
+  let mut rest_start = 0;
+  for (start, end, word) in words(text) {
+    if !matches(&word, terms) {
+      continue;
+    }
+    if start > rest_start {
+      parts.push(Highlighted {
+        text:     text[rest_start..start].to_string(),
+        is_match: false,
+      });
+    }
+    parts.push(Highlighted {
+      text:     text[start..end].to_string(),
+      is_match: true,
+    });
+    rest_start = end;
+  }
+  if rest_start < text.len() {
+    parts.push(Highlighted {
+      text:     text[rest_start..].to_string(),
+      is_match: false,
+    });
+  }
+  parts
+}
+
+/// The part of `body` around `around`, cut at whole words and highlighted.
+#[cfg(feature = "ssr")]
+fn snippet(body: &str, around: usize, terms: &[Term]) -> Vec<Highlighted> {
+  let start = body[..around]
+    .char_indices()
+    .rev()
+    .nth(SNIPPET_CONTEXT_CHARS)
+    .map_or(0, |(i, _)| i);
+  let end = body[around..]
+    .char_indices()
+    .nth(SNIPPET_CONTEXT_CHARS * 2)
+    .map_or(body.len(), |(i, _)| around + i);
+  let start = match start {
+    0 => 0,
+    start => body[start..around]
+      .find(char::is_whitespace)
+      .map_or(start, |space| start + space),
+  };
+  let end = match end == body.len() {
+    true => end,
+    false => body[around..end]
+      .rfind(char::is_whitespace)
+      .map_or(end, |space| around + space),
+  };
+
+  let text = body[start..end]
+    .split_whitespace()
+    .collect::<Vec<_>>()
+    .join(" ");
+  let mut parts = highlight(&text, terms);
+  let ellipsis = || Highlighted {
+    text:     "…".to_string(),
+    is_match: false,
+  };
+  if start > 0 {
+    parts.insert(0, ellipsis());
+  }
+  if end < body.len() {
+    parts.push(ellipsis());
+  }
+  parts
+}
+
+/// A post matching a search.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SearchResult {
+  pub path:       String,
+  pub title:      Vec<Highlighted>,
+  pub written_on: String,
+  pub snippet:    Vec<Highlighted>,
+}
+
+/// A public post, as the search box searches it as you type.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IndexedPost {
+  pub path:       String,
+  pub title:      String,
+  pub written_on: String,
+  /// The post's tags, as [`tag_key`]s.
+  pub tags:       Vec<String>,
+  /// Every word in the post's body, once each, in order.
+  pub words:      Vec<String>,
+}
+
+impl IndexedPost {
+  fn title_words(&self) -> Vec<String> {
+    words(&self.title)
+      .into_iter()
+      .map(|(_, _, word)| word)
+      .collect()
+  }
+
+  fn tag_words(&self) -> Vec<String> {
+    self
+      .tags
+      .iter()
+      .flat_map(|tag| words(tag).into_iter().map(|(_, _, word)| word))
+      .collect()
+  }
+
+  /// Whether a word of the body matches `term`.
+  fn in_body(&self, term: &Term) -> bool {
+    match term.fuzzy {
+      // the words are sorted, so any starting with the term follow the spot
+      // it would be in
+      false => self
+        .words
+        .get(
+          self
+            .words
+            .partition_point(|word| word.as_str() < term.text.as_str()),
+        )
+        .is_some_and(|word| term.matches(word)),
+      true => self.words.iter().any(|word| term.matches(word)),
+    }
+  }
+
+  /// Whether `term` matches a word of the post.
+  fn has(&self, term: &Term) -> bool {
+    count_matches(&self.title_words(), term) > 0
+      || count_matches(&self.tag_words(), term) > 0
+      || self.in_body(term)
+  }
+
+  /// How well the post matches `query`, or `None` if it doesn't, weighing
+  /// matches like [`search`] does but counting one in the body only once.
+  fn score(&self, query: &Query) -> Option<f64> {
+    if !query.tags.iter().all(|tag| self.tags.contains(tag)) {
+      return None;
+    }
+    let (title_words, tag_words) = (self.title_words(), self.tag_words());
+    let mut score = 0.0;
+    for term in query.terms.iter() {
+      let (in_title, in_tags) = (
+        count_matches(&title_words, term),
+        count_matches(&tag_words, term),
+      );
+      let in_body = self.in_body(term);
+      if in_title + in_tags == 0 && !in_body {
+        return None;
+      }
+      score += TITLE_WEIGHT * in_title as f64
+        + TAG_WEIGHT * in_tags as f64
+        + f64::from(u8::from(in_body));
+    }
+    Some(score)
+  }
+}
+
+/// A post matching what's typed into the search box.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct InstantResult {
+  pub path:         String,
+  pub title:        Vec<Highlighted>,
+  pub written_on:   String,
+  /// The words of the body which matched what the title didn't, since the
+  /// index has no snippets to show.
+  pub body_matches: Vec<String>,
+}
+
+/// The posts in `index` matching `query`, best first, and newest first among
+/// equals since the index is in that order.
+pub(crate) fn instant_results(
+  index: &[IndexedPost],
+  query: &str,
+) -> Vec<InstantResult> {
+  let mut query = Query::parse(query);
+  if query.is_empty() {
+    return Vec::new();
+  }
+  query.allow_typos(|term| index.iter().any(|post| post.has(term)));
+  let mut scored = index
+    .iter()
+    .filter_map(|post| Some((post.score(&query)?, post)))
+    .collect::<Vec<_>>();
+  scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+  scored
+    .into_iter()
+    .take(MAX_INSTANT_RESULTS)
+    .map(|(_, post)| {
+      let title_words = post.title_words();
+      let mut body_matches = query
+        .terms
+        .iter()
+        .filter(|term| count_matches(&title_words, term) == 0)
+        .filter_map(|term| post.words.iter().find(|word| term.matches(word)))
+        .cloned()
+        .collect::<Vec<_>>();
+      body_matches.sort();
+      body_matches.dedup();
+      body_matches.truncate(MAX_BODY_MATCHES);
+      InstantResult {
+        path: post.path.clone(),
+        title: highlight(&post.title, &query.terms),
+        written_on: post.written_on.clone(),
+        body_matches,
+      }
+    })
+    .collect()
+}
+
+/// Fetches the index the search box searches as you type.
+#[cfg(feature = "hydrate")]
+async fn fetch_index() -> Result<Vec<IndexedPost>, wasm_bindgen::JsValue> {
+  use leptos::web_sys::Response;
+  use wasm_bindgen::{JsCast, JsValue};
+  use wasm_bindgen_futures::JsFuture;
+
+  let response = JsFuture::from(window().fetch_with_str(SEARCH_INDEX_PATH))
+    .await?
+    .unchecked_into::<Response>();
+  if !response.ok() {
+    return Err(JsValue::from_str(&format!(
+      "the search index answered {}",
+      response.status()
+    )));
+  }
+  let text = JsFuture::from(response.text()?)
+    .await?
+    .as_string()
+    .unwrap_or_default();
+  serde_json::from_str(&text).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// The index the search box searches, and a function which fetches it the
+/// first time it's called, so that pages where nobody searches don't pay for
+/// it.
+pub(crate) fn lazy_index() -> (
+  ReadSignal<Option<Vec<IndexedPost>>>,
+  impl Fn() + Copy + 'static,
+) {
+  let (index, set_index) = create_signal(None::<Vec<IndexedPost>>);
+  let requested = store_value(false);
+  let load = move || {
+    if requested.get_value() {
+      return;
+    }
+    requested.set_value(true);
+    #[cfg(feature = "hydrate")]
+    spawn_local(async move {
+      match fetch_index().await {
+        Ok(posts) => set_index(Some(posts)),
+        Err(e) => {
+          logging::error!("failed to fetch the search index: {e:?}");
+          requested.set_value(false);
+        }
+      }
+    });
+    #[cfg(not(feature = "hydrate"))]
+    let _ = set_index;
+  };
+  (index, load)
+}
+
+/// Searches the public posts.
+#[server]
+pub async fn search_posts(
+  query: String,
+) -> Result<Vec<SearchResult>, ServerFnError> {
+  let index = expect_context::<crate::post_index::PostIndex>();
+  crate::posts::blocking(move || index.search(&query)).await
+}
+
+/// Text with its matches marked.
+pub(crate) fn highlighted(parts: Vec<Highlighted>) -> impl IntoView {
+  parts
+    .into_iter()
+    .map(|part| match part.is_match {
+      true => {
+        view! { <mark class="search-match">{part.text}</mark> }.into_view()
+      }
+      false => part.text.into_view(),
+    })
+    .collect_view()
+}
+
+/// A form which searches the posts. Once hydrated, it also drops down a list
+/// of the posts matching what's typed into it as it's typed, apart from the
+/// search it was rendered with, whose results are already on the page. The
+/// arrow keys move through the list, enter opens the chosen post, and escape
+/// closes it.
+#[crate::lazy_island]
+pub fn SearchForm(#[prop(optional)] query: String) -> impl IntoView {
+  let submitted = store_value(query.clone());
+  let (typed, set_typed) = create_signal(query.clone());
+  let (index, load_index) = lazy_index();
+  // which result is chosen with the arrow keys, if one is
+  let (active, set_active) = create_signal(None::<usize>);
+  let (closed, set_closed) = create_signal(false);
+
+  let results = create_memo(move |_| {
+    let query = typed();
+    if query.trim().is_empty() || submitted.with_value(|s| *s == query) {
+      return None;
+    }
+    index.with(|index| Some(instant_results(index.as_ref()?, &query)))
+  });
+  let shown = move || !closed() && results.with(|r| r.is_some());
+
+  let on_keydown = move |ev: ev::KeyboardEvent| {
+    let count = results.with_untracked(|r| r.as_ref().map_or(0, Vec::len));
+    let chosen = active.get_untracked();
+    match ev.key().as_str() {
+      "ArrowDown" | "ArrowUp" if count > 0 => {
+        ev.prevent_default();
+        set_closed(false);
+        set_active(Some(match (ev.key().as_str(), chosen) {
+          ("ArrowDown", None) => 0,
+          ("ArrowDown", Some(i)) => (i + 1) % count,
+          (_, None) => count - 1,
+          (_, Some(i)) => (i + count - 1) % count,
+        }));
+      }
+      "Enter" => {
+        let path = chosen.and_then(|i| {
+          results.with_untracked(|r| Some(r.as_ref()?.get(i)?.path.clone()))
+        });
+        // without a chosen result, enter searches as usual
+        if let Some(path) = path {
+          ev.prevent_default();
+          let _ = window().location().set_href(&format!("/post/{path}"));
+        }
+      }
+      "Escape" => {
+        set_closed(true);
+        set_active(None);
+      }
+      _ => {}
+    }
+  };
+
+  let result_item = move |(i, result): (usize, InstantResult)| {
+    let is_active = move || active() == Some(i);
+    let body_matches = (!result.body_matches.is_empty()).then(|| {
+      let words = result.body_matches.into_iter().enumerate().map(|(i, word)| {
+        view! { {(i > 0).then_some(", ")} <mark class="search-match">{word}</mark> }
+      });
+      view! {
+        <span class="block text-sm text-neutral-400">"Mentions " {words.collect_view()}</span>
+      }
+    });
+    view! {
+      <li
+        id=format!("search-result-{i}") role="option" class="px-2 py-1"
+        aria-selected=move || is_active().to_string() class:bg-neutral-700=is_active
+      >
+        <a href=format!("/post/{}", result.path)>{highlighted(result.title)}</a>
+        " - " <crate::dates::PostDate written_on=result.written_on />
+        {body_matches}
+      </li>
+    }
+  };
+
+  view! {
+    <form action=SEARCH_PATH method="get" role="search" class="relative my-4">
+      <div class="flex gap-2">
+        <input
+          type="search" name="q" value=query required placeholder="Search posts"
+          aria-label="Search posts" autocomplete="off" role="combobox"
+          aria-autocomplete="list" aria-controls="search-results"
+          aria-expanded=move || shown().to_string()
+          aria-activedescendant=move || active().map(|i| format!("search-result-{i}"))
+          class="bg-neutral-700 px-2 py-1 flex-1"
+          on:focus=move |_| load_index()
+          on:input=move |ev| {
+            load_index();
+            set_typed(event_target_value(&ev));
+            set_active(None);
+            set_closed(false);
+          }
+          on:keydown=on_keydown
+        />
+        <button type="submit" class="bg-neutral-700 px-4 py-1 hover:bg-neutral-600">"Search"</button>
+      </div>
+      <div
+        aria-live="polite"
+        class="absolute left-0 right-0 z-10 mt-1 bg-neutral-800 border border-neutral-600"
+        class:hidden=move || !shown()
+      >
+        { move || results().map(|results| match results.is_empty() {
+          true => view! { <p class="px-2 py-1 text-neutral-400">"No posts match."</p> }.into_view(),
+          false => view! {
+            <ul id="search-results" role="listbox" aria-label="Matching posts">
+              {results.into_iter().enumerate().map(result_item).collect_view()}
+            </ul>
+          }
+          .into_view(),
+        }) }
+      </div>
+    </form>
+  }
+}
+
+/// The results of the search in the `q` parameter.
+#[component]
+pub fn SearchPage() -> impl IntoView {
+  let query = use_query_map()
+    .get_untracked()
+    .get("q")
+    .cloned()
+    .unwrap_or_default();
+  let results_resource = create_resource(
+    {
+      let query = query.clone();
+      move || query.clone()
+    },
+    |query| async move {
+      match query.trim().is_empty() {
+        true => Ok(None),
+        false => search_posts(query).await.map(Some),
+      }
+    },
+  );
+
+  let shown_query = store_value(query.clone());
+  let result_item = |result: SearchResult| {
+    view! {
+      <li>
+        <a href=format!("/post/{}", result.path)>{highlighted(result.title)}</a>
+        " - " <crate::dates::PostDate written_on=result.written_on />
+        <p class="text-neutral-400 mt-1">{highlighted(result.snippet)}</p>
+      </li>
+    }
+  };
+
+  view! {
+    <Title text="Search" />
+    <Meta name="robots" content="noindex" />
+    <div class="markdown">
+      <h2>"Search"</h2>
+    </div>
+    <SearchForm query=query.clone() />
+    <p class="text-neutral-400 text-sm">
+      "Put words in quotes to find them in that order, and add " <code>"tag:<tag>"</code>
+      " to only search posts with the tag."
+    </p>
+    <Suspense fallback=|| view! { <crate::posts::PostListSkeleton /> }>
+      <ErrorBoundary fallback=|errors| view! { <ErrorTemplate errors /> }>
+        { move || results_resource.get().map(|r| r.map_err(AppError::from).map(|results| match results {
+          None => ().into_view(),
+          Some(results) if results.is_empty() => view! {
+            <p>"No posts match " <q>{shown_query.get_value()}</q> "."</p>
+          }
+          .into_view(),
+          Some(results) => view! {
+            <div class="markdown">
+              <ul>{results.into_iter().map(result_item).collect_view()}</ul>
+            </div>
+          }
+          .into_view(),
+        })) }
+      </ErrorBoundary>
+    </Suspense>
+  }
+}
+
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+  use super::*;
+  use crate::posts::{Post, PostMetadata};
+
+  fn post(title: &str, public: bool, tags: &[&str], plaintext: &str) -> Post {
+    Post {
+      html_content:  String::new(),
+      plaintext:     plaintext.to_string(),
+      path:          String::new(),
+      metadata:      PostMetadata {
+        title: title.to_string(),
+        written_on: "2024.01.01".to_string(),
+        public,
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        markdown: Default::default(),
+      },
+      toc:           Vec::new(),
+      likely_next:   None,
+      changed_since: None,
+    }
+  }
+
+  fn paths(index: &SearchIndex, query: &str) -> Vec<String> {
+    index
+      .search(query)
+      .into_iter()
+      .map(|result| result.path)
+      .collect()
+  }
+
+  #[test]
+  fn searches_rank_phrases_and_filter_by_tag() {
+    let posts = [
+      (
+        "nix".to_string(),
+        post(
+          "Packaging with Nix",
+          true,
+          &["Nix"],
+          "a site built with nix",
+        ),
+      ),
+      (
+        "static".to_string(),
+        post(
+          "Notes",
+          true,
+          &["Rust", "Game Dev"],
+          "a static site in rust, packaging aside",
+        ),
+      ),
+      (
+        "draft".to_string(),
+        post("Draft", false, &["Rust"], "a static site, unpublished"),
+      ),
+    ];
+    let mut index = SearchIndex::default();
+    index.replace_all(posts.iter().map(|(path, post)| (path, post)));
+
+    assert_eq!(paths(&index, "nix site"), ["nix"]);
+    // the title outweighs the body
+    assert_eq!(paths(&index, "packaging"), ["nix", "static"]);
+    assert_eq!(paths(&index, "\"static site\""), ["static"]);
+    assert!(paths(&index, "\"site static\"").is_empty());
+    assert_eq!(paths(&index, "tag:game-dev"), ["static"]);
+    assert_eq!(paths(&index, "site tag:rust"), ["static"]);
+    assert!(paths(&index, "nix tag:rust").is_empty());
+    // misspelled words match the closest indexed ones
+    assert_eq!(paths(&index, "stattic"), ["static"]);
+
+    index.remove("static");
+    assert!(paths(&index, "tag:rust").is_empty());
+    index.insert("nix", &post("Nix", true, &["Rust"], "now in rust"));
+    assert_eq!(paths(&index, "tag:rust"), ["nix"]);
+  }
+}
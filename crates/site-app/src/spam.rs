@@ -0,0 +1,352 @@
+//! Scores comments for spam as they're submitted.
+//!
+//! A comment's score is the sum of several signals: how many links it has,
+//! whether the same text has been posted on other posts, which phrases from
+//! the spam phrase list it contains, and which of its words have been seen in
+//! comments previously marked as spam or ham. Comments scoring above the
+//! moderation threshold wait in the moderation queue, and those above the
+//! rejection threshold are rejected outright. Marking a comment as spam or ham
+//! with [`mark_comment`] trains the word scores, with each comment counting
+//! once however many times it's marked.
+
+use std::collections::BTreeSet;
+
+use sha2::{Digest, Sha256};
+use site_db::{
+  storage::{CommentStatus, CommentStore, SpamStore},
+  DbError,
+};
+
+/// Scores at or above this go to the moderation queue.
+pub const DEFAULT_MODERATE_THRESHOLD: f64 = 3.0;
+/// Scores at or above this are rejected outright.
+pub const DEFAULT_REJECT_THRESHOLD: f64 = 8.0;
+
+/// How many links a comment may have before each one counts against it.
+const LINK_ALLOWANCE: usize = 1;
+/// The score of each link past the allowance.
+const LINK_WEIGHT: f64 = 1.5;
+/// The score of a comment whose text has been posted on other posts.
+const DUPLICATE_WEIGHT: f64 = 4.0;
+/// How many times a word must have been trained on before it's trusted.
+const MIN_TOKEN_OBSERVATIONS: i64 = 2;
+/// How many of the most decisive words count towards the score.
+const MAX_TOKENS_SCORED: usize = 15;
+/// The score of a word which has only ever been seen in spam (or, negated,
+/// in ham).
+const TOKEN_WEIGHT: f64 = 0.5;
+/// Words outside this length range are ignored.
+const TOKEN_LENGTHS: std::ops::RangeInclusive<usize> = 3..=24;
+
+/// The scores at which comments are held for moderation or rejected.
+#[derive(Clone, Copy, Debug)]
+pub struct Thresholds {
+  pub moderate: f64,
+  pub reject:   f64,
+}
+
+impl Default for Thresholds {
+  fn default() -> Self {
+    Thresholds {
+      moderate: DEFAULT_MODERATE_THRESHOLD,
+      reject:   DEFAULT_REJECT_THRESHOLD,
+    }
+  }
+}
+
+/// The classifier's judgement of a comment.
+#[derive(Clone, Debug)]
+pub struct Verdict {
+  /// The total spam score.
+  pub score:     f64,
+  /// Why the comment scored what it did, one signal per entry.
+  pub reasons:   Vec<String>,
+  /// Where the comment should start out in moderation.
+  pub status:    CommentStatus,
+  /// The hash of the comment's normalized text, to be stored with it.
+  pub body_hash: String,
+}
+
+/// Lowercases the text and collapses everything but letters and digits, so
+/// trivially edited copies of a comment normalize the same.
+fn normalize(body: &str) -> String {
+  body
+    .to_lowercase()
+    .split(|c: char| !c.is_alphanumeric())
+    .filter(|word| !word.is_empty())
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+/// Hashes a comment's normalized text.
+pub fn body_hash(body: &str) -> String {
+  format!("{:x}", Sha256::digest(normalize(body).as_bytes()))
+}
+
+/// Splits a comment into the distinct words the classifier trains on.
+pub fn tokenize(body: &str) -> Vec<String> {
+  normalize(body)
+    .split(' ')
+    .filter(|word| TOKEN_LENGTHS.contains(&word.chars().count()))
+    .map(str::to_string)
+    .collect::<BTreeSet<_>>()
+    .into_iter()
+    .collect()
+}
+
+fn count_links(body: &str) -> usize {
+  let body = body.to_lowercase();
+  ["http://", "https://", "www."]
+    .iter()
+    .map(|prefix| body.matches(prefix).count())
+    .sum::<usize>()
+    // `https://www.` shouldn't count twice
+    .saturating_sub(body.matches("://www.").count())
+}
+
+/// Scores a comment about to be posted on `post_path`.
+pub async fn classify(
+  db: &(impl CommentStore + SpamStore),
+  post_path: &str,
+  body: &str,
+  thresholds: Thresholds,
+) -> Result<Verdict, DbError> {
+  let mut score = 0.0;
+  let mut reasons = Vec::new();
+
+  let links = count_links(body);
+  if links > LINK_ALLOWANCE {
+    let link_score = (links - LINK_ALLOWANCE) as f64 * LINK_WEIGHT;
+    score += link_score;
+    reasons.push(format!("{links} links (+{link_score:.1})"));
+  }
+
+  let body_hash = body_hash(body);
+  let duplicates = db.count_duplicate_comments(&body_hash, post_path).await?;
+  if duplicates > 0 {
+    score += DUPLICATE_WEIGHT;
+    reasons.push(format!(
+      "posted on {duplicates} other posts (+{DUPLICATE_WEIGHT:.1})"
+    ));
+  }
+
+  let lowercase = body.to_lowercase();
+  for phrase in db.spam_phrases().await? {
+    if lowercase.contains(&phrase.phrase.to_lowercase()) {
+      score += phrase.weight;
+      reasons.push(format!(
+        "contains \"{}\" ({:+.1})",
+        phrase.phrase, phrase.weight
+      ));
+    }
+  }
+
+  let mut token_scores = db
+    .token_counts(&tokenize(body))
+    .await?
+    .into_iter()
+    .filter(|counts| counts.spam + counts.ham >= MIN_TOKEN_OBSERVATIONS)
+    .map(|counts| {
+      // smoothed so a word seen only a couple of times isn't decisive
+      let spamminess =
+        (counts.spam as f64 + 0.5) / ((counts.spam + counts.ham) as f64 + 1.0);
+      (counts.token, (spamminess - 0.5) * 2.0 * TOKEN_WEIGHT)
+    })
+    .collect::<Vec<_>>();
+  token_scores.sort_by(|a, b| b.1.abs().total_cmp(&a.1.abs()));
+  token_scores.truncate(MAX_TOKENS_SCORED);
+  let token_score = token_scores.iter().map(|(_, s)| s).sum::<f64>();
+  if !token_scores.is_empty() {
+    score += token_score;
+    reasons.push(format!(
+      "trained words {} ({token_score:+.1})",
+      token_scores
+        .iter()
+        .map(|(token, _)| token.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+    ));
+  }
+
+  let status = if score >= thresholds.reject {
+    CommentStatus::Rejected
+  } else if score >= thresholds.moderate {
+    CommentStatus::Pending
+  } else {
+    CommentStatus::Approved
+  };
+
+  Ok(Verdict {
+    score,
+    reasons,
+    status,
+    body_hash,
+  })
+}
+
+/// Marks a comment as spam, rejecting it, or as ham, approving it, and trains
+/// the classifier on its words. Marking a comment again only retrains it if
+/// the label changed. Returns `false` if the comment doesn't exist.
+pub async fn mark_comment(
+  db: &(impl CommentStore + SpamStore),
+  id: i64,
+  is_spam: bool,
+) -> Result<bool, DbError> {
+  let Some(comment) = db.comment(id).await? else {
+    return Ok(false);
+  };
+
+  let status = if is_spam {
+    CommentStatus::Rejected
+  } else {
+    CommentStatus::Approved
+  };
+  db.set_comment_status(id, status).await?;
+  db.train_comment(id, &tokenize(&comment.body), is_spam)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+  use site_db::{migrations, storage::NewComment, Database};
+
+  use super::*;
+
+  /// A fresh, migrated database in a temporary file.
+  async fn database(name: &str) -> Database {
+    let path = std::env::temp_dir()
+      .join(format!("spam-test-{}", std::process::id()))
+      .join(format!("{name}.db"));
+    let _ = std::fs::remove_file(&path);
+    let db = Database::connect(&format!("sqlite://{}", path.display()))
+      .await
+      .unwrap();
+    migrations::migrate(&db).await.unwrap();
+    db
+  }
+
+  async fn add_comment(db: &Database, post_path: &str, body: &str) -> i64 {
+    db.add_comment(NewComment {
+      post_path:  post_path.to_string(),
+      author:     "someone".to_string(),
+      body:       body.to_string(),
+      status:     CommentStatus::Approved,
+      spam_score: 0.0,
+      body_hash:  body_hash(body),
+    })
+    .await
+    .unwrap()
+    .id
+  }
+
+  async fn counts(db: &Database, token: &str) -> (i64, i64) {
+    db.token_counts(&[token.to_string()])
+      .await
+      .unwrap()
+      .first()
+      .map_or((0, 0), |counts| (counts.spam, counts.ham))
+  }
+
+  #[test]
+  fn links_are_counted_once_each() {
+    assert_eq!(count_links("no links here"), 0);
+    assert_eq!(count_links("see https://www.example.com"), 1);
+    assert_eq!(count_links("see HTTP://WWW.example.com"), 1);
+    assert_eq!(count_links("see www.example.com"), 1);
+    assert_eq!(
+      count_links("http://a.example https://b.example www.c.example"),
+      3
+    );
+  }
+
+  #[tokio::test]
+  async fn scores_are_held_or_rejected_at_the_thresholds() {
+    let db = database("thresholds").await;
+    // three links, two past the allowance, score 3.0
+    let body = "http://a.example http://b.example http://c.example";
+
+    let verdict = classify(&db, "/posts/a", body, Thresholds::default())
+      .await
+      .unwrap();
+    assert_eq!(verdict.score, 3.0);
+    assert_eq!(verdict.status, CommentStatus::Pending);
+
+    let lenient = Thresholds {
+      moderate: 3.5,
+      reject:   8.0,
+    };
+    let verdict = classify(&db, "/posts/a", body, lenient).await.unwrap();
+    assert_eq!(verdict.status, CommentStatus::Approved);
+
+    let strict = Thresholds {
+      moderate: 1.0,
+      reject:   3.0,
+    };
+    let verdict = classify(&db, "/posts/a", body, strict).await.unwrap();
+    assert_eq!(verdict.status, CommentStatus::Rejected);
+  }
+
+  #[tokio::test]
+  async fn comments_posted_elsewhere_count_as_duplicates() {
+    let db = database("duplicates").await;
+    add_comment(&db, "/posts/a", "Great post, visit my site!").await;
+
+    let verdict = classify(
+      &db,
+      "/posts/a",
+      "great post... visit my site",
+      Thresholds::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(verdict.score, 0.0);
+
+    let verdict = classify(
+      &db,
+      "/posts/b",
+      "great post... visit my site",
+      Thresholds::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(verdict.score, DUPLICATE_WEIGHT);
+  }
+
+  #[tokio::test]
+  async fn comments_are_trained_on_once() {
+    let db = database("training").await;
+    let id = add_comment(&db, "/posts/a", "cheap watches").await;
+
+    assert!(mark_comment(&db, id, true).await.unwrap());
+    assert!(mark_comment(&db, id, true).await.unwrap());
+    assert_eq!(counts(&db, "cheap").await, (1, 0));
+
+    // changing its label moves its words over
+    assert!(mark_comment(&db, id, false).await.unwrap());
+    assert_eq!(counts(&db, "cheap").await, (0, 1));
+    assert_eq!(
+      db.comment(id).await.unwrap().unwrap().status,
+      CommentStatus::Approved
+    );
+
+    assert!(!mark_comment(&db, id + 1, true).await.unwrap());
+  }
+
+  #[tokio::test]
+  async fn trained_words_count_towards_the_score() {
+    let db = database("trained-words").await;
+    for post_path in ["/posts/a", "/posts/b"] {
+      let id = add_comment(&db, post_path, "cheap watches").await;
+      mark_comment(&db, id, true).await.unwrap();
+    }
+
+    let verdict = classify(&db, "/posts/c", "Cheap!", Thresholds::default())
+      .await
+      .unwrap();
+    assert!(verdict.score > 0.0);
+    assert!(verdict
+      .reasons
+      .iter()
+      .any(|reason| reason.contains("cheap")));
+  }
+}
@@ -0,0 +1,316 @@
+//! The site's colour themes, as far as things outside the stylesheet need to
+//! know them, like the browser chrome, the favicons and highlighted code.
+//!
+//! Readers choose between the dark and light theme with [`ThemeToggle`], and
+//! the choice is kept in the [`THEME_COOKIE`] cookie. Pages are rendered in
+//! the theme it names, so they never show the other one first; the toggle
+//! only swaps the page's theme in place once the cookie is set.
+//!
+//! Until a reader chooses, pages follow the system's colour scheme. The
+//! stylesheet and the tags here pick it with media queries, so that's right
+//! without scripts, and [`THEME_SCRIPT`] gives the page the class of the
+//! scheme before it's shown, like a chosen theme's, for the toggle to start
+//! from. Browsers that can't tell get the dark theme.
+
+use leptos::*;
+use leptos_meta::{Link, Meta, Script, Stylesheet};
+use leptos_router::use_location;
+use serde::{Deserialize, Serialize};
+
+/// The cookie the reader's theme is kept in. It's readable by scripts, since
+/// it's only a preference.
+pub const THEME_COOKIE: &str = "theme";
+/// How long the cookie is kept.
+#[cfg(feature = "ssr")]
+const THEME_COOKIE_SECS: u64 = 365 * 24 * 60 * 60;
+/// The ID of the highlighted code's stylesheet, which the toggle swaps.
+const CODE_STYLESHEET_ID: &str = "code-theme";
+/// The ID of the `theme-color` meta tag, which the toggle updates.
+const THEME_COLOR_ID: &str = "theme-color";
+/// The IDs of the light scheme's stylesheet and `theme-color`, given
+/// alongside the dark ones until the reader chooses. The toggle removes them.
+const SYSTEM_CODE_STYLESHEET_ID: &str = "code-theme-light";
+const SYSTEM_THEME_COLOR_ID: &str = "theme-color-light";
+/// The media query of the system's light scheme.
+const LIGHT_SCHEME_QUERY: &str = "(prefers-color-scheme: light)";
+/// Gives a page without a chosen theme the class of the system's scheme.
+const THEME_SCRIPT: &str = "var c=document.documentElement.classList;c.\
+                            add(matchMedia('(prefers-color-scheme: \
+                            light)').matches?'light':'dark')";
+
+/// The colours of the site in one colour scheme.
+pub struct SchemeColors {
+  /// The page background, which browser chrome is tinted to match.
+  pub background: &'static str,
+}
+
+/// The site's colours in each colour scheme.
+pub struct ThemeConfig {
+  pub dark:  SchemeColors,
+  pub light: SchemeColors,
+}
+
+/// The site's theme.
+pub const THEME: ThemeConfig = ThemeConfig {
+  // `neutral-800` from the page background, which the light theme mirrors
+  dark:  SchemeColors {
+    background: "#262626",
+  },
+  light: SchemeColors {
+    background: "#e5e5e5",
+  },
+};
+
+/// A theme the reader can choose.
+#[derive(
+  Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorTheme {
+  #[default]
+  Dark,
+  Light,
+}
+
+impl ColorTheme {
+  /// The theme's name, which is both its cookie value and the class the page
+  /// is given.
+  pub fn name(self) -> &'static str {
+    match self {
+      ColorTheme::Dark => "dark",
+      ColorTheme::Light => "light",
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "dark" => Some(ColorTheme::Dark),
+      "light" => Some(ColorTheme::Light),
+      _ => None,
+    }
+  }
+
+  /// The theme the toggle switches to.
+  pub fn other(self) -> Self {
+    match self {
+      ColorTheme::Dark => ColorTheme::Light,
+      ColorTheme::Light => ColorTheme::Dark,
+    }
+  }
+
+  pub fn colors(self) -> &'static SchemeColors {
+    match self {
+      ColorTheme::Dark => &THEME.dark,
+      ColorTheme::Light => &THEME.light,
+    }
+  }
+
+  /// Where the stylesheet colouring highlighted code in the theme is served.
+  pub fn code_stylesheet(self) -> &'static str {
+    match self {
+      ColorTheme::Dark => "/code-dark.css",
+      ColorTheme::Light => "/code-light.css",
+    }
+  }
+
+  /// The stylesheet colouring highlighted code in the theme.
+  #[cfg(feature = "ssr")]
+  pub fn code_css(self) -> String {
+    use site_markdown::{highlight_css, CodeScheme};
+
+    highlight_css(match self {
+      ColorTheme::Dark => CodeScheme::Dark,
+      ColorTheme::Light => CodeScheme::Light,
+    })
+  }
+}
+
+/// The theme the reader chose, from the request's cookie, if they've chosen
+/// one.
+pub fn chosen_theme() -> Option<ColorTheme> {
+  #[cfg(feature = "ssr")]
+  {
+    let parts = use_context::<http::request::Parts>()?;
+    parts
+      .headers
+      .get_all(http::header::COOKIE)
+      .iter()
+      .filter_map(|cookies| cookies.to_str().ok())
+      .flat_map(|cookies| cookies.split(';'))
+      .filter_map(|cookie| cookie.trim().split_once('='))
+      .find(|(name, _)| *name == THEME_COOKIE)
+      .and_then(|(_, value)| ColorTheme::from_name(value))
+  }
+  #[cfg(not(feature = "ssr"))]
+  None
+}
+
+/// The `theme-color` of the page's theme, the stylesheet for its highlighted
+/// code, and the favicons, or both schemes' with the script picking the
+/// system's if the reader hasn't chosen. The SVG favicon adapts itself to the
+/// system's scheme; the PNG and multi-resolution ICO are for browsers without
+/// SVG favicon support.
+#[component]
+pub fn ThemeMeta(theme: Option<ColorTheme>) -> impl IntoView {
+  let theme_tags = match theme {
+    Some(theme) => view! {
+      <Meta name="theme-color" content=theme.colors().background attr:id=THEME_COLOR_ID />
+      <Stylesheet id=CODE_STYLESHEET_ID href=theme.code_stylesheet() />
+    }
+    .into_view(),
+    None => {
+      let dark_query = format!("not all and {LIGHT_SCHEME_QUERY}");
+      view! {
+        <Script>{THEME_SCRIPT}</Script>
+        <Meta
+          name="theme-color" content=THEME.dark.background
+          attr:id=THEME_COLOR_ID attr:media=dark_query.clone()
+        />
+        <Meta
+          name="theme-color" content=THEME.light.background
+          attr:id=SYSTEM_THEME_COLOR_ID attr:media=LIGHT_SCHEME_QUERY
+        />
+        <Stylesheet
+          id=CODE_STYLESHEET_ID href=ColorTheme::Dark.code_stylesheet()
+          attr:media=dark_query
+        />
+        <Stylesheet
+          id=SYSTEM_CODE_STYLESHEET_ID href=ColorTheme::Light.code_stylesheet()
+          attr:media=LIGHT_SCHEME_QUERY
+        />
+      }
+      .into_view()
+    }
+  };
+  view! {
+    {theme_tags}
+    <Link rel="icon" href="/favicon.ico" sizes="16x16 32x32 48x48" />
+    <Link rel="icon" href="/favicon.png" type_="image/png" sizes="48x48" />
+    <Link rel="icon" href="/favicon.svg" type_="image/svg+xml" />
+  }
+}
+
+/// Remembers `theme` as the reader's. A form sent without scripts goes back
+/// to `page`, rendered in the new theme.
+#[server]
+pub async fn choose_theme(
+  theme: ColorTheme,
+  page: String,
+) -> Result<(), ServerFnError> {
+  let config = crate::config::use_site_config();
+  let cookie = format!(
+    "{THEME_COOKIE}={}; Max-Age={THEME_COOKIE_SECS}; Path=/; SameSite=Lax{}",
+    theme.name(),
+    if config.base_url.starts_with("https:") {
+      "; Secure"
+    } else {
+      ""
+    }
+  );
+  let response = expect_context::<leptos_axum::ResponseOptions>();
+  response.append_header(
+    http::header::SET_COOKIE,
+    http::HeaderValue::from_str(&cookie).expect("cookies are valid headers"),
+  );
+
+  let accepts_html =
+    use_context::<http::request::Parts>().is_some_and(|parts| {
+      parts
+        .headers
+        .get(http::header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/html"))
+    });
+  if accepts_html {
+    // only back to a page of the site
+    let page = match page.starts_with('/') && !page.starts_with("//") {
+      true => page,
+      false => "/".to_string(),
+    };
+    leptos_axum::redirect(&page);
+  }
+  Ok(())
+}
+
+/// Swaps the page's theme for `theme` without reloading it.
+#[cfg(feature = "hydrate")]
+fn apply_theme(theme: ColorTheme) {
+  let document = document();
+  if let Some(root) = document.document_element() {
+    let _ = root.class_list().remove_1(theme.other().name());
+    let _ = root.class_list().add_1(theme.name());
+  }
+  if let Some(stylesheet) = document.get_element_by_id(CODE_STYLESHEET_ID) {
+    let _ = stylesheet.set_attribute("href", theme.code_stylesheet());
+    let _ = stylesheet.remove_attribute("media");
+  }
+  if let Some(meta) = document.get_element_by_id(THEME_COLOR_ID) {
+    let _ = meta.set_attribute("content", theme.colors().background);
+    let _ = meta.remove_attribute("media");
+  }
+  // the system's scheme no longer counts
+  for id in [SYSTEM_CODE_STYLESHEET_ID, SYSTEM_THEME_COLOR_ID] {
+    if let Some(element) = document.get_element_by_id(id) {
+      element.remove();
+    }
+  }
+}
+
+/// The theme the page is shown in, from its class.
+#[cfg(feature = "hydrate")]
+fn shown_theme() -> ColorTheme {
+  document()
+    .document_element()
+    .filter(|root| root.class_list().contains(ColorTheme::Light.name()))
+    .map_or(ColorTheme::Dark, |_| ColorTheme::Light)
+}
+
+/// The header's button switching between the themes.
+#[component]
+pub fn ThemeToggle(theme: Option<ColorTheme>) -> impl IntoView {
+  let page = use_location().pathname.get_untracked();
+  view! { <ThemeButton theme=theme.unwrap_or_default() page /> }
+}
+
+/// A form, which once hydrated switches the page in place and remembers the
+/// choice in the background. Without scripts, a reader who hasn't chosen is
+/// offered the light theme.
+#[crate::eager_island]
+fn ThemeButton(theme: ColorTheme, page: String) -> impl IntoView {
+  let (theme, set_theme) = create_signal(theme);
+  // the page may be in the system's scheme rather than the one rendered
+  #[cfg(feature = "hydrate")]
+  set_theme(shown_theme());
+
+  let on_submit = move |ev: ev::SubmitEvent| {
+    ev.prevent_default();
+    let chosen = theme.get_untracked().other();
+    set_theme(chosen);
+    #[cfg(feature = "hydrate")]
+    {
+      apply_theme(chosen);
+      spawn_local(async move {
+        if let Err(e) = choose_theme(chosen, String::new()).await {
+          logging::error!("failed to remember the theme: {e}");
+        }
+      });
+    }
+  };
+
+  let label = move || format!("Switch to the {} theme", theme().other().name());
+  view! {
+    <form method="post" action=ChooseTheme::url() on:submit=on_submit>
+      <input type="hidden" name="theme" value=move || theme().other().name() />
+      <input type="hidden" name="page" value=page />
+      <button
+        type="submit" title=label aria-label=label
+        class="px-2 text-neutral-400 hover:text-neutral-100"
+      >
+        { move || match theme() {
+          ColorTheme::Dark => "☀",
+          ColorTheme::Light => "☾",
+        } }
+      </button>
+    </form>
+  }
+}
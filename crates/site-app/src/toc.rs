@@ -0,0 +1,82 @@
+//! The table of contents shown beside posts on wide viewports.
+
+use leptos::*;
+pub use site_markdown::TocEntry;
+
+/// How far from the top of the viewport a heading becomes the current
+/// section, as an `IntersectionObserver` root margin.
+#[cfg(feature = "hydrate")]
+const CURRENT_SECTION_MARGIN: &str = "0px 0px -70% 0px";
+
+/// A list of links to each heading in a post, which highlights the section
+/// the reader is currently in.
+#[crate::eager_island]
+pub fn TableOfContents(entries: Vec<TocEntry>) -> impl IntoView {
+  let (active, set_active) = create_signal(None::<String>);
+
+  #[cfg(feature = "hydrate")]
+  {
+    use js_sys::{Array, Object, Reflect};
+    use leptos::web_sys::{
+      IntersectionObserver, IntersectionObserverEntry, IntersectionObserverInit,
+    };
+    use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+
+    let callback = Closure::<dyn Fn(Array)>::new(move |observed: Array| {
+      for entry in observed.iter() {
+        let entry = entry.unchecked_into::<IntersectionObserverEntry>();
+        if entry.is_intersecting() {
+          set_active(Some(entry.target().id()));
+        }
+      }
+    });
+
+    // set through `Reflect` because the `IntersectionObserverInit` setters
+    // differ between `web-sys` versions
+    let options = Object::new();
+    let _ = Reflect::set(
+      &options,
+      &JsValue::from_str("rootMargin"),
+      &JsValue::from_str(CURRENT_SECTION_MARGIN),
+    );
+
+    if let Ok(observer) = IntersectionObserver::new_with_options(
+      callback.as_ref().unchecked_ref(),
+      options.unchecked_ref::<IntersectionObserverInit>(),
+    ) {
+      for entry in entries.iter() {
+        if let Some(anchor) = document().get_element_by_id(&entry.id) {
+          observer.observe(&anchor);
+        }
+      }
+      on_cleanup(move || {
+        observer.disconnect();
+        drop(callback);
+      });
+    }
+  }
+  #[cfg(not(feature = "hydrate"))]
+  let _ = set_active;
+
+  // indent relative to the shallowest heading, since posts rarely use `h1`s
+  let top_level = entries.iter().map(|e| e.level).min().unwrap_or(1);
+
+  view! {
+    <nav aria-label="Table of contents" class="toc">
+      <p class="font-bold mb-2">"Contents"</p>
+      <ul>
+        { entries.into_iter().map(|entry| {
+          let id = entry.id.clone();
+          let is_active = move || active().as_deref() == Some(id.as_str());
+          view! {
+            <li class=format!("toc-depth-{}", (entry.level - top_level).min(2))>
+              <a href=format!("#{}", entry.id) class=("toc-active", is_active)>
+                {entry.title}
+              </a>
+            </li>
+          }
+        }).collect_view() }
+      </ul>
+    </nav>
+  }
+}
@@ -0,0 +1,133 @@
+//! How many times posts have been read, as counted by the server, see
+//! [`ViewsConfig`](crate::config::ViewsConfig). The counts are only shown if
+//! the config says so: on each post, and on a page of the most read posts.
+
+use leptos::*;
+use serde::{Deserialize, Serialize};
+
+use crate::error_template::{AppError, ErrorTemplate};
+
+/// Where the most read posts are listed.
+pub const POPULAR_PATH: &str = "/popular";
+
+/// Whether view counts are shown.
+pub fn is_shown() -> bool {
+  #[cfg(feature = "ssr")]
+  {
+    crate::config::use_site_config().views.show
+  }
+  #[cfg(not(feature = "ssr"))]
+  false
+}
+
+/// The path a post's views are counted under.
+#[cfg(feature = "ssr")]
+fn counted_path(path: &str) -> String { format!("/post/{path}") }
+
+/// How many times the post at `path` has been read, if counts are shown. A
+/// count that can't be fetched is left out rather than failing the page.
+#[cfg(feature = "ssr")]
+pub(crate) async fn shown_views(path: &str) -> Option<i64> {
+  use site_db::storage::AnalyticsStore;
+
+  if !is_shown() {
+    return None;
+  }
+  let db = crate::comments::use_database().ok()?;
+  match db.page_views(&counted_path(path)).await {
+    Ok(views) => Some(views),
+    Err(e) => {
+      log::error!("failed to fetch the views of `{path}`: {e}");
+      None
+    }
+  }
+}
+
+/// A public post and how many times it's been read.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PopularPost {
+  pub path:       String,
+  pub title:      String,
+  pub written_on: String,
+  pub views:      i64,
+}
+
+/// Every public post, most read first, or a `404` if counts aren't shown.
+#[server]
+pub async fn get_popular_posts() -> Result<Vec<PopularPost>, ServerFnError> {
+  use site_db::storage::AnalyticsStore;
+
+  if !is_shown() {
+    return Err(ServerFnError::new(AppError::NotFound));
+  }
+  let views = crate::comments::use_database()?
+    .all_page_views()
+    .await
+    .map_err(|e| ServerFnError::new(e.to_string()))?
+    .into_iter()
+    .map(|page| (page.path, page.views))
+    .collect::<std::collections::HashMap<_, _>>();
+
+  let index = expect_context::<crate::post_index::PostIndex>();
+  let mut posts = index
+    .sources()
+    .into_iter()
+    .filter_map(|(path, input)| {
+      let (metadata, _) = crate::posts::try_parse_frontmatter(&input).ok()?;
+      metadata.public.then(|| PopularPost {
+        views: views.get(&counted_path(&path)).copied().unwrap_or(0),
+        path,
+        title: metadata.title,
+        written_on: metadata.written_on,
+      })
+    })
+    .collect::<Vec<_>>();
+  // ties go to the newer post
+  posts.sort_by(|a, b| (b.views, &b.written_on).cmp(&(a.views, &a.written_on)));
+  Ok(posts)
+}
+
+/// A view count, like "12 views".
+#[component]
+pub fn ViewCount(views: i64) -> impl IntoView {
+  let noun = if views == 1 { "view" } else { "views" };
+  view! { <span class="text-neutral-400">{format!("{views} {noun}")}</span> }
+}
+
+/// The page listing every public post, most read first, or a `404` if counts
+/// aren't shown.
+#[component]
+pub fn PopularPage() -> impl IntoView {
+  if !is_shown() {
+    let mut outside_errors = Errors::default();
+    outside_errors.insert_with_default_key(AppError::NotFound);
+    return view! { <ErrorTemplate outside_errors /> }.into_view();
+  }
+  let posts_resource = create_resource(|| (), |_| get_popular_posts());
+
+  let post_list_item = |p: PopularPost| {
+    view! {
+      <li>
+        <a href=format!("/post/{}", p.path)>{p.title}</a>
+        " - " <crate::dates::PostDate written_on=p.written_on />
+        " · " <ViewCount views=p.views />
+      </li>
+    }
+  };
+
+  view! {
+    <Suspense fallback=|| view! { <crate::posts::PostListSkeleton /> }>
+      <ErrorBoundary fallback=|errors| view! { <ErrorTemplate errors /> }>
+        { move || posts_resource.get().map(|p| p.map_err(AppError::from).map(|posts| view! {
+          <div class="markdown">
+            <h2>"Most read posts"</h2>
+            <ul>
+              {posts.into_iter().map(post_list_item).collect_view()}
+            </ul>
+          </div>
+        }))}
+      </ErrorBoundary>
+    </Suspense>
+  }
+  .into_view()
+}
@@ -0,0 +1,321 @@
+//! Webmentions, which tell the pages a post links to that they've been linked
+//! to. The server sends them when a post is published or changed, as
+//! [`WebmentionsConfig`](crate::config::WebmentionsConfig) says, using the
+//! functions here to find the post's links and where each page takes its
+//! webmentions. How each went is listed in the admin area, under
+//! `/admin/webmentions`.
+
+use leptos::*;
+use serde::{Deserialize, Serialize};
+
+use crate::error_template::{AppError, ErrorTemplate};
+
+#[cfg(feature = "ssr")]
+mod sending {
+  use std::{net::IpAddr, time::Duration};
+
+  use hyper::{body::HttpBody, header, Body, Method, Request, Uri};
+
+  use crate::previews::{
+    attribute, client, decode_entities, resolve_url, tags,
+  };
+
+  /// How long finding an endpoint or sending to it can take, including
+  /// redirects.
+  const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+  /// How much of a page is read looking for its endpoint.
+  const MAX_BODY_BYTES: usize = 512 * 1024;
+  /// How many redirects are followed finding an endpoint.
+  const MAX_REDIRECTS: usize = 3;
+  const USER_AGENT: &str =
+    concat!("site-app/", env!("CARGO_PKG_VERSION"), " (webmentions)");
+
+  /// The pages a post's rendered HTML links to on other sites, each once and
+  /// without its fragment, in the order they're first linked.
+  pub fn external_links(html: &str) -> Vec<String> {
+    let own_site = crate::config::site_config().url("");
+    let mut links = Vec::new();
+    for tag in tags(html, "a") {
+      let Some(href) = attribute(tag, "href").map(decode_entities) else {
+        continue;
+      };
+      let link = href.split('#').next().unwrap_or_default().to_string();
+      let is_external = (link.starts_with("https://")
+        || link.starts_with("http://"))
+        && !link.starts_with(&own_site);
+      if is_external && !links.contains(&link) {
+        links.push(link);
+      }
+    }
+    links
+  }
+
+  /// Whether `rel` names `webmention` among its link types.
+  fn is_webmention_rel(rel: &str) -> bool {
+    rel
+      .trim_matches(|c| c == '"' || c == '\'')
+      .split_whitespace()
+      .any(|rel| rel.eq_ignore_ascii_case("webmention"))
+  }
+
+  /// The webmention endpoint in a `Link` header, like
+  /// `<https://example.com/webmention>; rel="webmention"`.
+  fn endpoint_in_link_header(header: &str) -> Option<&str> {
+    header.split(',').find_map(|link| {
+      let (target, params) = link.trim().strip_prefix('<')?.split_once('>')?;
+      params
+        .split(';')
+        .filter_map(|param| param.trim().split_once('='))
+        .any(|(name, value)| {
+          name.trim().eq_ignore_ascii_case("rel") && is_webmention_rel(value)
+        })
+        .then_some(target)
+    })
+  }
+
+  /// The webmention endpoint a page's HTML gives, from its first `<link>` or
+  /// `<a>` with `rel="webmention"`. Links are looked for before anchors, since
+  /// they're in the head, ahead of anything in the body.
+  fn endpoint_in_html(html: &str) -> Option<String> {
+    tags(html, "link")
+      .into_iter()
+      .chain(tags(html, "a"))
+      .find(|tag| attribute(tag, "rel").is_some_and(is_webmention_rel))
+      .and_then(|tag| attribute(tag, "href"))
+      .map(decode_entities)
+  }
+
+  fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+      IpAddr::V4(ip) => {
+        !(ip.is_loopback()
+          || ip.is_private()
+          || ip.is_link_local()
+          || ip.is_unspecified()
+          || ip.is_broadcast())
+      }
+      IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+        Some(ip) => is_public_ip(IpAddr::V4(ip)),
+        None => {
+          // unique local and link local addresses
+          let prefix = ip.segments()[0];
+          !(ip.is_loopback()
+            || ip.is_unspecified()
+            || prefix & 0xfe00 == 0xfc00
+            || prefix & 0xffc0 == 0xfe80)
+        }
+      },
+    }
+  }
+
+  /// Whether a URL is somewhere the server may send webmentions to. Pages can
+  /// name any endpoint they like, so ones on the server's own network, which
+  /// only it could reach, are turned down, as far as their host tells.
+  pub(crate) fn is_public(url: &str) -> bool {
+    let Some(host) = url.parse::<Uri>().ok().and_then(|uri| {
+      uri
+        .host()
+        .map(|host| host.trim_matches(['[', ']']).to_lowercase())
+    }) else {
+      return false;
+    };
+    if host == "localhost" || host.ends_with(".localhost") {
+      return false;
+    }
+    host.parse::<IpAddr>().map_or(true, is_public_ip)
+  }
+
+  async fn find_endpoint(target: &str) -> Result<Option<String>, String> {
+    let mut uri = target.parse::<Uri>().map_err(|e| e.to_string())?;
+
+    for _ in 0..=MAX_REDIRECTS {
+      let request = Request::get(uri.clone())
+        .header(header::ACCEPT, "text/html")
+        .header(header::USER_AGENT, USER_AGENT)
+        .body(Body::empty())
+        .map_err(|e| e.to_string())?;
+      let mut response =
+        client().request(request).await.map_err(|e| e.to_string())?;
+
+      if response.status().is_redirection() {
+        let location = response
+          .headers()
+          .get(header::LOCATION)
+          .and_then(|l| l.to_str().ok())
+          .and_then(|l| resolve_url(&uri, l))
+          .ok_or("redirected without a location")?;
+        uri = location
+          .parse()
+          .map_err(|e: hyper::http::uri::InvalidUri| e.to_string())?;
+        continue;
+      }
+      if !response.status().is_success() {
+        return Err(format!("responded with {}", response.status()));
+      }
+
+      let from_header = response
+        .headers()
+        .get_all(header::LINK)
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .find_map(|header| endpoint_in_link_header(header).map(str::to_string));
+      let endpoint = match from_header {
+        Some(endpoint) => Some(endpoint),
+        None => {
+          let mut body = Vec::new();
+          while let Some(chunk) = response.body_mut().data().await {
+            body.extend_from_slice(&chunk.map_err(|e| e.to_string())?);
+            if body.len() >= MAX_BODY_BYTES {
+              break;
+            }
+          }
+          endpoint_in_html(&String::from_utf8_lossy(&body))
+        }
+      };
+      // an empty endpoint is the page itself
+      return Ok(endpoint.map(|endpoint| match endpoint.as_str() {
+        "" => uri.to_string(),
+        endpoint => resolve_url(&uri, endpoint).unwrap_or_default(),
+      }));
+    }
+
+    Err("too many redirects".to_string())
+  }
+
+  /// Finds where `target` takes its webmentions, following redirects, or
+  /// `None` if it doesn't.
+  pub async fn discover_endpoint(
+    target: &str,
+  ) -> Result<Option<String>, String> {
+    let endpoint = tokio::time::timeout(REQUEST_TIMEOUT, find_endpoint(target))
+      .await
+      .unwrap_or_else(|_| Err("timed out".to_string()))?;
+    match endpoint {
+      Some(endpoint) if !is_public(&endpoint) => Err(format!(
+        "named `{endpoint}`, which isn't public, as its endpoint"
+      )),
+      endpoint => Ok(endpoint),
+    }
+  }
+
+  /// Sends a webmention that `source` links to `target`, returning the status
+  /// the endpoint answered with.
+  pub async fn send(
+    endpoint: &str,
+    source: &str,
+    target: &str,
+  ) -> Result<u16, String> {
+    let encode = |value| {
+      percent_encoding::utf8_percent_encode(
+        value,
+        percent_encoding::NON_ALPHANUMERIC,
+      )
+    };
+    let request = Request::builder()
+      .method(Method::POST)
+      .uri(endpoint)
+      .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+      .header(header::USER_AGENT, USER_AGENT)
+      .body(Body::from(format!(
+        "source={}&target={}",
+        encode(source),
+        encode(target)
+      )))
+      .map_err(|e| e.to_string())?;
+    let response =
+      tokio::time::timeout(REQUEST_TIMEOUT, client().request(request))
+        .await
+        .map_err(|_| "timed out".to_string())?
+        .map_err(|e| e.to_string())?;
+    Ok(response.status().as_u16())
+  }
+}
+
+#[cfg(feature = "ssr")]
+pub(crate) use sending::is_public;
+#[cfg(feature = "ssr")]
+pub use sending::{discover_endpoint, external_links, send};
+
+/// A webmention as the admin area lists it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AdminWebmention {
+  pub post_path: String,
+  pub target:    String,
+  pub status:    String,
+  pub detail:    Option<String>,
+  pub attempts:  i64,
+  /// When it was last sent, as `YYYY.MM.DD`.
+  pub sent_on:   String,
+}
+
+/// Every webmention sent, most recently sent first.
+#[server]
+pub async fn get_sent_webmentions(
+) -> Result<Vec<AdminWebmention>, ServerFnError> {
+  use site_db::storage::WebmentionStore;
+
+  crate::auth::require_admin().await?;
+  let webmentions = crate::comments::use_database()?
+    .sent_webmentions()
+    .await
+    .map_err(|e| ServerFnError::new(e.to_string()))?;
+  Ok(
+    webmentions
+      .into_iter()
+      .map(|webmention| AdminWebmention {
+        post_path: webmention.post_path,
+        target:    webmention.target,
+        status:    webmention.status.to_string(),
+        detail:    webmention.detail,
+        attempts:  webmention.attempts,
+        sent_on:   crate::comments::written_on(webmention.updated_at),
+      })
+      .collect(),
+  )
+}
+
+/// The webmentions sent for links in posts, with how each went.
+#[component]
+pub fn AdminWebmentions() -> impl IntoView {
+  let webmentions_resource =
+    create_blocking_resource(|| (), |_| get_sent_webmentions());
+
+  let webmention_row = |webmention: AdminWebmention| {
+    view! {
+      <tr class="border-t border-neutral-600 align-top">
+        <td class="py-1 pr-4">
+          <a class="text-periwinkle underline hover:no-underline" href=format!("/post/{}", webmention.post_path)>
+            {webmention.post_path.clone()}
+          </a>
+        </td>
+        <td class="py-1 pr-4 break-all">
+          <a class="text-periwinkle underline hover:no-underline" href=webmention.target.clone() rel="external">
+            {webmention.target.clone()}
+          </a>
+        </td>
+        <td class="py-1 pr-4">{webmention.status}</td>
+        <td class="py-1 pr-4 text-neutral-400">
+          {webmention.detail} " (" {webmention.attempts} " sent, last on " {webmention.sent_on} ")"
+        </td>
+      </tr>
+    }
+  };
+
+  view! {
+    <Suspense>
+      <ErrorBoundary fallback=|errors| view! { <ErrorTemplate errors /> }>
+        { move || webmentions_resource.get().map(|w| w.map_err(AppError::from).map(|webmentions| view! {
+          { webmentions.is_empty().then(|| view! {
+            <p class="text-neutral-400">"No webmentions have been sent."</p>
+          }) }
+          <table class="w-full text-left">
+            <thead>
+              <tr><th>"Post"</th><th>"Link"</th><th>"Status"</th><th>"Details"</th></tr>
+            </thead>
+            <tbody>{webmentions.into_iter().map(webmention_row).collect_view()}</tbody>
+          </table>
+        })) }
+      </ErrorBoundary>
+    </Suspense>
+  }
+}
@@ -0,0 +1,41 @@
+//! The zero-JS mode, in which the site ships no scripts at all and every
+//! navigation is a full page load. It's a baseline for readers with
+//! JavaScript turned off, and something to compare the islands against.
+//!
+//! Islands still render on the server, so each one has to make sense as
+//! plain HTML and only enhance it once it's hydrated. The server strips the
+//! scripts from the rendered pages, other than those marked with
+//! [`KEEP_SCRIPT_ATTRIBUTE`]; this module only decides what gets rendered
+//! differently.
+
+use leptos::*;
+use leptos_router::SsrMode;
+
+/// Marks a script which is kept without the site's own, since it doesn't
+/// depend on them, like the external analytics script.
+pub const KEEP_SCRIPT_ATTRIBUTE: &str = "data-zero-js-keep";
+
+/// Provided to every request when the site is served without scripts.
+#[derive(Clone, Copy, Debug)]
+pub struct ZeroJs;
+
+/// Provided to every request when pages are rendered whole before they're
+/// sent, as they are for exports, so that every render of a page is the same.
+#[derive(Clone, Copy, Debug)]
+pub struct Unstreamed;
+
+/// Whether the site is being served without scripts.
+pub fn enabled() -> bool { use_context::<ZeroJs>().is_some() }
+
+/// How pages are streamed. Out-of-order streaming needs a script to move each
+/// chunk into place, so without scripts pages are streamed in order, and
+/// [`Unstreamed`] pages aren't streamed at all.
+pub fn ssr_mode() -> SsrMode {
+  if use_context::<Unstreamed>().is_some() {
+    SsrMode::Async
+  } else if enabled() {
+    SsrMode::InOrder
+  } else {
+    SsrMode::OutOfOrder
+  }
+}
@@ -1,3 +1,8 @@
+const shades = [50, 100, 200, 300, 400, 500, 600, 700, 800, 900, 950];
+const scale = (name) => Object.fromEntries(
+	shades.map((shade) => [shade, `rgb(var(--${name}-${shade}) / <alpha-value>)`]),
+);
+
 module.exports = {
 	content: [
     "./crates/**/*.rs"
@@ -8,8 +13,12 @@ module.exports = {
 			'mono': ['Iosevka Custom', 'ui-monospace', 'SFMono-Regular', 'Menlo', 'Monaco', 'Consolas', "Liberation Mono", "Courier New", 'monospace'],
 		},
 		extend: {
+			// the greys and periwinkle come from variables in `main.css`, so that
+			// the light theme can swap them
 			colors: {
-				'periwinkle': "oklch(68% 0.164 273.6)",
+				'periwinkle': "oklch(var(--periwinkle) / <alpha-value>)",
+				'neutral': scale('neutral'),
+				'zinc': scale('zinc'),
 			},
 		},
 	},
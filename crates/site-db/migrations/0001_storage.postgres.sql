@@ -0,0 +1,25 @@
+CREATE TABLE comments (
+  id BIGSERIAL PRIMARY KEY,
+  post_path TEXT NOT NULL,
+  author TEXT NOT NULL,
+  body TEXT NOT NULL,
+  created_at BIGINT NOT NULL
+);
+CREATE INDEX comments_by_post ON comments (post_path, created_at);
+
+CREATE TABLE reactions (
+  post_path TEXT NOT NULL,
+  reaction TEXT NOT NULL,
+  count BIGINT NOT NULL DEFAULT 0,
+  PRIMARY KEY (post_path, reaction)
+);
+
+CREATE TABLE page_views (
+  path TEXT PRIMARY KEY,
+  views BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE TABLE subscribers (
+  email TEXT PRIMARY KEY,
+  subscribed_at BIGINT NOT NULL
+);
@@ -0,0 +1,6 @@
+CREATE TABLE push_subscriptions (
+  endpoint TEXT PRIMARY KEY,
+  p256dh TEXT NOT NULL,
+  auth TEXT NOT NULL,
+  subscribed_at BIGINT NOT NULL
+);
@@ -0,0 +1,16 @@
+ALTER TABLE comments ADD COLUMN status TEXT NOT NULL DEFAULT 'approved';
+ALTER TABLE comments ADD COLUMN spam_score REAL NOT NULL DEFAULT 0;
+ALTER TABLE comments ADD COLUMN body_hash TEXT NOT NULL DEFAULT '';
+CREATE INDEX comments_by_status ON comments (status, created_at);
+CREATE INDEX comments_by_body_hash ON comments (body_hash);
+
+CREATE TABLE spam_phrases (
+  phrase TEXT PRIMARY KEY,
+  weight REAL NOT NULL
+);
+
+CREATE TABLE spam_tokens (
+  token TEXT PRIMARY KEY,
+  spam_count INTEGER NOT NULL DEFAULT 0,
+  ham_count INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1,3 @@
+ALTER TABLE comments ADD COLUMN updated_at INTEGER NOT NULL DEFAULT 0;
+UPDATE comments SET updated_at = created_at;
+CREATE INDEX comments_by_post_updated ON comments (post_path, updated_at);
@@ -0,0 +1,11 @@
+CREATE TABLE sent_webmentions (
+  post_path TEXT NOT NULL,
+  target TEXT NOT NULL,
+  endpoint TEXT,
+  status TEXT NOT NULL,
+  detail TEXT,
+  attempts INTEGER NOT NULL,
+  updated_at INTEGER NOT NULL,
+  PRIMARY KEY (post_path, target)
+);
+CREATE INDEX sent_webmentions_by_update ON sent_webmentions (updated_at);
@@ -0,0 +1,5 @@
+CREATE TABLE activitypub_followers (
+  actor TEXT PRIMARY KEY,
+  inbox TEXT NOT NULL,
+  followed_at BIGINT NOT NULL
+);
@@ -0,0 +1 @@
+ALTER TABLE reactions ADD COLUMN reacted_at BIGINT NOT NULL DEFAULT 0;
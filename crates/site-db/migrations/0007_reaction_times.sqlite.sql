@@ -0,0 +1 @@
+ALTER TABLE reactions ADD COLUMN reacted_at INTEGER NOT NULL DEFAULT 0;
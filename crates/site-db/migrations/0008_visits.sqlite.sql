@@ -0,0 +1,9 @@
+CREATE TABLE visits (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  day TEXT NOT NULL,
+  path TEXT NOT NULL,
+  referrer TEXT,
+  country TEXT,
+  visitor TEXT NOT NULL
+);
+CREATE INDEX visits_by_day ON visits (day);
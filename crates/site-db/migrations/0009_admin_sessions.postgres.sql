@@ -0,0 +1,4 @@
+CREATE TABLE admin_sessions (
+  id TEXT PRIMARY KEY,
+  expires_at BIGINT NOT NULL
+);
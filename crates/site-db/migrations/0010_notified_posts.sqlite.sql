@@ -0,0 +1,6 @@
+CREATE TABLE notified_posts (
+  path TEXT PRIMARY KEY,
+  title TEXT NOT NULL,
+  notified_at INTEGER NOT NULL
+);
+CREATE INDEX notified_posts_title ON notified_posts (title);
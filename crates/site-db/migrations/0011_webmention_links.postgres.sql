@@ -0,0 +1 @@
+ALTER TABLE sent_webmentions ADD COLUMN linked BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1 @@
+ALTER TABLE sent_webmentions ADD COLUMN linked INTEGER NOT NULL DEFAULT 1;
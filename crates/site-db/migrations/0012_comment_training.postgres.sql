@@ -0,0 +1 @@
+ALTER TABLE comments ADD COLUMN trained_as TEXT;
@@ -0,0 +1,122 @@
+//! Persistent storage for the site's dynamic data.
+//!
+//! Features talk to the database through the traits in [`storage`], which are
+//! implemented for both SQLite and Postgres. The backend is chosen by the
+//! scheme of the database URL, so moving off a single SQLite file is a matter
+//! of configuration.
+
+pub mod migrations;
+pub mod storage;
+
+use std::str::FromStr;
+
+use sqlx::{
+  postgres::{PgPool, PgPoolOptions},
+  sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions},
+};
+use thiserror::Error;
+
+/// The environment variable holding the database URL.
+pub const DATABASE_URL_VAR: &str = "DATABASE_URL";
+/// The database used when `DATABASE_URL` isn't set.
+pub const DEFAULT_DATABASE_URL: &str = "sqlite://data/site.db";
+
+#[derive(Debug, Error)]
+pub enum DbError {
+  #[error("database error: {0}")]
+  Sqlx(#[from] sqlx::Error),
+  #[error("failed to create the database directory: {0}")]
+  Io(#[from] std::io::Error),
+  #[error("database schema doesn't match this build: {0}")]
+  SchemaMismatch(String),
+  #[error("unsupported database URL `{0}`: expected `sqlite:` or `postgres:`")]
+  UnsupportedUrl(String),
+}
+
+/// The kinds of database the site can store its data in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+  Sqlite,
+  Postgres,
+}
+
+impl Backend {
+  /// Picks the backend for a database URL by its scheme.
+  pub fn from_url(url: &str) -> Option<Self> {
+    let (scheme, _) = url.split_once(':')?;
+    match scheme {
+      "sqlite" => Some(Backend::Sqlite),
+      "postgres" | "postgresql" => Some(Backend::Postgres),
+      _ => None,
+    }
+  }
+}
+
+impl std::fmt::Display for Backend {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Backend::Sqlite => write!(f, "SQLite"),
+      Backend::Postgres => write!(f, "Postgres"),
+    }
+  }
+}
+
+/// A connection pool for one of the supported backends.
+#[derive(Debug, Clone)]
+pub(crate) enum Pool {
+  Sqlite(SqlitePool),
+  Postgres(PgPool),
+}
+
+/// A handle to the site's database.
+#[derive(Debug, Clone)]
+pub struct Database {
+  pool: Pool,
+}
+
+impl Database {
+  /// Connects to the database at `url`. SQLite databases are created if they
+  /// don't exist.
+  pub async fn connect(url: &str) -> Result<Self, DbError> {
+    let pool = match Backend::from_url(url) {
+      Some(Backend::Sqlite) => Pool::Sqlite(connect_sqlite(url).await?),
+      Some(Backend::Postgres) => {
+        Pool::Postgres(PgPoolOptions::new().connect(url).await?)
+      }
+      None => return Err(DbError::UnsupportedUrl(url.to_string())),
+    };
+    Ok(Database { pool })
+  }
+
+  /// Connects to the database named by the `DATABASE_URL` environment
+  /// variable, or [`DEFAULT_DATABASE_URL`].
+  pub async fn connect_from_env() -> Result<Self, DbError> {
+    let url = std::env::var(DATABASE_URL_VAR)
+      .unwrap_or_else(|_| DEFAULT_DATABASE_URL.to_string());
+    Database::connect(&url).await
+  }
+
+  /// The backend this database is stored in.
+  pub fn backend(&self) -> Backend {
+    match self.pool {
+      Pool::Sqlite(_) => Backend::Sqlite,
+      Pool::Postgres(_) => Backend::Postgres,
+    }
+  }
+
+  pub(crate) fn pool(&self) -> &Pool { &self.pool }
+}
+
+async fn connect_sqlite(url: &str) -> Result<SqlitePool, DbError> {
+  let options = SqliteConnectOptions::from_str(url)?
+    .create_if_missing(true)
+    .foreign_keys(true);
+
+  if let Some(parent) = options.get_filename().parent() {
+    if !parent.as_os_str().is_empty() {
+      std::fs::create_dir_all(parent)?;
+    }
+  }
+
+  Ok(SqlitePoolOptions::new().connect_with(options).await?)
+}
@@ -0,0 +1,291 @@
+//! Versioned schema migrations, embedded in the binary.
+//!
+//! Each applied migration is recorded in the `_migrations` table along with a
+//! checksum of its SQL. The server refuses to run against a database that has
+//! migrations this build doesn't know about, or whose applied migrations have
+//! since been edited, rather than risk corrupting data.
+
+use sha2::{Digest, Sha256};
+
+use crate::{Backend, Database, DbError, Pool};
+
+/// A single schema migration, written once for each backend.
+#[derive(Debug)]
+pub struct Migration {
+  /// The version of the migration. Migrations are applied in version order.
+  pub version:  i64,
+  /// A short description of the migration.
+  pub name:     &'static str,
+  /// The SQL to run against SQLite.
+  pub sqlite:   &'static str,
+  /// The SQL to run against Postgres.
+  pub postgres: &'static str,
+}
+
+impl Migration {
+  /// The SQL to run against `backend`.
+  pub fn sql(&self, backend: Backend) -> &'static str {
+    match backend {
+      Backend::Sqlite => self.sqlite,
+      Backend::Postgres => self.postgres,
+    }
+  }
+
+  fn checksum(&self, backend: Backend) -> String {
+    format!("{:x}", Sha256::digest(self.sql(backend).as_bytes()))
+  }
+}
+
+impl std::fmt::Display for Migration {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{:04} {}", self.version, self.name)
+  }
+}
+
+/// Every migration, in version order. Add new migrations to the end, and never
+/// edit one that has been deployed.
+pub const MIGRATIONS: &[Migration] = &[
+  Migration {
+    version:  1,
+    name:     "create storage tables",
+    sqlite:   include_str!("../migrations/0001_storage.sqlite.sql"),
+    postgres: include_str!("../migrations/0001_storage.postgres.sql"),
+  },
+  Migration {
+    version:  2,
+    name:     "create push subscriptions",
+    sqlite:   include_str!("../migrations/0002_push_subscriptions.sqlite.sql"),
+    postgres: include_str!(
+      "../migrations/0002_push_subscriptions.postgres.sql"
+    ),
+  },
+  Migration {
+    version:  3,
+    name:     "add comment moderation and spam rules",
+    sqlite:   include_str!("../migrations/0003_comment_moderation.sqlite.sql"),
+    postgres: include_str!(
+      "../migrations/0003_comment_moderation.postgres.sql"
+    ),
+  },
+  Migration {
+    version:  4,
+    name:     "track when comments change",
+    sqlite:   include_str!("../migrations/0004_comment_updates.sqlite.sql"),
+    postgres: include_str!("../migrations/0004_comment_updates.postgres.sql"),
+  },
+  Migration {
+    version:  5,
+    name:     "record sent webmentions",
+    sqlite:   include_str!("../migrations/0005_sent_webmentions.sqlite.sql"),
+    postgres: include_str!("../migrations/0005_sent_webmentions.postgres.sql"),
+  },
+  Migration {
+    version:  6,
+    name:     "create activitypub followers",
+    sqlite:   include_str!(
+      "../migrations/0006_activitypub_followers.sqlite.sql"
+    ),
+    postgres: include_str!(
+      "../migrations/0006_activitypub_followers.postgres.sql"
+    ),
+  },
+  Migration {
+    version:  7,
+    name:     "track when posts are reacted to",
+    sqlite:   include_str!("../migrations/0007_reaction_times.sqlite.sql"),
+    postgres: include_str!("../migrations/0007_reaction_times.postgres.sql"),
+  },
+  Migration {
+    version:  8,
+    name:     "record visits",
+    sqlite:   include_str!("../migrations/0008_visits.sqlite.sql"),
+    postgres: include_str!("../migrations/0008_visits.postgres.sql"),
+  },
+  Migration {
+    version:  9,
+    name:     "store admin sessions",
+    sqlite:   include_str!("../migrations/0009_admin_sessions.sqlite.sql"),
+    postgres: include_str!("../migrations/0009_admin_sessions.postgres.sql"),
+  },
+  Migration {
+    version:  10,
+    name:     "record notified posts",
+    sqlite:   include_str!("../migrations/0010_notified_posts.sqlite.sql"),
+    postgres: include_str!("../migrations/0010_notified_posts.postgres.sql"),
+  },
+  Migration {
+    version:  11,
+    name:     "record which webmentions are for links",
+    sqlite:   include_str!("../migrations/0011_webmention_links.sqlite.sql"),
+    postgres: include_str!("../migrations/0011_webmention_links.postgres.sql"),
+  },
+  Migration {
+    version:  12,
+    name:     "record how comments were trained",
+    sqlite:   include_str!("../migrations/0012_comment_training.sqlite.sql"),
+    postgres: include_str!("../migrations/0012_comment_training.postgres.sql"),
+  },
+];
+
+const CREATE_SQLITE_MIGRATIONS_TABLE: &str = "CREATE TABLE IF NOT EXISTS \
+                                              _migrations (
+  version INTEGER PRIMARY KEY,
+  name TEXT NOT NULL,
+  checksum TEXT NOT NULL,
+  applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+)";
+
+const CREATE_POSTGRES_MIGRATIONS_TABLE: &str = "CREATE TABLE IF NOT EXISTS \
+                                                _migrations (
+  version BIGINT PRIMARY KEY,
+  name TEXT NOT NULL,
+  checksum TEXT NOT NULL,
+  applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+)";
+
+const SELECT_APPLIED: &str =
+  "SELECT version, checksum FROM _migrations ORDER BY version";
+
+/// The migration state of a database.
+#[derive(Debug)]
+pub struct MigrationStatus {
+  /// The versions that have already been applied.
+  pub applied: Vec<i64>,
+  /// The migrations that have yet to be applied, in order.
+  pub pending: Vec<&'static Migration>,
+}
+
+async fn applied_migrations(
+  db: &Database,
+) -> Result<Vec<(i64, String)>, DbError> {
+  let rows = match db.pool() {
+    Pool::Sqlite(pool) => {
+      let table_exists = sqlx::query(
+        "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = \
+         '_migrations'",
+      )
+      .fetch_optional(pool)
+      .await?
+      .is_some();
+
+      if table_exists {
+        sqlx::query_as(SELECT_APPLIED).fetch_all(pool).await?
+      } else {
+        Vec::new()
+      }
+    }
+    Pool::Postgres(pool) => {
+      let table_exists = sqlx::query(
+        "SELECT 1 FROM information_schema.tables WHERE table_schema = \
+         current_schema() AND table_name = '_migrations'",
+      )
+      .fetch_optional(pool)
+      .await?
+      .is_some();
+
+      if table_exists {
+        sqlx::query_as(SELECT_APPLIED).fetch_all(pool).await?
+      } else {
+        Vec::new()
+      }
+    }
+  };
+  Ok(rows)
+}
+
+/// Compares the database's applied migrations against [`MIGRATIONS`] without
+/// modifying anything.
+pub async fn status(db: &Database) -> Result<MigrationStatus, DbError> {
+  let backend = db.backend();
+
+  let mut applied = Vec::new();
+  for (version, checksum) in applied_migrations(db).await? {
+    let Some(migration) = MIGRATIONS.iter().find(|m| m.version == version)
+    else {
+      return Err(DbError::SchemaMismatch(format!(
+        "migration {version} has been applied but is unknown to this build"
+      )));
+    };
+    if migration.checksum(backend) != checksum {
+      return Err(DbError::SchemaMismatch(format!(
+        "migration {migration} has changed since it was applied"
+      )));
+    }
+    applied.push(version);
+  }
+
+  let latest_applied = applied.iter().copied().max().unwrap_or(0);
+  let pending = MIGRATIONS
+    .iter()
+    .filter(|m| !applied.contains(&m.version))
+    .collect::<Vec<_>>();
+  if let Some(out_of_order) =
+    pending.iter().find(|m| m.version < latest_applied)
+  {
+    return Err(DbError::SchemaMismatch(format!(
+      "migration {out_of_order} is older than the latest applied migration \
+       ({latest_applied})"
+    )));
+  }
+
+  Ok(MigrationStatus { applied, pending })
+}
+
+/// Applies every pending migration, each in its own transaction, returning
+/// the migrations that were applied.
+pub async fn migrate(
+  db: &Database,
+) -> Result<Vec<&'static Migration>, DbError> {
+  let status = status(db).await?;
+  let backend = db.backend();
+
+  match db.pool() {
+    Pool::Sqlite(pool) => {
+      sqlx::query(CREATE_SQLITE_MIGRATIONS_TABLE)
+        .execute(pool)
+        .await?;
+    }
+    Pool::Postgres(pool) => {
+      sqlx::query(CREATE_POSTGRES_MIGRATIONS_TABLE)
+        .execute(pool)
+        .await?;
+    }
+  }
+
+  for migration in status.pending.iter() {
+    log::info!("applying migration {migration} to {backend}");
+
+    let sql = migration.sql(backend);
+    match db.pool() {
+      Pool::Sqlite(pool) => {
+        let mut tx = pool.begin().await?;
+        sqlx::raw_sql(sql).execute(&mut *tx).await?;
+        sqlx::query(
+          "INSERT INTO _migrations (version, name, checksum) VALUES (?, ?, ?)",
+        )
+        .bind(migration.version)
+        .bind(migration.name)
+        .bind(migration.checksum(backend))
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+      }
+      Pool::Postgres(pool) => {
+        let mut tx = pool.begin().await?;
+        sqlx::raw_sql(sql).execute(&mut *tx).await?;
+        sqlx::query(
+          "INSERT INTO _migrations (version, name, checksum) VALUES ($1, $2, \
+           $3)",
+        )
+        .bind(migration.version)
+        .bind(migration.name)
+        .bind(migration.checksum(backend))
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+      }
+    }
+  }
+
+  Ok(status.pending)
+}
@@ -0,0 +1,895 @@
+//! The storage layer for the site's dynamic data.
+//!
+//! Each kind of data gets its own trait, implemented once per backend in the
+//! [`sqlite`] and [`postgres`] modules. [`Database`] implements every trait by
+//! forwarding to whichever backend it's connected to, so features should take
+//! a `Database` (or a generic store) and never touch SQL directly.
+
+mod postgres;
+mod sqlite;
+
+use std::{
+  future::Future,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{Database, DbError, Pool};
+
+/// Where a comment is in moderation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommentStatus {
+  /// Shown on the post.
+  Approved,
+  /// Waiting in the moderation queue.
+  Pending,
+  /// Rejected as spam, and never shown.
+  Rejected,
+}
+
+impl CommentStatus {
+  /// The status as it's stored in the database.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      CommentStatus::Approved => "approved",
+      CommentStatus::Pending => "pending",
+      CommentStatus::Rejected => "rejected",
+    }
+  }
+
+  /// Parses a status as it's stored in the database.
+  pub fn parse(status: &str) -> Option<Self> {
+    match status {
+      "approved" => Some(CommentStatus::Approved),
+      "pending" => Some(CommentStatus::Pending),
+      "rejected" => Some(CommentStatus::Rejected),
+      _ => None,
+    }
+  }
+}
+
+impl std::fmt::Display for CommentStatus {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+/// A comment that hasn't been stored yet.
+#[derive(Debug, Clone)]
+pub struct NewComment {
+  /// The path of the post being commented on.
+  pub post_path:  String,
+  /// The name the commenter gave.
+  pub author:     String,
+  /// The text of the comment.
+  pub body:       String,
+  /// Where the comment starts out in moderation.
+  pub status:     CommentStatus,
+  /// How spammy the comment looked when it was submitted.
+  pub spam_score: f64,
+  /// A hash of the comment's normalized text, for spotting the same comment
+  /// posted in several places.
+  pub body_hash:  String,
+}
+
+/// A stored comment.
+#[derive(Debug, Clone)]
+pub struct Comment {
+  pub id:         i64,
+  pub post_path:  String,
+  pub author:     String,
+  pub body:       String,
+  /// When the comment was made, in seconds since the Unix epoch.
+  pub created_at: i64,
+  pub status:     CommentStatus,
+  pub spam_score: f64,
+}
+
+/// The columns a [`Comment`] is read from, in order.
+const COMMENT_COLUMNS: &str =
+  "id, post_path, author, body, created_at, status, spam_score";
+
+type CommentRow = (i64, String, String, String, i64, String, f64);
+
+fn comment_from_row(row: CommentRow) -> Comment {
+  let (id, post_path, author, body, created_at, status, spam_score) = row;
+  Comment {
+    id,
+    post_path,
+    author,
+    body,
+    created_at,
+    // unknown statuses can only come from manual edits, so err on the side of
+    // not showing the comment
+    status: CommentStatus::parse(&status).unwrap_or(CommentStatus::Pending),
+    spam_score,
+  }
+}
+
+/// A phrase which makes comments containing it more likely to be spam.
+#[derive(Debug, Clone)]
+pub struct SpamPhrase {
+  pub phrase: String,
+  /// How much the phrase adds to a comment's spam score.
+  pub weight: f64,
+}
+
+/// How often a word has appeared in comments marked as spam and as ham.
+#[derive(Debug, Clone)]
+pub struct TokenCounts {
+  pub token: String,
+  pub spam:  i64,
+  pub ham:   i64,
+}
+
+/// The number of times a reaction has been left on a post.
+#[derive(Debug, Clone)]
+pub struct ReactionCount {
+  pub reaction: String,
+  pub count:    i64,
+}
+
+/// The number of times a page has been viewed.
+#[derive(Debug, Clone)]
+pub struct PageViews {
+  pub path:  String,
+  pub views: i64,
+}
+
+/// A visit to a page, as the analytics beacon records it.
+#[derive(Debug, Clone)]
+pub struct Visit {
+  /// The day of the visit, as `YYYY-MM-DD` in UTC.
+  pub day:      String,
+  pub path:     String,
+  /// The host of the site the visitor came from, if another.
+  pub referrer: Option<String>,
+  /// The visitor's country, as a two-letter code.
+  pub country:  Option<String>,
+  /// An identifier for the visitor which changes every day.
+  pub visitor:  String,
+}
+
+/// How many visits, by how many visitors, a day had.
+#[derive(Debug, Clone)]
+pub struct DailyVisits {
+  pub day:      String,
+  pub visits:   i64,
+  pub visitors: i64,
+}
+
+/// What visits can be broken down by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VisitField {
+  Path,
+  Referrer,
+  Country,
+}
+
+impl VisitField {
+  fn column(self) -> &'static str {
+    match self {
+      VisitField::Path => "path",
+      VisitField::Referrer => "referrer",
+      VisitField::Country => "country",
+    }
+  }
+}
+
+/// How many visits had a value of a [`VisitField`].
+#[derive(Debug, Clone)]
+pub struct VisitCount {
+  pub value:  String,
+  pub visits: i64,
+}
+
+/// A newsletter subscriber.
+#[derive(Debug, Clone)]
+pub struct Subscriber {
+  pub email:         String,
+  /// When they subscribed, in seconds since the Unix epoch.
+  pub subscribed_at: i64,
+}
+
+/// A browser's Web Push subscription.
+#[derive(Debug, Clone)]
+pub struct PushSubscription {
+  /// The push service URL notifications are sent to.
+  pub endpoint: String,
+  /// The browser's public key, base64url-encoded.
+  pub p256dh:   String,
+  /// The browser's authentication secret, base64url-encoded.
+  pub auth:     String,
+}
+
+/// An account elsewhere following the site's ActivityPub actor.
+#[derive(Debug, Clone)]
+pub struct Follower {
+  /// The follower's actor ID.
+  pub actor: String,
+  /// The inbox new posts are delivered to, which is the follower's server's
+  /// shared inbox if it has one.
+  pub inbox: String,
+}
+
+/// Where a webmention sent for a link in a post got to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebmentionStatus {
+  /// Still being sent, or waiting to be retried.
+  Sending,
+  /// The linked site's endpoint accepted it.
+  Accepted,
+  /// The linked page doesn't take webmentions.
+  NoEndpoint,
+  /// The endpoint turned it down, or kept failing until it was given up on.
+  Failed,
+}
+
+impl WebmentionStatus {
+  /// The status as it's stored in the database.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      WebmentionStatus::Sending => "sending",
+      WebmentionStatus::Accepted => "accepted",
+      WebmentionStatus::NoEndpoint => "no-endpoint",
+      WebmentionStatus::Failed => "failed",
+    }
+  }
+
+  /// Parses a status as it's stored in the database.
+  pub fn parse(status: &str) -> Option<Self> {
+    match status {
+      "sending" => Some(WebmentionStatus::Sending),
+      "accepted" => Some(WebmentionStatus::Accepted),
+      "no-endpoint" => Some(WebmentionStatus::NoEndpoint),
+      "failed" => Some(WebmentionStatus::Failed),
+      _ => None,
+    }
+  }
+}
+
+impl std::fmt::Display for WebmentionStatus {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+/// The latest attempt at sending a webmention for a link in a post.
+#[derive(Debug, Clone)]
+pub struct SentWebmention {
+  pub post_path:  String,
+  /// The linked URL.
+  pub target:     String,
+  /// The endpoint the target's webmentions go to, once it's been found.
+  pub endpoint:   Option<String>,
+  pub status:     WebmentionStatus,
+  /// What the endpoint answered, or what went wrong.
+  pub detail:     Option<String>,
+  /// How many times it's been sent.
+  pub attempts:   i64,
+  /// Whether the post linked to the target when it was sent, rather than
+  /// having dropped the link.
+  pub linked:     bool,
+  /// When it was last sent, in seconds since the Unix epoch.
+  pub updated_at: i64,
+}
+
+/// The columns a [`SentWebmention`] is read from, in order.
+const SENT_WEBMENTION_COLUMNS: &str =
+  "post_path, target, endpoint, status, detail, attempts, linked, updated_at";
+
+type SentWebmentionRow = (
+  String,
+  String,
+  Option<String>,
+  String,
+  Option<String>,
+  i64,
+  bool,
+  i64,
+);
+
+fn sent_webmention_from_row(row: SentWebmentionRow) -> SentWebmention {
+  let (
+    post_path,
+    target,
+    endpoint,
+    status,
+    detail,
+    attempts,
+    linked,
+    updated_at,
+  ) = row;
+  SentWebmention {
+    post_path,
+    target,
+    endpoint,
+    status: WebmentionStatus::parse(&status)
+      .unwrap_or(WebmentionStatus::Failed),
+    detail,
+    attempts,
+    linked,
+    updated_at,
+  }
+}
+
+/// Stores comments on posts.
+pub trait CommentStore {
+  /// Stores a comment, returning it with its ID and timestamp.
+  fn add_comment(
+    &self,
+    comment: NewComment,
+  ) -> impl Future<Output = Result<Comment, DbError>> + Send;
+
+  /// Fetches a comment by ID.
+  fn comment(
+    &self,
+    id: i64,
+  ) -> impl Future<Output = Result<Option<Comment>, DbError>> + Send;
+
+  /// Fetches the approved comments on a post, oldest first.
+  fn comments_for_post(
+    &self,
+    post_path: &str,
+  ) -> impl Future<Output = Result<Vec<Comment>, DbError>> + Send;
+
+  /// Fetches every comment with the given status, oldest first.
+  fn comments_with_status(
+    &self,
+    status: CommentStatus,
+  ) -> impl Future<Output = Result<Vec<Comment>, DbError>> + Send;
+
+  /// Moves a comment through moderation, returning `false` if it doesn't
+  /// exist.
+  fn set_comment_status(
+    &self,
+    id: i64,
+    status: CommentStatus,
+  ) -> impl Future<Output = Result<bool, DbError>> + Send;
+
+  /// Deletes a comment, returning `false` if it doesn't exist. Deleting one
+  /// doesn't count as a change in [`comments_changed_at`], so approved
+  /// comments should be rejected instead, taking them off their post.
+  ///
+  /// [`comments_changed_at`]: CommentStore::comments_changed_at
+  fn delete_comment(
+    &self,
+    id: i64,
+  ) -> impl Future<Output = Result<bool, DbError>> + Send;
+
+  /// Counts the comments on posts other than `post_path` with the given body
+  /// hash.
+  fn count_duplicate_comments(
+    &self,
+    body_hash: &str,
+    post_path: &str,
+  ) -> impl Future<Output = Result<i64, DbError>> + Send;
+
+  /// When a comment on a post was last added or moved through moderation, in
+  /// seconds since the Unix epoch, or `None` if it's never had any.
+  fn comments_changed_at(
+    &self,
+    post_path: &str,
+  ) -> impl Future<Output = Result<Option<i64>, DbError>> + Send;
+}
+
+/// Stores the rules and training data of the comment spam classifier.
+pub trait SpamStore {
+  /// Fetches every spam phrase.
+  fn spam_phrases(
+    &self,
+  ) -> impl Future<Output = Result<Vec<SpamPhrase>, DbError>> + Send;
+
+  /// Adds a spam phrase, or updates the weight of an existing one.
+  fn add_spam_phrase(
+    &self,
+    phrase: &SpamPhrase,
+  ) -> impl Future<Output = Result<(), DbError>> + Send;
+
+  /// Removes a spam phrase, returning `false` if it didn't exist.
+  fn remove_spam_phrase(
+    &self,
+    phrase: &str,
+  ) -> impl Future<Output = Result<bool, DbError>> + Send;
+
+  /// Fetches the training counts of the given tokens. Tokens which have never
+  /// been trained on are left out.
+  fn token_counts(
+    &self,
+    tokens: &[String],
+  ) -> impl Future<Output = Result<Vec<TokenCounts>, DbError>> + Send;
+
+  /// Counts each of the given tokens, the words of comment `id`, once towards
+  /// spam or ham. A comment only ever counts once: training it the same way
+  /// again changes nothing, and training it the other way moves its tokens'
+  /// counts over. Returns `false` if the comment doesn't exist.
+  fn train_comment(
+    &self,
+    id: i64,
+    tokens: &[String],
+    is_spam: bool,
+  ) -> impl Future<Output = Result<bool, DbError>> + Send;
+}
+
+/// Stores reaction counts on posts.
+pub trait ReactionStore {
+  /// Records a reaction on a post, returning its new count.
+  fn add_reaction(
+    &self,
+    post_path: &str,
+    reaction: &str,
+  ) -> impl Future<Output = Result<i64, DbError>> + Send;
+
+  /// Fetches the counts of every reaction on a post, most popular first.
+  fn reaction_counts(
+    &self,
+    post_path: &str,
+  ) -> impl Future<Output = Result<Vec<ReactionCount>, DbError>> + Send;
+
+  /// Fetches when a post was last reacted to, if it ever was.
+  fn reactions_changed_at(
+    &self,
+    post_path: &str,
+  ) -> impl Future<Output = Result<Option<i64>, DbError>> + Send;
+}
+
+/// Stores page view counts.
+pub trait AnalyticsStore {
+  /// Records a view of a page, returning its new view count.
+  fn record_page_view(
+    &self,
+    path: &str,
+  ) -> impl Future<Output = Result<i64, DbError>> + Send;
+
+  /// Fetches the view count of a page.
+  fn page_views(
+    &self,
+    path: &str,
+  ) -> impl Future<Output = Result<i64, DbError>> + Send;
+
+  /// Fetches the view count of every page that's been viewed, most viewed
+  /// first.
+  fn all_page_views(
+    &self,
+  ) -> impl Future<Output = Result<Vec<PageViews>, DbError>> + Send;
+
+  /// Records a visit to a page.
+  fn record_visit(
+    &self,
+    visit: &Visit,
+  ) -> impl Future<Output = Result<(), DbError>> + Send;
+
+  /// Fetches the visits of each day since `since`, a `YYYY-MM-DD` day,
+  /// oldest first. Days without visits are left out.
+  fn daily_visits(
+    &self,
+    since: &str,
+  ) -> impl Future<Output = Result<Vec<DailyVisits>, DbError>> + Send;
+
+  /// Fetches the `limit` most common values of `field` among the visits
+  /// since `since`, most common first.
+  fn top_visits(
+    &self,
+    field: VisitField,
+    since: &str,
+    limit: i64,
+  ) -> impl Future<Output = Result<Vec<VisitCount>, DbError>> + Send;
+
+  /// Deletes the visits from before `day`, returning how many there were.
+  fn delete_visits_before(
+    &self,
+    day: &str,
+  ) -> impl Future<Output = Result<u64, DbError>> + Send;
+}
+
+/// Stores newsletter subscribers.
+pub trait SubscriberStore {
+  /// Adds a subscriber, returning `false` if they were already subscribed.
+  fn add_subscriber(
+    &self,
+    email: &str,
+  ) -> impl Future<Output = Result<bool, DbError>> + Send;
+
+  /// Removes a subscriber, returning `false` if they weren't subscribed.
+  fn remove_subscriber(
+    &self,
+    email: &str,
+  ) -> impl Future<Output = Result<bool, DbError>> + Send;
+
+  /// Fetches every subscriber, in the order they subscribed.
+  fn subscribers(
+    &self,
+  ) -> impl Future<Output = Result<Vec<Subscriber>, DbError>> + Send;
+}
+
+/// Stores readers' Web Push subscriptions.
+pub trait PushSubscriptionStore {
+  /// Stores a subscription, replacing the keys of an existing subscription
+  /// with the same endpoint.
+  fn add_push_subscription(
+    &self,
+    subscription: &PushSubscription,
+  ) -> impl Future<Output = Result<(), DbError>> + Send;
+
+  /// Removes a subscription, returning `false` if it wasn't stored.
+  fn remove_push_subscription(
+    &self,
+    endpoint: &str,
+  ) -> impl Future<Output = Result<bool, DbError>> + Send;
+
+  /// Fetches every subscription.
+  fn push_subscriptions(
+    &self,
+  ) -> impl Future<Output = Result<Vec<PushSubscription>, DbError>> + Send;
+}
+
+/// Stores how the webmentions for links in posts went.
+pub trait WebmentionStore {
+  /// Records an attempt at sending a webmention, replacing the last one for
+  /// the same post and target. Its `updated_at` is ignored in favour of now.
+  fn record_webmention(
+    &self,
+    webmention: &SentWebmention,
+  ) -> impl Future<Output = Result<(), DbError>> + Send;
+
+  /// Fetches the webmentions sent for links in a post.
+  fn webmentions_for_post(
+    &self,
+    post_path: &str,
+  ) -> impl Future<Output = Result<Vec<SentWebmention>, DbError>> + Send;
+
+  /// Fetches every webmention sent, most recently sent first.
+  fn sent_webmentions(
+    &self,
+  ) -> impl Future<Output = Result<Vec<SentWebmention>, DbError>> + Send;
+}
+
+/// Stores who follows the site's ActivityPub actor.
+pub trait FollowerStore {
+  /// Stores a follower, replacing the inbox of an existing follower with the
+  /// same actor.
+  fn add_follower(
+    &self,
+    follower: &Follower,
+  ) -> impl Future<Output = Result<(), DbError>> + Send;
+
+  /// Removes a follower, returning `false` if they weren't stored.
+  fn remove_follower(
+    &self,
+    actor: &str,
+  ) -> impl Future<Output = Result<bool, DbError>> + Send;
+
+  /// Fetches every follower, in the order they followed.
+  fn followers(
+    &self,
+  ) -> impl Future<Output = Result<Vec<Follower>, DbError>> + Send;
+}
+
+/// Stores the author's sessions, so that logging out ends them.
+pub trait SessionStore {
+  /// Stores a session lasting until `expires_at`, in seconds since the Unix
+  /// epoch, forgetting any which have expired.
+  fn start_session(
+    &self,
+    id: &str,
+    expires_at: i64,
+  ) -> impl Future<Output = Result<(), DbError>> + Send;
+
+  /// Whether a session is stored and hasn't expired.
+  fn has_session(
+    &self,
+    id: &str,
+  ) -> impl Future<Output = Result<bool, DbError>> + Send;
+
+  /// Forgets a session.
+  fn end_session(
+    &self,
+    id: &str,
+  ) -> impl Future<Output = Result<(), DbError>> + Send;
+}
+
+/// Stores which posts readers have been told about, so that each is announced
+/// once, however its file changes.
+pub trait NotifiedPostStore {
+  /// Whether any post has been announced yet.
+  fn any_notified(&self) -> impl Future<Output = Result<bool, DbError>> + Send;
+
+  /// Records that the post at `path`, titled `title`, has been announced,
+  /// returning whether it's new. A post is taken to have been announced
+  /// before if a post at the same path or with the same title was, so that
+  /// neither renaming its file nor its title announces it again.
+  fn record_notified(
+    &self,
+    path: &str,
+    title: &str,
+  ) -> impl Future<Output = Result<bool, DbError>> + Send;
+}
+
+/// Every kind of storage the site needs.
+pub trait Storage:
+  CommentStore
+  + SpamStore
+  + ReactionStore
+  + AnalyticsStore
+  + SubscriberStore
+  + PushSubscriptionStore
+  + WebmentionStore
+  + FollowerStore
+  + SessionStore
+  + NotifiedPostStore
+{
+}
+
+impl<T> Storage for T where
+  T: CommentStore
+    + SpamStore
+    + ReactionStore
+    + AnalyticsStore
+    + SubscriberStore
+    + PushSubscriptionStore
+    + WebmentionStore
+    + FollowerStore
+    + SessionStore
+    + NotifiedPostStore
+{
+}
+
+/// The current time, in seconds since the Unix epoch.
+fn now() -> i64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs() as i64)
+    .unwrap_or_default()
+}
+
+/// Forwards a store method to the backend the database is connected to.
+macro_rules! forward {
+  ($db:expr, $pool:ident => $call:expr) => {
+    match $db.pool() {
+      Pool::Sqlite($pool) => $call.await,
+      Pool::Postgres($pool) => $call.await,
+    }
+  };
+}
+
+impl CommentStore for Database {
+  async fn add_comment(&self, comment: NewComment) -> Result<Comment, DbError> {
+    forward!(self, pool => pool.add_comment(comment))
+  }
+
+  async fn comment(&self, id: i64) -> Result<Option<Comment>, DbError> {
+    forward!(self, pool => pool.comment(id))
+  }
+
+  async fn comments_for_post(
+    &self,
+    post_path: &str,
+  ) -> Result<Vec<Comment>, DbError> {
+    forward!(self, pool => pool.comments_for_post(post_path))
+  }
+
+  async fn comments_with_status(
+    &self,
+    status: CommentStatus,
+  ) -> Result<Vec<Comment>, DbError> {
+    forward!(self, pool => pool.comments_with_status(status))
+  }
+
+  async fn set_comment_status(
+    &self,
+    id: i64,
+    status: CommentStatus,
+  ) -> Result<bool, DbError> {
+    forward!(self, pool => pool.set_comment_status(id, status))
+  }
+
+  async fn delete_comment(&self, id: i64) -> Result<bool, DbError> {
+    forward!(self, pool => pool.delete_comment(id))
+  }
+
+  async fn count_duplicate_comments(
+    &self,
+    body_hash: &str,
+    post_path: &str,
+  ) -> Result<i64, DbError> {
+    forward!(self, pool => pool.count_duplicate_comments(body_hash, post_path))
+  }
+
+  async fn comments_changed_at(
+    &self,
+    post_path: &str,
+  ) -> Result<Option<i64>, DbError> {
+    forward!(self, pool => pool.comments_changed_at(post_path))
+  }
+}
+
+impl SpamStore for Database {
+  async fn spam_phrases(&self) -> Result<Vec<SpamPhrase>, DbError> {
+    forward!(self, pool => pool.spam_phrases())
+  }
+
+  async fn add_spam_phrase(&self, phrase: &SpamPhrase) -> Result<(), DbError> {
+    forward!(self, pool => pool.add_spam_phrase(phrase))
+  }
+
+  async fn remove_spam_phrase(&self, phrase: &str) -> Result<bool, DbError> {
+    forward!(self, pool => pool.remove_spam_phrase(phrase))
+  }
+
+  async fn token_counts(
+    &self,
+    tokens: &[String],
+  ) -> Result<Vec<TokenCounts>, DbError> {
+    forward!(self, pool => pool.token_counts(tokens))
+  }
+
+  async fn train_comment(
+    &self,
+    id: i64,
+    tokens: &[String],
+    is_spam: bool,
+  ) -> Result<bool, DbError> {
+    forward!(self, pool => pool.train_comment(id, tokens, is_spam))
+  }
+}
+
+impl ReactionStore for Database {
+  async fn add_reaction(
+    &self,
+    post_path: &str,
+    reaction: &str,
+  ) -> Result<i64, DbError> {
+    forward!(self, pool => pool.add_reaction(post_path, reaction))
+  }
+
+  async fn reaction_counts(
+    &self,
+    post_path: &str,
+  ) -> Result<Vec<ReactionCount>, DbError> {
+    forward!(self, pool => pool.reaction_counts(post_path))
+  }
+
+  async fn reactions_changed_at(
+    &self,
+    post_path: &str,
+  ) -> Result<Option<i64>, DbError> {
+    forward!(self, pool => pool.reactions_changed_at(post_path))
+  }
+}
+
+impl AnalyticsStore for Database {
+  async fn record_page_view(&self, path: &str) -> Result<i64, DbError> {
+    forward!(self, pool => pool.record_page_view(path))
+  }
+
+  async fn page_views(&self, path: &str) -> Result<i64, DbError> {
+    forward!(self, pool => pool.page_views(path))
+  }
+
+  async fn all_page_views(&self) -> Result<Vec<PageViews>, DbError> {
+    forward!(self, pool => pool.all_page_views())
+  }
+
+  async fn record_visit(&self, visit: &Visit) -> Result<(), DbError> {
+    forward!(self, pool => pool.record_visit(visit))
+  }
+
+  async fn daily_visits(
+    &self,
+    since: &str,
+  ) -> Result<Vec<DailyVisits>, DbError> {
+    forward!(self, pool => pool.daily_visits(since))
+  }
+
+  async fn top_visits(
+    &self,
+    field: VisitField,
+    since: &str,
+    limit: i64,
+  ) -> Result<Vec<VisitCount>, DbError> {
+    forward!(self, pool => pool.top_visits(field, since, limit))
+  }
+
+  async fn delete_visits_before(&self, day: &str) -> Result<u64, DbError> {
+    forward!(self, pool => pool.delete_visits_before(day))
+  }
+}
+
+impl SubscriberStore for Database {
+  async fn add_subscriber(&self, email: &str) -> Result<bool, DbError> {
+    forward!(self, pool => pool.add_subscriber(email))
+  }
+
+  async fn remove_subscriber(&self, email: &str) -> Result<bool, DbError> {
+    forward!(self, pool => pool.remove_subscriber(email))
+  }
+
+  async fn subscribers(&self) -> Result<Vec<Subscriber>, DbError> {
+    forward!(self, pool => pool.subscribers())
+  }
+}
+
+impl PushSubscriptionStore for Database {
+  async fn add_push_subscription(
+    &self,
+    subscription: &PushSubscription,
+  ) -> Result<(), DbError> {
+    forward!(self, pool => pool.add_push_subscription(subscription))
+  }
+
+  async fn remove_push_subscription(
+    &self,
+    endpoint: &str,
+  ) -> Result<bool, DbError> {
+    forward!(self, pool => pool.remove_push_subscription(endpoint))
+  }
+
+  async fn push_subscriptions(&self) -> Result<Vec<PushSubscription>, DbError> {
+    forward!(self, pool => pool.push_subscriptions())
+  }
+}
+
+impl WebmentionStore for Database {
+  async fn record_webmention(
+    &self,
+    webmention: &SentWebmention,
+  ) -> Result<(), DbError> {
+    forward!(self, pool => pool.record_webmention(webmention))
+  }
+
+  async fn webmentions_for_post(
+    &self,
+    post_path: &str,
+  ) -> Result<Vec<SentWebmention>, DbError> {
+    forward!(self, pool => pool.webmentions_for_post(post_path))
+  }
+
+  async fn sent_webmentions(&self) -> Result<Vec<SentWebmention>, DbError> {
+    forward!(self, pool => pool.sent_webmentions())
+  }
+}
+
+impl FollowerStore for Database {
+  async fn add_follower(&self, follower: &Follower) -> Result<(), DbError> {
+    forward!(self, pool => pool.add_follower(follower))
+  }
+
+  async fn remove_follower(&self, actor: &str) -> Result<bool, DbError> {
+    forward!(self, pool => pool.remove_follower(actor))
+  }
+
+  async fn followers(&self) -> Result<Vec<Follower>, DbError> {
+    forward!(self, pool => pool.followers())
+  }
+}
+
+impl SessionStore for Database {
+  async fn start_session(
+    &self,
+    id: &str,
+    expires_at: i64,
+  ) -> Result<(), DbError> {
+    forward!(self, pool => pool.start_session(id, expires_at))
+  }
+
+  async fn has_session(&self, id: &str) -> Result<bool, DbError> {
+    forward!(self, pool => pool.has_session(id))
+  }
+
+  async fn end_session(&self, id: &str) -> Result<(), DbError> {
+    forward!(self, pool => pool.end_session(id))
+  }
+}
+
+impl NotifiedPostStore for Database {
+  async fn any_notified(&self) -> Result<bool, DbError> {
+    forward!(self, pool => pool.any_notified())
+  }
+
+  async fn record_notified(
+    &self,
+    path: &str,
+    title: &str,
+  ) -> Result<bool, DbError> {
+    forward!(self, pool => pool.record_notified(path, title))
+  }
+}
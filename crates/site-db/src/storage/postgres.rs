@@ -0,0 +1,672 @@
+//! The Postgres implementation of the storage traits.
+
+use sqlx::{PgPool, Postgres, QueryBuilder};
+
+use super::{
+  comment_from_row, now, sent_webmention_from_row, AnalyticsStore, Comment,
+  CommentRow, CommentStatus, CommentStore, DailyVisits, Follower,
+  FollowerStore, NewComment, NotifiedPostStore, PageViews, PushSubscription,
+  PushSubscriptionStore, ReactionCount, ReactionStore, SentWebmention,
+  SentWebmentionRow, SessionStore, SpamPhrase, SpamStore, Subscriber,
+  SubscriberStore, TokenCounts, Visit, VisitCount, VisitField, WebmentionStore,
+  COMMENT_COLUMNS, SENT_WEBMENTION_COLUMNS,
+};
+use crate::DbError;
+
+impl CommentStore for PgPool {
+  async fn add_comment(&self, comment: NewComment) -> Result<Comment, DbError> {
+    let created_at = now();
+    let id = sqlx::query_scalar(
+      "INSERT INTO comments (post_path, author, body, created_at, status, \
+       spam_score, body_hash, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7, \
+       $8) RETURNING id",
+    )
+    .bind(&comment.post_path)
+    .bind(&comment.author)
+    .bind(&comment.body)
+    .bind(created_at)
+    .bind(comment.status.as_str())
+    .bind(comment.spam_score)
+    .bind(&comment.body_hash)
+    .bind(created_at)
+    .fetch_one(self)
+    .await?;
+
+    Ok(Comment {
+      id,
+      post_path: comment.post_path,
+      author: comment.author,
+      body: comment.body,
+      created_at,
+      status: comment.status,
+      spam_score: comment.spam_score,
+    })
+  }
+
+  async fn comment(&self, id: i64) -> Result<Option<Comment>, DbError> {
+    let row: Option<CommentRow> = sqlx::query_as(&format!(
+      "SELECT {COMMENT_COLUMNS} FROM comments WHERE id = $1"
+    ))
+    .bind(id)
+    .fetch_optional(self)
+    .await?;
+    Ok(row.map(comment_from_row))
+  }
+
+  async fn comments_for_post(
+    &self,
+    post_path: &str,
+  ) -> Result<Vec<Comment>, DbError> {
+    let rows: Vec<CommentRow> = sqlx::query_as(&format!(
+      "SELECT {COMMENT_COLUMNS} FROM comments WHERE post_path = $1 AND status \
+       = $2 ORDER BY created_at, id"
+    ))
+    .bind(post_path)
+    .bind(CommentStatus::Approved.as_str())
+    .fetch_all(self)
+    .await?;
+    Ok(rows.into_iter().map(comment_from_row).collect())
+  }
+
+  async fn comments_with_status(
+    &self,
+    status: CommentStatus,
+  ) -> Result<Vec<Comment>, DbError> {
+    let rows: Vec<CommentRow> = sqlx::query_as(&format!(
+      "SELECT {COMMENT_COLUMNS} FROM comments WHERE status = $1 ORDER BY \
+       created_at, id"
+    ))
+    .bind(status.as_str())
+    .fetch_all(self)
+    .await?;
+    Ok(rows.into_iter().map(comment_from_row).collect())
+  }
+
+  async fn set_comment_status(
+    &self,
+    id: i64,
+    status: CommentStatus,
+  ) -> Result<bool, DbError> {
+    let result = sqlx::query(
+      "UPDATE comments SET status = $1, updated_at = $2 WHERE id = $3",
+    )
+    .bind(status.as_str())
+    .bind(now())
+    .bind(id)
+    .execute(self)
+    .await?;
+    Ok(result.rows_affected() > 0)
+  }
+
+  async fn delete_comment(&self, id: i64) -> Result<bool, DbError> {
+    let result = sqlx::query("DELETE FROM comments WHERE id = $1")
+      .bind(id)
+      .execute(self)
+      .await?;
+    Ok(result.rows_affected() > 0)
+  }
+
+  async fn count_duplicate_comments(
+    &self,
+    body_hash: &str,
+    post_path: &str,
+  ) -> Result<i64, DbError> {
+    Ok(
+      sqlx::query_scalar(
+        "SELECT COUNT(*) FROM comments WHERE body_hash = $1 AND post_path != \
+         $2",
+      )
+      .bind(body_hash)
+      .bind(post_path)
+      .fetch_one(self)
+      .await?,
+    )
+  }
+  async fn comments_changed_at(
+    &self,
+    post_path: &str,
+  ) -> Result<Option<i64>, DbError> {
+    Ok(
+      sqlx::query_scalar(
+        "SELECT MAX(updated_at) FROM comments WHERE post_path = $1",
+      )
+      .bind(post_path)
+      .fetch_one(self)
+      .await?,
+    )
+  }
+}
+
+impl SpamStore for PgPool {
+  async fn spam_phrases(&self) -> Result<Vec<SpamPhrase>, DbError> {
+    let rows: Vec<(String, f64)> =
+      sqlx::query_as("SELECT phrase, weight FROM spam_phrases ORDER BY phrase")
+        .fetch_all(self)
+        .await?;
+    Ok(
+      rows
+        .into_iter()
+        .map(|(phrase, weight)| SpamPhrase { phrase, weight })
+        .collect(),
+    )
+  }
+
+  async fn add_spam_phrase(&self, phrase: &SpamPhrase) -> Result<(), DbError> {
+    sqlx::query(
+      "INSERT INTO spam_phrases (phrase, weight) VALUES ($1, $2) ON CONFLICT \
+       (phrase) DO UPDATE SET weight = excluded.weight",
+    )
+    .bind(&phrase.phrase)
+    .bind(phrase.weight)
+    .execute(self)
+    .await?;
+    Ok(())
+  }
+
+  async fn remove_spam_phrase(&self, phrase: &str) -> Result<bool, DbError> {
+    let result = sqlx::query("DELETE FROM spam_phrases WHERE phrase = $1")
+      .bind(phrase)
+      .execute(self)
+      .await?;
+    Ok(result.rows_affected() > 0)
+  }
+
+  async fn token_counts(
+    &self,
+    tokens: &[String],
+  ) -> Result<Vec<TokenCounts>, DbError> {
+    if tokens.is_empty() {
+      return Ok(Vec::new());
+    }
+
+    let mut query = QueryBuilder::<Postgres>::new(
+      "SELECT token, spam_count, ham_count FROM spam_tokens WHERE token IN (",
+    );
+    let mut separated = query.separated(", ");
+    for token in tokens {
+      separated.push_bind(token);
+    }
+    separated.push_unseparated(")");
+
+    let rows: Vec<(String, i64, i64)> =
+      query.build_query_as().fetch_all(self).await?;
+    Ok(
+      rows
+        .into_iter()
+        .map(|(token, spam, ham)| TokenCounts { token, spam, ham })
+        .collect(),
+    )
+  }
+
+  async fn train_comment(
+    &self,
+    id: i64,
+    tokens: &[String],
+    is_spam: bool,
+  ) -> Result<bool, DbError> {
+    let label = if is_spam { "spam" } else { "ham" };
+
+    let mut tx = self.begin().await?;
+    let Some(trained_as) = sqlx::query_scalar::<_, Option<String>>(
+      "SELECT trained_as FROM comments WHERE id = $1",
+    )
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+      return Ok(false);
+    };
+    if trained_as.as_deref() == Some(label) {
+      return Ok(true);
+    }
+
+    // a comment trained the other way has its counts taken back off
+    let untrain = i64::from(trained_as.is_some());
+    let (spam, ham) = if is_spam {
+      (1, -untrain)
+    } else {
+      (-untrain, 1)
+    };
+    for token in tokens {
+      sqlx::query(
+        "INSERT INTO spam_tokens (token, spam_count, ham_count) VALUES ($1, \
+         $2, $3) ON CONFLICT (token) DO UPDATE SET spam_count = \
+         GREATEST(spam_tokens.spam_count + $4, 0), ham_count = \
+         GREATEST(spam_tokens.ham_count + $5, 0)",
+      )
+      .bind(token)
+      .bind(spam.max(0))
+      .bind(ham.max(0))
+      .bind(spam)
+      .bind(ham)
+      .execute(&mut *tx)
+      .await?;
+    }
+    sqlx::query("UPDATE comments SET trained_as = $1 WHERE id = $2")
+      .bind(label)
+      .bind(id)
+      .execute(&mut *tx)
+      .await?;
+    tx.commit().await?;
+    Ok(true)
+  }
+}
+
+impl ReactionStore for PgPool {
+  async fn add_reaction(
+    &self,
+    post_path: &str,
+    reaction: &str,
+  ) -> Result<i64, DbError> {
+    Ok(
+      sqlx::query_scalar(
+        "INSERT INTO reactions (post_path, reaction, count, reacted_at) \
+         VALUES ($1, $2, 1, $3) ON CONFLICT (post_path, reaction) DO UPDATE \
+         SET count = reactions.count + 1, reacted_at = excluded.reacted_at \
+         RETURNING count",
+      )
+      .bind(post_path)
+      .bind(reaction)
+      .bind(now())
+      .fetch_one(self)
+      .await?,
+    )
+  }
+
+  async fn reaction_counts(
+    &self,
+    post_path: &str,
+  ) -> Result<Vec<ReactionCount>, DbError> {
+    let rows: Vec<(String, i64)> = sqlx::query_as(
+      "SELECT reaction, count FROM reactions WHERE post_path = $1 ORDER BY \
+       count DESC, reaction",
+    )
+    .bind(post_path)
+    .fetch_all(self)
+    .await?;
+
+    Ok(
+      rows
+        .into_iter()
+        .map(|(reaction, count)| ReactionCount { reaction, count })
+        .collect(),
+    )
+  }
+
+  async fn reactions_changed_at(
+    &self,
+    post_path: &str,
+  ) -> Result<Option<i64>, DbError> {
+    Ok(
+      sqlx::query_scalar(
+        "SELECT MAX(reacted_at) FROM reactions WHERE post_path = $1",
+      )
+      .bind(post_path)
+      .fetch_one(self)
+      .await?,
+    )
+  }
+}
+
+impl AnalyticsStore for PgPool {
+  async fn record_page_view(&self, path: &str) -> Result<i64, DbError> {
+    Ok(
+      sqlx::query_scalar(
+        "INSERT INTO page_views (path, views) VALUES ($1, 1) ON CONFLICT \
+         (path) DO UPDATE SET views = page_views.views + 1 RETURNING views",
+      )
+      .bind(path)
+      .fetch_one(self)
+      .await?,
+    )
+  }
+
+  async fn page_views(&self, path: &str) -> Result<i64, DbError> {
+    let views =
+      sqlx::query_scalar("SELECT views FROM page_views WHERE path = $1")
+        .bind(path)
+        .fetch_optional(self)
+        .await?;
+    Ok(views.unwrap_or(0))
+  }
+
+  async fn all_page_views(&self) -> Result<Vec<PageViews>, DbError> {
+    let rows: Vec<(String, i64)> = sqlx::query_as(
+      "SELECT path, views FROM page_views ORDER BY views DESC, path",
+    )
+    .fetch_all(self)
+    .await?;
+
+    Ok(
+      rows
+        .into_iter()
+        .map(|(path, views)| PageViews { path, views })
+        .collect(),
+    )
+  }
+
+  async fn record_visit(&self, visit: &Visit) -> Result<(), DbError> {
+    sqlx::query(
+      "INSERT INTO visits (day, path, referrer, country, visitor) VALUES ($1, \
+       $2, $3, $4, $5)",
+    )
+    .bind(&visit.day)
+    .bind(&visit.path)
+    .bind(&visit.referrer)
+    .bind(&visit.country)
+    .bind(&visit.visitor)
+    .execute(self)
+    .await?;
+    Ok(())
+  }
+
+  async fn daily_visits(
+    &self,
+    since: &str,
+  ) -> Result<Vec<DailyVisits>, DbError> {
+    let rows: Vec<(String, i64, i64)> = sqlx::query_as(
+      "SELECT day, COUNT(*), COUNT(DISTINCT visitor) FROM visits WHERE day >= \
+       $1 GROUP BY day ORDER BY day",
+    )
+    .bind(since)
+    .fetch_all(self)
+    .await?;
+
+    Ok(
+      rows
+        .into_iter()
+        .map(|(day, visits, visitors)| DailyVisits {
+          day,
+          visits,
+          visitors,
+        })
+        .collect(),
+    )
+  }
+
+  async fn top_visits(
+    &self,
+    field: VisitField,
+    since: &str,
+    limit: i64,
+  ) -> Result<Vec<VisitCount>, DbError> {
+    let column = field.column();
+    let rows: Vec<(String, i64)> = sqlx::query_as(&format!(
+      "SELECT {column}, COUNT(*) AS visits FROM visits WHERE day >= $1 AND \
+       {column} IS NOT NULL GROUP BY {column} ORDER BY visits DESC, {column} \
+       LIMIT $2"
+    ))
+    .bind(since)
+    .bind(limit)
+    .fetch_all(self)
+    .await?;
+
+    Ok(
+      rows
+        .into_iter()
+        .map(|(value, visits)| VisitCount { value, visits })
+        .collect(),
+    )
+  }
+
+  async fn delete_visits_before(&self, day: &str) -> Result<u64, DbError> {
+    let result = sqlx::query("DELETE FROM visits WHERE day < $1")
+      .bind(day)
+      .execute(self)
+      .await?;
+    Ok(result.rows_affected())
+  }
+}
+
+impl SubscriberStore for PgPool {
+  async fn add_subscriber(&self, email: &str) -> Result<bool, DbError> {
+    let result = sqlx::query(
+      "INSERT INTO subscribers (email, subscribed_at) VALUES ($1, $2) ON \
+       CONFLICT (email) DO NOTHING",
+    )
+    .bind(email)
+    .bind(now())
+    .execute(self)
+    .await?;
+    Ok(result.rows_affected() > 0)
+  }
+
+  async fn remove_subscriber(&self, email: &str) -> Result<bool, DbError> {
+    let result = sqlx::query("DELETE FROM subscribers WHERE email = $1")
+      .bind(email)
+      .execute(self)
+      .await?;
+    Ok(result.rows_affected() > 0)
+  }
+
+  async fn subscribers(&self) -> Result<Vec<Subscriber>, DbError> {
+    let rows: Vec<(String, i64)> = sqlx::query_as(
+      "SELECT email, subscribed_at FROM subscribers ORDER BY subscribed_at, \
+       email",
+    )
+    .fetch_all(self)
+    .await?;
+
+    Ok(
+      rows
+        .into_iter()
+        .map(|(email, subscribed_at)| Subscriber {
+          email,
+          subscribed_at,
+        })
+        .collect(),
+    )
+  }
+}
+
+impl PushSubscriptionStore for PgPool {
+  async fn add_push_subscription(
+    &self,
+    subscription: &PushSubscription,
+  ) -> Result<(), DbError> {
+    sqlx::query(
+      "INSERT INTO push_subscriptions (endpoint, p256dh, auth, subscribed_at) \
+       VALUES ($1, $2, $3, $4) ON CONFLICT (endpoint) DO UPDATE SET p256dh = \
+       excluded.p256dh, auth = excluded.auth",
+    )
+    .bind(&subscription.endpoint)
+    .bind(&subscription.p256dh)
+    .bind(&subscription.auth)
+    .bind(now())
+    .execute(self)
+    .await?;
+    Ok(())
+  }
+
+  async fn remove_push_subscription(
+    &self,
+    endpoint: &str,
+  ) -> Result<bool, DbError> {
+    let result =
+      sqlx::query("DELETE FROM push_subscriptions WHERE endpoint = $1")
+        .bind(endpoint)
+        .execute(self)
+        .await?;
+    Ok(result.rows_affected() > 0)
+  }
+
+  async fn push_subscriptions(&self) -> Result<Vec<PushSubscription>, DbError> {
+    let rows: Vec<(String, String, String)> = sqlx::query_as(
+      "SELECT endpoint, p256dh, auth FROM push_subscriptions ORDER BY \
+       subscribed_at",
+    )
+    .fetch_all(self)
+    .await?;
+
+    Ok(
+      rows
+        .into_iter()
+        .map(|(endpoint, p256dh, auth)| PushSubscription {
+          endpoint,
+          p256dh,
+          auth,
+        })
+        .collect(),
+    )
+  }
+}
+
+impl WebmentionStore for PgPool {
+  async fn record_webmention(
+    &self,
+    webmention: &SentWebmention,
+  ) -> Result<(), DbError> {
+    sqlx::query(
+      "INSERT INTO sent_webmentions (post_path, target, endpoint, status, \
+       detail, attempts, linked, updated_at) VALUES ($1, $2, $3, $4, $5, $6, \
+       $7, $8) ON CONFLICT (post_path, target) DO UPDATE SET endpoint = \
+       excluded.endpoint, status = excluded.status, detail = excluded.detail, \
+       attempts = excluded.attempts, linked = excluded.linked, updated_at = \
+       excluded.updated_at",
+    )
+    .bind(&webmention.post_path)
+    .bind(&webmention.target)
+    .bind(&webmention.endpoint)
+    .bind(webmention.status.as_str())
+    .bind(&webmention.detail)
+    .bind(webmention.attempts)
+    .bind(webmention.linked)
+    .bind(now())
+    .execute(self)
+    .await?;
+    Ok(())
+  }
+
+  async fn webmentions_for_post(
+    &self,
+    post_path: &str,
+  ) -> Result<Vec<SentWebmention>, DbError> {
+    let rows: Vec<SentWebmentionRow> = sqlx::query_as(&format!(
+      "SELECT {SENT_WEBMENTION_COLUMNS} FROM sent_webmentions WHERE post_path \
+       = $1 ORDER BY target"
+    ))
+    .bind(post_path)
+    .fetch_all(self)
+    .await?;
+    Ok(rows.into_iter().map(sent_webmention_from_row).collect())
+  }
+
+  async fn sent_webmentions(&self) -> Result<Vec<SentWebmention>, DbError> {
+    let rows: Vec<SentWebmentionRow> = sqlx::query_as(&format!(
+      "SELECT {SENT_WEBMENTION_COLUMNS} FROM sent_webmentions ORDER BY \
+       updated_at DESC, post_path, target"
+    ))
+    .fetch_all(self)
+    .await?;
+    Ok(rows.into_iter().map(sent_webmention_from_row).collect())
+  }
+}
+
+impl FollowerStore for PgPool {
+  async fn add_follower(&self, follower: &Follower) -> Result<(), DbError> {
+    sqlx::query(
+      "INSERT INTO activitypub_followers (actor, inbox, followed_at) VALUES \
+       ($1, $2, $3) ON CONFLICT (actor) DO UPDATE SET inbox = excluded.inbox",
+    )
+    .bind(&follower.actor)
+    .bind(&follower.inbox)
+    .bind(now())
+    .execute(self)
+    .await?;
+    Ok(())
+  }
+
+  async fn remove_follower(&self, actor: &str) -> Result<bool, DbError> {
+    let result =
+      sqlx::query("DELETE FROM activitypub_followers WHERE actor = $1")
+        .bind(actor)
+        .execute(self)
+        .await?;
+    Ok(result.rows_affected() > 0)
+  }
+
+  async fn followers(&self) -> Result<Vec<Follower>, DbError> {
+    let rows: Vec<(String, String)> = sqlx::query_as(
+      "SELECT actor, inbox FROM activitypub_followers ORDER BY followed_at",
+    )
+    .fetch_all(self)
+    .await?;
+
+    Ok(
+      rows
+        .into_iter()
+        .map(|(actor, inbox)| Follower { actor, inbox })
+        .collect(),
+    )
+  }
+}
+
+impl SessionStore for PgPool {
+  async fn start_session(
+    &self,
+    id: &str,
+    expires_at: i64,
+  ) -> Result<(), DbError> {
+    sqlx::query("DELETE FROM admin_sessions WHERE expires_at <= $1")
+      .bind(now())
+      .execute(self)
+      .await?;
+    sqlx::query("INSERT INTO admin_sessions (id, expires_at) VALUES ($1, $2)")
+      .bind(id)
+      .bind(expires_at)
+      .execute(self)
+      .await?;
+    Ok(())
+  }
+
+  async fn has_session(&self, id: &str) -> Result<bool, DbError> {
+    let session: Option<i64> =
+      sqlx::query_scalar("SELECT expires_at FROM admin_sessions WHERE id = $1")
+        .bind(id)
+        .fetch_optional(self)
+        .await?;
+    Ok(session.is_some_and(|expires_at| expires_at > now()))
+  }
+
+  async fn end_session(&self, id: &str) -> Result<(), DbError> {
+    sqlx::query("DELETE FROM admin_sessions WHERE id = $1")
+      .bind(id)
+      .execute(self)
+      .await?;
+    Ok(())
+  }
+}
+
+impl NotifiedPostStore for PgPool {
+  async fn any_notified(&self) -> Result<bool, DbError> {
+    let notified: bool =
+      sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM notified_posts)")
+        .fetch_one(self)
+        .await?;
+    Ok(notified)
+  }
+
+  async fn record_notified(
+    &self,
+    path: &str,
+    title: &str,
+  ) -> Result<bool, DbError> {
+    let notified: Option<String> = sqlx::query_scalar(
+      "SELECT path FROM notified_posts WHERE path = $1 OR title = $2 LIMIT 1",
+    )
+    .bind(path)
+    .bind(title)
+    .fetch_optional(self)
+    .await?;
+    sqlx::query(
+      "INSERT INTO notified_posts (path, title, notified_at) VALUES ($1, $2, \
+       $3) ON CONFLICT (path) DO UPDATE SET title = excluded.title",
+    )
+    .bind(path)
+    .bind(title)
+    .bind(now())
+    .execute(self)
+    .await?;
+    Ok(notified.is_none())
+  }
+}
@@ -0,0 +1,25 @@
+//! The WASM entrypoint of the lazily loaded bundle, which hydrates the islands
+//! readers only need once they reach for them, like the search box and the
+//! command palette. The site's bundle loads it when they do; see
+//! `site-frontend`.
+//!
+//! `cargo-leptos` only builds the site's bundle, so this one is built on its
+//! own with `wasm-bindgen`, into the same directory, as `site-lazy`. See the
+//! `lazy-bundle` recipe in the justfile.
+
+#![cfg(target_arch = "wasm32")]
+
+// This has to be imported for `wasm_bindgen` to work.
+use leptos::*;
+#[allow(unused_imports)]
+use site_app::*;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+#[wasm_bindgen]
+pub fn hydrate() {
+  // initializes logging using the `log` crate, apart from the site's bundle's
+  _ = console_log::init_with_level(log::Level::Debug);
+  console_error_panic_hook::set_once();
+
+  leptos::leptos_dom::HydrationCtx::stop_hydrating();
+}
@@ -0,0 +1,47 @@
+// Loads the lazily loaded bundle, `site-frontend-lazy`, once a reader reaches
+// for one of its islands, and hydrates them.
+
+let loading = null;
+let loaded = false;
+
+function load(js, wasm) {
+  loading ??= import(js).then(async (mod) => {
+    await mod.default(wasm);
+    for (const island of document.querySelectorAll("leptos-island")) {
+      const hydrate = mod["_island_" + island.dataset.component];
+      if (hydrate) hydrate(island);
+    }
+    mod.hydrate();
+    loaded = true;
+  });
+  return loading;
+}
+
+// Loads the bundle on the command palette's shortcut, then presses it again
+// for the palette, which is listening by then.
+export function loadOnShortcut(js, wasm) {
+  const onKeydown = (ev) => {
+    if (ev.key.toLowerCase() !== "k" || !(ev.metaKey || ev.ctrlKey)) return;
+    removeEventListener("keydown", onKeydown);
+    // something else loaded it, so the palette has the shortcut already
+    if (loaded) return;
+    ev.preventDefault();
+    load(js, wasm).then(() =>
+      dispatchEvent(
+        new KeyboardEvent("keydown", {
+          key: ev.key,
+          metaKey: ev.metaKey,
+          ctrlKey: ev.ctrlKey,
+        }),
+      ),
+    );
+  };
+  addEventListener("keydown", onKeydown);
+}
+
+// Loads the bundle once the island is pointed at or focused.
+export function loadOnReach(island, js, wasm) {
+  for (const event of ["pointerenter", "focusin"]) {
+    island.addEventListener(event, () => load(js, wasm), { once: true });
+  }
+}
@@ -1,8 +1,36 @@
+//! The WASM entrypoint, which hydrates the site's islands.
+//!
+//! Only `#[island]` components and what they use end up in the module, so
+//! plain components never reach readers. Islands that readers only need once
+//! they reach for them, like the search box and the command palette, are
+//! hydrated by a second bundle, `site-frontend-lazy`, instead. Leptos calls
+//! every island's hydration function from this module, so those islands get
+//! stand-ins here, which load the lazy bundle when they're used.
+
+#![cfg(target_arch = "wasm32")]
+
 // This has to be imported for `wasm_bindgen` to work.
 use leptos::*;
 #[allow(unused_imports)]
 use site_app::*;
-use wasm_bindgen::prelude::wasm_bindgen;
+use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
+
+#[wasm_bindgen(module = "/src/lazy.js")]
+extern "C" {
+  #[wasm_bindgen(js_name = loadOnShortcut)]
+  fn load_on_shortcut(js: &str, wasm: &str);
+  #[wasm_bindgen(js_name = loadOnReach)]
+  fn load_on_reach(island: &JsValue, js: &str, wasm: &str);
+}
+
+/// Where the lazily loaded bundle's script and module are served, as
+/// `wasm-bindgen` names them.
+fn lazy_bundle() -> (String, String) {
+  (
+    format!("/pkg/{LAZY_BUNDLE_NAME}.js"),
+    format!("/pkg/{LAZY_BUNDLE_NAME}_bg.wasm"),
+  )
+}
 
 #[wasm_bindgen]
 pub fn hydrate() {
@@ -12,3 +40,19 @@ pub fn hydrate() {
 
   leptos::leptos_dom::HydrationCtx::stop_hydrating();
 }
+
+/// Stands in for the command palette, which opens with a shortcut.
+#[wasm_bindgen]
+#[allow(non_snake_case)]
+pub fn _island_Palette(_island: JsValue) {
+  let (js, wasm) = lazy_bundle();
+  load_on_shortcut(&js, &wasm);
+}
+
+/// Stands in for the search box, whose results drop down as it's typed into.
+#[wasm_bindgen]
+#[allow(non_snake_case)]
+pub fn _island_SearchForm(island: JsValue) {
+  let (js, wasm) = lazy_bundle();
+  load_on_reach(&island, &js, &wasm);
+}
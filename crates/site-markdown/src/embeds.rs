@@ -0,0 +1,38 @@
+//! The markup that the pipeline emits for embedded components and third-party
+//! embeds, which the frontend swaps for the real thing.
+
+/// Opens a marker for an embedded component in rendered post HTML.
+pub const MARKER_OPEN: &str = "<!--component:";
+/// Closes a marker for an embedded component in rendered post HTML.
+pub const MARKER_CLOSE: &str = "-->";
+
+/// The class of the element which stands in for a third-party embed. It
+/// carries the embed's iframe source and title in `data-embed-src` and
+/// `data-embed-title`.
+pub const FACADE_CLASS: &str = "embed-facade";
+/// The class of the link inside a facade which loads the embed. It links to
+/// the content itself, so it still works without JS.
+pub const FACADE_BUTTON_CLASS: &str = "embed-facade-button";
+
+/// Whether an iframe source is one that facades may load: a YouTube video, a
+/// tweet, or a Mastodon post's `/@user/id/embed` page on any instance.
+pub fn is_allowed_embed_src(src: &str) -> bool {
+  const PREFIXES: &[&str] = &[
+    "https://www.youtube-nocookie.com/embed/",
+    "https://platform.twitter.com/embed/Tweet.html?",
+  ];
+  if PREFIXES.iter().any(|prefix| src.starts_with(prefix)) {
+    return true;
+  }
+
+  let Some(rest) = src.strip_prefix("https://") else {
+    return false;
+  };
+  matches!(
+    rest.split('/').collect::<Vec<_>>().as_slice(),
+    [host, user, id, "embed"]
+      if !host.is_empty()
+        && user.starts_with('@')
+        && id.chars().all(|c| c.is_ascii_digit())
+  )
+}
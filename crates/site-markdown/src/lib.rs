@@ -0,0 +1,70 @@
+//! The markdown pipeline which renders posts to HTML, along with the
+//! information the rest of the site needs about them, like their headings and
+//! links.
+//!
+//! The pipeline doesn't know about Leptos or the content directory: the site
+//! gathers what it needs to resolve links against into [`Options`] and calls
+//! [`render`]. The markup conventions that the frontend relies on to enhance
+//! rendered posts are in [`embeds`], which is available without the `render`
+//! feature so that the frontend can use it without pulling in the pipeline.
+
+pub mod embeds;
+#[cfg(feature = "render")]
+mod render;
+
+#[cfg(feature = "render")]
+pub use render::*;
+use serde::{Deserialize, Serialize};
+
+/// A heading in a post.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TocEntry {
+  /// The heading level, from 1 to 6.
+  pub level: u8,
+  /// The heading's text, without markup.
+  pub title: String,
+  /// The ID the heading's anchor was given.
+  pub id:    String,
+}
+
+/// Which markdown extensions a document is rendered with. Posts can override
+/// any of them in their frontmatter.
+///
+/// There's no option for math: the version of `pulldown-cmark` in use doesn't
+/// parse it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarkdownOptions {
+  /// Curls quotes and turns `--` and `...` into dashes and ellipses.
+  pub smart_punctuation:  bool,
+  /// GFM tables.
+  pub tables:             bool,
+  /// `[^note]` footnotes.
+  pub footnotes:          bool,
+  /// `~~struck~~` text.
+  pub strikethrough:      bool,
+  /// `- [ ]` task lists.
+  pub task_lists:         bool,
+  /// `# Heading {#id .class}` attributes.
+  pub heading_attributes: bool,
+  /// `{{< name args... >}}` shortcodes.
+  pub shortcodes:         bool,
+  /// Whether the output is passed through an allowlist-based sanitizer, for
+  /// documents that can't be trusted with raw HTML, or with including files.
+  pub sanitize:           bool,
+}
+
+impl Default for MarkdownOptions {
+  fn default() -> Self {
+    MarkdownOptions {
+      smart_punctuation:  true,
+      tables:             true,
+      footnotes:          true,
+      strikethrough:      true,
+      task_lists:         true,
+      heading_attributes: true,
+      shortcodes:         true,
+      sanitize:           false,
+    }
+  }
+}
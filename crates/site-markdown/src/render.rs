@@ -0,0 +1,1245 @@
+mod shortcodes;
+
+use std::{
+  collections::{HashMap, HashSet},
+  io::Cursor,
+  sync::OnceLock,
+};
+
+use pulldown_cmark::{
+  escape::{escape_href, escape_html},
+  CodeBlockKind, CowStr, Event, LinkType, Tag,
+};
+use serde::{Deserialize, Serialize};
+use syntect::{
+  highlighting::{Theme, ThemeSet},
+  html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator},
+  parsing::{SyntaxReference, SyntaxSet},
+  util::LinesWithEndings,
+};
+
+use crate::{MarkdownOptions, TocEntry};
+
+/// The metadata shown in a link's preview card.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LinkPreview {
+  pub title:       String,
+  pub description: Option<String>,
+  /// The absolute URL of the page's icon.
+  pub icon:        Option<String>,
+}
+
+/// Information about the rest of the site that the pipeline resolves links
+/// against.
+#[derive(Debug, Default)]
+pub struct LinkContext {
+  /// The titles of every post, keyed by path.
+  pub post_titles:   HashMap<String, String>,
+  /// The previews fetched for standalone URLs, keyed by URL.
+  pub link_previews: HashMap<String, LinkPreview>,
+}
+
+/// How a document is rendered.
+#[derive(Debug)]
+pub struct Options<'a> {
+  /// What links in the document are resolved against.
+  pub links:      &'a LinkContext,
+  /// The names of the components that `{{< component >}}` may embed.
+  pub components: &'a [&'a str],
+  /// Which extensions the document is rendered with.
+  pub markdown:   &'a MarkdownOptions,
+}
+
+/// The output of the markdown pipeline.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RenderedPost {
+  /// The rendered HTML.
+  pub html:       String,
+  /// Every heading, in document order.
+  pub toc:        Vec<TocEntry>,
+  /// The destinations of every link in the document, in document order.
+  pub links:      Vec<String>,
+  /// The document's text content, without any markup.
+  pub plaintext:  String,
+  /// The number of words in the document's text content.
+  pub word_count: usize,
+  /// Problems found while rendering, like unknown shortcodes.
+  pub warnings:   Vec<Warning>,
+  /// The repository files the document depends on, like included files, so
+  /// that caches of the output can tell when it's out of date.
+  pub files:      Vec<String>,
+  /// The posts whose titles the document's wiki links were rendered with,
+  /// whether or not they exist.
+  pub titles:     Vec<String>,
+  /// The standalone URLs whose previews the document was rendered with,
+  /// whether or not one was fetched.
+  pub previews:   Vec<String>,
+}
+
+/// A problem found while rendering a document.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Warning {
+  /// What's wrong.
+  pub message: String,
+  /// The markdown that caused the problem, as rendered. It's as written
+  /// except for smart punctuation, so it can be searched for in the source to
+  /// find where the problem is.
+  pub snippet: String,
+}
+
+impl std::fmt::Display for Warning {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(&self.message)
+  }
+}
+
+/// Renders a single `[[post-path]]` or `[[post-path|link text]]` wiki link
+/// body into link events, returning the events, the link destination and the
+/// path of the post linked to.
+fn render_wiki_link<'a>(
+  inner: &str,
+  context: &LinkContext,
+) -> ([Event<'a>; 3], String, String) {
+  let (target, text) = match inner.split_once('|') {
+    Some((target, text)) => (target.trim(), Some(text.trim())),
+    None => (inner.trim(), None),
+  };
+  // posts can be linked to by their file names, which are slugified to paths
+  let (post_path, anchor) = match target.split_once('#') {
+    Some((path, anchor)) => (slug::slugify(path), Some(anchor)),
+    None => (slug::slugify(target), None),
+  };
+  let title = context.post_titles.get(&post_path);
+
+  let href = match anchor {
+    Some(anchor) => format!("/post/{post_path}#{anchor}"),
+    None => format!("/post/{post_path}"),
+  };
+  let text = text.or(title.map(String::as_str)).unwrap_or(target);
+  let class = if title.is_some() {
+    "wikilink"
+  } else {
+    "wikilink wikilink-missing"
+  };
+
+  let mut open_tag = String::new();
+  open_tag.push_str("<a href=\"");
+  escape_html(&mut open_tag, &href).unwrap();
+  open_tag.push_str("\" class=\"");
+  open_tag.push_str(class);
+  open_tag.push_str("\">");
+
+  let events = [
+    Event::Html(CowStr::from(open_tag)),
+    Event::Text(CowStr::from(text.to_string())),
+    Event::Html(CowStr::from("</a>")),
+  ];
+  (events, href, post_path)
+}
+
+/// Merges consecutive text events outside of code blocks. pulldown-cmark splits
+/// text around brackets and other punctuation, which would otherwise hide
+/// syntax like wiki links and shortcodes from later passes.
+fn merge_text(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+  let mut in_code_block = false;
+  let mut out_events: Vec<Event<'_>> = Vec::new();
+
+  for event in events {
+    match event {
+      Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+      Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+      Event::Text(ref t) if !in_code_block => {
+        if let Some(Event::Text(previous)) = out_events.last_mut() {
+          *previous = CowStr::from(format!("{previous}{t}"));
+          continue;
+        }
+      }
+      _ => {}
+    }
+    out_events.push(event);
+  }
+
+  out_events
+}
+
+/// Splits text on `open ... close` delimited spans (on a single line),
+/// replacing each span with the events produced by `replace`.
+fn replace_delimited<'a>(
+  text: &str,
+  open: &str,
+  close: &str,
+  mut replace: impl FnMut(&str) -> Vec<Event<'a>>,
+) -> Vec<Event<'a>> {
+  let mut events = Vec::new();
+  let mut rest = text;
+  let mut plain = String::new();
+
+  while let Some(start) = rest.find(open) {
+    let body_start = start + open.len();
+    let Some(len) = rest[body_start..].find(close) else {
+      break;
+    };
+    let inner = &rest[body_start..body_start + len];
+    if inner.trim().is_empty() || inner.contains('\n') {
+      plain.push_str(&rest[..body_start]);
+      rest = &rest[body_start..];
+      continue;
+    }
+
+    plain.push_str(&rest[..start]);
+    if !plain.is_empty() {
+      events.push(Event::Text(CowStr::from(std::mem::take(&mut plain))));
+    }
+    events.extend(replace(inner));
+    rest = &rest[body_start + len + close.len()..];
+  }
+  plain.push_str(rest);
+  if !plain.is_empty() {
+    events.push(Event::Text(CowStr::from(plain)));
+  }
+
+  events
+}
+
+/// Replaces `[[post-path]]` and `[[post-path|link text]]` wiki links with links
+/// to the corresponding post. Returns the new events, the destinations of the
+/// wiki links and the paths of the posts they link to.
+fn resolve_wiki_links<'a>(
+  events: Vec<Event<'a>>,
+  context: &LinkContext,
+) -> (Vec<Event<'a>>, Vec<String>, Vec<String>) {
+  let mut in_code_block = false;
+  let mut links = Vec::new();
+  let mut posts = Vec::new();
+  let mut out_events = Vec::new();
+
+  for event in events {
+    match event {
+      Event::Text(t) if !in_code_block && t.contains("[[") => {
+        out_events.extend(replace_delimited(&t, "[[", "]]", |inner| {
+          let (link_events, href, post_path) = render_wiki_link(inner, context);
+          links.push(href);
+          posts.push(post_path);
+          link_events.into()
+        }));
+        continue;
+      }
+      Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+      Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+      _ => {}
+    }
+    out_events.push(event);
+  }
+
+  (out_events, links, posts)
+}
+
+/// The markup that opens a spoiler. The spoiler is hidden by CSS until it's
+/// focused, which clicking it also does, so it works without JS.
+const SPOILER_OPEN: &str =
+  "<span class=\"spoiler\" tabindex=\"0\" title=\"Spoiler: click to reveal\">";
+
+/// Replaces `||spoiler||` spans with text that's hidden until revealed.
+fn render_spoilers(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+  let mut in_code_block = false;
+  let mut out_events = Vec::new();
+
+  for event in events {
+    match event {
+      Event::Text(t) if !in_code_block && t.contains("||") => {
+        out_events.extend(replace_delimited(&t, "||", "||", |inner| {
+          vec![
+            Event::Html(CowStr::from(SPOILER_OPEN)),
+            Event::Text(CowStr::from(inner.to_string())),
+            Event::Html(CowStr::from("</span>")),
+          ]
+        }));
+        continue;
+      }
+      Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+      Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+      _ => {}
+    }
+    out_events.push(event);
+  }
+
+  out_events
+}
+
+/// Whether some text is nothing but an absolute URL.
+fn is_url(text: &str) -> bool {
+  (text.starts_with("https://") || text.starts_with("http://"))
+    && !text.contains(char::is_whitespace)
+}
+
+/// Finds the URL that's the only content of a paragraph, either as plain text
+/// or as an autolink.
+fn standalone_url<'a>(paragraph: &'a [Event<'_>]) -> Option<&'a str> {
+  match paragraph {
+    [Event::Text(text)] if is_url(text.trim()) => Some(text.trim()),
+    [Event::Start(Tag::Link(LinkType::Autolink, dest, _)), Event::Text(_), Event::End(Tag::Link(..))]
+      if is_url(dest) =>
+    {
+      Some(dest)
+    }
+    _ => None,
+  }
+}
+
+/// Finds every URL that's alone in a paragraph, and so gets a preview card when
+/// the document is rendered with `options`.
+pub fn standalone_urls(
+  markdown: &str,
+  options: &MarkdownOptions,
+) -> Vec<String> {
+  let parser =
+    pulldown_cmark::Parser::new_ext(markdown, parser_options(options));
+  let events = merge_text(parser.into_iter().collect());
+
+  events
+    .split(|e| matches!(e, Event::Start(Tag::Paragraph)))
+    .skip(1)
+    .filter_map(|rest| {
+      let len = rest
+        .iter()
+        .position(|e| matches!(e, Event::End(Tag::Paragraph)))?;
+      standalone_url(&rest[..len]).map(str::to_string)
+    })
+    .collect()
+}
+
+fn render_link_card(url: &str, preview: &LinkPreview) -> String {
+  let host = url
+    .split("://")
+    .nth(1)
+    .and_then(|rest| rest.split('/').next())
+    .unwrap_or(url);
+
+  let mut html = String::from("<a class=\"link-card\" href=\"");
+  escape_href(&mut html, url).unwrap();
+  html.push_str("\">");
+  if let Some(icon) = &preview.icon {
+    html.push_str("<img class=\"link-card-icon\" src=\"");
+    escape_href(&mut html, icon).unwrap();
+    html.push_str("\" alt=\"\" loading=\"lazy\">");
+  }
+  html.push_str(
+    "<span class=\"link-card-text\"><span class=\"link-card-title\">",
+  );
+  escape_html(&mut html, &preview.title).unwrap();
+  html.push_str("</span>");
+  if let Some(description) = &preview.description {
+    html.push_str("<span class=\"link-card-description\">");
+    escape_html(&mut html, description).unwrap();
+    html.push_str("</span>");
+  }
+  html.push_str("<span class=\"link-card-url\">");
+  escape_html(&mut html, host).unwrap();
+  html.push_str("</span></span></a>");
+  html
+}
+
+/// Replaces paragraphs consisting solely of a URL with a preview card, or
+/// with a plain link if there's no preview for it. Returns the new events and
+/// the URLs.
+fn render_link_previews<'a>(
+  events: Vec<Event<'a>>,
+  context: &LinkContext,
+) -> (Vec<Event<'a>>, Vec<String>) {
+  let mut out_events: Vec<Event<'a>> = Vec::new();
+  let mut urls = Vec::new();
+
+  for event in events {
+    if !matches!(event, Event::End(Tag::Paragraph)) {
+      out_events.push(event);
+      continue;
+    }
+
+    let start = out_events
+      .iter()
+      .rposition(|e| matches!(e, Event::Start(Tag::Paragraph)));
+    let url = start
+      .and_then(|start| standalone_url(&out_events[start + 1..]))
+      .map(str::to_string);
+
+    match (start, url) {
+      (Some(start), Some(url)) => {
+        out_events.truncate(start);
+        urls.push(url.clone());
+        match context.link_previews.get(&url) {
+          Some(preview) => out_events
+            .push(Event::Html(CowStr::from(render_link_card(&url, preview)))),
+          None => {
+            let dest = CowStr::from(url.clone());
+            out_events.extend([
+              Event::Start(Tag::Paragraph),
+              Event::Start(Tag::Link(
+                LinkType::Autolink,
+                dest.clone(),
+                CowStr::from(""),
+              )),
+              Event::Text(CowStr::from(url)),
+              Event::End(Tag::Link(LinkType::Autolink, dest, CowStr::from(""))),
+              event,
+            ]);
+          }
+        }
+      }
+      _ => out_events.push(event),
+    }
+  }
+
+  (out_events, urls)
+}
+
+/// Renders a shortcode body, turning failures into a warning and, in debug
+/// builds, a visible error in the page. Files the shortcode reads are added to
+/// `files`.
+fn render_shortcode_or_error(
+  body: &str,
+  standalone: bool,
+  options: &Options,
+  warnings: &mut Vec<Warning>,
+  files: &mut Vec<String>,
+) -> String {
+  match shortcodes::render_shortcode(body, standalone, options, files) {
+    Ok(html) => html,
+    Err(warning) => {
+      let html = if cfg!(debug_assertions) {
+        let mut html = String::from("<span class=\"shortcode-error\">");
+        escape_html(&mut html, &warning).unwrap();
+        html.push_str("</span>");
+        html
+      } else {
+        String::new()
+      };
+      warnings.push(Warning {
+        message: warning,
+        snippet: format!("{{{{<{body}>}}}}"),
+      });
+      html
+    }
+  }
+}
+
+/// Expands `{{< name args... >}}` shortcodes. A shortcode alone in a paragraph
+/// replaces the whole paragraph, so that block-level embeds aren't wrapped in
+/// a `<p>`. Returns the new events, any warnings, and the files the
+/// shortcodes read.
+fn expand_shortcodes<'a>(
+  events: Vec<Event<'a>>,
+  options: &Options,
+) -> (Vec<Event<'a>>, Vec<Warning>, Vec<String>) {
+  let mut in_code_block = false;
+  let mut warnings = Vec::new();
+  let mut files = Vec::new();
+  let mut out_events: Vec<Event<'_>> = Vec::new();
+  let mut events = events.into_iter().peekable();
+
+  while let Some(event) = events.next() {
+    match event {
+      Event::Text(t) if !in_code_block && t.contains("{{<") => {
+        let trimmed = t.trim();
+        let standalone = trimmed.starts_with("{{<")
+          && trimmed.ends_with(">}}")
+          && trimmed.matches("{{<").count() == 1
+          && matches!(out_events.last(), Some(Event::Start(Tag::Paragraph)))
+          && matches!(events.peek(), Some(Event::End(Tag::Paragraph)));
+
+        if standalone {
+          out_events.pop();
+          events.next();
+          let body = &trimmed[3..trimmed.len() - 3];
+          let html = render_shortcode_or_error(
+            body,
+            true,
+            options,
+            &mut warnings,
+            &mut files,
+          );
+          out_events.push(Event::Html(CowStr::from(html)));
+        } else {
+          out_events.extend(replace_delimited(&t, "{{<", ">}}", |body| {
+            let html = render_shortcode_or_error(
+              body,
+              false,
+              options,
+              &mut warnings,
+              &mut files,
+            );
+            vec![Event::Html(CowStr::from(html))]
+          }));
+        }
+        continue;
+      }
+      Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+      Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+      _ => {}
+    }
+    out_events.push(event);
+  }
+
+  (out_events, warnings, files)
+}
+
+/// Replaces GFM task list checkboxes with styled markers, labelled for screen
+/// readers with whether the task is done.
+fn style_task_lists(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+  let mut out_events: Vec<Event<'_>> = Vec::new();
+  let mut events = events.into_iter().peekable();
+
+  while let Some(event) = events.next() {
+    let Event::TaskListMarker(checked) = event else {
+      out_events.push(event);
+      continue;
+    };
+
+    if let Some(item @ Event::Start(Tag::Item)) = out_events.last_mut() {
+      *item = Event::Html(CowStr::from(if checked {
+        "<li class=\"task-list-item task-list-item-checked\">"
+      } else {
+        "<li class=\"task-list-item\">"
+      }));
+    }
+    // in loose lists, the marker belongs inside the item's first paragraph
+    if matches!(events.peek(), Some(Event::Start(Tag::Paragraph))) {
+      out_events.extend(events.next());
+    }
+    out_events.push(Event::Html(CowStr::from(if checked {
+      "<span class=\"task-list-marker\" aria-hidden=\"true\"></span><span \
+       class=\"sr-only\">Done: </span>"
+    } else {
+      "<span class=\"task-list-marker\" aria-hidden=\"true\"></span><span \
+       class=\"sr-only\">To do: </span>"
+    })));
+  }
+
+  out_events
+}
+
+/// Wraps tables in a container that scrolls horizontally, so that wide tables
+/// don't overflow the column on narrow screens.
+fn wrap_tables(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+  events
+    .into_iter()
+    .flat_map(|event| match event {
+      Event::Start(Tag::Table(_)) => vec![
+        Event::Html(CowStr::from("<div class=\"table-wrapper\">")),
+        event,
+      ],
+      Event::End(Tag::Table(_)) => {
+        vec![event, Event::Html(CowStr::from("</div>"))]
+      }
+      _ => vec![event],
+    })
+    .collect()
+}
+
+/// Whether a line of a paragraph is a definition, i.e. starts with `: `.
+fn is_definition(line: &[Event<'_>]) -> bool {
+  matches!(line.first(), Some(Event::Text(t)) if t.starts_with(": "))
+}
+
+/// Renders definition lists, written as paragraphs of term lines each
+/// followed by one or more `: definition` lines. Consecutive lists are merged,
+/// so terms can be separated by blank lines.
+fn render_definition_lists(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+  let mut out_events: Vec<Event<'_>> = Vec::new();
+  let mut events = events.into_iter();
+
+  while let Some(event) = events.next() {
+    if !matches!(event, Event::Start(Tag::Paragraph)) {
+      out_events.push(event);
+      continue;
+    }
+
+    let mut paragraph = Vec::new();
+    for event in events.by_ref() {
+      if matches!(event, Event::End(Tag::Paragraph)) {
+        break;
+      }
+      paragraph.push(event);
+    }
+    let lines = paragraph
+      .split(|e| matches!(e, Event::SoftBreak))
+      .collect::<Vec<_>>();
+
+    let is_definition_list = lines.len() >= 2
+      && !is_definition(lines[0])
+      && lines.iter().any(|line| is_definition(line));
+    if !is_definition_list {
+      out_events.push(Event::Start(Tag::Paragraph));
+      out_events.extend(paragraph);
+      out_events.push(Event::End(Tag::Paragraph));
+      continue;
+    }
+
+    if matches!(out_events.last(), Some(Event::Html(html)) if &**html == "</dl>")
+    {
+      out_events.pop();
+    } else {
+      out_events.push(Event::Html(CowStr::from("<dl>")));
+    }
+    for line in lines {
+      let mut line = line.to_vec();
+      let (open, close) = if is_definition(&line) {
+        if let Some(Event::Text(t)) = line.first_mut() {
+          *t = CowStr::from(t[2..].to_string());
+        }
+        ("<dd>", "</dd>")
+      } else {
+        ("<dt>", "</dt>")
+      };
+      out_events.push(Event::Html(CowStr::from(open)));
+      out_events.extend(line);
+      out_events.push(Event::Html(CowStr::from(close)));
+    }
+    out_events.push(Event::Html(CowStr::from("</dl>")));
+  }
+
+  out_events
+}
+
+/// Returns `slug`, or `slug` with the first free `-2`, `-3`, ... suffix if
+/// it's already been used in this document.
+fn unique_heading_id(slug: String, used: &mut HashSet<String>) -> String {
+  let mut candidate = slug.clone();
+  let mut suffix = 2;
+  while used.contains(&candidate) {
+    candidate = format!("{slug}-{suffix}");
+    suffix += 1;
+  }
+  used.insert(candidate.clone());
+  candidate
+}
+
+fn add_markdown_heading_ids(
+  events: Vec<Event<'_>>,
+) -> (Vec<Event<'_>>, Vec<TocEntry>) {
+  let mut parsing_header = false;
+  let mut heading_text = String::new();
+  let mut used_ids = HashSet::new();
+  let mut headings = Vec::new();
+  let mut events_to_return = Vec::new();
+
+  for event in events {
+    match event {
+      Event::Start(pulldown_cmark::Tag::Heading(_, _, _)) => {
+        parsing_header = true;
+        heading_text.clear();
+      }
+      Event::End(pulldown_cmark::Tag::Heading(level, _, _)) => {
+        parsing_header = false;
+        let heading_id =
+          unique_heading_id(slug::slugify(&heading_text), &mut used_ids);
+
+        events_to_return.push(Event::Text(CowStr::from(" ")));
+        events_to_return.push(Event::Html(CowStr::from(format!(
+          "<a href=\"#{id}\" id=\"{id}\" class=\"heading-anchor\" \
+           aria-label=\"Copy link to this section\">#</a>",
+          id = heading_id
+        ))));
+        headings.push(TocEntry {
+          level: level as u8,
+          title: heading_text.trim().to_string(),
+          id:    heading_id,
+        });
+      }
+      // inline code and the text inside emphasis both count towards the slug
+      Event::Text(ref text) | Event::Code(ref text) => {
+        if parsing_header {
+          heading_text.push_str(text);
+        }
+      }
+      _ => {}
+    }
+    events_to_return.push(event);
+  }
+
+  (events_to_return, headings)
+}
+
+/// Rewrites a relative link to a sibling markdown file (`other-post.md`,
+/// `./other-post.md#heading`) to the URL of that post.
+fn rewrite_relative_link(dest: &str) -> Option<String> {
+  if dest.contains(':') || dest.starts_with('/') || dest.starts_with('#') {
+    return None;
+  }
+
+  let (path, anchor) = match dest.split_once('#') {
+    Some((path, anchor)) => (path, Some(anchor)),
+    None => (dest, None),
+  };
+  let post_path = path
+    .strip_prefix("./")
+    .unwrap_or(path)
+    .strip_suffix(".md")?;
+  if post_path.is_empty() || post_path.contains('/') {
+    return None;
+  }
+  let post_path = percent_encoding::percent_decode_str(post_path)
+    .decode_utf8_lossy()
+    .into_owned();
+  let post_path = slug::slugify(post_path);
+
+  Some(match anchor {
+    Some(anchor) => format!("/post/{post_path}#{anchor}"),
+    None => format!("/post/{post_path}"),
+  })
+}
+
+fn rewrite_relative_links(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+  fn rewrite_tag(tag: Tag<'_>) -> Tag<'_> {
+    match tag {
+      Tag::Link(link_type, dest, title) => {
+        let dest = rewrite_relative_link(&dest)
+          .map(CowStr::from)
+          .unwrap_or(dest);
+        Tag::Link(link_type, dest, title)
+      }
+      tag => tag,
+    }
+  }
+
+  events
+    .into_iter()
+    .map(|event| match event {
+      Event::Start(tag) => Event::Start(rewrite_tag(tag)),
+      Event::End(tag) => Event::End(rewrite_tag(tag)),
+      event => event,
+    })
+    .collect()
+}
+
+/// Renders footnote references and definitions, numbering them in order of
+/// first appearance. IDs are prefixed with `fn-` (and `fnref-` for the first
+/// reference) so they can't collide with heading IDs, and the footnote
+/// popovers find a definition by following its reference's `href`.
+fn number_footnotes(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+  let mut numbers = HashMap::new();
+  let mut number_of = |name: &str| {
+    let next = numbers.len() + 1;
+    *numbers.entry(name.to_string()).or_insert(next)
+  };
+  let mut referenced = HashSet::new();
+
+  events
+    .into_iter()
+    .map(|event| match event {
+      Event::FootnoteReference(name) => {
+        let n = number_of(&name);
+        let id = if referenced.insert(n) {
+          format!(" id=\"fnref-{n}\"")
+        } else {
+          String::new()
+        };
+        Event::Html(CowStr::from(format!(
+          "<sup class=\"footnote-reference\"><a \
+           href=\"#fn-{n}\"{id}>{n}</a></sup>"
+        )))
+      }
+      Event::Start(Tag::FootnoteDefinition(name)) => {
+        let n = number_of(&name);
+        Event::Html(CowStr::from(format!(
+          "<div class=\"footnote-definition\" id=\"fn-{n}\"><sup \
+           class=\"footnote-definition-label\">{n}</sup>"
+        )))
+      }
+      Event::End(Tag::FootnoteDefinition(name)) => {
+        let n = number_of(&name);
+        Event::Html(CowStr::from(format!(
+          "<a href=\"#fnref-{n}\" class=\"footnote-backref\" \
+           aria-label=\"Back to reference {n}\">↩</a></div>"
+        )))
+      }
+      event => event,
+    })
+    .collect()
+}
+
+fn collect_links(events: &[Event<'_>]) -> Vec<String> {
+  events
+    .iter()
+    .filter_map(|event| match event {
+      Event::Start(Tag::Link(_, dest, _)) => Some(dest.to_string()),
+      _ => None,
+    })
+    .collect()
+}
+
+fn extract_plaintext(events: &[Event<'_>]) -> String {
+  let mut plaintext = String::new();
+
+  for event in events {
+    match event {
+      Event::Text(text) | Event::Code(text) => plaintext.push_str(text),
+      Event::SoftBreak => plaintext.push(' '),
+      Event::HardBreak => plaintext.push('\n'),
+      Event::End(
+        Tag::Paragraph
+        | Tag::Heading(..)
+        | Tag::CodeBlock(_)
+        | Tag::BlockQuote
+        | Tag::Item
+        | Tag::TableRow
+        | Tag::TableHead
+        | Tag::FootnoteDefinition(_),
+      ) => plaintext.push('\n'),
+      Event::End(Tag::TableCell) => plaintext.push(' '),
+      _ => {}
+    }
+  }
+
+  plaintext
+    .lines()
+    .map(str::trim)
+    .filter(|line| !line.is_empty())
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+fn syntax_set() -> &'static SyntaxSet {
+  static SYNTAX_SET: OnceLock<SyntaxSet> = OnceLock::new();
+  SYNTAX_SET.get_or_init(SyntaxSet::load_defaults_nonewlines)
+}
+
+/// The colour schemes code is highlighted in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodeScheme {
+  Dark,
+  Light,
+}
+
+/// The prefix of the classes highlighted code is marked up with, so they
+/// can't collide with the site's own.
+const HIGHLIGHT_CLASS_STYLE: ClassStyle =
+  ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+fn theme(scheme: CodeScheme) -> &'static Theme {
+  static DARK: OnceLock<Theme> = OnceLock::new();
+  static LIGHT: OnceLock<Theme> = OnceLock::new();
+  match scheme {
+    CodeScheme::Dark => DARK.get_or_init(|| {
+      ThemeSet::load_from_reader(&mut Cursor::new(include_str!(
+        "./rose-pine.tmTheme"
+      )))
+      .unwrap()
+    }),
+    CodeScheme::Light => LIGHT.get_or_init(|| {
+      let mut theme_set = ThemeSet::load_defaults();
+      theme_set.themes.remove("InspiredGitHub").unwrap()
+    }),
+  }
+}
+
+/// The stylesheet that colours highlighted code in `scheme`. Code blocks are
+/// marked up with classes rather than colours, so that the page can swap
+/// this to change their scheme.
+pub fn highlight_css(scheme: CodeScheme) -> String {
+  css_for_theme_with_class_style(theme(scheme), HIGHLIGHT_CLASS_STYLE).unwrap()
+}
+
+/// The fence flag that opts a ```` ```rust ```` block into a playground link,
+/// as in ```` ```rust,playground ````.
+const PLAYGROUND_FLAG: &str = "playground";
+
+/// Splits a fence's info string into its language and any flags after it,
+/// separated by commas or spaces.
+fn split_fence_info(info: Option<&str>) -> (Option<&str>, Vec<&str>) {
+  let mut parts = info
+    .unwrap_or_default()
+    .split([',', ' '])
+    .map(str::trim)
+    .filter(|part| !part.is_empty());
+  (parts.next(), parts.collect())
+}
+
+/// Builds a share link that opens `code` in the Rust Playground.
+fn playground_url(code: &str) -> String {
+  let code = percent_encoding::utf8_percent_encode(
+    code,
+    percent_encoding::NON_ALPHANUMERIC,
+  );
+  format!(
+    "https://play.rust-lang.org/?version=stable&amp;mode=debug&amp;\
+     edition=2021&amp;code={code}"
+  )
+}
+
+/// Highlights a code block, using the syntax matching `token` (a language name
+/// or file extension) if there is one.
+pub(crate) fn highlight_code_block(code: &str, token: Option<&str>) -> String {
+  let syntax_set = syntax_set();
+  let syntax = token
+    .and_then(find_syntax)
+    .unwrap_or_else(|| syntax_set.find_syntax_plain_text());
+
+  let mut generator = ClassedHTMLGenerator::new_with_class_style(
+    syntax,
+    syntax_set,
+    HIGHLIGHT_CLASS_STYLE,
+  );
+  for line in LinesWithEndings::from(code) {
+    generator
+      .parse_html_for_line_which_includes_newline(line)
+      .unwrap();
+  }
+  format!("<pre class=\"hl-code\">{}</pre>", generator.finalize())
+}
+
+/// Code block languages that are written differently from the token of the
+/// syntax that highlights them.
+const LANGUAGE_ALIASES: &[(&str, &str)] = &[("shell", "sh"), ("console", "sh")];
+/// Code block languages that are deliberately rendered as plain text, because
+/// there's no syntax for them (syntect's defaults don't include TOML).
+const PLAIN_LANGUAGES: &[&str] = &["text", "plain", "toml"];
+
+fn find_syntax(token: &str) -> Option<&'static SyntaxReference> {
+  let token = LANGUAGE_ALIASES
+    .iter()
+    .find(|(alias, _)| alias.eq_ignore_ascii_case(token))
+    .map_or(token, |(_, token)| token);
+  syntax_set().find_syntax_by_token(token)
+}
+
+/// Whether a code block language (a language name or file extension) is one
+/// the pipeline knows, i.e. has a syntax to highlight it with or is meant to
+/// be plain text.
+pub fn is_known_language(token: &str) -> bool {
+  PLAIN_LANGUAGES.contains(&token.to_ascii_lowercase().as_str())
+    || find_syntax(token).is_some()
+}
+
+/// Highlights code blocks, returning a warning for each block in a language
+/// that can't be highlighted.
+fn highlight_code(events: Vec<Event<'_>>) -> (Vec<Event<'_>>, Vec<Warning>) {
+  let mut warnings = Vec::new();
+  let mut code_block_info = None;
+  let mut in_code_block = false;
+
+  let mut to_highlight = String::new();
+  let mut out_events = Vec::new();
+
+  for event in events {
+    match event {
+      Event::Start(Tag::CodeBlock(kind)) => {
+        code_block_info = match kind {
+          CodeBlockKind::Fenced(info) => Some(info),
+          CodeBlockKind::Indented => None,
+        };
+        in_code_block = true;
+      }
+      Event::End(Tag::CodeBlock(_)) => {
+        if !in_code_block {
+          panic!("this should never happen");
+        }
+        let (lang, flags) = split_fence_info(code_block_info.as_deref());
+        if let Some(lang) = lang.filter(|lang| !is_known_language(lang)) {
+          warnings.push(Warning {
+            message: format!("unknown code block language `{lang}`"),
+            snippet: code_block_info.as_deref().unwrap_or(lang).to_string(),
+          });
+        }
+        let mut html = highlight_code_block(&to_highlight, lang);
+        if lang == Some("rust") && flags.contains(&PLAYGROUND_FLAG) {
+          html = format!(
+            "<div class=\"code-block\">{html}<a class=\"playground-link\" \
+             href=\"{}\">Run on Rust Playground</a></div>",
+            playground_url(&to_highlight)
+          );
+        }
+
+        to_highlight.clear();
+        in_code_block = false;
+        out_events.push(Event::Html(CowStr::from(html)));
+      }
+      Event::Text(t) => {
+        if in_code_block {
+          to_highlight.push_str(&t);
+        } else {
+          out_events.push(Event::Text(t));
+        }
+      }
+      e => {
+        out_events.push(e);
+      }
+    }
+  }
+
+  (out_events, warnings)
+}
+
+/// Strips anything but an allowlist of tags and attributes from rendered HTML,
+/// so that untrusted posts can't inject scripts. The allowlist covers the
+/// markup that the pipeline itself produces.
+fn sanitize_html(html: &str) -> String {
+  ammonia::Builder::default()
+    .add_generic_attributes(["class", "id", "aria-label", "aria-hidden"])
+    .add_tag_attributes("span", ["tabindex", "title"])
+    .add_tag_attributes("div", ["data-embed-src", "data-embed-title"])
+    .add_tag_attributes("img", ["loading"])
+    .attribute_filter(|element, attribute, value| match (element, attribute) {
+      ("div", "data-embed-src")
+        if !crate::embeds::is_allowed_embed_src(value) =>
+      {
+        None
+      }
+      ("span", "tabindex") if value != "0" => None,
+      _ => Some(value.into()),
+    })
+    // keeps the markers for embedded components
+    .strip_comments(false)
+    .clean(html)
+    .to_string()
+}
+
+/// The parser extensions enabled by `options`.
+fn parser_options(options: &MarkdownOptions) -> pulldown_cmark::Options {
+  use pulldown_cmark::Options;
+
+  let mut parser_options = Options::empty();
+  for (enabled, extension) in [
+    (options.smart_punctuation, Options::ENABLE_SMART_PUNCTUATION),
+    (options.tables, Options::ENABLE_TABLES),
+    (options.footnotes, Options::ENABLE_FOOTNOTES),
+    (options.strikethrough, Options::ENABLE_STRIKETHROUGH),
+    (options.task_lists, Options::ENABLE_TASKLISTS),
+    (
+      options.heading_attributes,
+      Options::ENABLE_HEADING_ATTRIBUTES,
+    ),
+  ] {
+    parser_options.set(extension, enabled);
+  }
+  parser_options
+}
+
+/// Renders a markdown document to HTML.
+pub fn render(markdown: &str, options: &Options) -> RenderedPost {
+  let parser =
+    pulldown_cmark::Parser::new_ext(markdown, parser_options(options.markdown));
+  let events = merge_text(parser.into_iter().collect());
+  let (events, mut warnings, files) = if options.markdown.shortcodes {
+    expand_shortcodes(events, options)
+  } else {
+    (events, Vec::new(), Vec::new())
+  };
+  let (events, wiki_links, titles) = resolve_wiki_links(events, options.links);
+  let (events, previews) = render_link_previews(events, options.links);
+  let events = render_spoilers(events);
+  let events = style_task_lists(events);
+  let events = wrap_tables(events);
+  let (events, toc) = add_markdown_heading_ids(events);
+  let events = rewrite_relative_links(events);
+  let mut links = collect_links(&events);
+  links.extend(wiki_links);
+  let plaintext = extract_plaintext(&events);
+  let word_count = plaintext.split_whitespace().count();
+  let events = render_definition_lists(events);
+  let events = number_footnotes(events);
+  let (events, code_warnings) = highlight_code(events);
+  warnings.extend(code_warnings);
+  let mut html_output = String::new();
+  pulldown_cmark::html::push_html(&mut html_output, events.into_iter());
+  if options.markdown.sanitize {
+    html_output = sanitize_html(&html_output);
+  }
+
+  RenderedPost {
+    html: html_output,
+    toc,
+    links,
+    plaintext,
+    word_count,
+    warnings,
+    files,
+    titles,
+    previews,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn render_markdown(markdown: &str) -> RenderedPost {
+    render_with(markdown, &MarkdownOptions::default())
+  }
+
+  fn render_with(markdown: &str, options: &MarkdownOptions) -> RenderedPost {
+    let links = LinkContext::default();
+    render(markdown, &Options {
+      links:      &links,
+      components: &["ColorPickerDemo"],
+      markdown:   options,
+    })
+  }
+
+  #[test]
+  fn standalone_urls_are_found_with_the_documents_extensions() {
+    let markdown =
+      "https://a.example/\n\nSee[^1].\n\n[^1]: https://b.example/\n";
+    assert_eq!(standalone_urls(markdown, &MarkdownOptions::default()), [
+      "https://a.example/",
+      "https://b.example/"
+    ]);
+    // without footnotes, the definition is a link reference instead
+    let options = MarkdownOptions {
+      footnotes: false,
+      ..MarkdownOptions::default()
+    };
+    assert_eq!(standalone_urls(markdown, &options), ["https://a.example/"]);
+  }
+
+  #[test]
+  fn renders_record_the_titles_and_previews_they_use() {
+    let rendered = render_markdown(
+      "See [[Hello World]] and [[missing|this]].\n\nhttps://example.com/\n\n\
+       A [link](https://example.org/) in a sentence.\n\n`[[not-a-link]]`\n",
+    );
+    assert_eq!(rendered.titles, ["hello-world", "missing"]);
+    assert_eq!(rendered.previews, ["https://example.com/"]);
+  }
+
+  #[test]
+  fn renders_commonmark_and_extensions() {
+    let post = render_markdown(
+      "Some *emphasis* and ~~struck~~ text.\n\n| a | b |\n|---|---|\n| 1 | 2 \
+       |\n\n- [x] done\n",
+    );
+    assert!(post.html.contains("<em>emphasis</em>"), "{}", post.html);
+    assert!(post.html.contains("<del>struck</del>"), "{}", post.html);
+    assert!(post.html.contains("<table>"), "{}", post.html);
+    assert!(
+      post.html.contains("task-list-item-checked"),
+      "{}",
+      post.html
+    );
+  }
+
+  #[test]
+  fn extensions_can_be_turned_off() {
+    let options = MarkdownOptions {
+      strikethrough: false,
+      tables: false,
+      ..MarkdownOptions::default()
+    };
+    let post = render_with("~~struck~~\n\n| a |\n|---|\n| 1 |\n", &options);
+    assert!(!post.html.contains("<del>"), "{}", post.html);
+    assert!(!post.html.contains("<table>"), "{}", post.html);
+  }
+
+  #[test]
+  fn headings_get_unique_ids() {
+    let post = render_markdown(
+      "# Getting Started\n\n## Setup\n\ntext\n\n## Setup\n\n### The `render` \
+       fn\n",
+    );
+    let ids = post.toc.iter().map(|e| e.id.as_str()).collect::<Vec<_>>();
+    assert_eq!(ids, [
+      "getting-started",
+      "setup",
+      "setup-2",
+      "the-render-fn"
+    ]);
+    let levels = post.toc.iter().map(|e| e.level).collect::<Vec<_>>();
+    assert_eq!(levels, [1, 2, 2, 3]);
+    assert_eq!(post.toc[3].title, "The render fn");
+    for id in ids {
+      assert!(post.html.contains(&format!("id=\"{id}\"")), "{}", post.html);
+    }
+  }
+
+  #[test]
+  fn counts_the_words_of_the_text_only() {
+    let post = render_markdown(
+      "# Two words\n\nThree *more* words, and [a link](https://example.com).\n",
+    );
+    assert_eq!(post.word_count, 8, "{:?}", post.plaintext);
+    assert!(!post.plaintext.contains('<'));
+    assert!(!post.plaintext.contains("example.com"));
+  }
+
+  #[test]
+  fn unknown_code_languages_are_warned_about() {
+    let post =
+      render_markdown("```rust\nfn main() {}\n```\n\n```nonsense\nx\n```\n");
+    assert_eq!(post.warnings.len(), 1, "{:?}", post.warnings);
+    assert_eq!(
+      post.warnings[0].message,
+      "unknown code block language `nonsense`"
+    );
+    assert_eq!(post.warnings[0].snippet, "nonsense");
+  }
+
+  #[test]
+  fn unknown_shortcodes_and_components_are_warned_about() {
+    let post = render_markdown(
+      "{{< nonsense >}}\n\n{{< component NoSuchComponent >}}\n\n{{< component \
+       ColorPickerDemo >}}\n",
+    );
+    let snippets = post
+      .warnings
+      .iter()
+      .map(|w| w.snippet.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(
+      snippets,
+      ["{{< nonsense >}}", "{{< component NoSuchComponent >}}"],
+      "{:?}",
+      post.warnings
+    );
+    assert_eq!(post.warnings[0].message, "unknown shortcode `nonsense`");
+  }
+
+  #[test]
+  fn includes_are_confined_to_the_include_root() {
+    let messages = |markdown: &str, options: &MarkdownOptions| {
+      render_with(markdown, options)
+        .warnings
+        .into_iter()
+        .map(|w| w.message)
+        .collect::<Vec<_>>()
+    };
+    let markdown = "{{< include \".env\" >}}\n\n{{< include \"site.toml\" \
+                    >}}\n\n{{< include \"examples/../site.toml\" >}}\n\n{{< \
+                    include \"examples/.secret\" >}}\n";
+    assert_eq!(messages(markdown, &MarkdownOptions::default()), [
+      "included path `.env` must be inside `examples/`",
+      "included path `site.toml` must be inside `examples/`",
+      "included path `examples/../site.toml` must be inside `examples/`",
+      "included path `examples/.secret` must be inside `examples/`",
+    ]);
+
+    let sanitized = MarkdownOptions {
+      sanitize: true,
+      ..MarkdownOptions::default()
+    };
+    assert_eq!(
+      messages("{{< include \"examples/foo.rs\" >}}\n", &sanitized),
+      ["sanitized posts can't include files, like `examples/foo.rs`"]
+    );
+  }
+
+  #[test]
+  fn documents_without_problems_have_no_warnings() {
+    let post = render_markdown("# Fine\n\nNothing to see here.\n");
+    assert!(post.warnings.is_empty(), "{:?}", post.warnings);
+  }
+
+  #[test]
+  fn sanitizing_removes_scripts() {
+    let options = MarkdownOptions {
+      sanitize: true,
+      ..MarkdownOptions::default()
+    };
+    let markdown = "# Heading\n\n<script>alert(1)</script>\n\n<a href=\"#\" \
+                    onclick=\"alert(1)\">link</a>\n";
+    let post = render_with(markdown, &options);
+    assert!(!post.html.contains("<script"), "{}", post.html);
+    assert!(!post.html.contains("onclick"), "{}", post.html);
+    // the heading anchors survive
+    assert!(post.html.contains("id=\"heading\""), "{}", post.html);
+    assert!(render_markdown(markdown).html.contains("<script>"));
+  }
+}
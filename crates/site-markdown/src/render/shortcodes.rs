@@ -0,0 +1,397 @@
+//! Shortcodes are `{{< name args... >}}` directives in post markdown which
+//! expand to HTML, so that embeds and other repeated patterns don't need raw
+//! HTML in posts.
+
+use std::collections::HashMap;
+
+use pulldown_cmark::escape::escape_html;
+
+use super::Options;
+use crate::embeds::{
+  FACADE_BUTTON_CLASS, FACADE_CLASS, MARKER_CLOSE, MARKER_OPEN,
+};
+
+/// A parsed shortcode invocation.
+#[derive(Debug)]
+pub struct Shortcode {
+  /// The name of the shortcode, e.g. `youtube`.
+  pub name:       String,
+  /// Arguments given without a key, in order.
+  pub positional: Vec<String>,
+  /// Arguments given as `key=value` or `key="value"`.
+  pub named:      HashMap<String, String>,
+  /// Whether the shortcode is alone in its paragraph, i.e. is a block.
+  pub standalone: bool,
+}
+
+/// A function which renders a shortcode to HTML, or describes why it can't.
+type ShortcodeHandler = fn(&Shortcode, &Options) -> Result<String, String>;
+
+/// The registry of known shortcodes.
+const SHORTCODES: &[(&str, ShortcodeHandler)] = &[
+  ("youtube", youtube),
+  ("mastodon", mastodon),
+  ("tweet", tweet),
+  ("figure", figure),
+  ("component", component),
+  ("include", include),
+];
+
+// smart punctuation may have already curled the quotes by the time we see them
+fn is_quote(c: char) -> bool { matches!(c, '"' | '“' | '”') }
+
+fn read_quoted(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+  let mut value = String::new();
+  for c in chars.by_ref() {
+    if is_quote(c) {
+      break;
+    }
+    value.push(c);
+  }
+  value
+}
+
+impl Shortcode {
+  /// Parses the body of a shortcode, i.e. everything between `{{<` and `>}}`.
+  pub fn parse(body: &str, standalone: bool) -> Option<Shortcode> {
+    let mut chars = body.trim().chars().peekable();
+    let mut tokens: Vec<(Option<String>, String)> = Vec::new();
+
+    while let Some(&c) = chars.peek() {
+      if c.is_whitespace() {
+        chars.next();
+      } else if is_quote(c) {
+        chars.next();
+        tokens.push((None, read_quoted(&mut chars)));
+      } else {
+        let mut token = String::new();
+        let mut key = None;
+        while let Some(&c) = chars.peek() {
+          if c.is_whitespace() {
+            break;
+          }
+          chars.next();
+          if c == '=' && key.is_none() {
+            key = Some(std::mem::take(&mut token));
+            if chars.peek().is_some_and(|c| is_quote(*c)) {
+              chars.next();
+              token = read_quoted(&mut chars);
+              break;
+            }
+          } else {
+            token.push(c);
+          }
+        }
+        tokens.push((key, token));
+      }
+    }
+
+    let mut tokens = tokens.into_iter();
+    let name = match tokens.next()? {
+      (None, name) if !name.is_empty() => name,
+      _ => return None,
+    };
+
+    let mut shortcode = Shortcode {
+      name,
+      positional: Vec::new(),
+      named: HashMap::new(),
+      standalone,
+    };
+    for (key, value) in tokens {
+      match key {
+        Some(key) => {
+          shortcode.named.insert(key, value);
+        }
+        None => shortcode.positional.push(value),
+      }
+    }
+    Some(shortcode)
+  }
+
+  /// Fetches an argument by key, falling back to a positional index.
+  pub fn arg(&self, key: &str, index: usize) -> Option<&str> {
+    self
+      .named
+      .get(key)
+      .or(self.positional.get(index))
+      .map(String::as_str)
+  }
+
+  /// The repository file the shortcode reads, if any.
+  pub fn file(&self) -> Option<&str> {
+    match self.name.as_str() {
+      "include" => self.arg("path", 0),
+      _ => None,
+    }
+  }
+
+  fn required_arg(&self, key: &str, index: usize) -> Result<&str, String> {
+    self.arg(key, index).ok_or_else(|| {
+      format!("shortcode `{}` is missing argument `{key}`", self.name)
+    })
+  }
+}
+
+fn escaped(value: &str) -> String {
+  let mut output = String::new();
+  escape_html(&mut output, value).unwrap();
+  output
+}
+
+/// A third-party embed, rendered as a facade that only loads the embed once
+/// it's clicked.
+struct Facade<'a> {
+  /// Which kind of embed this is, for styling.
+  kind:   &'a str,
+  /// The embed's iframe source.
+  src:    String,
+  /// The embed's iframe title.
+  title:  &'a str,
+  /// Where the content can be seen without the embed.
+  href:   &'a str,
+  /// What clicking the facade does.
+  label:  String,
+  /// The host the embed loads content from.
+  origin: &'a str,
+}
+
+impl Facade<'_> {
+  fn render(&self) -> String {
+    format!(
+      "<div class=\"embed {FACADE_CLASS} embed-{}\" data-embed-src=\"{}\" \
+       data-embed-title=\"{}\"><a class=\"{FACADE_BUTTON_CLASS}\" \
+       href=\"{}\"><span class=\"embed-facade-label\">{}</span><span \
+       class=\"embed-facade-notice\">Loads content from {}</span></a></div>",
+      self.kind,
+      escaped(&self.src),
+      escaped(self.title),
+      escaped(self.href),
+      escaped(&self.label),
+      escaped(self.origin),
+    )
+  }
+}
+
+/// `{{< youtube VIDEO_ID >}}`
+fn youtube(shortcode: &Shortcode, _: &Options) -> Result<String, String> {
+  let id = shortcode.required_arg("id", 0)?;
+  if !id
+    .chars()
+    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+  {
+    return Err(format!("malformed YouTube video ID `{id}`"));
+  }
+
+  Ok(
+    Facade {
+      kind:   "youtube",
+      src:    format!("https://www.youtube-nocookie.com/embed/{id}?autoplay=1"),
+      title:  "YouTube video player",
+      href:   &format!("https://www.youtube.com/watch?v={id}"),
+      label:  "▶ Play video".to_string(),
+      origin: "youtube-nocookie.com",
+    }
+    .render(),
+  )
+}
+
+/// `{{< mastodon "https://instance/@user/POST_ID" >}}`
+fn mastodon(shortcode: &Shortcode, _: &Options) -> Result<String, String> {
+  let url = shortcode.required_arg("url", 0)?;
+  let malformed = || format!("malformed Mastodon post URL `{url}`");
+  let (host, user, id) = match url
+    .strip_prefix("https://")
+    .ok_or_else(malformed)?
+    .trim_end_matches('/')
+    .split('/')
+    .collect::<Vec<_>>()
+    .as_slice()
+  {
+    [host, user, id]
+      if user.starts_with('@') && id.chars().all(|c| c.is_ascii_digit()) =>
+    {
+      (host.to_string(), user.to_string(), id.to_string())
+    }
+    _ => return Err(malformed()),
+  };
+
+  Ok(
+    Facade {
+      kind:   "mastodon",
+      src:    format!("https://{host}/{user}/{id}/embed"),
+      title:  "Mastodon post",
+      href:   url,
+      label:  format!("Show {user}@{host}'s post"),
+      origin: &host,
+    }
+    .render(),
+  )
+}
+
+/// `{{< tweet "https://twitter.com/user/status/TWEET_ID" >}}`
+fn tweet(shortcode: &Shortcode, _: &Options) -> Result<String, String> {
+  let url = shortcode.required_arg("url", 0)?;
+  let id = url
+    .trim_end_matches('/')
+    .rsplit_once("/status/")
+    .map(|(_, id)| id)
+    .filter(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()))
+    .ok_or_else(|| format!("malformed tweet URL `{url}`"))?;
+
+  Ok(
+    Facade {
+      kind:   "tweet",
+      src:    format!(
+        "https://platform.twitter.com/embed/Tweet.html?id={id}&dnt=true"
+      ),
+      title:  "Tweet",
+      href:   url,
+      label:  "Show tweet".to_string(),
+      origin: "platform.twitter.com",
+    }
+    .render(),
+  )
+}
+
+/// `{{< figure src="/image.png" alt="..." caption="..." >}}`
+fn figure(shortcode: &Shortcode, _: &Options) -> Result<String, String> {
+  let src = shortcode.required_arg("src", 0)?;
+  let alt = shortcode.arg("alt", 1).unwrap_or_default();
+
+  let mut html = format!(
+    "<figure><img src=\"{}\" alt=\"{}\" loading=\"lazy\" />",
+    escaped(src),
+    escaped(alt)
+  );
+  if let Some(caption) = shortcode.arg("caption", 2) {
+    html.push_str(&format!("<figcaption>{}</figcaption>", escaped(caption)));
+  }
+  html.push_str("</figure>");
+  Ok(html)
+}
+
+/// `{{< component "Name" >}}`, which embeds one of the interactive
+/// components named in [`Options::components`].
+fn component(
+  shortcode: &Shortcode,
+  options: &Options,
+) -> Result<String, String> {
+  let name = shortcode.required_arg("name", 0)?;
+  if !shortcode.standalone {
+    return Err(format!("component `{name}` must be alone in its paragraph"));
+  }
+  if !options.components.contains(&name) {
+    return Err(format!("unknown component `{name}`"));
+  }
+  Ok(format!("{MARKER_OPEN}{name}{MARKER_CLOSE}"))
+}
+
+/// Parses a 1-based, inclusive line range like `10..30`, `10..`, or `..30`.
+fn parse_line_range(range: &str) -> Option<(usize, Option<usize>)> {
+  let (start, end) = range.split_once("..")?;
+  let start = match start.trim() {
+    "" => 1,
+    start => start.parse().ok().filter(|start| *start >= 1)?,
+  };
+  let end = match end.trim() {
+    "" => None,
+    end => Some(end.parse().ok().filter(|end| *end >= start)?),
+  };
+  Some((start, end))
+}
+
+/// The directory `{{< include >}}` may read files from, relative to the
+/// working directory. Anything outside of it, like the site's configuration,
+/// could hold secrets.
+const INCLUDE_ROOT: &str = "examples";
+
+/// `{{< include "examples/foo.rs" lines=10..30 >}}`, which inlines a file
+/// from the repository's [`INCLUDE_ROOT`] (or a range of its lines) as a
+/// highlighted code block. Sanitized posts aren't trusted to read files.
+fn include(shortcode: &Shortcode, options: &Options) -> Result<String, String> {
+  let path = shortcode.required_arg("path", 0)?;
+  if !shortcode.standalone {
+    return Err(format!(
+      "include of `{path}` must be alone in its paragraph"
+    ));
+  }
+  if options.markdown.sanitize {
+    return Err(format!(
+      "sanitized posts can't include files, like `{path}`"
+    ));
+  }
+
+  // only allow files inside the include root, and none that are hidden
+  let relative_path = std::path::Path::new(path);
+  let outside_root =
+    || format!("included path `{path}` must be inside `{INCLUDE_ROOT}/`");
+  if !relative_path.starts_with(INCLUDE_ROOT)
+    || relative_path.components().any(|c| match c {
+      std::path::Component::Normal(segment) => {
+        segment.to_string_lossy().starts_with('.')
+      }
+      _ => true,
+    })
+  {
+    return Err(outside_root());
+  }
+  // symlinks could still lead out of it
+  let failed = |e: std::io::Error| format!("failed to include `{path}`: {e}");
+  let root = std::fs::canonicalize(INCLUDE_ROOT).map_err(failed)?;
+  let resolved = std::fs::canonicalize(relative_path).map_err(failed)?;
+  if !resolved.starts_with(root) {
+    return Err(outside_root());
+  }
+
+  let contents = std::fs::read_to_string(resolved)
+    .map_err(|e| format!("failed to include `{path}`: {e}"))?;
+
+  let code = match shortcode.arg("lines", 1) {
+    Some(range) => {
+      let (start, end) = parse_line_range(range)
+        .ok_or_else(|| format!("malformed line range `{range}`"))?;
+      let line_count = contents.lines().count();
+      if start > line_count || end.is_some_and(|end| end > line_count) {
+        return Err(format!(
+          "line range `{range}` is out of bounds for `{path}` ({line_count} \
+           lines)"
+        ));
+      }
+      contents
+        .lines()
+        .skip(start - 1)
+        .take(end.map_or(usize::MAX, |end| end - start + 1))
+        .collect::<Vec<_>>()
+        .join("\n")
+    }
+    None => contents.trim_end().to_string(),
+  };
+
+  let lang = shortcode
+    .named
+    .get("lang")
+    .map(String::as_str)
+    .or_else(|| relative_path.extension().and_then(|ext| ext.to_str()));
+  Ok(super::highlight_code_block(&format!("{code}\n"), lang))
+}
+
+/// Renders the body of a shortcode to HTML using the handler registered for
+/// its name, adding the file it reads to `files`. The file is added even if it
+/// couldn't be read, since creating it would change the output.
+pub fn render_shortcode(
+  body: &str,
+  standalone: bool,
+  options: &Options,
+  files: &mut Vec<String>,
+) -> Result<String, String> {
+  let shortcode = Shortcode::parse(body, standalone)
+    .ok_or_else(|| format!("malformed shortcode `{}`", body.trim()))?;
+  files.extend(shortcode.file().map(ToString::to_string));
+
+  let (_, handler) = SHORTCODES
+    .iter()
+    .find(|(name, _)| *name == shortcode.name)
+    .ok_or_else(|| format!("unknown shortcode `{}`", shortcode.name))?;
+  handler(&shortcode, options)
+}
@@ -0,0 +1,302 @@
+//! Serves the site's ActivityPub actor, see [`site_app::activitypub`], and
+//! delivers each new post to the actor's followers.
+//!
+//! The routes are always there, but answer `404` unless
+//! [`ActivityPubConfig`](site_app::config::ActivityPubConfig) enables the
+//! actor.
+
+use std::time::Duration;
+
+use axum::{
+  body::Bytes,
+  extract::{Path, Query, State},
+  http::{header, HeaderMap, Method, StatusCode, Uri},
+  response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use serde_json::Value;
+use site_app::{
+  activitypub::{self, ActorKey},
+  live::LiveEvent,
+  post_index::PostIndex,
+  posts::blocking,
+};
+use site_db::{
+  storage::{Follower, FollowerStore},
+  Database,
+};
+use tokio::{sync::broadcast::error::RecvError, task::JoinSet};
+
+use crate::{live::LiveEvents, state::AppState};
+
+/// How many times an activity is delivered to an inbox before it's given up
+/// on.
+const MAX_ATTEMPTS: u32 = 3;
+/// How long to wait before delivering an activity again, doubling after each
+/// attempt.
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+fn document(content_type: &'static str, document: Value) -> Response {
+  (
+    [
+      (header::CONTENT_TYPE, content_type),
+      // so that clients on other sites can look the actor up too
+      (header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"),
+    ],
+    document.to_string(),
+  )
+    .into_response()
+}
+
+#[derive(Deserialize)]
+pub struct WebfingerQuery {
+  resource: String,
+}
+
+/// Answers WebFinger lookups for the actor, by its account or its ID.
+pub async fn webfinger(
+  State(state): State<AppState>,
+  Query(query): Query<WebfingerQuery>,
+) -> Response {
+  let is_actor = query.resource.eq_ignore_ascii_case(&activitypub::account())
+    || query.resource == activitypub::actor_id();
+  if state.activitypub.is_none() || !is_actor {
+    return StatusCode::NOT_FOUND.into_response();
+  }
+  document("application/jrd+json", activitypub::webfinger())
+}
+
+/// Serves the actor document.
+pub async fn actor(State(state): State<AppState>) -> Response {
+  let Some(key) = &state.activitypub else {
+    return StatusCode::NOT_FOUND.into_response();
+  };
+  document(activitypub::ACTIVITY_JSON, activitypub::actor(key))
+}
+
+/// Serves the outbox, which publishes the newest public posts.
+pub async fn outbox(State(state): State<AppState>) -> Response {
+  if state.activitypub.is_none() {
+    return StatusCode::NOT_FOUND.into_response();
+  }
+  let index = state.index.clone();
+  match blocking(move || index.public_posts()).await {
+    Ok(posts) => {
+      document(activitypub::ACTIVITY_JSON, activitypub::outbox(&posts))
+    }
+    Err(e) => {
+      log::error!("failed to list posts for the outbox: {e}");
+      StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    }
+  }
+}
+
+/// Serves the followers collection.
+pub async fn followers(State(state): State<AppState>) -> Response {
+  if state.activitypub.is_none() {
+    return StatusCode::NOT_FOUND.into_response();
+  }
+  match state.db.followers().await {
+    Ok(followers) => document(
+      activitypub::ACTIVITY_JSON,
+      activitypub::followers(followers.len()),
+    ),
+    Err(e) => {
+      log::error!("failed to fetch followers: {e}");
+      StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    }
+  }
+}
+
+/// Serves a public post's `Article`.
+pub async fn article(
+  State(state): State<AppState>,
+  Path(path): Path<String>,
+) -> Response {
+  if state.activitypub.is_none() {
+    return StatusCode::NOT_FOUND.into_response();
+  }
+  let index = state.index.clone();
+  match blocking(move || index.post(&path)).await {
+    Ok(Some(Ok(post))) if post.metadata.public => {
+      document(activitypub::ACTIVITY_JSON, activitypub::article(&post))
+    }
+    _ => StatusCode::NOT_FOUND.into_response(),
+  }
+}
+
+/// Takes activities sent to the actor, once their signature checks out.
+/// Follows are accepted straight away, and undone follows and deleted
+/// accounts drop the follower. Anything else is ignored.
+pub async fn inbox(
+  State(state): State<AppState>,
+  method: Method,
+  uri: Uri,
+  headers: HeaderMap,
+  body: Bytes,
+) -> Response {
+  let Some(key) = &state.activitypub else {
+    return StatusCode::NOT_FOUND.into_response();
+  };
+  let Ok(activity) = serde_json::from_slice::<Value>(&body) else {
+    return StatusCode::BAD_REQUEST.into_response();
+  };
+  let target = uri.path_and_query().map_or("/", |target| target.as_str());
+  let sender = match activitypub::verify_request(
+    key,
+    method.as_str(),
+    target,
+    &headers,
+    &body,
+  )
+  .await
+  {
+    Ok(sender) => sender,
+    Err(e) => {
+      log::info!("turned away an activity: {e}");
+      return StatusCode::UNAUTHORIZED.into_response();
+    }
+  };
+  if activity["actor"].as_str() != Some(sender.id.as_str()) {
+    return StatusCode::UNAUTHORIZED.into_response();
+  }
+
+  let object = &activity["object"];
+  let result = match activity["type"].as_str() {
+    Some("Follow")
+      if object.as_str() == Some(activitypub::actor_id().as_str()) =>
+    {
+      let follower = Follower {
+        actor: sender.id.clone(),
+        inbox: sender.delivery_inbox().to_string(),
+      };
+      let result = state.db.add_follower(&follower).await;
+      if result.is_ok() {
+        log::info!("followed by `{}`", sender.id);
+        let accept = activitypub::accept(&activity);
+        let key = key.clone();
+        tokio::spawn(async move {
+          deliver_with_retries(&key, &sender.inbox, &accept).await
+        });
+      }
+      result
+    }
+    // the site only follows one actor, so any follow undone is that one
+    Some("Undo")
+      if object["type"].as_str() == Some("Follow") || object.is_string() =>
+    {
+      remove_follower(&state.db, &sender.id).await
+    }
+    Some("Delete") if object.as_str() == Some(sender.id.as_str()) => {
+      remove_follower(&state.db, &sender.id).await
+    }
+    _ => Ok(()),
+  };
+  match result {
+    Ok(()) => StatusCode::ACCEPTED.into_response(),
+    Err(e) => {
+      log::error!("failed to store an activity from `{}`: {e}", sender.id);
+      StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    }
+  }
+}
+
+async fn remove_follower(
+  db: &Database,
+  actor: &str,
+) -> Result<(), site_db::DbError> {
+  if db.remove_follower(actor).await? {
+    log::info!("unfollowed by `{actor}`");
+  }
+  Ok(())
+}
+
+/// Delivers each new post published on `events` to the actor's followers.
+pub async fn deliver_new_posts(
+  events: LiveEvents,
+  db: Database,
+  index: PostIndex,
+  key: ActorKey,
+) {
+  let mut receiver = events.subscribe();
+
+  loop {
+    let path = match receiver.recv().await {
+      Ok(LiveEvent::NewPost { path, .. }) => path,
+      Ok(_) => continue,
+      Err(RecvError::Lagged(skipped)) => {
+        log::warn!("activitypub delivery missed {skipped} live events");
+        continue;
+      }
+      Err(RecvError::Closed) => return,
+    };
+
+    tokio::spawn(deliver_post(db.clone(), index.clone(), key.clone(), path));
+  }
+}
+
+/// Delivers a post to every follower's inbox, once for each shared inbox.
+async fn deliver_post(
+  db: Database,
+  index: PostIndex,
+  key: ActorKey,
+  path: String,
+) {
+  let Ok(Some(Ok(post))) = blocking(move || index.post(&path)).await else {
+    return;
+  };
+  if !post.metadata.public {
+    return;
+  }
+  let followers = match db.followers().await {
+    Ok(followers) => followers,
+    Err(e) => {
+      log::error!("failed to fetch followers: {e}");
+      return;
+    }
+  };
+  let mut inboxes = followers
+    .into_iter()
+    .map(|follower| follower.inbox)
+    .collect::<Vec<_>>();
+  inboxes.sort();
+  inboxes.dedup();
+  if inboxes.is_empty() {
+    return;
+  }
+  log::info!("delivering `{}` to {} inboxes", post.path, inboxes.len());
+
+  let activity = activitypub::create(&post);
+  let mut deliveries = JoinSet::new();
+  for inbox in inboxes {
+    let (key, activity) = (key.clone(), activity.clone());
+    deliveries.spawn(async move {
+      deliver_with_retries(&key, &inbox, &activity).await
+    });
+  }
+  while deliveries.join_next().await.is_some() {}
+}
+
+/// Delivers an activity until the inbox takes or rejects it.
+async fn deliver_with_retries(key: &ActorKey, inbox: &str, activity: &Value) {
+  let mut retry_delay = FIRST_RETRY_DELAY;
+
+  for attempt in 1..=MAX_ATTEMPTS {
+    let error = match activitypub::deliver(key, inbox, activity).await {
+      Ok(200..=299) => return,
+      // asked to be tried later, or failed on the inbox's side
+      Ok(status @ (429 | 500..=599)) => format!("answered {status}"),
+      Ok(status) => {
+        log::warn!("`{inbox}` rejected an activity, answering {status}");
+        return;
+      }
+      Err(e) => e,
+    };
+    if attempt == MAX_ATTEMPTS {
+      log::warn!("gave up delivering an activity to `{inbox}`: {error}");
+      return;
+    }
+    tokio::time::sleep(retry_delay).await;
+    retry_delay *= 2;
+  }
+}
@@ -0,0 +1,192 @@
+//! Records the visits pages send beacons about, see
+//! [`AnalyticsConfig`](site_app::config::AnalyticsConfig).
+//!
+//! Visitors are told apart by a hash of their address and browser with a
+//! salt that's only kept in memory and replaced when the day changes, which is
+//! also when visits older than the retention period are deleted. Only the
+//! home page and public posts are recorded, referrers only by their host and
+//! only from other sites.
+
+use std::sync::{Arc, Mutex};
+
+use axum::{
+  body::to_bytes,
+  extract::{Request, State},
+  http::{header, HeaderMap, StatusCode},
+};
+use sha2::{Digest, Sha256};
+use site_app::{
+  analytics::{day, Beacon},
+  auth::has_admin_session,
+  posts::normalize_post_path,
+};
+use site_db::storage::{AnalyticsStore, Visit};
+
+use crate::state::AppState;
+
+/// The most a beacon can weigh, in bytes.
+const MAX_BEACON_BYTES: usize = 4096;
+
+/// The salt visitors are hashed with on a day.
+struct DailySalt {
+  day:  String,
+  salt: [u8; 32],
+}
+
+/// The salt of the current day.
+#[derive(Clone)]
+pub struct Analytics {
+  state: AppState,
+  salt:  Arc<Mutex<Option<DailySalt>>>,
+}
+
+impl Analytics {
+  pub fn new(state: AppState) -> Self {
+    Analytics {
+      state,
+      salt: Default::default(),
+    }
+  }
+
+  /// The salt of `today`, replacing the last day's and deleting the visits
+  /// that have expired if the day changed.
+  fn salt(&self, today: time::Date) -> [u8; 32] {
+    let mut salt = self.salt.lock().unwrap();
+    match &*salt {
+      Some(salt) if salt.day == day(today) => salt.salt,
+      _ => {
+        let new_salt = rand_salt();
+        *salt = Some(DailySalt {
+          day:  day(today),
+          salt: new_salt,
+        });
+
+        let retention = time::Duration::days(i64::from(
+          self.state.config.analytics.retention_days,
+        ));
+        let expired = day(today - retention);
+        let db = self.state.db.clone();
+        tokio::spawn(async move {
+          match db.delete_visits_before(&expired).await {
+            Ok(0) => {}
+            Ok(deleted) => log::info!("deleted {deleted} expired visits"),
+            Err(e) => log::error!("failed to delete expired visits: {e}"),
+          }
+        });
+        new_salt
+      }
+    }
+  }
+}
+
+fn rand_salt() -> [u8; 32] {
+  Sha256::digest(uuid::Uuid::new_v4().as_bytes()).into()
+}
+
+/// The path a beacon was sent from, if it's recorded.
+fn recorded_path(state: &AppState, path: &str) -> Option<String> {
+  if path == "/" {
+    return Some(path.to_string());
+  }
+  let post = path
+    .strip_prefix("/post/")
+    .filter(|post| !post.contains('/'))?;
+  let post = percent_encoding::percent_decode_str(post).decode_utf8_lossy();
+  let post = normalize_post_path(&post)?;
+  state
+    .index
+    .is_public(&post)
+    .then(|| format!("/post/{post}"))
+}
+
+/// The host of a referrer, unless it's the site itself.
+fn referrer_host(state: &AppState, referrer: &str) -> Option<String> {
+  let host_of = |url: &str| {
+    let rest = url.split_once("://")?.1;
+    let host = rest.split(['/', '?', '#']).next()?;
+    let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+    Some(host.to_ascii_lowercase()).filter(|host| !host.is_empty())
+  };
+  let host = host_of(referrer)?;
+  (Some(&host) != host_of(&state.config.base_url).as_ref()).then_some(host)
+}
+
+/// The visitor's country, from the header the proxy sets, if it's set.
+fn country(state: &AppState, headers: &HeaderMap) -> Option<String> {
+  let name = state.config.analytics.country_header.as_deref()?;
+  let country = headers
+    .get(name)?
+    .to_str()
+    .ok()?
+    .trim()
+    .to_ascii_uppercase();
+  (country.len() == 2 && country.bytes().all(|b| b.is_ascii_alphanumeric()))
+    .then_some(country)
+}
+
+/// Records the visit a page's beacon tells of. Beacons aren't answered with
+/// anything, so only whether it was taken is sent back.
+pub async fn beacon(
+  State(analytics): State<Analytics>,
+  request: Request,
+) -> StatusCode {
+  let state = &analytics.state;
+  if !state.config.analytics.enabled {
+    return StatusCode::NOT_FOUND;
+  }
+  let headers = request.headers().clone();
+  // requests made from within the server have no client
+  let Some(client) = state
+    .config
+    .rate_limit
+    .client_ip(&headers, request.extensions())
+  else {
+    return StatusCode::NO_CONTENT;
+  };
+  if !crate::views::is_reader(&headers)
+    || has_admin_session(&state.config, &state.db, &headers).await
+  {
+    return StatusCode::NO_CONTENT;
+  }
+  let Ok(body) = to_bytes(request.into_body(), MAX_BEACON_BYTES).await else {
+    return StatusCode::PAYLOAD_TOO_LARGE;
+  };
+  let Ok(beacon) = serde_json::from_slice::<Beacon>(&body) else {
+    return StatusCode::BAD_REQUEST;
+  };
+  let Some(path) = recorded_path(state, &beacon.path) else {
+    return StatusCode::NO_CONTENT;
+  };
+
+  let today = time::OffsetDateTime::now_utc().date();
+  let user_agent = headers
+    .get(header::USER_AGENT)
+    .and_then(|v| v.to_str().ok())
+    .unwrap_or_default();
+  let hash = Sha256::new()
+    .chain_update(analytics.salt(today))
+    .chain_update(client.to_string())
+    .chain_update([0])
+    .chain_update(user_agent)
+    .finalize();
+  // a part of the hash is plenty to tell a day's visitors apart
+  let visitor = format!(
+    "{:016x}",
+    u64::from_be_bytes(hash[..8].try_into().expect("hashes are 32 bytes"))
+  );
+
+  let visit = Visit {
+    day: day(today),
+    path,
+    referrer: referrer_host(state, &beacon.referrer),
+    country: country(state, &headers),
+    visitor,
+  };
+  match state.db.record_visit(&visit).await {
+    Ok(()) => StatusCode::NO_CONTENT,
+    Err(e) => {
+      log::error!("failed to record a visit: {e}");
+      StatusCode::INTERNAL_SERVER_ERROR
+    }
+  }
+}
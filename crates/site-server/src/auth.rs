@@ -0,0 +1,119 @@
+//! The server's side of logging in, see [`site_app::auth`]: the routes for
+//! logging in with GitHub, and [`require_admin`], which guards routes served
+//! outside of Leptos that only the author may use, like uploads.
+
+use std::collections::HashMap;
+
+use axum::{
+  extract::{Query, Request, State},
+  http::{header, HeaderMap, StatusCode},
+  middleware::Next,
+  response::{Html, IntoResponse, Redirect, Response},
+};
+use site_app::{
+  auth::{github, github_config, has_admin_session, start_session},
+  config::SiteConfig,
+};
+
+use crate::state::AppState;
+
+/// Answers requests without an admin session with a `401`. Add it to a route
+/// with `route_layer`, so that it doesn't turn away requests which wouldn't
+/// match the route anyway.
+pub async fn require_admin(
+  State(state): State<AppState>,
+  request: Request,
+  next: Next,
+) -> Response {
+  if has_admin_session(&state.config, &state.db, request.headers()).await {
+    next.run(request).await
+  } else {
+    (StatusCode::UNAUTHORIZED, "Log in to the admin area first.")
+      .into_response()
+  }
+}
+
+/// Sends the author to GitHub to log in.
+pub async fn github_start(State(config): State<SiteConfig>) -> Response {
+  let Some(github) = github_config(&config) else {
+    return StatusCode::NOT_FOUND.into_response();
+  };
+  let (url, cookie) = github::authorize(&config, github);
+  (
+    [(header::SET_COOKIE, cookie)],
+    [(header::CACHE_CONTROL, "no-store")],
+    Redirect::to(&url),
+  )
+    .into_response()
+}
+
+/// The page logging in with GitHub ends on, which goes on to the admin area
+/// itself. The session cookie is `SameSite=Strict`, so it isn't sent along
+/// redirects from GitHub, but is from a page of the site's own.
+const LOGGED_IN_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta http-equiv="refresh" content="0; url=/admin">
+<title>Logged In</title>
+</head>
+<body><p>Logged in! Go on to <a href="/admin">the admin area</a>.</p></body>
+</html>
+"#;
+
+/// Where GitHub sends the author back to, logging them in if they're the
+/// configured account.
+pub async fn github_callback(
+  State(state): State<AppState>,
+  Query(query): Query<HashMap<String, String>>,
+  headers: HeaderMap,
+) -> Response {
+  let config = &state.config;
+  let Some(github) = github_config(config) else {
+    return StatusCode::NOT_FOUND.into_response();
+  };
+  let cookies = headers
+    .get_all(header::COOKIE)
+    .iter()
+    .filter_map(|cookies| cookies.to_str().ok())
+    .collect::<Vec<_>>()
+    .join("; ");
+  let result = match (query.get("code"), query.get("state")) {
+    (Some(code), Some(state)) => {
+      github::log_in(config, github, code, state, &cookies).await
+    }
+    _ => Err(
+      query
+        .get("error_description")
+        .cloned()
+        .unwrap_or_else(|| "GitHub sent no code".to_string()),
+    ),
+  };
+
+  let mut response = match result {
+    Ok(()) => match start_session(config, &state.db).await {
+      Ok(cookie) => {
+        log::info!("admin logged in with GitHub");
+        let cookie = cookie.expect("GitHub is configured");
+        ([(header::SET_COOKIE, cookie)], Html(LOGGED_IN_PAGE)).into_response()
+      }
+      Err(e) => {
+        log::error!("couldn't start an admin session: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+      }
+    },
+    Err(e) => {
+      log::warn!("failed admin login with GitHub: {e}");
+      Redirect::to("/admin?login=github").into_response()
+    }
+  };
+  let headers = response.headers_mut();
+  if let Ok(cookie) = github::expired_state_cookie().parse() {
+    headers.append(header::SET_COOKIE, cookie);
+  }
+  headers.insert(
+    header::CACHE_CONTROL,
+    header::HeaderValue::from_static("no-store"),
+  );
+  response
+}
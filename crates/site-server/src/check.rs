@@ -0,0 +1,272 @@
+//! The `check` command, which validates the runtime configuration before the
+//! server is trusted with traffic.
+
+use std::path::Path;
+
+use leptos::{get_configuration, LeptosOptions};
+use site_app::posts::{posts_dir, read_post_sources, try_parse_frontmatter};
+use site_db::{migrations, Database};
+
+/// The outcome of a single check.
+struct CheckResult {
+  name:    &'static str,
+  outcome: Result<String, String>,
+}
+
+/// A report of every check that was run.
+#[derive(Default)]
+pub struct CheckReport {
+  results: Vec<CheckResult>,
+}
+
+impl CheckReport {
+  fn record(&mut self, name: &'static str, outcome: Result<String, String>) {
+    self.results.push(CheckResult { name, outcome });
+  }
+
+  /// Whether every check passed.
+  pub fn passed(&self) -> bool {
+    self.results.iter().all(|r| r.outcome.is_ok())
+  }
+}
+
+impl std::fmt::Display for CheckReport {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    for result in self.results.iter() {
+      match &result.outcome {
+        Ok(detail) => writeln!(f, "[ ok ] {}: {detail}", result.name)?,
+        Err(detail) => writeln!(f, "[FAIL] {}: {detail}", result.name)?,
+      }
+    }
+    let failures = self.results.iter().filter(|r| r.outcome.is_err()).count();
+    write!(f, "{} checks, {} failed", self.results.len(), failures)
+  }
+}
+
+fn check_content_dir() -> Result<String, String> {
+  let posts_dir = posts_dir();
+  let entries = std::fs::read_dir(&posts_dir)
+    .map_err(|e| format!("can't read `{}`: {e}", posts_dir.display()))?;
+  let count = entries
+    .filter_map(Result::ok)
+    .filter(|e| e.path().extension().is_some_and(|ext| ext == "md"))
+    .count();
+  Ok(format!("`{}` has {count} posts", posts_dir.display()))
+}
+
+fn check_frontmatter() -> Result<String, String> {
+  let sources = read_post_sources();
+  let errors = sources
+    .iter()
+    .filter_map(|(path, input)| {
+      try_parse_frontmatter(input)
+        .err()
+        .map(|e| format!("`{path}`: {e}"))
+    })
+    .collect::<Vec<_>>();
+
+  if errors.is_empty() {
+    Ok(format!("{} posts parsed", sources.len()))
+  } else {
+    Err(errors.join("; "))
+  }
+}
+
+/// Finds the paths referenced by `url(...)` in a stylesheet.
+pub(crate) fn css_urls(css: &str) -> Vec<&str> {
+  css
+    .split("url(")
+    .skip(1)
+    .filter_map(|rest| rest.split_once(')'))
+    .map(|(url, _)| url.trim().trim_matches(['\'', '"']))
+    .collect()
+}
+
+fn check_assets(options: &LeptosOptions) -> Result<String, String> {
+  let site_root = Path::new(&options.site_root);
+  let output_name = &options.output_name;
+  let pkg_dir = format!("{}/{output_name}", options.site_pkg_dir);
+  let lazy_bundle =
+    format!("{}/{}", options.site_pkg_dir, site_app::LAZY_BUNDLE_NAME);
+
+  let mut required = vec![
+    format!("{pkg_dir}.css"),
+    format!("{pkg_dir}.js"),
+    format!("{pkg_dir}.wasm"),
+    format!("{lazy_bundle}.js"),
+    format!("{lazy_bundle}_bg.wasm"),
+    "favicon.ico".to_string(),
+    "favicon.png".to_string(),
+    "favicon.svg".to_string(),
+  ];
+  required.extend(
+    css_urls(site_app::FONTS_CSS)
+      .into_iter()
+      .map(|url| url.trim_start_matches('/').to_string()),
+  );
+
+  let missing = required
+    .iter()
+    .filter(|path| !site_root.join(path).is_file())
+    .map(String::as_str)
+    .collect::<Vec<_>>();
+
+  if missing.is_empty() {
+    Ok(format!(
+      "{} assets present in `{}`",
+      required.len(),
+      site_root.display()
+    ))
+  } else {
+    Err(format!(
+      "missing from `{}`: {}",
+      site_root.display(),
+      missing.join(", ")
+    ))
+  }
+}
+
+async fn check_migrations() -> Result<String, String> {
+  let db = Database::connect_from_env()
+    .await
+    .map_err(|e| e.to_string())?;
+  let status = migrations::status(&db).await.map_err(|e| e.to_string())?;
+
+  if status.pending.is_empty() {
+    Ok(format!(
+      "{} migrations applied to {}",
+      status.applied.len(),
+      db.backend()
+    ))
+  } else {
+    Err(format!(
+      "pending migrations: {}",
+      status
+        .pending
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+    ))
+  }
+}
+
+fn check_push() -> Result<String, String> {
+  match crate::push::VapidConfig::from_env() {
+    Ok(Some(vapid)) => {
+      Ok(format!("enabled, public key {}", vapid.public_key()))
+    }
+    Ok(None) => Ok(format!(
+      "disabled, set `{}` to enable",
+      crate::push::VAPID_PRIVATE_KEY_VAR
+    )),
+    Err(e) => Err(format!("invalid VAPID key: {e}")),
+  }
+}
+
+fn check_tls() -> Result<String, String> {
+  match &site_app::config::site_config().tls {
+    Some(tls) => crate::tls::acceptor(tls)
+      .map(|_| format!("serving HTTPS with `{}`", tls.cert.display())),
+    None => Ok("disabled, HTTPS is left to a proxy".to_string()),
+  }
+}
+
+fn check_redirects() -> Result<String, String> {
+  let file = &site_app::config::site_config().redirects;
+  crate::redirects::Redirects::load(file)
+    .map(|redirects| format!("{} redirects", redirects.len()))
+}
+
+fn check_admin() -> Result<String, String> {
+  let config = site_app::config::site_config();
+  if let Some(hash) = &config.admin.password_hash {
+    site_app::auth::validate_hash(hash)
+      .map_err(|e| format!("the password hash {e}"))?;
+  }
+  if let Some(github) = &config.admin.github {
+    if github.client_id.is_empty() || github.user.is_empty() {
+      return Err("GitHub needs a `client_id` and a `user`".to_string());
+    }
+    if github.client_secret.is_empty() {
+      return Err(
+        "GitHub needs a `client_secret`, or `SITE_ADMIN_GITHUB_CLIENT_SECRET`"
+          .to_string(),
+      );
+    }
+  }
+  let configured =
+    config.admin.password_hash.is_some() || config.admin.github.is_some();
+  if configured && config.session_secret.is_none() {
+    return Err(
+      "the admin area needs a session secret, set `SITE_SESSION_SECRET`"
+        .to_string(),
+    );
+  }
+  let methods = site_app::auth::auth_methods(config)
+    .into_iter()
+    .map(|method| match method {
+      site_app::auth::AuthMethod::Password => "a password",
+      site_app::auth::AuthMethod::GitHub => "GitHub",
+    })
+    .collect::<Vec<_>>();
+  if methods.is_empty() {
+    return Ok(
+      "disabled, set a password hash or a GitHub app to enable".to_string(),
+    );
+  }
+  Ok(format!(
+    "enabled at `/admin`, logged in to with {}",
+    methods.join(" or ")
+  ))
+}
+
+async fn check_smtp() -> Result<String, String> {
+  let Some(smtp) = &site_app::config::site_config().smtp else {
+    return Ok(
+      "disabled, so there's no newsletter or contact form".to_string(),
+    );
+  };
+  site_app::mail::check(smtp)
+    .await
+    .map(|()| format!("connected to `{}`", smtp.host))
+    .map_err(|e| format!("couldn't connect to `{}`: {e}", smtp.host))
+}
+
+/// Runs every check and returns the report.
+pub async fn run() -> CheckReport {
+  let mut report = CheckReport::default();
+
+  let options = get_configuration(None)
+    .await
+    .map(|conf| conf.leptos_options)
+    .map_err(|e| e.to_string());
+  report.record(
+    "leptos configuration",
+    options
+      .as_ref()
+      .map(|o| format!("site address is {}", o.site_addr))
+      .map_err(Clone::clone),
+  );
+
+  // an invalid configuration exits before any checks run
+  let config = site_app::config::site_config();
+  report.record(
+    "site configuration",
+    Ok(format!("`{}` at {}", config.title, config.base_url)),
+  );
+  report.record("content directory", check_content_dir());
+  report.record("post frontmatter", check_frontmatter());
+  report.record("database migrations", check_migrations().await);
+  report.record("web push", check_push());
+  report.record("TLS", check_tls());
+  report.record("redirects", check_redirects());
+  report.record("admin", check_admin());
+  report.record("SMTP", check_smtp().await);
+
+  if let Ok(options) = &options {
+    report.record("site assets", check_assets(options));
+  }
+
+  report
+}
@@ -0,0 +1,204 @@
+//! The `check-content` command, which runs every post through the full
+//! pipeline and reports each problem with where it is, so that content can be
+//! checked before it's deployed.
+
+use std::collections::HashMap;
+
+use site_app::{
+  dates::iso_date,
+  links::find_dead_links,
+  posts::{
+    link_context, post_files, posts_dir, read_post_sources, render_post,
+    try_parse_frontmatter,
+  },
+};
+
+/// A problem with a post.
+struct Problem {
+  /// The post's file.
+  file:    String,
+  /// The 1-based line of the file the problem is on, if it could be found.
+  line:    Option<usize>,
+  message: String,
+}
+
+impl std::fmt::Display for Problem {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self.line {
+      Some(line) => write!(f, "{}:{line}: {}", self.file, self.message),
+      None => write!(f, "{}: {}", self.file, self.message),
+    }
+  }
+}
+
+/// A report of every problem found in the content.
+pub struct ContentReport {
+  posts:    usize,
+  problems: Vec<Problem>,
+}
+
+impl ContentReport {
+  /// Whether no problems were found.
+  pub fn passed(&self) -> bool { self.problems.is_empty() }
+}
+
+impl std::fmt::Display for ContentReport {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    for problem in self.problems.iter() {
+      writeln!(f, "{problem}")?;
+    }
+    write!(
+      f,
+      "{} posts checked, {} problems",
+      self.posts,
+      self.problems.len()
+    )
+  }
+}
+
+// smart punctuation curls quotes before the pipeline reports what it saw
+fn straighten_quotes(text: &str) -> String {
+  text.replace(['“', '”'], "\"").replace(['‘', '’'], "'")
+}
+
+/// Finds the first line of `input` which contains every one of `needles`.
+fn find_line(input: &str, needles: &[&str]) -> Option<usize> {
+  let needles = needles
+    .iter()
+    .map(|needle| straighten_quotes(needle))
+    .collect::<Vec<_>>();
+  input
+    .lines()
+    .position(|line| {
+      let line = straighten_quotes(line);
+      needles.iter().all(|needle| line.contains(needle.as_str()))
+    })
+    .map(|index| index + 1)
+}
+
+/// The byte offset of the start of the line after the first `lines` lines.
+fn line_offset(input: &str, lines: usize) -> usize {
+  input.split_inclusive('\n').take(lines).map(str::len).sum()
+}
+
+/// Runs every check over every post and returns the report.
+pub fn run() -> ContentReport {
+  let sources = read_post_sources();
+  let context = link_context(&sources);
+  let files = post_files();
+  let file_of = |path: &str| {
+    files
+      .iter()
+      .find(|(p, _)| p == path)
+      .map(|(_, file)| file.clone())
+      .unwrap_or_else(|| posts_dir().join(format!("{path}.md")))
+      .display()
+      .to_string()
+  };
+  let mut problems = Vec::new();
+
+  for (path, input) in sources.iter() {
+    let file = file_of(path);
+    let (metadata, content) = match try_parse_frontmatter(input) {
+      Ok(parsed) => parsed,
+      Err(e) => {
+        problems.push(Problem {
+          file,
+          line: Some(1),
+          message: e,
+        });
+        continue;
+      }
+    };
+
+    if iso_date(&metadata.written_on).is_none() {
+      problems.push(Problem {
+        file:    file.clone(),
+        line:    find_line(input, &["written_on:"]),
+        message: format!(
+          "`written_on` is `{}`, not a `YYYY.MM.DD` date",
+          metadata.written_on
+        ),
+      });
+    }
+
+    // a snippet that appears more than once is looked for after the line
+    // where it was last found
+    let mut searched_to = HashMap::<String, usize>::new();
+    for warning in render_post(path, &content, &metadata, &context).warnings {
+      let snippet = warning.snippet.trim();
+      let start = searched_to.get(snippet).copied().unwrap_or(0);
+      let line = find_line(&input[line_offset(input, start)..], &[snippet])
+        .map(|line| line + start);
+      if let Some(line) = line {
+        searched_to.insert(snippet.to_string(), line);
+      }
+      problems.push(Problem {
+        file: file.clone(),
+        line,
+        message: warning.message,
+      });
+    }
+  }
+
+  // files whose names differ only in case or punctuation have the same path,
+  // and only the first is served
+  let mut slugs = HashMap::<&str, Vec<String>>::new();
+  for (path, file) in files.iter() {
+    slugs
+      .entry(path)
+      .or_default()
+      .push(file.display().to_string());
+  }
+  for (slug, files) in slugs.into_iter().filter(|(_, f)| f.len() > 1) {
+    for file in files.iter() {
+      let others = files
+        .iter()
+        .filter(|other| *other != file)
+        .map(|other| format!("`{other}`"))
+        .collect::<Vec<_>>();
+      problems.push(Problem {
+        file:    file.clone(),
+        line:    None,
+        message: format!(
+          "duplicate slug `{slug}`, shared with {}",
+          others.join(", ")
+        ),
+      });
+    }
+  }
+
+  let inputs = sources.iter().cloned().collect::<HashMap<_, _>>();
+  for dead_link in find_dead_links() {
+    let input = inputs.get(&dead_link.source).map_or("", String::as_str);
+    // relative and wiki links are written differently from how they resolve,
+    // so fall back to the line that mentions the linked post
+    let (target_path, anchor) = dead_link
+      .target
+      .trim_start_matches("/post/")
+      .split_once('#')
+      .map_or(
+        (dead_link.target.trim_start_matches("/post/"), None),
+        |(p, a)| (p, Some(a)),
+      );
+    let fallback = match anchor {
+      Some(anchor) => vec![target_path, anchor],
+      None => vec![target_path],
+    };
+    problems.push(Problem {
+      file:    file_of(&dead_link.source),
+      line:    find_line(input, &[&dead_link.target])
+        .or_else(|| find_line(input, &fallback)),
+      message: format!(
+        "dead link to `{}`: {}",
+        dead_link.target, dead_link.reason
+      ),
+    });
+  }
+
+  problems.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+  ContentReport {
+    posts: sources.len(),
+    problems,
+  }
+}
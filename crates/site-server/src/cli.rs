@@ -0,0 +1,118 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::generate_fixtures::FixtureKind;
+
+/// The server for the blog.
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+  #[command(subcommand)]
+  pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+  /// Serve the site. This is the default.
+  Serve,
+  /// Validate the runtime configuration and exit, non-zero on failure.
+  Check,
+  /// Run every post through the full pipeline and report problems with their
+  /// file and line, exiting non-zero if there are any.
+  CheckContent,
+  /// Apply pending database migrations.
+  Migrate {
+    /// List the pending migrations without applying them.
+    #[arg(long)]
+    dry_run: bool,
+  },
+  /// Crawl the rendered site and export it as static files, with the error
+  /// page as `404.html`. Only files which changed since the last export are
+  /// rewritten.
+  ExportStatic {
+    /// The directory to export to.
+    #[arg(long, default_value = "dist")]
+    out:      PathBuf,
+    /// The URL the site will be hosted at. Defaults to the configured base
+    /// URL.
+    #[arg(long)]
+    base_url: Option<String>,
+    /// Rewrite every file, ignoring the previous export.
+    #[arg(long)]
+    full:     bool,
+  },
+  /// Crawl the rendered site and write it to a WARC archive, with a CDX index
+  /// next to it.
+  ExportWarc {
+    /// Where to write the archive. Defaults to a timestamped file in
+    /// `archive/`.
+    #[arg(long)]
+    out:      Option<PathBuf>,
+    /// The URL the archived pages are recorded under. Defaults to the
+    /// configured base URL.
+    #[arg(long)]
+    base_url: Option<String>,
+  },
+  /// Write an archive of synthetic posts, for measuring performance against
+  /// far more content than the site has.
+  GenerateFixtures {
+    /// How many posts to generate.
+    count: usize,
+    /// The directory to write the posts to.
+    #[arg(long, default_value = "load-test/content/posts")]
+    out:   PathBuf,
+    /// What the posts are made of.
+    #[arg(long, value_enum, default_value_t = FixtureKind::Mixed)]
+    kind:  FixtureKind,
+    /// About how many words of prose each post has.
+    #[arg(long, default_value_t = 1500)]
+    words: usize,
+    /// The seed the posts are generated from.
+    #[arg(long, default_value_t = 1)]
+    seed:  u64,
+  },
+  /// Read a password from stdin and print its hash, for the admin area's
+  /// `password_hash`, as an Argon2id PHC string.
+  HashPassword,
+  /// Create a new private post, dated today, with a path made from its title.
+  NewPost { title: String },
+  /// Manage the comment spam classifier and moderation queue.
+  Spam {
+    #[command(subcommand)]
+    command: SpamCommand,
+  },
+}
+
+#[derive(Subcommand)]
+pub enum SpamCommand {
+  /// List the known-spam phrases and their weights.
+  Phrases,
+  /// Add a known-spam phrase, or change the weight of an existing one.
+  AddPhrase {
+    phrase: String,
+    /// How much the phrase adds to a comment's spam score. Negative weights
+    /// vouch for a comment instead.
+    #[arg(long, default_value_t = 2.0, allow_negative_numbers = true)]
+    weight: f64,
+  },
+  /// Remove a known-spam phrase.
+  RemovePhrase { phrase: String },
+  /// List the comments waiting in the moderation queue.
+  Queue,
+  /// Mark a comment as spam or ham, and train the classifier on it.
+  Mark { id: i64, verdict: SpamVerdict },
+  /// Score some text as though it were posted as a comment.
+  Score {
+    text: String,
+    /// The post the comment would be posted on.
+    #[arg(long, default_value = "")]
+    post: String,
+  },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum SpamVerdict {
+  Spam,
+  Ham,
+}
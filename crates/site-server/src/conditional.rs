@@ -0,0 +1,273 @@
+//! Conditional requests for rendered pages, so that a repeat visitor
+//! revalidating a page that hasn't changed gets an empty `304 Not Modified`
+//! rather than the whole page again.
+//!
+//! Pages are tagged with a hash of what was rendered, so revalidating by
+//! `If-None-Match` still costs a render but not the transfer. The tags are
+//! weak, since the compression layer may encode the same page differently,
+//! and leave out the page's script nonce, which is new on every render.
+//!
+//! Pages built from posts also get a `Last-Modified` date, from the post
+//! files' modification times and, for post pages, when their comments or
+//! reactions last changed. Those can be revalidated by `If-Modified-Since`
+//! without rendering at all. Post pages are streamed, so they aren't held back
+//! to be hashed and only get the date. Static files get both from `ServeDir`.
+//!
+//! Pages with a form that's signed with when it was rendered, like the comment
+//! form under posts, are never revalidated: a `304` would leave the reader
+//! with the timestamp of whenever their copy was rendered, and the form is
+//! turned down once that's too long ago. They get neither a tag nor a date,
+//! and are marked `no-cache` so browsers fetch them again every time.
+//!
+//! Pages are rendered in the reader's theme, which their cookie holds, so
+//! they vary by it: a browser mustn't revalidate a page it has in one theme
+//! after switching to the other.
+
+use std::{path::Path, sync::OnceLock, time::SystemTime};
+
+use axum::{
+  body::Body,
+  extract::{Request, State},
+  http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+  middleware::Next,
+  response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+use site_app::{
+  config::SiteConfig,
+  post_index::PostIndex,
+  posts::{post_file, posts_dir},
+};
+use site_db::{
+  storage::{CommentStore, ReactionStore},
+  Database,
+};
+
+use crate::state::AppState;
+
+/// Whether an `If-None-Match` header matches an entity tag, using the weak
+/// comparison that conditional GETs call for.
+fn if_none_match_matches(if_none_match: &str, etag: &str) -> bool {
+  let opaque_tag = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+  if_none_match.trim() == "*"
+    || if_none_match
+      .split(',')
+      .any(|tag| opaque_tag(tag) == opaque_tag(etag))
+}
+
+fn is_html(response: &Response) -> bool {
+  response
+    .headers()
+    .get(header::CONTENT_TYPE)
+    .and_then(|v| v.to_str().ok())
+    .is_some_and(|v| v.starts_with("text/html"))
+}
+
+async fn modified(file: &Path) -> Option<SystemTime> {
+  tokio::fs::metadata(file).await.ok()?.modified().ok()
+}
+
+/// When the content of the page at `path` last changed, if it's built from
+/// public posts. Every page also changes when the server is redeployed, so it's
+/// never earlier than when the server started.
+async fn last_modified(
+  index: &PostIndex,
+  db: &Database,
+  path: &str,
+) -> Option<SystemTime> {
+  static STARTED: OnceLock<SystemTime> = OnceLock::new();
+  let started = *STARTED.get_or_init(SystemTime::now);
+
+  let content_modified = if path == "/" {
+    // the directory itself changes when a post is removed
+    let posts_dir = posts_dir();
+    let mut latest = modified(&posts_dir).await;
+    let mut entries = tokio::fs::read_dir(&posts_dir).await.ok()?;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+      let modified = entry.metadata().await.ok()?.modified().ok();
+      latest = latest.max(modified);
+    }
+    latest?
+  } else {
+    let post = path.strip_prefix("/post/")?;
+    let (post, is_changes) = match post.strip_suffix("/changes") {
+      Some(post) => (post, true),
+      None => (post, false),
+    };
+    // drafts are missing, and mustn't give away that they exist with a 304
+    if !index.is_public(post) {
+      return None;
+    }
+    let file_modified = modified(&post_file(post)?).await?;
+    if is_changes {
+      file_modified
+    } else {
+      // a page whose comments or reactions can't be checked can't be said to
+      // be unchanged
+      let comments_changed = db.comments_changed_at(post).await.ok()?;
+      let reactions_changed = db.reactions_changed_at(post).await.ok()?;
+      let changed = comments_changed
+        .max(reactions_changed)
+        .and_then(|secs| u64::try_from(secs).ok())
+        .map(|secs| {
+          SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs)
+        });
+      file_modified.max(changed.unwrap_or(SystemTime::UNIX_EPOCH))
+    }
+  };
+
+  // HTTP dates only have whole seconds
+  let latest = content_modified.max(started);
+  httpdate::parse_http_date(&httpdate::fmt_http_date(latest)).ok()
+}
+
+/// Whether the page at `path` is streamed, i.e. is a post page.
+fn is_streamed(path: &str) -> bool {
+  path
+    .strip_prefix("/post/")
+    .is_some_and(|post| !post.contains('/'))
+}
+
+/// Whether the page at `path` has a form with a signed `rendered_at`
+/// timestamp, i.e. is the contact page or a post page with the site's own
+/// comments.
+fn has_form_timestamp(config: &SiteConfig, path: &str) -> bool {
+  path == "/contact" || (is_streamed(path) && config.comments.embed.is_none())
+}
+
+/// The script nonce a page was rendered with, from its security policy.
+pub(crate) fn nonce(headers: &HeaderMap) -> Option<String> {
+  let policy = headers
+    .get(header::CONTENT_SECURITY_POLICY)?
+    .to_str()
+    .ok()?;
+  let nonce = policy.split_once("'nonce-")?.1;
+  Some(nonce[..nonce.find('\'')?].to_string())
+}
+
+/// The hash of a page's content, leaving out the script nonce it was rendered
+/// with, so that renders of the same content have the same hash.
+pub(crate) fn content_hash(body: &[u8], nonce: Option<&str>) -> String {
+  match nonce {
+    Some(nonce) => {
+      let page = String::from_utf8_lossy(body).replace(nonce, "");
+      format!("{:x}", Sha256::digest(page))
+    }
+    None => format!("{:x}", Sha256::digest(body)),
+  }
+}
+
+/// Marks a page as varying by the reader's cookie.
+fn vary_by_cookie(response: &mut Response) {
+  response
+    .headers_mut()
+    .append(header::VARY, HeaderValue::from_static("cookie"));
+}
+
+fn not_modified(mut response: Response) -> Response {
+  *response.status_mut() = StatusCode::NOT_MODIFIED;
+  response.headers_mut().remove(header::CONTENT_LENGTH);
+  // the cached page is still for the nonce of its own policy
+  response
+    .headers_mut()
+    .remove(header::CONTENT_SECURITY_POLICY);
+  *response.body_mut() = Body::empty();
+  response
+}
+
+/// Tags successful HTML responses to GETs and HEADs, answering requests
+/// whose `If-None-Match` or `If-Modified-Since` match with a `304`.
+pub async fn conditional(
+  State(state): State<AppState>,
+  request: Request,
+  next: Next,
+) -> Response {
+  // a `HEAD` is answered with the same headers its `GET` would get
+  if !matches!(*request.method(), Method::GET | Method::HEAD) {
+    return next.run(request).await;
+  }
+  if has_form_timestamp(&state.config, request.uri().path()) {
+    let mut response = next.run(request).await;
+    if response.status() == StatusCode::OK && is_html(&response) {
+      vary_by_cookie(&mut response);
+      response
+        .headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    }
+    return response;
+  }
+  let headers = request.headers();
+  let if_none_match = headers
+    .get(header::IF_NONE_MATCH)
+    .and_then(|v| v.to_str().ok())
+    .map(str::to_string);
+  let if_modified_since = headers
+    .get(header::IF_MODIFIED_SINCE)
+    .and_then(|v| v.to_str().ok())
+    .and_then(|date| httpdate::parse_http_date(date).ok());
+  let path = request.uri().path().to_string();
+  let last_modified = last_modified(&state.index, &state.db, &path).await;
+  let last_modified_header = last_modified.map(|date| {
+    HeaderValue::from_str(&httpdate::fmt_http_date(date))
+      .expect("HTTP dates are valid headers")
+  });
+
+  // `If-Modified-Since` only counts without `If-None-Match`
+  if if_none_match.is_none()
+    && if_modified_since
+      .zip(last_modified)
+      .is_some_and(|(since, modified)| modified <= since)
+  {
+    let mut response = not_modified(Response::default());
+    vary_by_cookie(&mut response);
+    if let Some(last_modified) = last_modified_header {
+      response
+        .headers_mut()
+        .insert(header::LAST_MODIFIED, last_modified);
+    }
+    return response;
+  }
+
+  let mut response = next.run(request).await;
+  if response.status() != StatusCode::OK
+    || !is_html(&response)
+    || response.headers().contains_key(header::ETAG)
+  {
+    return response;
+  }
+  vary_by_cookie(&mut response);
+  if is_streamed(&path) {
+    if let Some(last_modified) = last_modified_header {
+      response
+        .headers_mut()
+        .insert(header::LAST_MODIFIED, last_modified);
+    }
+    return response;
+  }
+
+  let nonce = nonce(response.headers());
+  let (mut parts, body) = response.into_parts();
+  let body = match axum::body::to_bytes(body, usize::MAX).await {
+    Ok(body) => body,
+    Err(e) => {
+      log::error!("failed to read a rendered page: {e}");
+      return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+  };
+  // half the hash is plenty to tell versions of a page apart
+  let hash = content_hash(&body, nonce.as_deref());
+  let etag = format!("W/\"{}\"", &hash[..32]);
+  parts.headers.insert(
+    header::ETAG,
+    HeaderValue::from_str(&etag).expect("entity tags are valid headers"),
+  );
+  if let Some(last_modified) = last_modified_header {
+    parts.headers.insert(header::LAST_MODIFIED, last_modified);
+  }
+
+  let response = Response::from_parts(parts, Body::from(body));
+  if if_none_match.is_some_and(|v| if_none_match_matches(&v, &etag)) {
+    return not_modified(response);
+  }
+  response
+}
@@ -0,0 +1,244 @@
+//! Crawls the rendered site in-process, for the export commands.
+//!
+//! Pages are requested from the router directly rather than over the network,
+//! starting from the homepage and following every same-site `href`, `src`,
+//! CSS `url(...)` and script import.
+
+use std::collections::{BTreeSet, VecDeque};
+
+use axum::{
+  body::{Body, Bytes},
+  http::{header, response::Parts, Request, StatusCode},
+  Router,
+};
+use time::OffsetDateTime;
+use tower::ServiceExt;
+
+/// Paths which aren't linked from pages but are still part of the site. The
+/// lazy bundle's paths are built by the main bundle at runtime.
+fn extra_seeds() -> [String; 4] {
+  [
+    site_app::push::SERVICE_WORKER_PATH.to_string(),
+    site_app::search::SEARCH_INDEX_PATH.to_string(),
+    format!("/pkg/{}.js", site_app::LAZY_BUNDLE_NAME),
+    format!("/pkg/{}_bg.wasm", site_app::LAZY_BUNDLE_NAME),
+  ]
+}
+/// The most responses a crawl will fetch, in case of a crawler trap.
+const MAX_RESPONSES: usize = 10_000;
+
+/// A response fetched by the crawler.
+pub struct Crawled {
+  /// The path that was requested.
+  pub path:    String,
+  /// The response, without its body.
+  pub parts:   Parts,
+  /// The response body, read in full.
+  pub body:    Bytes,
+  /// The response's MIME type, without parameters.
+  pub mime:    String,
+  /// When the response was fetched.
+  pub fetched: OffsetDateTime,
+}
+
+impl Crawled {
+  /// Whether the response was successful, and so was crawled for links.
+  pub fn is_ok(&self) -> bool { self.parts.status == StatusCode::OK }
+}
+
+/// Resolves a link found on the page at `from` to a site path, if it points
+/// into the site.
+fn resolve_link(link: &str, from: &str, base_url: &str) -> Option<String> {
+  let link = link.trim().replace("&amp;", "&");
+  let link = link.split('#').next().unwrap_or_default();
+  let link = link.strip_prefix(base_url).unwrap_or(link);
+
+  if link.is_empty() || link.starts_with("//") || link.contains(':') {
+    return None;
+  }
+  let path = if link.starts_with('/') {
+    link.to_string()
+  } else {
+    let dir = from.rsplit_once('/').map_or("", |(dir, _)| dir);
+    format!("{dir}/{link}")
+  };
+
+  // collapses `.` and `..` segments
+  let mut segments: Vec<&str> = Vec::new();
+  for segment in path.split('/').skip(1) {
+    match segment {
+      "." => {}
+      ".." => {
+        segments.pop();
+      }
+      _ => segments.push(segment),
+    }
+  }
+  Some(format!("/{}", segments.join("/")))
+}
+
+/// Finds the values of every `href` and `src` attribute in a page.
+fn html_links(html: &str) -> Vec<&str> {
+  ["href=\"", "src=\""]
+    .iter()
+    .flat_map(|attribute| html.split(attribute).skip(1))
+    .filter_map(|rest| rest.split_once('"'))
+    .map(|(link, _)| link)
+    .collect()
+}
+
+/// Finds the modules a script imports by name, like the snippets
+/// `wasm-bindgen` puts under a hashed directory next to its bundles.
+fn script_imports(script: &str) -> Vec<&str> {
+  ["from ", "import("]
+    .iter()
+    .flat_map(|keyword| script.split(keyword).skip(1))
+    .filter_map(|rest| {
+      let quote = rest.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+      rest[1..].split_once(quote).map(|(module, _)| module)
+    })
+    .collect()
+}
+
+/// Whether a path can be crawled. Server fns only answer POSTs, and the live
+/// event stream never ends.
+fn is_crawlable(path: &str) -> bool {
+  !path.starts_with("/api/") && path != site_app::live::EVENTS_PATH
+}
+
+/// A breadth-first crawl of the site behind a router.
+pub struct Crawler {
+  app:      Router,
+  base_url: String,
+  seen:     BTreeSet<String>,
+  queue:    VecDeque<String>,
+  fetched:  usize,
+}
+
+impl Crawler {
+  /// Starts a crawl of `app`, as though it were served from `base_url`.
+  pub fn new(app: Router, base_url: &str) -> Self {
+    let mut seen = BTreeSet::from(["/".to_string()]);
+    seen.extend(extra_seeds());
+    let queue = seen.iter().cloned().collect();
+
+    Crawler {
+      app,
+      base_url: base_url.trim_end_matches('/').to_string(),
+      seen,
+      queue,
+      fetched: 0,
+    }
+  }
+
+  /// The URL the site is crawled as, without a trailing slash.
+  pub fn base_url(&self) -> &str { &self.base_url }
+
+  /// Fetches the next page, queueing the pages it links to. Returns `None`
+  /// once the whole site has been crawled.
+  pub async fn next(&mut self) -> Option<Result<Crawled, axum::Error>> {
+    if self.fetched >= MAX_RESPONSES && !self.queue.is_empty() {
+      log::warn!("stopping after {MAX_RESPONSES} responses");
+      return None;
+    }
+    let path = self.queue.pop_front()?;
+    self.fetched += 1;
+
+    let crawled = match self.fetch(path).await {
+      Ok(crawled) => crawled,
+      Err(e) => return Some(Err(e)),
+    };
+
+    if crawled.is_ok() {
+      self.queue_links(&crawled);
+    } else {
+      log::warn!("`{}` responded with {}", crawled.path, crawled.parts.status);
+    }
+    Some(Ok(crawled))
+  }
+
+  /// Fetches a single path, without following its links or counting it
+  /// towards the crawl.
+  pub async fn fetch(&self, path: String) -> Result<Crawled, axum::Error> {
+    let request = Request::get(&path)
+      .header(
+        header::HOST,
+        self.base_url.split("://").last().unwrap_or_default(),
+      )
+      .body(Body::empty())
+      .expect("crawled paths are valid URIs");
+    let response = self
+      .app
+      .clone()
+      .oneshot(request)
+      .await
+      .unwrap_or_else(|e| match e {});
+    let fetched = OffsetDateTime::now_utc();
+
+    let (parts, body) = response.into_parts();
+    let body = axum::body::to_bytes(body, usize::MAX).await?;
+    let mime = parts
+      .headers
+      .get(header::CONTENT_TYPE)
+      .and_then(|v| v.to_str().ok())
+      .unwrap_or("application/octet-stream")
+      .split(';')
+      .next()
+      .unwrap_or_default()
+      .trim()
+      .to_string();
+    Ok(Crawled {
+      path,
+      parts,
+      body,
+      mime,
+      fetched,
+    })
+  }
+
+  fn queue_links(&mut self, crawled: &Crawled) {
+    let text = String::from_utf8_lossy(&crawled.body);
+    let links = match crawled.mime.as_str() {
+      "text/html" => {
+        // inline stylesheets can reference fonts too
+        let mut links = html_links(&text);
+        links.extend(crate::check::css_urls(&text));
+        links
+      }
+      "text/css" => crate::check::css_urls(&text),
+      "text/javascript" => script_imports(&text),
+      _ => Vec::new(),
+    };
+
+    for link in links {
+      if let Some(link) = resolve_link(link, &crawled.path, &self.base_url) {
+        if is_crawlable(&link) && self.seen.insert(link.clone()) {
+          self.queue.push_back(link);
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn script_imports_are_found_by_name() {
+    let script = "import { loadOnReach } from \
+                  './snippets/site-frontend-1a2b/src/lazy.js';\nimport * as \
+                  wasm from \"./site_bg.js\";\nconst x = await \
+                  import('/pkg/site-lazy.js');\nconst url = new \
+                  URL('site_bg.wasm', import.meta.url);\nArray.from (values);";
+    assert_eq!(script_imports(script), [
+      "./snippets/site-frontend-1a2b/src/lazy.js",
+      "./site_bg.js",
+      "/pkg/site-lazy.js",
+    ]);
+    assert_eq!(
+      resolve_link(script_imports(script)[0], "/pkg/site.js", "http://x"),
+      Some("/pkg/snippets/site-frontend-1a2b/src/lazy.js".to_string())
+    );
+  }
+}
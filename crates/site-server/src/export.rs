@@ -0,0 +1,258 @@
+//! The `export-static` command, which crawls the rendered site and writes it
+//! out as static files, so it can be hosted without the server.
+//!
+//! Exports are differential. A manifest of the hash of every exported file is
+//! kept in the output directory, and files whose content hasn't changed since
+//! the last export aren't rewritten. Pages are rendered whole rather than
+//! streamed, so that renders of the same content are the same, and are
+//! compared without their script nonce and form timestamps, which are new
+//! every time. Since every page is re-rendered, pages which depend on a
+//! changed post, like the homepage, are picked up too. The paths which changed
+//! are written to a changes file next to the manifest, for selective CDN
+//! uploads and purges.
+//!
+//! The error page is exported as `404.html`, which static hosts like Netlify,
+//! Cloudflare Pages and GitHub Pages serve for paths that don't exist.
+
+use std::{
+  collections::BTreeMap,
+  fmt, io,
+  path::{Path, PathBuf},
+};
+
+use axum::{
+  http::{HeaderMap, StatusCode},
+  Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  conditional::{content_hash, nonce},
+  crawl::Crawler,
+};
+
+/// The file in the output directory which records the last export.
+const MANIFEST_FILE: &str = ".export-manifest.json";
+/// The file in the output directory which lists what the last export changed.
+const CHANGES_FILE: &str = ".export-changes.json";
+/// The file the error page is exported to.
+const NOT_FOUND_FILE: &str = "404.html";
+/// A path which no route answers, requested to render the error page.
+const NOT_FOUND_PROBE: &str = "/404";
+
+/// How forms give the time they were rendered, which is new on every render,
+/// see `site_app::comments`.
+const FORM_TIMESTAMP: &str = "name=\"rendered_at\" value=\"";
+
+/// The hash of every file in an export, keyed by path relative to the output
+/// directory.
+#[derive(Default, Serialize, Deserialize)]
+struct Manifest {
+  files: BTreeMap<String, String>,
+}
+
+/// A file which an export changed.
+#[derive(Clone, Serialize)]
+struct ChangedFile {
+  /// The URL path the file is served at, for purging.
+  path: String,
+  /// The file's path relative to the output directory, for uploading.
+  file: String,
+}
+
+/// What an export changed, relative to the previous export.
+#[derive(Default, Serialize)]
+pub struct Changes {
+  added:     Vec<ChangedFile>,
+  updated:   Vec<ChangedFile>,
+  removed:   Vec<ChangedFile>,
+  #[serde(skip)]
+  unchanged: usize,
+}
+
+impl fmt::Display for Changes {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{} added, {} updated, {} removed, {} unchanged",
+      self.added.len(),
+      self.updated.len(),
+      self.removed.len(),
+      self.unchanged
+    )
+  }
+}
+
+/// The file a URL path is exported to. Pages are written as `index.html`
+/// inside a directory named after them, so they keep their extensionless
+/// URLs on static hosts.
+fn file_for_path(path: &str, mime: &str) -> String {
+  let path = path.trim_start_matches('/');
+  let has_extension = path
+    .rsplit('/')
+    .next()
+    .is_some_and(|segment| segment.contains('.'));
+
+  if mime == "text/html" && !has_extension {
+    if path.is_empty() {
+      "index.html".to_string()
+    } else {
+      format!("{}/index.html", path.trim_end_matches('/'))
+    }
+  } else {
+    path.to_string()
+  }
+}
+
+/// The URL path a file is served at, the inverse of [`file_for_path`].
+fn path_for_file(file: &str) -> String {
+  match file.strip_suffix("index.html") {
+    Some(dir) => format!("/{}", dir.trim_end_matches('/')),
+    None => format!("/{file}"),
+  }
+}
+
+/// The hash a file is compared to the previous export's with. Pages are
+/// compared without what's new on every render: their script nonce, and when
+/// their forms were rendered.
+fn export_hash(headers: &HeaderMap, body: &[u8]) -> String {
+  let Some(nonce) = nonce(headers) else {
+    return content_hash(body, None);
+  };
+  let page = String::from_utf8_lossy(body);
+  let mut parts = page.split(FORM_TIMESTAMP);
+  let mut page = parts.next().unwrap_or_default().to_string();
+  for part in parts {
+    page.push_str(FORM_TIMESTAMP);
+    page.push_str(&part[part.find('"').unwrap_or_default()..]);
+  }
+  content_hash(page.as_bytes(), Some(&nonce))
+}
+
+fn read_manifest(out: &Path) -> Manifest {
+  std::fs::read(out.join(MANIFEST_FILE))
+    .ok()
+    .and_then(|manifest| serde_json::from_slice(&manifest).ok())
+    .unwrap_or_default()
+}
+
+fn write_json(path: &Path, value: &impl Serialize) -> io::Result<()> {
+  let json = serde_json::to_vec_pretty(value).map_err(io::Error::other)?;
+  std::fs::write(path, json)
+}
+
+/// Writes a file to the export unless it's unchanged since the previous
+/// export, recording it in the manifest and the changes.
+fn export_file(
+  out: &Path,
+  previous: &Manifest,
+  manifest: &mut Manifest,
+  changes: &mut Changes,
+  changed: ChangedFile,
+  headers: &HeaderMap,
+  body: &[u8],
+) -> io::Result<()> {
+  let hash = export_hash(headers, body);
+  let target: PathBuf = out.join(&changed.file);
+
+  match previous.files.get(&changed.file) {
+    Some(previous_hash) if *previous_hash == hash && target.is_file() => {
+      changes.unchanged += 1;
+    }
+    previous_hash => {
+      if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+      }
+      std::fs::write(&target, body)?;
+      match previous_hash {
+        Some(_) => changes.updated.push(changed.clone()),
+        None => changes.added.push(changed.clone()),
+      }
+    }
+  }
+  manifest.files.insert(changed.file, hash);
+  Ok(())
+}
+
+/// Crawls the site behind `app` and exports it to `out`, only rewriting files
+/// which changed since the last export unless `full` is set.
+pub async fn export(
+  app: Router,
+  base_url: &str,
+  out: &Path,
+  full: bool,
+) -> io::Result<Changes> {
+  let previous = if full {
+    Manifest::default()
+  } else {
+    read_manifest(out)
+  };
+  let mut manifest = Manifest::default();
+  let mut changes = Changes::default();
+
+  let mut crawler = Crawler::new(app, base_url);
+  while let Some(crawled) = crawler.next().await {
+    let crawled = crawled.map_err(io::Error::other)?;
+    if !crawled.is_ok() {
+      continue;
+    }
+
+    let file = file_for_path(&crawled.path, &crawled.mime);
+    export_file(
+      out,
+      &previous,
+      &mut manifest,
+      &mut changes,
+      ChangedFile {
+        path: crawled.path,
+        file,
+      },
+      &crawled.parts.headers,
+      &crawled.body,
+    )?;
+  }
+
+  let not_found = crawler
+    .fetch(NOT_FOUND_PROBE.to_string())
+    .await
+    .map_err(io::Error::other)?;
+  if not_found.parts.status == StatusCode::NOT_FOUND {
+    export_file(
+      out,
+      &previous,
+      &mut manifest,
+      &mut changes,
+      ChangedFile {
+        path: format!("/{NOT_FOUND_FILE}"),
+        file: NOT_FOUND_FILE.to_string(),
+      },
+      &not_found.parts.headers,
+      &not_found.body,
+    )?;
+  } else {
+    log::warn!(
+      "`{NOT_FOUND_PROBE}` responded with {}, so there's no error page",
+      not_found.parts.status
+    );
+  }
+
+  for file in previous.files.keys() {
+    if manifest.files.contains_key(file) {
+      continue;
+    }
+    match std::fs::remove_file(out.join(file)) {
+      Ok(()) => {}
+      Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+      Err(e) => return Err(e),
+    }
+    changes.removed.push(ChangedFile {
+      path: path_for_file(file),
+      file: file.clone(),
+    });
+  }
+
+  std::fs::create_dir_all(out)?;
+  write_json(&out.join(MANIFEST_FILE), &manifest)?;
+  write_json(&out.join(CHANGES_FILE), &changes)?;
+  Ok(changes)
+}
@@ -1,56 +1,239 @@
+use std::sync::Arc;
+
 use axum::{
   body::Body,
   extract::State,
-  http::{Request, Response, StatusCode, Uri},
+  http::{
+    header, HeaderMap, HeaderValue, Method, Request, Response, StatusCode, Uri,
+  },
   response::{IntoResponse, Response as AxumResponse},
+  Extension,
 };
 use leptos::*;
 use site_app::App;
 use tower::ServiceExt;
 use tower_http::services::ServeDir;
 
+use crate::{mime::MimeTypes, state::AppState};
+
 pub async fn file_and_error_handler(
   uri: Uri,
-  State(options): State<LeptosOptions>,
+  State(state): State<AppState>,
+  Extension(mime_types): Extension<Arc<MimeTypes>>,
   req: Request<Body>,
 ) -> AxumResponse {
-  let root = options.site_root.clone();
+  let options = &state.leptos_options;
+  if !is_servable_path(uri.path()) {
+    return render_app(state, req).await;
+  }
+  // uploaded images live with the content rather than the build
+  let root = if uri.path().starts_with(crate::upload::IMAGES_PATH_PREFIX) {
+    state.config.content_dir.to_string_lossy().into_owned()
+  } else {
+    options.site_root.clone()
+  };
+
   let res = get_static_file(
     uri.clone(),
+    req.method().clone(),
+    req.headers().clone(),
     &root,
+    &mime_types,
     matches!(options.env, leptos_config::Env::PROD),
   )
   .await
   .unwrap();
 
-  if res.status() == StatusCode::OK {
+  if matches!(
+    res.status(),
+    StatusCode::OK
+      | StatusCode::PARTIAL_CONTENT
+      | StatusCode::NOT_MODIFIED
+      | StatusCode::RANGE_NOT_SATISFIABLE
+  ) {
     res.into_response()
   } else {
-    let handler =
-      leptos_axum::render_app_to_stream(options, move || view! { <App/> });
+    render_app(state, req).await
+  }
+}
+
+async fn render_app(state: AppState, req: Request<Body>) -> AxumResponse {
+  let options = state.leptos_options.clone();
+  let (zero_js, streamed) = (state.zero_js, state.streamed);
+  let context = move || state.provide_context();
+  // see `site_app::zero_js::ssr_mode`
+  if !streamed {
+    let handler = leptos_axum::render_app_async_with_context(
+      options,
+      context,
+      move || view! { <App/> },
+    );
+    handler(req).await.into_response()
+  } else if zero_js {
+    let handler = leptos_axum::render_app_to_stream_in_order_with_context(
+      options,
+      context,
+      move || view! { <App/> },
+    );
+    handler(req).await.into_response()
+  } else {
+    let handler = leptos_axum::render_app_to_stream_with_context(
+      options,
+      context,
+      move || view! { <App/> },
+    );
     handler(req).await.into_response()
   }
 }
-async fn get_static_file(
-  uri: Uri,
+
+/// Gives a page rendered whole the type streamed pages are given, which
+/// rendering it whole leaves out.
+pub async fn html_by_default(mut response: AxumResponse) -> AxumResponse {
+  if !response.headers().contains_key(header::CONTENT_TYPE) {
+    response.headers_mut().insert(
+      header::CONTENT_TYPE,
+      HeaderValue::from_static("text/html; charset=utf-8"),
+    );
+  }
+  response
+}
+
+/// Whether a request path may be served from the site root at all.
+///
+/// Paths are checked after percent-decoding. Any segment that starts with a
+/// dot is refused, which covers dotfiles, dot-directories, and `..`
+/// traversal, as are backslashes and NUL bytes which could be interpreted as
+/// separators or terminators by the OS. `ServeDir` never produces directory
+/// listings, so directories either resolve to their `index.html` or 404.
+fn is_servable_path(path: &str) -> bool {
+  let Ok(decoded) = percent_encoding::percent_decode_str(path).decode_utf8()
+  else {
+    return false;
+  };
+
+  !decoded.contains(['\\', '\0'])
+    && decoded.split('/').all(|segment| !segment.starts_with('.'))
+}
+
+/// Whether a partial response should be sent for a request with the given
+/// `If-Range` header, given the `Last-Modified` of the file being served.
+///
+/// Static files don't carry entity tags, so only dates can match, and they
+/// must match exactly (a strong comparison).
+fn if_range_matches(if_range: &str, last_modified: Option<&str>) -> bool {
+  if if_range.starts_with('"') || if_range.starts_with("W/") {
+    return false;
+  }
+  last_modified == Some(if_range)
+}
+
+/// Paths whose files never change without their URL changing too. The
+/// frontend bundle is hashed in release builds, apart from the lazily loaded
+/// one, fonts are only ever replaced under new names, and uploaded images are
+/// named after their contents.
+const IMMUTABLE_PREFIXES: &[&str] =
+  &["/pkg/", "/fonts/", crate::upload::IMAGES_PATH_PREFIX];
+/// How long-lived, never-changing files are cached for.
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+/// How everything else is cached: kept, but revalidated with its
+/// `Last-Modified` date before each use.
+const REVALIDATE_CACHE_CONTROL: &str = "public, max-age=0, must-revalidate";
+
+/// The `Cache-Control` header for a static file. Nothing is immutable outside
+/// release builds, where the bundle isn't hashed.
+fn cache_control(path: &str, release: bool) -> &'static str {
+  let is_lazy_bundle = path
+    .strip_prefix("/pkg/")
+    .is_some_and(|name| name.starts_with(site_app::LAZY_BUNDLE_NAME));
+  if release
+    && !is_lazy_bundle
+    && IMMUTABLE_PREFIXES.iter().any(|p| path.starts_with(p))
+  {
+    IMMUTABLE_CACHE_CONTROL
+  } else {
+    REVALIDATE_CACHE_CONTROL
+  }
+}
+
+async fn serve_file(
+  uri: &Uri,
+  method: &Method,
+  headers: &HeaderMap,
   root: &str,
-  cache: bool,
-) -> Result<Response<Body>, (StatusCode, String)> {
-  let req = Request::builder()
+) -> Result<Response<Body>, std::convert::Infallible> {
+  let mut req = Request::builder()
     .uri(uri.clone())
+    .method(method.clone())
     .body(Body::empty())
     .unwrap();
+  // forward the headers `ServeDir` cares about, i.e. ranges, conditionals,
+  // and encodings
+  *req.headers_mut() = headers.clone();
+
   // `ServeDir` implements `tower::Service` so we can call it with
-  // `tower::ServiceExt::oneshot` This path is relative to the cargo root
-  match ServeDir::new(root).oneshot(req).await {
+  // `tower::ServiceExt::oneshot` This path is relative to the cargo root.
+  // Precompressed variants are served as they are, and the compression layer
+  // leaves responses which already have an encoding alone.
+  ServeDir::new(root)
+    .precompressed_br()
+    .precompressed_gzip()
+    .oneshot(req)
+    .await
+    .map(IntoResponse::into_response)
+}
+
+async fn get_static_file(
+  uri: Uri,
+  method: Method,
+  mut headers: HeaderMap,
+  root: &str,
+  mime_types: &MimeTypes,
+  release: bool,
+) -> Result<Response<Body>, (StatusCode, String)> {
+  let mut result = serve_file(&uri, &method, &headers, root).await;
+
+  // `ServeDir` handles `Range` but not `If-Range`, so if the file has changed
+  // since the client's partial copy we re-request the whole thing
+  if let (Ok(res), Some(if_range)) = (&result, headers.get(header::IF_RANGE)) {
+    let last_modified = res
+      .headers()
+      .get(header::LAST_MODIFIED)
+      .and_then(|v| v.to_str().ok());
+    let if_range = if_range.to_str().unwrap_or_default();
+
+    if matches!(
+      res.status(),
+      StatusCode::PARTIAL_CONTENT | StatusCode::RANGE_NOT_SATISFIABLE
+    ) && !if_range_matches(if_range, last_modified)
+    {
+      headers.remove(header::RANGE);
+      result = serve_file(&uri, &method, &headers, root).await;
+    }
+  }
+
+  match result {
     Ok(res) => {
-      let mut response = res.into_response();
-      if cache {
-        response.headers_mut().insert(
-          "Cache-Control",
-          "public, max-age=31536000, immutable".parse().unwrap(),
-        );
+      let mut response = res;
+      if matches!(
+        response.status(),
+        StatusCode::OK | StatusCode::PARTIAL_CONTENT
+      ) {
+        let content_type = mime_types.for_path(uri.path()).or_else(|| {
+          response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(MimeTypes::with_charset)
+        });
+        if let Some(content_type) = content_type {
+          response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, content_type);
+        }
       }
+      response.headers_mut().insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(cache_control(uri.path(), release)),
+      );
       Ok(response)
     }
     Err(err) => Err((
@@ -59,3 +242,139 @@ async fn get_static_file(
     )),
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// A fresh directory laid out like the content directory, with a private
+  /// post beside the uploaded images, which are served from it.
+  fn content_dir() -> std::path::PathBuf {
+    let dir = std::env::temp_dir()
+      .join(format!("fileserv-test-{}", uuid::Uuid::new_v4().simple()));
+    std::fs::create_dir_all(dir.join("posts")).unwrap();
+    std::fs::create_dir_all(dir.join("images/some-post")).unwrap();
+    std::fs::write(
+      dir.join("posts/private.md"),
+      "---\ntitle: Private\nprivate: true\n---\nsecret",
+    )
+    .unwrap();
+    std::fs::write(dir.join("images/some-post/photo.png"), "png").unwrap();
+    std::fs::write(dir.join("images/.hidden"), "hidden").unwrap();
+    dir
+  }
+
+  async fn get(root: &std::path::Path, path: &str) -> StatusCode {
+    serve_file(
+      &path.parse().unwrap(),
+      &Method::GET,
+      &HeaderMap::new(),
+      &root.to_string_lossy(),
+    )
+    .await
+    .unwrap()
+    .status()
+  }
+
+  #[test]
+  fn ordinary_paths_are_servable() {
+    for path in [
+      "/",
+      "/favicon.ico",
+      "/pkg/site.wasm",
+      "/fonts/iosevka.woff2",
+      "/images/some-post/photo.png",
+      "/images/a%20photo.png",
+      "/post/some.post",
+    ] {
+      assert!(is_servable_path(path), "`{path}` is refused");
+    }
+  }
+
+  #[test]
+  fn dotfiles_are_refused() {
+    for path in [
+      "/.env",
+      "/.git/config",
+      "/pkg/.hidden",
+      "/images/.well-hidden/photo.png",
+      "/%2eenv",
+      "/%2Egit/HEAD",
+    ] {
+      assert!(!is_servable_path(path), "`{path}` is servable");
+    }
+  }
+
+  #[test]
+  fn traversal_is_refused() {
+    for path in [
+      "/..",
+      "/../Cargo.toml",
+      "/images/../posts/private.md",
+      "/images/some-post/../../posts/private.md",
+      "/images/%2e%2e/posts/private.md",
+      "/images/%2E%2E%2Fposts%2Fprivate.md",
+      "/images/..%2fposts/private.md",
+    ] {
+      assert!(!is_servable_path(path), "`{path}` is servable");
+    }
+  }
+
+  #[test]
+  fn separators_and_terminators_are_refused() {
+    for path in [
+      "/images\\..\\posts\\private.md",
+      "/images%5c..%5cposts%5cprivate.md",
+      "/images/photo.png%00.md",
+      // not UTF-8 once decoded
+      "/images/%ff.png",
+    ] {
+      assert!(!is_servable_path(path), "`{path}` is servable");
+    }
+  }
+
+  #[tokio::test]
+  async fn directories_are_never_listed() {
+    let dir = content_dir();
+    for path in ["/images", "/images/", "/images/some-post/", "/posts/"] {
+      assert_ne!(get(&dir, path).await, StatusCode::OK, "`{path}` is listed");
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[tokio::test]
+  async fn only_images_are_served_from_the_content_directory() {
+    let dir = content_dir();
+    assert_eq!(
+      get(&dir, "/images/some-post/photo.png").await,
+      StatusCode::OK
+    );
+    // refused before reaching the file server, and not found even if it
+    // were reached
+    for path in [
+      "/images/../posts/private.md",
+      "/images/%2e%2e/posts/private.md",
+    ] {
+      assert!(!is_servable_path(path), "`{path}` is servable");
+      assert_ne!(get(&dir, path).await, StatusCode::OK, "`{path}` is served");
+    }
+    assert!(!is_servable_path("/images/.hidden"));
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn only_the_hashed_bundle_is_immutable() {
+    assert_eq!(
+      cache_control("/pkg/site.wasm", true),
+      IMMUTABLE_CACHE_CONTROL
+    );
+    assert_eq!(
+      cache_control("/pkg/site.wasm", false),
+      REVALIDATE_CACHE_CONTROL
+    );
+    // the lazily loaded bundle keeps its name between releases
+    for path in ["/pkg/site-lazy.js", "/pkg/site-lazy_bg.wasm"] {
+      assert_eq!(cache_control(path, true), REVALIDATE_CACHE_CONTROL);
+    }
+  }
+}
@@ -0,0 +1,208 @@
+//! The `generate-fixtures` command, which writes an archive of synthetic
+//! posts for measuring how the site performs with far more content than it
+//! has.
+//!
+//! Posts are generated from a seed, so the same arguments always produce the
+//! same archive and measurements can be compared between changes.
+
+use std::{fmt::Write as _, path::Path};
+
+use clap::ValueEnum;
+
+/// What the generated posts are made of.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum FixtureKind {
+  /// Prose interleaved with highlighted code blocks.
+  Code,
+  /// Prose interleaved with figures.
+  Images,
+  /// Prose with headings, lists, footnotes and tables.
+  LongForm,
+  /// Each of the other kinds in turn.
+  Mixed,
+}
+
+/// The words prose is made of, separated by spaces.
+const WORDS: &str =
+  "the a server render cache post island signal request response markdown \
+   header type trait async future borrow lifetime crate module build deploy \
+   content reader browser query index search export static dynamic compile \
+   runtime thread channel value and of to in is that with for it on but \
+   quickly carefully eventually rarely always never";
+
+/// A small, seeded pseudo-random generator. Statistical quality doesn't
+/// matter here, only that it's reproducible.
+struct Rng(u64);
+
+impl Rng {
+  fn next(&mut self) -> u64 {
+    // xorshift64*, which never leaves a non-zero state
+    self.0 ^= self.0 >> 12;
+    self.0 ^= self.0 << 25;
+    self.0 ^= self.0 >> 27;
+    self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+  }
+
+  /// A number in `0..n`.
+  fn below(&mut self, n: usize) -> usize { (self.next() % n as u64) as usize }
+
+  fn word(&mut self) -> &'static str {
+    let count = WORDS.split(' ').count();
+    WORDS.split(' ').nth(self.below(count)).unwrap_or_default()
+  }
+}
+
+/// Writes a sentence of between 6 and 20 words.
+fn sentence(rng: &mut Rng, out: &mut String) -> usize {
+  let count = 6 + rng.below(15);
+  for i in 0..count {
+    let word = rng.word();
+    if i == 0 {
+      let mut chars = word.chars();
+      out.extend(chars.next().map(|c| c.to_ascii_uppercase()));
+      out.push_str(chars.as_str());
+    } else {
+      out.push(' ');
+      out.push_str(word);
+    }
+  }
+  out.push_str(". ");
+  count
+}
+
+/// Writes a paragraph, sometimes linking to an earlier post. Returns how many
+/// words it wrote.
+fn paragraph(rng: &mut Rng, out: &mut String, index: usize) -> usize {
+  let mut words = 0;
+  for _ in 0..2 + rng.below(4) {
+    words += sentence(rng, out);
+  }
+  if index > 0 && rng.below(4) == 0 {
+    let _ = write!(out, "See also [[fixture-{}]].", rng.below(index));
+  }
+  out.push_str("\n\n");
+  words
+}
+
+fn code_block(rng: &mut Rng, out: &mut String) {
+  out.push_str("```rust\n");
+  for f in 0..1 + rng.below(3) {
+    let (name, arg) = (rng.word(), rng.word());
+    let _ = writeln!(
+      out,
+      "/// Handles the {name}.\npub fn {name}_{f}({arg}: &str) -> \
+       Option<usize> {{\n  let count = {arg}.len() * {};\n  if count > {} \
+       {{\n    return None;\n  }}\n  Some(count)\n}}\n",
+      1 + rng.below(9),
+      rng.below(1000),
+    );
+  }
+  out.push_str("```\n\n");
+}
+
+fn figure(rng: &mut Rng, out: &mut String) {
+  let _ = writeln!(
+    out,
+    "{{{{< figure src=\"/fixtures/image-{}.png\" alt=\"A {} {}\" \
+     caption=\"The {} {}.\" >}}}}\n",
+    rng.below(100),
+    rng.word(),
+    rng.word(),
+    rng.word(),
+    rng.word(),
+  );
+}
+
+fn long_form_block(rng: &mut Rng, out: &mut String, footnotes: &mut usize) {
+  match rng.below(4) {
+    0 => {
+      let _ = writeln!(out, "## The {} {}\n", rng.word(), rng.word());
+    }
+    1 => {
+      for _ in 0..2 + rng.below(4) {
+        let _ = writeln!(out, "- The {} {}", rng.word(), rng.word());
+      }
+      out.push('\n');
+    }
+    2 => {
+      *footnotes += 1;
+      let _ = writeln!(
+        out,
+        "Consider the {}.[^{footnotes}]\n\n[^{footnotes}]: The {} is {}.\n",
+        rng.word(),
+        rng.word(),
+        rng.word(),
+      );
+    }
+    _ => {
+      out.push_str("| Name | Value |\n| ---- | ----- |\n");
+      for _ in 0..2 + rng.below(4) {
+        let _ = writeln!(out, "| {} | {} |", rng.word(), rng.below(1000));
+      }
+      out.push('\n');
+    }
+  }
+}
+
+/// Generates the body of the post at `index`, of about `words` words of
+/// prose.
+fn body(
+  rng: &mut Rng,
+  kind: FixtureKind,
+  index: usize,
+  words: usize,
+) -> String {
+  let kind = match kind {
+    FixtureKind::Mixed => [
+      FixtureKind::Code,
+      FixtureKind::Images,
+      FixtureKind::LongForm,
+    ][index % 3],
+    kind => kind,
+  };
+
+  let mut out = String::new();
+  let mut written = 0;
+  let mut footnotes = 0;
+  while written < words {
+    written += paragraph(rng, &mut out, index);
+    match kind {
+      FixtureKind::Code => code_block(rng, &mut out),
+      FixtureKind::Images => figure(rng, &mut out),
+      _ => long_form_block(rng, &mut out, &mut footnotes),
+    }
+  }
+  out
+}
+
+/// Writes `count` synthetic posts of about `words` words each to `out`, as
+/// `fixture-<n>.md`. Returns how many bytes of markdown were written.
+pub fn generate(
+  out: &Path,
+  count: usize,
+  kind: FixtureKind,
+  words: usize,
+  seed: u64,
+) -> std::io::Result<usize> {
+  std::fs::create_dir_all(out)?;
+  let mut rng = Rng(seed.max(1));
+  // spread the posts a day apart, ending at a fixed date so that reruns match
+  let last_date =
+    time::Date::from_calendar_date(2024, time::Month::January, 1).unwrap();
+
+  let mut bytes = 0;
+  for index in 0..count {
+    let date = last_date - time::Duration::days((count - index) as i64);
+    let post = format!(
+      "---\ntitle: \"Fixture Post {index}\"\nwritten_on: \
+       \"{:04}.{:02}.{:02}\"\npublic: true\n---\n\n{}",
+      date.year(),
+      u8::from(date.month()),
+      date.day(),
+      body(&mut rng, kind, index, words),
+    );
+    std::fs::write(out.join(format!("fixture-{index}.md")), &post)?;
+    bytes += post.len();
+  }
+  Ok(bytes)
+}
@@ -0,0 +1,174 @@
+use std::sync::Arc;
+
+use axum::{
+  middleware,
+  routing::{get, post},
+  Extension, Router,
+};
+use fileserv::file_and_error_handler;
+use leptos_axum::{
+  generate_route_list_with_exclusions_and_ssg_and_context, LeptosRoutes,
+};
+use site_app::{theme::ColorTheme, App};
+use state::AppState;
+use tower_http::compression::CompressionLayer;
+
+pub mod activitypub;
+pub mod analytics;
+pub mod auth;
+pub mod check;
+pub mod check_content;
+pub mod cli;
+pub mod conditional;
+pub mod crawl;
+pub mod export;
+pub mod fileserv;
+pub mod generate_fixtures;
+pub mod limits;
+pub mod listen;
+pub mod live;
+pub mod mime;
+pub mod new_post;
+pub mod newsletter;
+pub mod normalize;
+pub mod push;
+pub mod rate_limit;
+pub mod redirects;
+pub mod search;
+pub mod security;
+pub mod spam;
+pub mod state;
+pub mod telemetry;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod theme;
+pub mod tls;
+pub mod upload;
+pub mod views;
+pub mod warc;
+pub mod webmentions;
+pub mod zero_js;
+
+/// Builds the site's router.
+pub fn router(state: AppState) -> Router {
+  let context = {
+    let state = state.clone();
+    move || state.provide_context()
+  };
+  // with the context, since it decides how some routes are streamed
+  let (routes, _) = generate_route_list_with_exclusions_and_ssg_and_context(
+    App,
+    None,
+    context.clone(),
+  );
+
+  let router = Router::new()
+    .route(
+      "/api/*fn_name",
+      post({
+        let context = context.clone();
+        move |req| leptos_axum::handle_server_fns_with_context(context, req)
+      }),
+    )
+    .route(site_app::live::EVENTS_PATH, get(live::events_handler))
+    .route(
+      site_app::search::SEARCH_INDEX_PATH,
+      get(search::search_index),
+    )
+    .route(
+      ColorTheme::Dark.code_stylesheet(),
+      get(|| theme::code_stylesheet(ColorTheme::Dark)),
+    )
+    .route(
+      ColorTheme::Light.code_stylesheet(),
+      get(|| theme::code_stylesheet(ColorTheme::Light)),
+    )
+    .route(
+      site_app::analytics::BEACON_PATH,
+      post(analytics::beacon)
+        .with_state(analytics::Analytics::new(state.clone())),
+    )
+    .route(
+      upload::UPLOAD_PATH,
+      post(upload::upload_images).route_layer(middleware::from_fn_with_state(
+        state.clone(),
+        auth::require_admin,
+      )),
+    )
+    .route(site_app::auth::github::START_PATH, get(auth::github_start))
+    .route(
+      site_app::auth::github::CALLBACK_PATH,
+      get(auth::github_callback),
+    )
+    .route(
+      site_app::activitypub::WEBFINGER_PATH,
+      get(activitypub::webfinger),
+    )
+    .route(site_app::activitypub::ACTOR_PATH, get(activitypub::actor))
+    .route(site_app::activitypub::INBOX_PATH, post(activitypub::inbox))
+    .route(site_app::activitypub::OUTBOX_PATH, get(activitypub::outbox))
+    .route(
+      site_app::activitypub::FOLLOWERS_PATH,
+      get(activitypub::followers),
+    )
+    .route(
+      &format!("{}/:path", site_app::activitypub::ARTICLES_PATH),
+      get(activitypub::article),
+    )
+    .leptos_routes_with_context(&state, routes, context, App)
+    .fallback(file_and_error_handler)
+    .layer(Extension(Arc::new(mime::MimeTypes::from_env())));
+  // pages rendered whole aren't given a type, so first of all
+  let router = match state.streamed {
+    true => router,
+    false => router.layer(middleware::map_response(fileserv::html_by_default)),
+  };
+  // innermost otherwise, so that nothing sees the scripts
+  let router = if state.zero_js {
+    router.layer(middleware::from_fn(zero_js::strip_scripts))
+  } else {
+    router
+  };
+
+  // inside the conditional requests, so that pages revalidated without being
+  // rendered again aren't counted
+  let router = if state.config.views.count {
+    router.layer(middleware::from_fn_with_state(
+      views::ViewCounter::new(state.clone()),
+      views::count_views,
+    ))
+  } else {
+    router
+  };
+
+  router
+    // inside compression, so pages are tagged by what was rendered
+    .layer(middleware::from_fn_with_state(
+      state.clone(),
+      conditional::conditional,
+    ))
+    .layer(middleware::from_fn_with_state(
+      state.clone(),
+      limits::limit_requests,
+    ))
+    .layer(middleware::from_fn_with_state(
+      rate_limit::RateLimiter::new(state.config.rate_limit.clone()),
+      rate_limit::rate_limit,
+    ))
+    .layer(middleware::from_fn_with_state(
+      state.clone(),
+      redirects::redirect,
+    ))
+    // outside the redirects, so that they only need to list canonical paths
+    .layer(middleware::from_fn_with_state(
+      state.clone(),
+      normalize::normalize_paths,
+    ))
+    .layer(middleware::from_fn_with_state(
+      state.clone(),
+      security::security_headers,
+    ))
+    .layer(CompressionLayer::new())
+    .layer(middleware::from_fn(telemetry::log_requests))
+    .with_state(state)
+}
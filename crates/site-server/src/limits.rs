@@ -0,0 +1,81 @@
+//! Timeouts and body size limits for every request, see [`LimitsConfig`], so
+//! that slow or oversized requests are turned away rather than tying up the
+//! server. How long clients have to send their headers is limited when
+//! connections are accepted, in [`listen`](crate::listen).
+
+use std::time::Duration;
+
+use axum::{
+  body::Body,
+  extract::{Request, State},
+  http::{header, StatusCode},
+  middleware::Next,
+  response::{IntoResponse, Response},
+};
+use http_body_util::Limited;
+use leptos::server_fn::ServerFn;
+use site_app::{
+  config::{LimitsConfig, SiteConfig},
+  editor::SavePost,
+};
+
+/// The paths under which uploads are accepted, with the upload limits rather
+/// than the usual ones.
+pub const UPLOAD_PATH_PREFIX: &str = "/upload/";
+
+/// The timeout and body limit of a request to `path`. Posts saved from the
+/// admin editor count as uploads, since they're far bigger than other server
+/// fns' arguments.
+fn limits_of(limits: &LimitsConfig, path: &str) -> (Duration, u64) {
+  if path.starts_with(UPLOAD_PATH_PREFIX) || path == SavePost::PATH {
+    (
+      Duration::from_secs(limits.upload_request_timeout_secs),
+      limits.upload_body_bytes,
+    )
+  } else {
+    (
+      Duration::from_secs(limits.request_timeout_secs),
+      limits.body_bytes,
+    )
+  }
+}
+
+/// Answers requests whose bodies are too big with a `413`, and requests which
+/// take too long with a `408`.
+pub async fn limit_requests(
+  State(config): State<SiteConfig>,
+  request: Request,
+  next: Next,
+) -> Response {
+  let (timeout, body_bytes) = limits_of(&config.limits, request.uri().path());
+
+  // bodies that say up front they're too big are turned away unread, and the
+  // rest are cut off once they pass the limit
+  let length = request
+    .headers()
+    .get(header::CONTENT_LENGTH)
+    .and_then(|v| v.to_str().ok())
+    .and_then(|v| v.parse::<u64>().ok());
+  if length.is_some_and(|length| length > body_bytes) {
+    return (
+      StatusCode::PAYLOAD_TOO_LARGE,
+      format!("Requests can be at most {body_bytes} bytes."),
+    )
+      .into_response();
+  }
+  let request = request.map(|body| {
+    Body::new(Limited::new(
+      body,
+      usize::try_from(body_bytes).unwrap_or(usize::MAX),
+    ))
+  });
+
+  match tokio::time::timeout(timeout, next.run(request)).await {
+    Ok(response) => response,
+    Err(_) => (
+      StatusCode::REQUEST_TIMEOUT,
+      format!("Requests have to finish within {timeout:?}."),
+    )
+      .into_response(),
+  }
+}
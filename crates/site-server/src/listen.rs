@@ -0,0 +1,77 @@
+//! Accepting connections, over TLS or not. Unlike `axum::serve`, clients only
+//! get so long to send each request's headers, so that slow connections
+//! dribbling them out can't be held open forever.
+
+use std::time::Duration;
+
+use axum::{extract::ConnectInfo, http::Request, Router};
+use hyper_util::{
+  rt::{TokioExecutor, TokioIo, TokioTimer},
+  server::conn::auto::Builder,
+  service::TowerToHyperService,
+};
+use tokio::{
+  io::{AsyncRead, AsyncWrite},
+  net::TcpListener,
+};
+use tokio_rustls::TlsAcceptor;
+use tower::ServiceExt;
+
+/// A connection, encrypted or not.
+trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Io for T {}
+
+/// Serves the app to every connection on `listener`, over HTTPS if there's
+/// an `acceptor`.
+pub async fn serve(
+  listener: TcpListener,
+  app: Router,
+  acceptor: Option<TlsAcceptor>,
+  header_read_timeout: Duration,
+) {
+  let mut builder = Builder::new(TokioExecutor::new());
+  builder
+    .http1()
+    .timer(TokioTimer::new())
+    .header_read_timeout(header_read_timeout);
+
+  loop {
+    let (stream, remote) = match listener.accept().await {
+      Ok(connection) => connection,
+      Err(e) => {
+        log::warn!("failed to accept a connection: {e}");
+        continue;
+      }
+    };
+    // as `axum::serve` would, for rate limiting
+    let app = app.clone().map_request(move |mut request: Request<_>| {
+      request.extensions_mut().insert(ConnectInfo(remote));
+      request
+    });
+    let builder = builder.clone();
+    let acceptor = acceptor.clone();
+    tokio::spawn(async move {
+      let stream: Box<dyn Io> = match acceptor {
+        // failed handshakes are mostly scanners and clients giving up
+        Some(acceptor) => match acceptor.accept(stream).await {
+          Ok(stream) => Box::new(stream),
+          Err(e) => {
+            log::debug!("TLS handshake with {remote} failed: {e}");
+            return;
+          }
+        },
+        None => Box::new(stream),
+      };
+      if let Err(e) = builder
+        .serve_connection_with_upgrades(
+          TokioIo::new(stream),
+          TowerToHyperService::new(app),
+        )
+        .await
+      {
+        log::debug!("connection with {remote} failed: {e}");
+      }
+    });
+  }
+}
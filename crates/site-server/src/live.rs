@@ -0,0 +1,154 @@
+//! The server-sent events endpoint, and the content watcher which feeds it
+//! and keeps the post index up to date.
+
+use std::{convert::Infallible, time::Duration};
+
+use axum::{
+  extract::State,
+  response::sse::{Event, KeepAlive, Sse},
+};
+use futures::stream::{self, Stream};
+use site_app::{
+  live::LiveEvent,
+  post_index::{PostIndex, SourceChange},
+  posts::try_parse_frontmatter,
+};
+use site_db::{storage::NotifiedPostStore, Database};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// How many events a client may fall behind by before it's disconnected.
+/// Disconnected clients reconnect on their own, so a slow client costs at most
+/// this many buffered events rather than holding up everyone else.
+pub const CLIENT_BUFFER: usize = 64;
+/// How often the content directory is checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Broadcasts live events to every subscribed client.
+#[derive(Clone, Debug)]
+pub struct LiveEvents {
+  sender: broadcast::Sender<LiveEvent>,
+}
+
+impl Default for LiveEvents {
+  fn default() -> Self {
+    LiveEvents {
+      sender: broadcast::channel(CLIENT_BUFFER).0,
+    }
+  }
+}
+
+impl LiveEvents {
+  /// Sends an event to every subscribed client.
+  pub fn publish(&self, event: LiveEvent) {
+    // an error just means nobody is listening
+    let _ = self.sender.send(event);
+  }
+
+  /// Subscribes to every event published from now on.
+  pub fn subscribe(&self) -> broadcast::Receiver<LiveEvent> {
+    self.sender.subscribe()
+  }
+}
+
+/// Streams live events to the client until it disconnects or falls more than
+/// [`CLIENT_BUFFER`] events behind.
+pub async fn events_handler(
+  State(events): State<LiveEvents>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+  let receiver = events.subscribe();
+
+  let stream = stream::unfold(receiver, |mut receiver| async move {
+    loop {
+      match receiver.recv().await {
+        Ok(event) => {
+          match Event::default().event(event.name()).json_data(&event) {
+            Ok(sse_event) => return Some((Ok(sse_event), receiver)),
+            Err(e) => log::error!("failed to encode live event: {e}"),
+          }
+        }
+        Err(RecvError::Lagged(skipped)) => {
+          log::warn!("disconnecting live event client {skipped} events behind");
+          return None;
+        }
+        Err(RecvError::Closed) => return None,
+      }
+    }
+  });
+
+  Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// Polls the content directory, updating the post index and publishing an
+/// event for each post that changes and each post that's published.
+///
+/// A post is published once it's public, whether it's new or a draft made
+/// public, and each is announced once, as the [`NotifiedPostStore`] records.
+/// Posts published while the server was down are announced when it starts,
+/// except the first time, when every post already public is taken to have
+/// been announced.
+pub async fn watch_content(events: LiveEvents, db: Database, index: PostIndex) {
+  let mut interval = tokio::time::interval(WATCH_INTERVAL);
+  // the first tick is immediate, and waiting for the next gives everything
+  // sending announcements a moment to subscribe
+  interval.tick().await;
+  interval.tick().await;
+  match db.any_notified().await {
+    Ok(any_notified) => {
+      let paths = index.sources().into_iter().map(|(path, _)| path);
+      announce_published(&events, &db, &index, paths, any_notified).await;
+    }
+    Err(e) => log::error!("failed to find which posts were announced: {e}"),
+  }
+
+  loop {
+    interval.tick().await;
+    let changes = tokio::task::block_in_place(|| index.refresh());
+
+    let mut changed = Vec::new();
+    for change in changes {
+      match change {
+        SourceChange::Added { path, .. } => changed.push(path),
+        SourceChange::Updated { path } => {
+          events.publish(LiveEvent::PostUpdated { path: path.clone() });
+          changed.push(path);
+        }
+        SourceChange::Removed { .. } => {}
+      }
+    }
+    announce_published(&events, &db, &index, changed, true).await;
+  }
+}
+
+/// Publishes a [`LiveEvent::NewPost`] for each of the posts at `paths` which
+/// is public and hasn't been announced, recording that it has been. Unless
+/// `announce` is set, they're only recorded.
+async fn announce_published(
+  events: &LiveEvents,
+  db: &Database,
+  index: &PostIndex,
+  paths: impl IntoIterator<Item = String>,
+  announce: bool,
+) {
+  for path in paths {
+    let Some((metadata, _)) = index
+      .source(&path)
+      .and_then(|input| try_parse_frontmatter(&input).ok())
+      .filter(|(metadata, _)| metadata.public)
+    else {
+      continue;
+    };
+    match db.record_notified(&path, &metadata.title).await {
+      Ok(true) if announce => {
+        log::info!("announcing `{path}`");
+        events.publish(LiveEvent::NewPost {
+          path,
+          title: metadata.title,
+        })
+      }
+      Ok(_) => {}
+      Err(e) => {
+        log::error!("failed to record that `{path}` was announced: {e}")
+      }
+    }
+  }
+}
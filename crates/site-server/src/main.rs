@@ -1,31 +1,327 @@
-use axum::{routing::post, Router};
-use fileserv::file_and_error_handler;
-use leptos::*;
-use leptos_axum::{generate_route_list, LeptosRoutes};
-use site_app::*;
-use tower_http::compression::CompressionLayer;
+use std::time::Duration;
 
-pub mod fileserv;
+use axum::Router;
+use clap::Parser;
+use leptos::*;
+use site_app::{
+  config::{site_config, SiteConfig},
+  post_index::PostIndex,
+};
+use site_db::{migrations, Database};
+use site_server::{state::AppState, *};
 
 #[tokio::main]
 async fn main() {
-  simple_logger::init_with_level(log::Level::Info)
-    .expect("couldn't initialize logging");
+  let cli = cli::Cli::parse();
+
+  telemetry::init();
+
+  match SiteConfig::load() {
+    Ok(config) => config.install(),
+    Err(e) => {
+      log::error!("refusing to start: {e}");
+      std::process::exit(1);
+    }
+  }
+
+  match cli.command.unwrap_or(cli::Command::Serve) {
+    cli::Command::Serve => serve().await,
+    cli::Command::Check => {
+      let report = check::run().await;
+      println!("{report}");
+      if !report.passed() {
+        std::process::exit(1);
+      }
+    }
+    cli::Command::CheckContent => {
+      let report = check_content::run();
+      println!("{report}");
+      if !report.passed() {
+        std::process::exit(1);
+      }
+    }
+    cli::Command::GenerateFixtures {
+      count,
+      out,
+      kind,
+      words,
+      seed,
+    } => match generate_fixtures::generate(&out, count, kind, words, seed) {
+      Ok(bytes) => {
+        println!("wrote {count} posts ({bytes} bytes) to `{}`", out.display())
+      }
+      Err(e) => {
+        log::error!("failed to write to `{}`: {e}", out.display());
+        std::process::exit(1);
+      }
+    },
+    cli::Command::Migrate { dry_run } => migrate(dry_run).await,
+    cli::Command::HashPassword => {
+      let mut password = String::new();
+      if let Err(e) = std::io::stdin().read_line(&mut password) {
+        log::error!("failed to read the password: {e}");
+        std::process::exit(1);
+      }
+      let password = password.trim_end_matches(['\r', '\n']);
+      if password.is_empty() {
+        log::error!("the password can't be empty");
+        std::process::exit(1);
+      }
+      println!("{}", site_app::auth::hash_password(password));
+    }
+    cli::Command::NewPost { title } => match new_post::create(&title) {
+      Ok(path) => println!("created `{}`", path.display()),
+      Err(e) => {
+        log::error!("{e}");
+        std::process::exit(1);
+      }
+    },
+    cli::Command::Spam { command } => spam::run(command).await,
+    cli::Command::ExportWarc { out, base_url } => {
+      export_warc(out, base_url).await
+    }
+    cli::Command::ExportStatic {
+      out,
+      base_url,
+      full,
+    } => export_static(out, base_url, full).await,
+  }
+}
+
+async fn migrate(dry_run: bool) {
+  let db = Database::connect_from_env()
+    .await
+    .expect("failed to connect to the database");
+
+  if dry_run {
+    let status = migrations::status(&db)
+      .await
+      .expect("failed to read migration status");
+    println!("{} migrations applied", status.applied.len());
+    for migration in status.pending.iter() {
+      println!("pending: {migration}");
+    }
+  } else {
+    let applied = migrations::migrate(&db)
+      .await
+      .expect("failed to apply migrations");
+    println!("applied {} migrations", applied.len());
+  }
+}
+
+/// Builds a router for the export commands, which render the site without
+/// serving it.
+async fn export_router() -> Router {
+  let db = Database::connect_from_env()
+    .await
+    .expect("failed to connect to the database");
+  migrations::migrate(&db)
+    .await
+    .expect("failed to apply migrations");
 
   let conf = get_configuration(None).await.unwrap();
-  let leptos_options = conf.leptos_options;
-  let addr = leptos_options.site_addr;
-  let routes = generate_route_list(App);
-
-  let app = Router::new()
-    .route("/api/*fn_name", post(leptos_axum::handle_server_fns))
-    .leptos_routes(&leptos_options, routes, App)
-    .fallback(file_and_error_handler)
-    .layer(CompressionLayer::new())
-    .with_state(leptos_options);
-
-  log::info!("listening on http://{}", &addr);
-  axum::serve(tokio::net::TcpListener::bind(&addr).await.unwrap(), app)
+  router(AppState {
+    leptos_options: conf.leptos_options,
+    config: site_config().clone(),
+    db,
+    index: PostIndex::load(),
+    live_events: live::LiveEvents::default(),
+    push_config: None,
+    activitypub: None,
+    redirects: redirects::Redirects::default(),
+    zero_js: zero_js::from_env(),
+    // so that exports of the same content are the same
+    streamed: false,
+  })
+}
+
+async fn export_warc(
+  out: Option<std::path::PathBuf>,
+  base_url: Option<String>,
+) {
+  let out = out.unwrap_or_else(warc::default_archive_path);
+  let base_url = base_url.unwrap_or_else(|| site_config().base_url.clone());
+  match warc::export(export_router().await, &base_url, &out).await {
+    Ok(summary) => println!("{summary}"),
+    Err(e) => {
+      log::error!("failed to write `{}`: {e}", out.display());
+      std::process::exit(1);
+    }
+  }
+}
+
+async fn export_static(
+  out: std::path::PathBuf,
+  base_url: Option<String>,
+  full: bool,
+) {
+  let base_url = base_url.unwrap_or_else(|| site_config().base_url.clone());
+  match export::export(export_router().await, &base_url, &out, full).await {
+    Ok(changes) => {
+      site_app::render_cache::prune();
+      println!("exported to `{}`: {changes}", out.display())
+    }
+    Err(e) => {
+      log::error!("failed to export to `{}`: {e}", out.display());
+      std::process::exit(1);
+    }
+  }
+}
+
+async fn serve() {
+  let db = Database::connect_from_env()
     .await
-    .unwrap();
+    .expect("failed to connect to the database");
+  if let Err(e) = migrations::migrate(&db).await {
+    log::error!("refusing to start: {e}");
+    std::process::exit(1);
+  }
+
+  // rendered first, so that the checks below render from the cache
+  let index = PostIndex::load();
+  for dead_link in site_app::links::find_dead_links() {
+    log::warn!("{dead_link}");
+  }
+  for (path, warning) in site_app::posts::find_render_warnings() {
+    log::warn!("in post `{path}`: {warning}");
+  }
+  // every post has just been rendered, so the rest of the cache is stale
+  site_app::render_cache::prune();
+
+  let conf = get_configuration(None).await.unwrap();
+  let leptos_options = conf.leptos_options;
+  let mut config = site_config().clone();
+  // `cargo leptos watch` reloads the page over a websocket
+  if matches!(leptos_options.env, leptos_config::Env::DEV) {
+    config.security.connect_src.push("ws:".to_string());
+  }
+  let addr = config.bind_address.unwrap_or(leptos_options.site_addr);
+  let tls = config.tls.as_ref().map(|tls| match tls::acceptor(tls) {
+    Ok(acceptor) => (acceptor, tls.redirect_address),
+    Err(e) => {
+      log::error!("refusing to start: {e}");
+      std::process::exit(1);
+    }
+  });
+
+  let live_events = live::LiveEvents::default();
+
+  let vapid = match push::VapidConfig::from_env() {
+    Ok(vapid) => vapid,
+    Err(e) => {
+      log::error!("refusing to start: invalid VAPID key: {e}");
+      std::process::exit(1);
+    }
+  };
+  let push_config = vapid.as_ref().map(|vapid| site_app::push::PushConfig {
+    public_key: vapid.public_key(),
+  });
+  if let Some(vapid) = vapid {
+    tokio::spawn(push::notify_new_posts(
+      live_events.clone(),
+      db.clone(),
+      vapid,
+    ));
+  }
+
+  if config.webmentions.send {
+    tokio::spawn(webmentions::send_for_changed_posts(
+      live_events.clone(),
+      db.clone(),
+      index.clone(),
+    ));
+  }
+
+  let activitypub = config.activitypub.enabled.then(|| {
+    match site_app::activitypub::ActorKey::load_or_generate(
+      &config.activitypub.key_file,
+    ) {
+      Ok(key) => key,
+      Err(e) => {
+        log::error!("refusing to start: invalid ActivityPub key: {e}");
+        std::process::exit(1);
+      }
+    }
+  });
+  if let Some(key) = &activitypub {
+    tokio::spawn(activitypub::deliver_new_posts(
+      live_events.clone(),
+      db.clone(),
+      index.clone(),
+      key.clone(),
+    ));
+  }
+
+  let admin_configured =
+    config.admin.password_hash.is_some() || config.admin.github.is_some();
+  if admin_configured && config.session_secret.is_none() {
+    log::error!(
+      "refusing to start the admin area: set `SITE_SESSION_SECRET` to a \
+       random secret to sign sessions with"
+    );
+  }
+
+  // the signup isn't offered without both, so there's nobody to send to
+  let newsletter_key = site_app::auth::signing_key(&config);
+  if let (Some(smtp), Some(key)) = (&config.smtp, newsletter_key) {
+    tokio::spawn(newsletter::send_new_posts(
+      live_events.clone(),
+      db.clone(),
+      index.clone(),
+      smtp.clone(),
+      key,
+    ));
+  }
+
+  // after everything announcing new posts, so that it's listening
+  tokio::spawn(live::watch_content(
+    live_events.clone(),
+    db.clone(),
+    index.clone(),
+  ));
+
+  let zero_js = zero_js::from_env();
+  if zero_js {
+    log::info!("serving without scripts");
+  }
+  let redirects = match redirects::Redirects::load(&config.redirects) {
+    Ok(redirects) => redirects,
+    Err(e) => {
+      log::error!("refusing to start: {e}");
+      std::process::exit(1);
+    }
+  };
+  if !redirects.is_empty() {
+    log::info!("redirecting {} old paths", redirects.len());
+  }
+  let header_read_timeout =
+    Duration::from_secs(config.limits.header_read_timeout_secs);
+  let app = router(AppState {
+    leptos_options,
+    config,
+    db,
+    index,
+    live_events,
+    push_config,
+    activitypub,
+    redirects,
+    zero_js,
+    streamed: true,
+  });
+
+  let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
+  let acceptor = match tls {
+    Some((acceptor, redirect_address)) => {
+      if let Some(redirect_address) = redirect_address {
+        tokio::spawn(tls::redirect_to_https(redirect_address, addr.port()));
+      }
+      log::info!("listening on https://{}", &addr);
+      Some(acceptor)
+    }
+    None => {
+      log::info!("listening on http://{}", &addr);
+      None
+    }
+  };
+  listen::serve(listener, app, acceptor, header_read_timeout).await
 }
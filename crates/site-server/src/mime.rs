@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use axum::http::HeaderValue;
+
+/// Content types which are served for each file extension, rather than
+/// relying on `ServeDir`'s guesses. Text types always declare their charset.
+const DEFAULT_MIME_TYPES: &[(&str, &str)] = &[
+  ("html", "text/html; charset=utf-8"),
+  ("css", "text/css; charset=utf-8"),
+  ("js", "text/javascript; charset=utf-8"),
+  ("mjs", "text/javascript; charset=utf-8"),
+  ("json", "application/json"),
+  ("wasm", "application/wasm"),
+  ("md", "text/markdown; charset=utf-8"),
+  ("txt", "text/plain; charset=utf-8"),
+  ("xml", "application/xml; charset=utf-8"),
+  ("svg", "image/svg+xml"),
+  ("png", "image/png"),
+  ("jpg", "image/jpeg"),
+  ("jpeg", "image/jpeg"),
+  ("gif", "image/gif"),
+  ("webp", "image/webp"),
+  ("avif", "image/avif"),
+  ("ico", "image/x-icon"),
+  ("woff", "font/woff"),
+  ("woff2", "font/woff2"),
+  ("ttf", "font/ttf"),
+  ("otf", "font/otf"),
+  ("pdf", "application/pdf"),
+  ("mp4", "video/mp4"),
+  ("webm", "video/webm"),
+  ("cast", "application/x-asciicast"),
+];
+
+/// The environment variable holding extra `ext=type` pairs, comma-separated.
+const MIME_OVERRIDES_VAR: &str = "SITE_MIME_OVERRIDES";
+
+/// A table of file extensions to content types.
+#[derive(Debug, Clone)]
+pub struct MimeTypes {
+  types: HashMap<String, HeaderValue>,
+}
+
+impl Default for MimeTypes {
+  fn default() -> Self {
+    MimeTypes {
+      types: DEFAULT_MIME_TYPES
+        .iter()
+        .map(|(ext, mime)| (ext.to_string(), HeaderValue::from_static(mime)))
+        .collect(),
+    }
+  }
+}
+
+impl MimeTypes {
+  /// Builds the default table, applying any overrides from the
+  /// `SITE_MIME_OVERRIDES` environment variable, e.g.
+  /// `SITE_MIME_OVERRIDES="cast=text/plain,map=application/json"`.
+  pub fn from_env() -> Self {
+    let mut mime_types = MimeTypes::default();
+
+    let Ok(overrides) = std::env::var(MIME_OVERRIDES_VAR) else {
+      return mime_types;
+    };
+    for pair in overrides.split(',').filter(|p| !p.trim().is_empty()) {
+      let parsed = pair.split_once('=').and_then(|(ext, mime)| {
+        Some((ext.trim(), HeaderValue::from_str(mime.trim()).ok()?))
+      });
+      match parsed {
+        Some((ext, mime)) => {
+          mime_types.types.insert(ext.to_lowercase(), mime);
+        }
+        None => {
+          log::warn!("ignoring malformed {MIME_OVERRIDES_VAR} entry `{pair}`")
+        }
+      }
+    }
+
+    mime_types
+  }
+
+  /// Finds the content type to serve a path with, if its extension is known.
+  pub fn for_path(&self, path: &str) -> Option<HeaderValue> {
+    let (_, ext) = path.rsplit_once('.')?;
+    self.types.get(&ext.to_lowercase()).cloned()
+  }
+
+  /// Adds a charset to a guessed textual content type that lacks one.
+  pub fn with_charset(content_type: &HeaderValue) -> Option<HeaderValue> {
+    let content_type = content_type.to_str().ok()?;
+    if content_type.starts_with("text/") && !content_type.contains("charset") {
+      HeaderValue::from_str(&format!("{content_type}; charset=utf-8")).ok()
+    } else {
+      None
+    }
+  }
+}
@@ -0,0 +1,41 @@
+//! The `new-post` command, which scaffolds a post with valid frontmatter.
+
+use std::{io::Write, path::PathBuf};
+
+use site_app::posts::{posts_dir, try_parse_frontmatter};
+
+/// Builds the contents of a new, private post written today.
+fn scaffold(title: &str, today: time::Date) -> String {
+  // a JSON string is also a valid double-quoted YAML scalar
+  let title = serde_json::to_string(title).expect("strings always serialize");
+  format!(
+    "---\ntitle: {title}\nwritten_on: \"{:04}.{:02}.{:02}\"\npublic: \
+     false\n---\n\n",
+    today.year(),
+    u8::from(today.month()),
+    today.day(),
+  )
+}
+
+/// Creates `content/posts/<slug>.md` for a post with the given title, and
+/// returns its path. Existing posts are never overwritten.
+pub fn create(title: &str) -> Result<PathBuf, String> {
+  let slug = slug::slugify(title);
+  if slug.is_empty() {
+    return Err(format!("can't make a post path from the title `{title}`"));
+  }
+
+  let contents = scaffold(title, time::OffsetDateTime::now_utc().date());
+  if let Err(e) = try_parse_frontmatter(&contents) {
+    return Err(format!("generated {e}"));
+  }
+
+  let path = posts_dir().join(format!("{slug}.md"));
+  std::fs::OpenOptions::new()
+    .write(true)
+    .create_new(true)
+    .open(&path)
+    .and_then(|mut file| file.write_all(contents.as_bytes()))
+    .map_err(|e| format!("can't create `{}`: {e}", path.display()))?;
+  Ok(path)
+}
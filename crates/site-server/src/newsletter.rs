@@ -0,0 +1,107 @@
+//! Emails each new post to the newsletter's subscribers, see
+//! [`site_app::newsletter`].
+//!
+//! Each subscriber gets their own email, since each has their own link for
+//! unsubscribing, and they're sent [`BATCH_SIZE`] at a time so that a long
+//! list doesn't run into the SMTP server's sending limits.
+
+use std::time::Duration;
+
+use site_app::{
+  config::SmtpConfig, live::LiveEvent, mail, newsletter, post_index::PostIndex,
+  posts::blocking,
+};
+use site_db::{storage::SubscriberStore, Database};
+use tokio::{sync::broadcast::error::RecvError, task::JoinSet};
+
+use crate::live::LiveEvents;
+
+/// How many emails are sent at once.
+const BATCH_SIZE: usize = 10;
+/// How long to wait between batches.
+const BATCH_DELAY: Duration = Duration::from_secs(5);
+
+/// Emails each new post published on `events` to the subscribers. `key` is
+/// what the links for unsubscribing are signed with.
+pub async fn send_new_posts(
+  events: LiveEvents,
+  db: Database,
+  index: PostIndex,
+  smtp: SmtpConfig,
+  key: String,
+) {
+  let mut receiver = events.subscribe();
+
+  loop {
+    let path = match receiver.recv().await {
+      Ok(LiveEvent::NewPost { path, .. }) => path,
+      Ok(_) => continue,
+      Err(RecvError::Lagged(skipped)) => {
+        log::warn!("newsletter sender missed {skipped} live events");
+        continue;
+      }
+      Err(RecvError::Closed) => return,
+    };
+
+    tokio::spawn(send_post(
+      db.clone(),
+      index.clone(),
+      smtp.clone(),
+      key.clone(),
+      path,
+    ));
+  }
+}
+
+/// Emails a post to every subscriber.
+async fn send_post(
+  db: Database,
+  index: PostIndex,
+  smtp: SmtpConfig,
+  key: String,
+  path: String,
+) {
+  let Ok(Some(Ok(post))) = blocking(move || index.post(&path)).await else {
+    return;
+  };
+  if !post.metadata.public {
+    return;
+  }
+  let subscribers = match db.subscribers().await {
+    Ok(subscribers) => subscribers,
+    Err(e) => {
+      log::error!("failed to fetch newsletter subscribers: {e}");
+      return;
+    }
+  };
+  if subscribers.is_empty() {
+    return;
+  }
+  log::info!(
+    "emailing `{}` to {} subscribers",
+    post.path,
+    subscribers.len()
+  );
+
+  let mut failed = 0;
+  for (i, batch) in subscribers.chunks(BATCH_SIZE).enumerate() {
+    if i > 0 {
+      tokio::time::sleep(BATCH_DELAY).await;
+    }
+    let mut sends = JoinSet::new();
+    for subscriber in batch {
+      let email = newsletter::post_email(&key, &post, &subscriber.email);
+      let smtp = smtp.clone();
+      sends.spawn(async move { mail::send(&smtp, &email).await });
+    }
+    while let Some(sent) = sends.join_next().await {
+      if let Ok(Err(e)) = sent {
+        log::warn!("failed to email `{}` to a subscriber: {e}", post.path);
+        failed += 1;
+      }
+    }
+  }
+  if failed > 0 {
+    log::warn!("`{}` wasn't emailed to {failed} subscribers", post.path);
+  }
+}
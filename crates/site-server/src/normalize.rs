@@ -0,0 +1,69 @@
+//! Gives every page exactly one URL, redirecting variants of it there so that
+//! crawlers don't index duplicates. Repeated and trailing slashes are dropped,
+//! and a post's path in another form that gives the same slug, like
+//! `/post/A_New_TiKV` or `/post/%C3%9Cber%20Caching`, goes to the post.
+
+use axum::{
+  extract::{Request, State},
+  http::{header, HeaderValue, Method, StatusCode},
+  middleware::Next,
+  response::{IntoResponse, Response},
+};
+use site_app::{post_index::PostIndex, posts::slug_of};
+
+/// The canonical form of `path`, if it isn't already.
+fn canonical_path(index: &PostIndex, path: &str) -> Option<String> {
+  let mut segments = path
+    .split('/')
+    .filter(|segment| !segment.is_empty())
+    .map(str::to_string)
+    .collect::<Vec<_>>();
+
+  // only post paths are slugified, as static files' names are case-sensitive
+  if let [prefix, post, rest @ ..] = segments.as_mut_slice() {
+    let is_post_page = matches!(rest, [] | [_])
+      && prefix.eq_ignore_ascii_case("post")
+      && rest.iter().all(|rest| rest.eq_ignore_ascii_case("changes"));
+    if is_post_page && index.source(post).is_none() {
+      let decoded = percent_encoding::percent_decode_str(post);
+      let slug = slug_of(&decoded.decode_utf8_lossy());
+      if index.source(&slug).is_some() {
+        *prefix = "post".to_string();
+        *post = slug;
+        rest
+          .iter_mut()
+          .for_each(|rest| *rest = "changes".to_string());
+      }
+    }
+  }
+
+  let canonical = format!("/{}", segments.join("/"));
+  (canonical != path).then_some(canonical)
+}
+
+/// Redirects page requests to their canonical URL.
+pub async fn normalize_paths(
+  State(index): State<PostIndex>,
+  request: Request,
+  next: Next,
+) -> Response {
+  // anything else can't be followed through a redirect by a crawler anyway
+  if !matches!(*request.method(), Method::GET | Method::HEAD) {
+    return next.run(request).await;
+  }
+  let Some(path) = canonical_path(&index, request.uri().path()) else {
+    return next.run(request).await;
+  };
+  let location = match request.uri().query() {
+    Some(query) => format!("{path}?{query}"),
+    None => path,
+  };
+  match HeaderValue::from_str(&location) {
+    Ok(location) => (StatusCode::MOVED_PERMANENTLY, [(
+      header::LOCATION,
+      location,
+    )])
+      .into_response(),
+    Err(_) => StatusCode::BAD_REQUEST.into_response(),
+  }
+}
@@ -0,0 +1,186 @@
+//! Sends Web Push notifications to subscribed readers when a post is
+//! published.
+//!
+//! Push is enabled by setting `VAPID_PRIVATE_KEY` to a base64url-encoded raw
+//! P-256 private key, which can be generated with:
+//!
+//! ```sh
+//! openssl ecparam -genkey -name prime256v1 -noout -outform DER \
+//!   | tail -c +8 | head -c 32 | base64 | tr '/+' '_-' | tr -d '='
+//! ```
+//!
+//! `VAPID_SUBJECT` should be a `mailto:` or `https:` URL push services can use
+//! to contact the site's operator, and defaults to the author's email.
+
+use std::sync::Arc;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::Serialize;
+use site_app::{config::site_config, live::LiveEvent};
+use site_db::{
+  storage::{PushSubscription, PushSubscriptionStore},
+  Database,
+};
+use tokio::{sync::broadcast::error::RecvError, task::JoinSet};
+use web_push::{
+  ContentEncoding, HyperWebPushClient, PartialVapidSignatureBuilder,
+  SubscriptionInfo, VapidSignatureBuilder, WebPushClient, WebPushError,
+  WebPushMessageBuilder,
+};
+
+use crate::live::LiveEvents;
+
+/// The environment variable holding the VAPID private key.
+pub const VAPID_PRIVATE_KEY_VAR: &str = "VAPID_PRIVATE_KEY";
+/// The environment variable holding the VAPID subject.
+pub const VAPID_SUBJECT_VAR: &str = "VAPID_SUBJECT";
+/// How long push services should hold on to a notification for a browser
+/// that's offline, in seconds.
+const NOTIFICATION_TTL: u32 = 60 * 60 * 24;
+
+/// The keys push notifications are signed with.
+#[derive(Clone)]
+pub struct VapidConfig {
+  signer:  PartialVapidSignatureBuilder,
+  subject: String,
+}
+
+impl VapidConfig {
+  /// Reads the VAPID configuration from the environment, returning `None` if
+  /// push isn't configured.
+  pub fn from_env() -> Result<Option<Self>, WebPushError> {
+    let Ok(private_key) = std::env::var(VAPID_PRIVATE_KEY_VAR) else {
+      return Ok(None);
+    };
+    let signer = VapidSignatureBuilder::from_base64_no_sub(
+      private_key.trim(),
+      web_push::URL_SAFE_NO_PAD,
+    )?;
+    let subject = std::env::var(VAPID_SUBJECT_VAR)
+      .unwrap_or_else(|_| format!("mailto:{}", site_config().author.email));
+
+    Ok(Some(VapidConfig { signer, subject }))
+  }
+
+  /// The public key browsers subscribe with, base64url-encoded.
+  pub fn public_key(&self) -> String {
+    URL_SAFE_NO_PAD.encode(self.signer.get_public_key())
+  }
+}
+
+/// The payload the service worker turns into a notification.
+#[derive(Serialize)]
+struct Notification {
+  title: String,
+  url:   String,
+}
+
+/// Notifies every subscriber of each new post published on `events`.
+pub async fn notify_new_posts(
+  events: LiveEvents,
+  db: Database,
+  vapid: VapidConfig,
+) {
+  let client = Arc::new(HyperWebPushClient::new());
+  let mut receiver = events.subscribe();
+
+  loop {
+    let (path, title) = match receiver.recv().await {
+      Ok(LiveEvent::NewPost { path, title }) => (path, title),
+      Ok(_) => continue,
+      Err(RecvError::Lagged(skipped)) => {
+        log::warn!("push notifier missed {skipped} live events");
+        continue;
+      }
+      Err(RecvError::Closed) => return,
+    };
+
+    tokio::spawn(notify_post(
+      client.clone(),
+      db.clone(),
+      vapid.clone(),
+      path,
+      title,
+    ));
+  }
+}
+
+/// Notifies every subscriber of a post, all at once.
+async fn notify_post(
+  client: Arc<HyperWebPushClient>,
+  db: Database,
+  vapid: VapidConfig,
+  path: String,
+  title: String,
+) {
+  let subscriptions = match db.push_subscriptions().await {
+    Ok(subscriptions) => subscriptions,
+    Err(e) => {
+      log::error!("failed to fetch push subscriptions: {e}");
+      return;
+    }
+  };
+  let notification = Arc::new(Notification {
+    title,
+    url: site_config().url(&format!("/post/{path}")),
+  });
+  log::info!(
+    "sending push notifications for `{path}` to {} subscribers",
+    subscriptions.len()
+  );
+
+  let mut sends = JoinSet::new();
+  for subscription in subscriptions {
+    let (client, vapid, notification) =
+      (client.clone(), vapid.clone(), notification.clone());
+    sends.spawn(async move {
+      let sent = send(&client, &vapid, &subscription, &notification).await;
+      (subscription, sent)
+    });
+  }
+  while let Some(sent) = sends.join_next().await {
+    let Ok((subscription, sent)) = sent else {
+      continue;
+    };
+    match sent {
+      Ok(()) => {}
+      // the browser has unsubscribed, so stop sending to it
+      Err(WebPushError::EndpointNotValid | WebPushError::EndpointNotFound) => {
+        if let Err(e) =
+          db.remove_push_subscription(&subscription.endpoint).await
+        {
+          log::error!("failed to remove push subscription: {e}");
+        }
+      }
+      Err(e) => log::warn!(
+        "failed to send push notification to {}: {e}",
+        subscription.endpoint
+      ),
+    }
+  }
+}
+
+async fn send(
+  client: &HyperWebPushClient,
+  vapid: &VapidConfig,
+  subscription: &PushSubscription,
+  notification: &Notification,
+) -> Result<(), WebPushError> {
+  let info = SubscriptionInfo::new(
+    subscription.endpoint.as_str(),
+    subscription.p256dh.as_str(),
+    subscription.auth.as_str(),
+  );
+
+  let mut signature = vapid.signer.clone().add_sub_info(&info);
+  signature.add_claim("sub", vapid.subject.as_str());
+
+  let payload = serde_json::to_vec(notification)
+    .expect("notifications are always serializable");
+  let mut message = WebPushMessageBuilder::new(&info);
+  message.set_payload(ContentEncoding::Aes128Gcm, &payload);
+  message.set_vapid_signature(signature.build()?);
+  message.set_ttl(NOTIFICATION_TTL);
+
+  client.send(message.build()?).await
+}
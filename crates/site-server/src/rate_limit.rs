@@ -0,0 +1,191 @@
+//! Per-client rate limiting of server fns and form submissions, so that a
+//! misbehaving crawler can't keep the server busy rendering. Each client has a
+//! bucket of calls, see [`RateLimitConfig`], and pages and static files aren't
+//! limited at all.
+
+use std::{
+  collections::HashMap,
+  net::IpAddr,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+use axum::{
+  extract::{Request, State},
+  http::{header, HeaderValue, Method, StatusCode},
+  middleware::Next,
+  response::{IntoResponse, Response},
+};
+use site_app::config::RateLimitConfig;
+
+/// How many clients are remembered. Past it, those with full buckets, which
+/// would be no different if forgotten, are dropped, and then those seen least
+/// recently, down to [`EVICTED_CLIENTS`] fewer.
+const MAX_CLIENTS: usize = 10_000;
+/// How many clients are forgotten at once, so that it isn't done for every
+/// new one.
+const EVICTED_CLIENTS: usize = MAX_CLIENTS / 10;
+
+/// A client's remaining calls, as of when it last made one.
+struct Bucket {
+  calls:   f64,
+  updated: Instant,
+}
+
+/// Every client's bucket. Cloning it gives another handle to the same
+/// buckets.
+#[derive(Clone)]
+pub struct RateLimiter {
+  config:  RateLimitConfig,
+  buckets: Arc<Mutex<HashMap<IpAddr, Bucket>>>,
+}
+
+impl RateLimiter {
+  pub fn new(config: RateLimitConfig) -> Self {
+    RateLimiter {
+      config,
+      buckets: Default::default(),
+    }
+  }
+
+  /// A client can't be allowed no calls at all, or it'd never get any back.
+  fn burst(&self) -> u32 { self.config.burst.max(1) }
+
+  /// Calls regained per second.
+  fn refill_rate(&self) -> f64 {
+    f64::from(self.burst()) / self.config.window_secs.max(1) as f64
+  }
+
+  /// Takes a call from the client's bucket, or says how long until it can
+  /// make another.
+  fn take(&self, client: IpAddr) -> Result<(), Duration> {
+    let burst = f64::from(self.burst());
+    let rate = self.refill_rate();
+    let now = Instant::now();
+    let refilled = |bucket: &Bucket| {
+      let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+      (bucket.calls + elapsed * rate).min(burst)
+    };
+
+    let mut buckets = self.buckets.lock().unwrap();
+    if buckets.len() >= MAX_CLIENTS && !buckets.contains_key(&client) {
+      buckets.retain(|_, bucket| refilled(bucket) < burst);
+    }
+    if buckets.len() >= MAX_CLIENTS && !buckets.contains_key(&client) {
+      let mut seen = buckets.values().map(|b| b.updated).collect::<Vec<_>>();
+      let evicted = buckets.len() - (MAX_CLIENTS - EVICTED_CLIENTS);
+      let (_, last_evicted, _) = seen.select_nth_unstable(evicted - 1);
+      let last_evicted = *last_evicted;
+      buckets.retain(|_, bucket| bucket.updated > last_evicted);
+    }
+    let bucket = buckets.entry(client).or_insert(Bucket {
+      calls:   burst,
+      updated: now,
+    });
+    bucket.calls = refilled(bucket);
+    bucket.updated = now;
+    if bucket.calls < 1.0 {
+      return Err(Duration::from_secs_f64((1.0 - bucket.calls) / rate));
+    }
+    bucket.calls -= 1.0;
+    Ok(())
+  }
+
+  /// The address of the client making a request, if it's known.
+  fn client(&self, request: &Request) -> Option<IpAddr> {
+    self
+      .config
+      .client_ip(request.headers(), request.extensions())
+  }
+}
+
+/// Whether a request counts against the client's calls: server fns, and any
+/// form submission.
+fn is_limited(request: &Request) -> bool {
+  request.uri().path().starts_with("/api/")
+    || !matches!(
+      *request.method(),
+      Method::GET | Method::HEAD | Method::OPTIONS
+    )
+}
+
+fn too_many_requests(retry_after: Duration) -> Response {
+  let secs = (retry_after.as_secs_f64().ceil() as u64).max(1);
+  let mut response = (
+    StatusCode::TOO_MANY_REQUESTS,
+    format!(
+      "You're doing that a little too often. Give it {secs} second{} and try \
+       again.",
+      if secs == 1 { "" } else { "s" }
+    ),
+  )
+    .into_response();
+  response
+    .headers_mut()
+    .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+  response
+}
+
+/// Answers limited requests with a `429` once their client is out of calls.
+pub async fn rate_limit(
+  State(limiter): State<RateLimiter>,
+  request: Request,
+  next: Next,
+) -> Response {
+  if !limiter.config.enabled || !is_limited(&request) {
+    return next.run(request).await;
+  }
+  // requests made from within the server, like exports, have no client
+  let Some(client) = limiter.client(&request) else {
+    return next.run(request).await;
+  };
+  match limiter.take(client) {
+    Ok(()) => next.run(request).await,
+    Err(retry_after) => {
+      log::debug!("rate limited {client}");
+      too_many_requests(retry_after)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::Ipv4Addr;
+
+  use super::*;
+
+  fn client(i: usize) -> IpAddr { Ipv4Addr::from(i as u32).into() }
+
+  #[test]
+  fn clients_are_evicted_down_to_the_cap() {
+    let limiter = RateLimiter::new(RateLimitConfig {
+      burst: 2,
+      window_secs: 3600,
+      ..Default::default()
+    });
+    // none of their buckets are full, so none can be dropped for free
+    for i in 0..MAX_CLIENTS * 2 {
+      limiter.take(client(i)).unwrap();
+      assert!(limiter.buckets.lock().unwrap().len() <= MAX_CLIENTS);
+    }
+
+    let buckets = limiter.buckets.lock().unwrap();
+    // the newest client is kept, and the oldest have gone
+    assert!(buckets.contains_key(&client(MAX_CLIENTS * 2 - 1)));
+    assert!(!buckets.contains_key(&client(0)));
+  }
+
+  #[test]
+  fn known_clients_keep_their_buckets_at_the_cap() {
+    let limiter = RateLimiter::new(RateLimitConfig {
+      burst: 1,
+      window_secs: 3600,
+      ..Default::default()
+    });
+    for i in 0..MAX_CLIENTS {
+      limiter.take(client(i)).unwrap();
+    }
+    assert!(limiter.take(client(0)).is_err());
+    assert_eq!(limiter.buckets.lock().unwrap().len(), MAX_CLIENTS);
+  }
+}
@@ -0,0 +1,114 @@
+//! Redirects from old paths, like those of a previous blog, to where their
+//! content lives now. They're read at startup from the file the
+//! [`SiteConfig`](site_app::config::SiteConfig) names, which maps each old
+//! path to a new one, permanently unless a status is given:
+//!
+//! ```toml
+//! "/2023/05/building-this-blog.html" = "/post/building-this-blog"
+//! "/drafts" = { to = "/", status = 302 }
+//! ```
+//!
+//! A request's query string is kept unless the new path has its own.
+
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use axum::{
+  extract::{Request, State},
+  http::{header, HeaderValue, StatusCode},
+  middleware::Next,
+  response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Entry {
+  Permanent(String),
+  WithStatus { to: String, status: u16 },
+}
+
+struct Redirect {
+  to:     String,
+  status: StatusCode,
+}
+
+/// Every redirect, by the path it's from. Cloning it gives another handle to
+/// the same redirects.
+#[derive(Clone, Default)]
+pub struct Redirects {
+  redirects: Arc<HashMap<String, Redirect>>,
+}
+
+impl Redirects {
+  /// Reads the redirects from `file`, if it exists, describing what's wrong
+  /// with it if it's invalid.
+  pub fn load(file: &Path) -> Result<Self, String> {
+    let input = match std::fs::read_to_string(file) {
+      Ok(input) => input,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+        return Ok(Redirects::default());
+      }
+      Err(e) => return Err(format!("can't read `{}`: {e}", file.display())),
+    };
+    let entries = toml::from_str::<HashMap<String, Entry>>(&input)
+      .map_err(|e| format!("invalid `{}`: {e}", file.display()))?;
+
+    let mut redirects = HashMap::new();
+    for (from, entry) in entries {
+      let (to, status) = match entry {
+        Entry::Permanent(to) => (to, StatusCode::MOVED_PERMANENTLY),
+        Entry::WithStatus { to, status } => (to, status_of(&from, status)?),
+      };
+      if !from.starts_with('/') {
+        return Err(format!("redirect from `{from}` isn't an absolute path"));
+      }
+      if HeaderValue::from_str(&to).is_err() {
+        return Err(format!("redirect from `{from}` goes to an invalid URL"));
+      }
+      redirects.insert(from, Redirect { to, status });
+    }
+    Ok(Redirects {
+      redirects: Arc::new(redirects),
+    })
+  }
+
+  /// How many redirects there are.
+  pub fn len(&self) -> usize { self.redirects.len() }
+
+  pub fn is_empty(&self) -> bool { self.redirects.is_empty() }
+}
+
+fn status_of(from: &str, status: u16) -> Result<StatusCode, String> {
+  StatusCode::from_u16(status)
+    .ok()
+    .filter(|status| {
+      status.is_redirection() && *status != StatusCode::NOT_MODIFIED
+    })
+    .ok_or_else(|| {
+      format!("redirect from `{from}` has `{status}`, which isn't a redirect")
+    })
+}
+
+/// Redirects requests for the old paths, before they're routed.
+pub async fn redirect(
+  State(redirects): State<Redirects>,
+  request: Request,
+  next: Next,
+) -> Response {
+  let Some(redirect) = redirects.redirects.get(request.uri().path()) else {
+    return next.run(request).await;
+  };
+  let location = match request.uri().query() {
+    Some(query) if !redirect.to.contains('?') => {
+      format!("{}?{query}", redirect.to)
+    }
+    _ => redirect.to.clone(),
+  };
+  match HeaderValue::from_str(&location) {
+    Ok(location) => {
+      (redirect.status, [(header::LOCATION, location)]).into_response()
+    }
+    // only a query string the client sent could make it invalid
+    Err(_) => StatusCode::BAD_REQUEST.into_response(),
+  }
+}
@@ -0,0 +1,35 @@
+//! Serves the index the search box searches as you type, see
+//! [`site_app::search`].
+
+use axum::{
+  extract::State,
+  http::{header, StatusCode},
+  response::{IntoResponse, Response},
+  Json,
+};
+use site_app::posts::blocking;
+
+use crate::state::AppState;
+
+/// How long browsers keep the index before fetching it again, in seconds,
+/// which is the longest a new post can take to show up as you type.
+const INDEX_MAX_AGE_SECS: u64 = 5 * 60;
+
+/// Serves every public post's words.
+pub async fn search_index(State(state): State<AppState>) -> Response {
+  let index = state.index.clone();
+  match blocking(move || index.search_index()).await {
+    Ok(posts) => (
+      [(
+        header::CACHE_CONTROL,
+        format!("public, max-age={INDEX_MAX_AGE_SECS}"),
+      )],
+      Json(posts),
+    )
+      .into_response(),
+    Err(e) => {
+      log::error!("failed to build the search index: {e}");
+      StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    }
+  }
+}
@@ -0,0 +1,64 @@
+//! The security headers, configured by the
+//! [`SecurityConfig`](site_app::config::SecurityConfig). Rendered pages set
+//! their own Content Security Policy, see [`site_app::csp`], and every other
+//! response gets one without a nonce.
+
+use axum::{
+  extract::{Request, State},
+  http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+  middleware::Next,
+  response::Response,
+};
+use site_app::config::SiteConfig;
+
+/// The `X-Frame-Options` equivalent to `frame-ancestors`, for browsers which
+/// predate it, if there is one.
+fn frame_options(frame_ancestors: &[String]) -> Option<&'static str> {
+  match frame_ancestors {
+    [source] if source == "'none'" => Some("DENY"),
+    [source] if source == "'self'" => Some("SAMEORIGIN"),
+    _ => None,
+  }
+}
+
+fn insert_if_missing(headers: &mut HeaderMap, name: HeaderName, value: &str) {
+  if headers.contains_key(&name) {
+    return;
+  }
+  match HeaderValue::from_str(value) {
+    Ok(value) => {
+      headers.insert(name, value);
+    }
+    Err(_) => log::error!("the configured `{name}` isn't a valid header"),
+  }
+}
+
+/// Adds the security headers to each response which doesn't have them.
+pub async fn security_headers(
+  State(config): State<SiteConfig>,
+  request: Request,
+  next: Next,
+) -> Response {
+  let mut response = next.run(request).await;
+  // a `304` updates the cached response's headers, and a policy with a
+  // different nonce would block the cached page's scripts
+  if response.status() == StatusCode::NOT_MODIFIED {
+    return response;
+  }
+
+  let security = &config.security;
+  let headers = response.headers_mut();
+  insert_if_missing(headers, header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+  insert_if_missing(
+    headers,
+    header::REFERRER_POLICY,
+    &security.referrer_policy,
+  );
+  if let Some(policy) = site_app::csp::policy(&config, None) {
+    insert_if_missing(headers, header::CONTENT_SECURITY_POLICY, &policy);
+  }
+  if let Some(frame_options) = frame_options(&security.frame_ancestors) {
+    insert_if_missing(headers, header::X_FRAME_OPTIONS, frame_options);
+  }
+  response
+}
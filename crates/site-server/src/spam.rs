@@ -0,0 +1,74 @@
+//! The `spam` command, which manages the comment spam classifier. Comments can
+//! also be moderated from the admin area, see [`site_app::moderation`].
+
+use site_app::spam::{self, Thresholds};
+use site_db::{
+  storage::{CommentStatus, CommentStore, SpamPhrase, SpamStore},
+  Database,
+};
+
+use crate::cli::{SpamCommand, SpamVerdict};
+
+/// Runs a `spam` subcommand, exiting non-zero on failure.
+pub async fn run(command: SpamCommand) {
+  let db = Database::connect_from_env()
+    .await
+    .expect("failed to connect to the database");
+  site_db::migrations::migrate(&db)
+    .await
+    .expect("failed to apply migrations");
+
+  if let Err(e) = run_with(&db, command).await {
+    log::error!("{e}");
+    std::process::exit(1);
+  }
+}
+
+async fn run_with(
+  db: &Database,
+  command: SpamCommand,
+) -> Result<(), site_db::DbError> {
+  match command {
+    SpamCommand::Phrases => {
+      for phrase in db.spam_phrases().await? {
+        println!("{:+.1}\t{}", phrase.weight, phrase.phrase);
+      }
+    }
+    SpamCommand::AddPhrase { phrase, weight } => {
+      db.add_spam_phrase(&SpamPhrase { phrase, weight }).await?;
+    }
+    SpamCommand::RemovePhrase { phrase } => {
+      if !db.remove_spam_phrase(&phrase).await? {
+        log::warn!("no such phrase: {phrase:?}");
+      }
+    }
+    SpamCommand::Queue => {
+      for comment in db.comments_with_status(CommentStatus::Pending).await? {
+        println!(
+          "#{} on {} by {} (score {:.1}):\n  {}",
+          comment.id,
+          comment.post_path,
+          comment.author,
+          comment.spam_score,
+          comment.body.replace('\n', "\n  ")
+        );
+      }
+    }
+    SpamCommand::Mark { id, verdict } => {
+      let is_spam = matches!(verdict, SpamVerdict::Spam);
+      if !spam::mark_comment(db, id, is_spam).await? {
+        log::error!("no comment with id {id}");
+        std::process::exit(1);
+      }
+    }
+    SpamCommand::Score { text, post } => {
+      let verdict =
+        spam::classify(db, &post, &text, Thresholds::default()).await?;
+      println!("score {:.1}, would be {}", verdict.score, verdict.status);
+      for reason in verdict.reasons {
+        println!("  {reason}");
+      }
+    }
+  }
+  Ok(())
+}
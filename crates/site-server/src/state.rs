@@ -0,0 +1,81 @@
+//! The state shared by every request.
+
+use axum::extract::FromRef;
+use leptos::{provide_context, LeptosOptions};
+use site_app::{
+  activitypub::ActorKey,
+  config::SiteConfig,
+  post_index::PostIndex,
+  push::PushConfig,
+  zero_js::{Unstreamed, ZeroJs},
+};
+use site_db::Database;
+
+use crate::{live::LiveEvents, redirects::Redirects};
+
+/// Everything the server holds for the lifetime of the process. Handlers take
+/// the parts they need as `State`, and server fns get them from context.
+#[derive(Clone)]
+pub struct AppState {
+  pub leptos_options: LeptosOptions,
+  pub config:         SiteConfig,
+  pub db:             Database,
+  /// The posts, read and rendered once and kept up to date by the content
+  /// watcher.
+  pub index:          PostIndex,
+  pub live_events:    LiveEvents,
+  /// Without a push configuration, the push opt-in is left out of the
+  /// rendered pages.
+  pub push_config:    Option<PushConfig>,
+  /// The ActivityPub actor's key, if the actor is enabled.
+  pub activitypub:    Option<ActorKey>,
+  pub redirects:      Redirects,
+  /// Whether pages are served without scripts, see [`site_app::zero_js`].
+  pub zero_js:        bool,
+  /// Whether pages are streamed as their parts are ready. Exports render them
+  /// whole instead, see [`site_app::zero_js::Unstreamed`].
+  pub streamed:       bool,
+}
+
+impl AppState {
+  /// Makes the parts of the state which server fns use available to them.
+  pub fn provide_context(&self) {
+    // for the Content Security Policy, see `site_app::csp`
+    leptos::nonce::provide_nonce();
+    provide_context(self.config.clone());
+    provide_context(self.db.clone());
+    provide_context(self.index.clone());
+    if self.zero_js {
+      provide_context(ZeroJs);
+    }
+    if !self.streamed {
+      provide_context(Unstreamed);
+    }
+    // the push opt-in can't do anything without scripts
+    if let Some(push_config) =
+      self.push_config.clone().filter(|_| !self.zero_js)
+    {
+      provide_context(push_config);
+    }
+  }
+}
+
+impl FromRef<AppState> for LeptosOptions {
+  fn from_ref(state: &AppState) -> Self { state.leptos_options.clone() }
+}
+
+impl FromRef<AppState> for SiteConfig {
+  fn from_ref(state: &AppState) -> Self { state.config.clone() }
+}
+
+impl FromRef<AppState> for LiveEvents {
+  fn from_ref(state: &AppState) -> Self { state.live_events.clone() }
+}
+
+impl FromRef<AppState> for Redirects {
+  fn from_ref(state: &AppState) -> Self { state.redirects.clone() }
+}
+
+impl FromRef<AppState> for PostIndex {
+  fn from_ref(state: &AppState) -> Self { state.index.clone() }
+}
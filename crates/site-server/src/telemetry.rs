@@ -0,0 +1,442 @@
+//! Logging with `tracing` spans, so that what's logged while handling a
+//! request or rendering a post says which one it was, and slow spans can be
+//! picked out by how long they took.
+//!
+//! This is a small subscriber rather than `tracing-subscriber`, since all the
+//! site needs is the scope of each line and an env filter. `log` records are
+//! written the same way, in the scope of whatever span they're logged in.
+//!
+//! What's logged is set by `RUST_LOG`, as a default level and
+//! per-target levels, e.g. `RUST_LOG=info,site_app::post_index=debug`. Spans
+//! are mostly at `debug`, and log how long they were open when they close.
+
+use std::{
+  cell::RefCell,
+  collections::HashMap,
+  fmt::{self, Write as _},
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+  },
+  time::Instant,
+};
+
+use axum::{
+  body::{Body, Bytes},
+  extract::Request,
+  http::{Method, StatusCode},
+  middleware::Next,
+  response::Response,
+};
+use futures::StreamExt;
+use tracing::{
+  field::{Field, Visit},
+  level_filters::LevelFilter,
+  span, Event, Instrument, Level, Metadata, Span, Subscriber,
+};
+
+/// The environment variable holding the filter.
+pub const LOG_FILTER_VAR: &str = "RUST_LOG";
+
+/// How verbose a level is, from `0` for nothing to `5` for everything.
+fn verbosity(level: Level) -> u8 {
+  match level {
+    Level::ERROR => 1,
+    Level::WARN => 2,
+    Level::INFO => 3,
+    Level::DEBUG => 4,
+    Level::TRACE => 5,
+  }
+}
+
+fn parse_verbosity(level: &str) -> Option<u8> {
+  match level.trim().to_lowercase().as_str() {
+    "off" => Some(0),
+    "error" => Some(1),
+    "warn" => Some(2),
+    "info" => Some(3),
+    "debug" => Some(4),
+    "trace" => Some(5),
+    _ => None,
+  }
+}
+
+/// Which levels are logged for which targets.
+struct Filter {
+  default:    u8,
+  /// Targets and their levels, longest first so that the most specific wins.
+  directives: Vec<(String, u8)>,
+}
+
+impl Filter {
+  fn parse(filter: &str) -> Self {
+    let mut parsed = Filter {
+      default:    verbosity(Level::INFO),
+      directives: Vec::new(),
+    };
+    for directive in filter.split(',').filter(|d| !d.trim().is_empty()) {
+      let level = match directive.split_once('=') {
+        Some((target, level)) => parse_verbosity(level).map(|level| {
+          parsed.directives.push((target.trim().to_string(), level))
+        }),
+        None => parse_verbosity(directive).map(|level| parsed.default = level),
+      };
+      if level.is_none() {
+        eprintln!(
+          "ignoring malformed {LOG_FILTER_VAR} directive `{directive}`"
+        );
+      }
+    }
+    parsed
+      .directives
+      .sort_by_key(|(target, _)| std::cmp::Reverse(target.len()));
+    parsed
+  }
+
+  fn enabled(&self, target: &str, verbosity: u8) -> bool {
+    let max = self
+      .directives
+      .iter()
+      .find(|(prefix, _)| {
+        target
+          .strip_prefix(prefix.as_str())
+          .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+      })
+      .map_or(self.default, |(_, level)| *level);
+    verbosity <= max
+  }
+
+  fn max_verbosity(&self) -> u8 {
+    self
+      .directives
+      .iter()
+      .map(|(_, level)| *level)
+      .fold(self.default, u8::max)
+  }
+}
+
+/// Writes a span's or event's fields as ` name=value`, keeping the message
+/// apart.
+#[derive(Default)]
+struct Fields {
+  message: String,
+  fields:  String,
+}
+
+impl Visit for Fields {
+  fn record_str(&mut self, field: &Field, value: &str) {
+    self.record_debug(field, &format_args!("{value}"))
+  }
+
+  fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+    if field.name() == "message" {
+      let _ = write!(self.message, "{value:?}");
+    } else {
+      let _ = write!(self.fields, " {}={value:?}", field.name());
+    }
+  }
+}
+
+struct SpanData {
+  metadata: &'static Metadata<'static>,
+  fields:   String,
+  parent:   Option<u64>,
+  opened:   Instant,
+  /// How many handles to the span are alive.
+  refs:     usize,
+}
+
+thread_local! {
+  /// The spans entered on this thread, innermost last.
+  static STACK: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+struct Inner {
+  filter:  Filter,
+  spans:   Mutex<HashMap<u64, SpanData>>,
+  next_id: AtomicU64,
+}
+
+impl Inner {
+  /// Describes the span and its parents, outermost first, e.g.
+  /// `request{method=GET}:render_post{path="a"}: `.
+  fn scope(&self, innermost: Option<u64>) -> String {
+    let spans = self.spans.lock().unwrap();
+    let mut scope = Vec::new();
+    let mut next = innermost;
+    while let Some(span) = next.and_then(|id| spans.get(&id)) {
+      scope.push(match span.fields.trim() {
+        "" => span.metadata.name().to_string(),
+        fields => format!("{}{{{fields}}}", span.metadata.name()),
+      });
+      next = span.parent;
+    }
+    if scope.is_empty() {
+      return String::new();
+    }
+    scope.reverse();
+    format!("{}: ", scope.join(":"))
+  }
+
+  fn current_span() -> Option<u64> {
+    STACK.with(|stack| stack.borrow().last().copied())
+  }
+
+  fn write(&self, level: &str, target: &str, scope: &str, message: &str) {
+    let now = time::OffsetDateTime::now_utc();
+    eprintln!(
+      "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z {level:<5} [{target}] \
+       {scope}{message}",
+      now.year(),
+      u8::from(now.month()),
+      now.day(),
+      now.hour(),
+      now.minute(),
+      now.second(),
+      now.millisecond(),
+    );
+  }
+}
+
+/// The subscriber every span and event goes to.
+#[derive(Clone)]
+struct Telemetry(Arc<Inner>);
+
+impl Subscriber for Telemetry {
+  fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+    self
+      .0
+      .filter
+      .enabled(metadata.target(), verbosity(*metadata.level()))
+  }
+
+  fn max_level_hint(&self) -> Option<LevelFilter> {
+    [
+      LevelFilter::OFF,
+      LevelFilter::ERROR,
+      LevelFilter::WARN,
+      LevelFilter::INFO,
+      LevelFilter::DEBUG,
+      LevelFilter::TRACE,
+    ]
+    .get(usize::from(self.0.filter.max_verbosity()))
+    .copied()
+  }
+
+  fn new_span(&self, attributes: &span::Attributes<'_>) -> span::Id {
+    let mut fields = Fields::default();
+    attributes.record(&mut fields);
+    let parent = match attributes.parent() {
+      Some(parent) => Some(parent.into_u64()),
+      None if attributes.is_contextual() => Inner::current_span(),
+      None => None,
+    };
+
+    let id = self.0.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+    self.0.spans.lock().unwrap().insert(id, SpanData {
+      metadata: attributes.metadata(),
+      fields: fields.fields,
+      parent,
+      opened: Instant::now(),
+      refs: 1,
+    });
+    span::Id::from_u64(id)
+  }
+
+  fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+    let mut fields = Fields::default();
+    values.record(&mut fields);
+    if let Some(span) = self.0.spans.lock().unwrap().get_mut(&span.into_u64()) {
+      span.fields.push_str(&fields.fields);
+    }
+  }
+
+  fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+  fn event(&self, event: &Event<'_>) {
+    let mut fields = Fields::default();
+    event.record(&mut fields);
+    let parent = match event.parent() {
+      Some(parent) => Some(parent.into_u64()),
+      None if event.is_contextual() => Inner::current_span(),
+      None => None,
+    };
+    let metadata = event.metadata();
+    self.0.write(
+      metadata.level().as_str(),
+      metadata.target(),
+      &self.0.scope(parent),
+      &format!("{}{}", fields.message, fields.fields),
+    );
+  }
+
+  fn enter(&self, span: &span::Id) {
+    STACK.with(|stack| stack.borrow_mut().push(span.into_u64()));
+  }
+
+  fn exit(&self, span: &span::Id) {
+    STACK.with(|stack| {
+      let mut stack = stack.borrow_mut();
+      if let Some(position) =
+        stack.iter().rposition(|id| *id == span.into_u64())
+      {
+        stack.remove(position);
+      }
+    });
+  }
+
+  fn clone_span(&self, span: &span::Id) -> span::Id {
+    if let Some(span) = self.0.spans.lock().unwrap().get_mut(&span.into_u64()) {
+      span.refs += 1;
+    }
+    span.clone()
+  }
+
+  fn try_close(&self, span: span::Id) -> bool {
+    let id = span.into_u64();
+    let (metadata, opened) = {
+      let mut spans = self.0.spans.lock().unwrap();
+      let Some(span) = spans.get_mut(&id) else {
+        return false;
+      };
+      span.refs -= 1;
+      if span.refs > 0 {
+        return false;
+      }
+      (span.metadata, span.opened)
+    };
+
+    let scope = self.0.scope(Some(id));
+    self.0.spans.lock().unwrap().remove(&id);
+    self.0.write(
+      metadata.level().as_str(),
+      metadata.target(),
+      &scope,
+      &format!("closed after {:.2?}", opened.elapsed()),
+    );
+    true
+  }
+}
+
+impl log::Log for Telemetry {
+  fn enabled(&self, metadata: &log::Metadata) -> bool {
+    // `log` levels count from error as 1, the same as verbosity
+    self
+      .0
+      .filter
+      .enabled(metadata.target(), metadata.level() as usize as u8)
+  }
+
+  fn log(&self, record: &log::Record) {
+    if !log::Log::enabled(self, record.metadata()) {
+      return;
+    }
+    self.0.write(
+      record.level().as_str(),
+      record.target(),
+      &self.0.scope(Inner::current_span()),
+      &record.args().to_string(),
+    );
+  }
+
+  fn flush(&self) {}
+}
+
+/// Starts logging, filtered by [`LOG_FILTER_VAR`].
+pub fn init() {
+  let filter =
+    Filter::parse(&std::env::var(LOG_FILTER_VAR).unwrap_or_default());
+  // `log` levels count from off as 0, the same as verbosity
+  let max_level = log::LevelFilter::iter()
+    .nth(usize::from(filter.max_verbosity()))
+    .unwrap_or(log::LevelFilter::Trace);
+  let telemetry = Telemetry(Arc::new(Inner {
+    filter,
+    spans: Mutex::default(),
+    next_id: AtomicU64::new(0),
+  }));
+
+  tracing::subscriber::set_global_default(telemetry.clone())
+    .expect("couldn't initialize tracing");
+  log::set_boxed_logger(Box::new(telemetry))
+    .expect("couldn't initialize logging");
+  log::set_max_level(max_level);
+}
+
+/// Paths which are requested alongside pages, or held open, and would drown
+/// the pages out of the request log. They're logged at `debug`.
+const QUIET_PREFIXES: &[&str] =
+  &["/pkg/", "/fonts/", "/favicon", site_app::live::EVENTS_PATH];
+
+/// A line of the request log, written once the response body has been sent
+/// or dropped, so that its size and duration cover the whole response.
+struct AccessLog {
+  span:    Span,
+  method:  Method,
+  path:    String,
+  status:  StatusCode,
+  bytes:   u64,
+  started: Instant,
+}
+
+impl AccessLog {
+  fn count(&mut self, chunk: &Result<Bytes, axum::Error>) {
+    if let Ok(chunk) = chunk {
+      self.bytes += chunk.len() as u64;
+    }
+  }
+}
+
+impl Drop for AccessLog {
+  fn drop(&mut self) {
+    let _span = self.span.enter();
+    let AccessLog {
+      method,
+      path,
+      status,
+      bytes,
+      ..
+    } = self;
+    let status = status.as_u16();
+    let elapsed = self.started.elapsed();
+    if QUIET_PREFIXES.iter().any(|prefix| path.starts_with(prefix)) {
+      tracing::debug!("{method} {path} {status} {bytes}B in {elapsed:.2?}");
+    } else {
+      tracing::info!("{method} {path} {status} {bytes}B in {elapsed:.2?}");
+    }
+  }
+}
+
+/// Runs each request in a span with its method and path, and logs its
+/// status, size and duration once it's been sent.
+pub async fn log_requests(request: Request, next: Next) -> Response {
+  let method = request.method().clone();
+  let path = request.uri().path().to_string();
+  let span = tracing::debug_span!(
+    "request",
+    method = %method,
+    path = %path,
+    status = tracing::field::Empty,
+  );
+  let started = Instant::now();
+  let response = next.run(request).instrument(span.clone()).await;
+  span.record("status", response.status().as_u16());
+
+  let mut log = AccessLog {
+    span,
+    method,
+    path,
+    status: response.status(),
+    bytes: 0,
+    started,
+  };
+  // count the body as it's sent, since most pages are streamed
+  let (parts, body) = response.into_parts();
+  let body = body.into_data_stream().map(move |chunk| {
+    // through a method, so the closure takes the log rather than a copy of
+    // its count, and it's written when the body is dropped
+    log.count(&chunk);
+    chunk
+  });
+  Response::from_parts(parts, Body::from_stream(body))
+}
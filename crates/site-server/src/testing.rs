@@ -0,0 +1,258 @@
+//! A harness which runs the whole site against the fixture content in
+//! `fixtures/content`, for end-to-end checks of content features.
+//!
+//! The fixtures are copied into a fresh temporary directory, which becomes
+//! the working directory while the site is up, since posts are read from a
+//! path relative to it. Only one [`TestSite`] can be up at a time.
+//!
+//! It's built for the crate's own tests, and for the tests in `tests/` with
+//! the `testing` feature. New content features should add a fixture post
+//! using them and a test there.
+
+use std::path::{Path, PathBuf};
+
+use axum::{
+  body::{Body, Bytes},
+  http::{header, HeaderMap, Request, StatusCode},
+  Router,
+};
+use leptos::LeptosOptions;
+use site_app::post_index::PostIndex;
+use site_db::{migrations, Database, DEFAULT_DATABASE_URL};
+use tokio::sync::{Mutex, MutexGuard};
+use tower::ServiceExt;
+
+/// The fixture content tree, as checked in.
+const FIXTURES_DIR: &str =
+  concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/content");
+/// The workspace, whose manifest has the Leptos project, and which the site
+/// root in it is relative to.
+const WORKSPACE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../..");
+
+/// Held while a site is up, since the working directory is process-wide.
+static WORKING_DIR: Mutex<()> = Mutex::const_new(());
+
+/// The site's Leptos options, from the project in the workspace's manifest, as
+/// `cargo-leptos` would give them to the server. The site root is made
+/// absolute, since the working directory changes.
+fn leptos_options() -> Result<LeptosOptions, String> {
+  let workspace = Path::new(WORKSPACE_DIR);
+  let manifest = std::fs::read_to_string(workspace.join("Cargo.toml"))
+    .map_err(|e| format!("failed to read the workspace's manifest: {e}"))?;
+  let manifest = manifest
+    .parse::<toml::Table>()
+    .map_err(|e| format!("failed to parse the workspace's manifest: {e}"))?;
+  let project = manifest
+    .get("workspace")
+    .and_then(|w| w.get("metadata"))
+    .and_then(|m| m.get("leptos"))
+    .and_then(|l| l.get(0))
+    .ok_or("the workspace's manifest has no Leptos project")?;
+  let setting = |key: &str| {
+    project
+      .get(key)
+      .and_then(toml::Value::as_str)
+      .ok_or(format!("the Leptos project has no `{key}`"))
+  };
+
+  Ok(
+    LeptosOptions::builder()
+      .output_name(setting("name")?)
+      .site_root(workspace.join(setting("site-root")?).to_string_lossy())
+      .site_pkg_dir(setting("site-pkg-dir")?)
+      .build(),
+  )
+}
+
+/// Copies a directory tree, creating `to` if needed.
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+  std::fs::create_dir_all(to)?;
+  for entry in std::fs::read_dir(from)? {
+    let entry = entry?;
+    let target = to.join(entry.file_name());
+    if entry.file_type()?.is_dir() {
+      copy_dir(&entry.path(), &target)?;
+    } else {
+      std::fs::copy(entry.path(), target)?;
+    }
+  }
+  Ok(())
+}
+
+/// The site, running against a temporary copy of the fixture content with its
+/// own empty database.
+pub struct TestSite {
+  app:          Router,
+  index:        PostIndex,
+  dir:          PathBuf,
+  previous_dir: PathBuf,
+  _guard:       MutexGuard<'static, ()>,
+}
+
+impl TestSite {
+  /// Copies the fixtures into a temporary directory and builds the site's
+  /// router over them.
+  pub async fn start() -> Result<Self, String> { Self::start_with(true).await }
+
+  /// Starts the site as the export commands build it, rendering pages whole
+  /// rather than streaming them. How routes are streamed is only decided for
+  /// the first site on a thread, so it can't be changed once a site is up.
+  pub async fn start_for_export() -> Result<Self, String> {
+    Self::start_with(false).await
+  }
+
+  async fn start_with(streamed: bool) -> Result<Self, String> {
+    let guard = WORKING_DIR.lock().await;
+
+    let leptos_options = leptos_options()?;
+    let previous_dir = std::env::current_dir()
+      .map_err(|e| format!("can't read the working directory: {e}"))?;
+
+    let dir = std::env::temp_dir()
+      .join(format!("site-test-{}", uuid::Uuid::new_v4().simple()));
+    copy_dir(Path::new(FIXTURES_DIR), &dir.join("content"))
+      .map_err(|e| format!("failed to copy the fixtures: {e}"))?;
+    std::env::set_current_dir(&dir)
+      .map_err(|e| format!("can't enter `{}`: {e}", dir.display()))?;
+
+    // the site is dropped, restoring the working directory, on any error from
+    // here on
+    let mut site = TestSite {
+      app: Router::new(),
+      index: PostIndex::default(),
+      dir,
+      previous_dir,
+      _guard: guard,
+    };
+
+    // `DATABASE_URL` is ignored so that a real database is never touched
+    let db = Database::connect(DEFAULT_DATABASE_URL)
+      .await
+      .map_err(|e| format!("failed to create the database: {e}"))?;
+    migrations::migrate(&db)
+      .await
+      .map_err(|e| format!("failed to apply migrations: {e}"))?;
+    site.index.refresh();
+
+    let state = crate::state::AppState {
+      leptos_options,
+      config: site_app::config::SiteConfig::default(),
+      db,
+      index: site.index.clone(),
+      live_events: crate::live::LiveEvents::default(),
+      push_config: None,
+      activitypub: None,
+      redirects: crate::redirects::Redirects::default(),
+      zero_js: false,
+      streamed,
+    };
+    site.app = crate::router(state);
+    Ok(site)
+  }
+
+  /// The temporary directory the site is running in. Fixture posts are in
+  /// `content/posts` inside it, and can be changed while the site is up, as
+  /// long as [`refresh`](TestSite::refresh) is called after.
+  pub fn dir(&self) -> &Path { &self.dir }
+
+  /// The site's router, for driving it the way the export commands do.
+  pub fn router(&self) -> Router { self.app.clone() }
+
+  /// Picks up changes to the fixture posts, as the content watcher would.
+  pub fn refresh(&self) { self.index.refresh(); }
+
+  /// Makes a GET request to the site.
+  pub async fn get(&self, path: &str) -> TestResponse {
+    let request = Request::get(path)
+      .header(header::HOST, "localhost")
+      .body(Body::empty())
+      .expect("test paths are valid URIs");
+    self.send(request).await
+  }
+
+  /// Sends a request to the site.
+  pub async fn send(&self, request: Request<Body>) -> TestResponse {
+    let path = request.uri().to_string();
+    let response = self
+      .app
+      .clone()
+      .oneshot(request)
+      .await
+      .unwrap_or_else(|e| match e {});
+    let (parts, body) = response.into_parts();
+    let body = axum::body::to_bytes(body, usize::MAX)
+      .await
+      .unwrap_or_else(|e| panic!("failed to read the body of `{path}`: {e}"));
+
+    TestResponse {
+      path,
+      status: parts.status,
+      headers: parts.headers,
+      body,
+    }
+  }
+}
+
+impl Drop for TestSite {
+  fn drop(&mut self) {
+    let _ = std::env::set_current_dir(&self.previous_dir);
+    let _ = std::fs::remove_dir_all(&self.dir);
+  }
+}
+
+/// A response from a [`TestSite`], with assertions that panic describing the
+/// response when they fail.
+pub struct TestResponse {
+  /// The path that was requested.
+  pub path:    String,
+  pub status:  StatusCode,
+  pub headers: HeaderMap,
+  pub body:    Bytes,
+}
+
+impl TestResponse {
+  /// The response body, as text.
+  pub fn text(&self) -> String { String::from_utf8_lossy(&self.body).into() }
+
+  /// Asserts that the response has the given status.
+  pub fn assert_status(&self, status: StatusCode) -> &Self {
+    assert_eq!(
+      self.status, status,
+      "`{}` responded with {}, not {status}",
+      self.path, self.status
+    );
+    self
+  }
+
+  /// Asserts that the response has a header with the given value.
+  pub fn assert_header(&self, name: header::HeaderName, value: &str) -> &Self {
+    let actual = self.headers.get(&name).and_then(|v| v.to_str().ok());
+    assert_eq!(
+      actual,
+      Some(value),
+      "`{}` has `{name}` {actual:?}, not `{value}`",
+      self.path
+    );
+    self
+  }
+
+  /// Asserts that the response body contains `needle`.
+  pub fn assert_contains(&self, needle: &str) -> &Self {
+    assert!(
+      self.text().contains(needle),
+      "`{}` doesn't contain `{needle}`",
+      self.path
+    );
+    self
+  }
+
+  /// Asserts that the response body doesn't contain `needle`.
+  pub fn assert_not_contains(&self, needle: &str) -> &Self {
+    assert!(
+      !self.text().contains(needle),
+      "`{}` contains `{needle}`",
+      self.path
+    );
+    self
+  }
+}
@@ -0,0 +1,36 @@
+//! Serves the stylesheets that colour highlighted code in each theme, see
+//! [`site_app::theme`].
+
+use std::sync::OnceLock;
+
+use axum::{
+  http::header,
+  response::{IntoResponse, Response},
+};
+use site_app::theme::ColorTheme;
+
+/// How long browsers keep a stylesheet before fetching it again, in seconds.
+/// They only change when the server is redeployed.
+const STYLESHEET_MAX_AGE_SECS: u64 = 24 * 60 * 60;
+
+/// Serves the stylesheet colouring highlighted code in `theme`.
+pub async fn code_stylesheet(theme: ColorTheme) -> Response {
+  static DARK: OnceLock<String> = OnceLock::new();
+  static LIGHT: OnceLock<String> = OnceLock::new();
+  let css = match theme {
+    ColorTheme::Dark => &DARK,
+    ColorTheme::Light => &LIGHT,
+  }
+  .get_or_init(|| theme.code_css());
+  (
+    [
+      (header::CONTENT_TYPE, "text/css; charset=utf-8".to_string()),
+      (
+        header::CACHE_CONTROL,
+        format!("public, max-age={STYLESHEET_MAX_AGE_SECS}"),
+      ),
+    ],
+    css.clone(),
+  )
+    .into_response()
+}
@@ -0,0 +1,146 @@
+//! Serving HTTPS directly, for deployments too small to be worth a reverse
+//! proxy, see [`TlsConfig`]. Connections are encrypted with rustls, and then
+//! served by [`listen::serve`](crate::listen::serve).
+
+use std::{net::SocketAddr, path::Path, sync::Arc};
+
+use axum::{
+  extract::Host,
+  http::{uri::PathAndQuery, Uri},
+  response::Redirect,
+  Router,
+};
+use site_app::config::TlsConfig;
+use tokio::net::TcpListener;
+use tokio_rustls::{
+  rustls::{
+    crypto::ring,
+    pki_types::{
+      pem::{self, PemObject},
+      CertificateDer, PrivateKeyDer,
+    },
+    ServerConfig,
+  },
+  TlsAcceptor,
+};
+
+/// Reads the certificate and key, describing what's wrong with them if they
+/// can't be used.
+pub fn acceptor(config: &TlsConfig) -> Result<TlsAcceptor, String> {
+  let read = |file: &Path| {
+    std::fs::read(file)
+      .map_err(|e| format!("can't read `{}`: {e}", file.display()))
+  };
+  let pem_error = |file: &Path, e: pem::Error| {
+    format!("can't read `{}` as PEM: {e}", file.display())
+  };
+
+  let certs = CertificateDer::pem_slice_iter(&read(&config.cert)?)
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|e| pem_error(&config.cert, e))?;
+  if certs.is_empty() {
+    return Err(format!("`{}` holds no certificates", config.cert.display()));
+  }
+  let key = PrivateKeyDer::from_pem_slice(&read(&config.key)?).map_err(
+    |e| match e {
+      pem::Error::NoItemsFound => format!(
+        "`{}` holds no private key, which should be PKCS #8 (`BEGIN PRIVATE \
+         KEY`), PKCS #1 (`BEGIN RSA PRIVATE KEY`) or SEC1 (`BEGIN EC PRIVATE \
+         KEY`)",
+        config.key.display()
+      ),
+      e => pem_error(&config.key, e),
+    },
+  )?;
+
+  let config =
+    ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+      .with_safe_default_protocol_versions()
+      .map_err(|e| format!("can't set up TLS: {e}"))?
+      .with_no_client_auth()
+      .with_single_cert(certs, key)
+      .map_err(|e| format!("invalid certificate or key: {e}"))?;
+  Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Where a plain HTTP request should be redirected to, on the same host but
+/// over HTTPS on `https_port`.
+fn https_uri(host: &str, uri: &Uri, https_port: u16) -> Option<Uri> {
+  // the host may come with the plain HTTP port, which doesn't carry over
+  let authority = host.parse::<axum::http::uri::Authority>().ok()?;
+  let host = match https_port {
+    443 => authority.host().to_string(),
+    port => format!("{}:{port}", authority.host()),
+  };
+  let path = uri.path_and_query().map_or("/", PathAndQuery::as_str);
+  format!("https://{host}{path}").parse().ok()
+}
+
+/// Redirects every request on `addr` to HTTPS on `https_port`.
+pub async fn redirect_to_https(addr: SocketAddr, https_port: u16) {
+  let listener = match TcpListener::bind(addr).await {
+    Ok(listener) => listener,
+    Err(e) => {
+      log::error!("can't redirect HTTP on {addr}: {e}");
+      return;
+    }
+  };
+  let redirect = move |Host(host): Host, uri: Uri| async move {
+    match https_uri(&host, &uri, https_port) {
+      Some(uri) => Ok(Redirect::permanent(&uri.to_string())),
+      None => Err(axum::http::StatusCode::BAD_REQUEST),
+    }
+  };
+
+  log::info!("redirecting http://{addr} to HTTPS");
+  if let Err(e) = axum::serve(listener, Router::new().fallback(redirect)).await
+  {
+    log::error!("stopped redirecting HTTP on {addr}: {e}");
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn config(cert: &str, key: &str) -> TlsConfig {
+    let fixture = |name: &str| {
+      Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/tls")
+        .join(name)
+    };
+    TlsConfig {
+      cert:             fixture(cert),
+      key:              fixture(key),
+      redirect_address: None,
+    }
+  }
+
+  #[test]
+  fn keys_are_read_in_each_pem_format() {
+    for (cert, key) in [
+      ("rsa.cert.pem", "pkcs8.key.pem"),
+      ("rsa.cert.pem", "rsa.key.pem"),
+      ("ec.cert.pem", "ec.key.pem"),
+    ] {
+      if let Err(e) = acceptor(&config(cert, key)) {
+        panic!("`{key}` wasn't read: {e}");
+      }
+    }
+  }
+
+  #[test]
+  fn unusable_certificates_and_keys_are_described() {
+    let error = |cert, key| acceptor(&config(cert, key)).err().unwrap();
+
+    assert!(error("rsa.cert.pem", "missing.pem").contains("can't read"));
+    assert!(
+      error("rsa.key.pem", "rsa.key.pem").contains("holds no certificates")
+    );
+    assert!(
+      error("rsa.cert.pem", "rsa.cert.pem").contains("holds no private key")
+    );
+    assert!(error("ec.cert.pem", "rsa.key.pem")
+      .contains("invalid certificate or key"));
+  }
+}
@@ -0,0 +1,586 @@
+//! Image uploads from the admin area, so that images can be added to posts
+//! from the browser. Images are stored in the content directory's `images`,
+//! under the post they're for or shared by every post, and the upload is
+//! answered with the markdown to paste into the post.
+//!
+//! Each image is named after a hash of its contents, so that it can be cached
+//! forever and uploading it again changes nothing. Images are stored as they
+//! were sent, apart from their metadata: JPEG, PNG and WebP files can carry
+//! where and with what they were taken, which is stripped before they're
+//! stored. AVIF files keep theirs in items the rest of the file points into by
+//! offset, so ones with EXIF or XMP metadata are turned down instead.
+
+use std::path::{Path, PathBuf};
+
+use axum::{
+  extract::{multipart::Field, Multipart, State},
+  http::{header, HeaderMap, StatusCode},
+  response::{IntoResponse, Redirect, Response},
+};
+use sha2::{Digest, Sha256};
+use site_app::{
+  config::SiteConfig,
+  post_index::PostIndex,
+  posts::{normalize_post_path, slug_of},
+};
+
+use crate::state::AppState;
+
+/// Where images are uploaded to.
+pub const UPLOAD_PATH: &str = "/upload/images";
+/// Where uploaded images are served from, within the content directory.
+pub const IMAGES_PATH_PREFIX: &str = "/images/";
+
+/// The kinds of image which can be uploaded. SVGs aren't among them, since
+/// they can hold scripts.
+#[derive(Clone, Copy)]
+enum ImageKind {
+  Png,
+  Jpeg,
+  Gif,
+  Webp,
+  Avif,
+}
+
+impl ImageKind {
+  /// What kind of image a file is, by its first bytes rather than whatever
+  /// the browser claims.
+  fn sniff(data: &[u8]) -> Option<Self> {
+    match data {
+      [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', ..] => {
+        Some(ImageKind::Png)
+      }
+      [0xff, 0xd8, 0xff, ..] => Some(ImageKind::Jpeg),
+      [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(ImageKind::Gif),
+      [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => {
+        Some(ImageKind::Webp)
+      }
+      [_, _, _, _, b'f', b't', b'y', b'p', b'a', b'v', b'i', b'f' | b's', ..] => {
+        Some(ImageKind::Avif)
+      }
+      _ => None,
+    }
+  }
+
+  fn extension(self) -> &'static str {
+    match self {
+      ImageKind::Png => "png",
+      ImageKind::Jpeg => "jpg",
+      ImageKind::Gif => "gif",
+      ImageKind::Webp => "webp",
+      ImageKind::Avif => "avif",
+    }
+  }
+}
+
+/// The orientation an EXIF segment gives its image, if it's rotated or
+/// flipped.
+fn exif_orientation(segment: &[u8]) -> Option<u16> {
+  let tiff = segment.strip_prefix(b"Exif\0\0")?;
+  let big_endian = match tiff.get(..2)? {
+    b"MM" => true,
+    b"II" => false,
+    _ => return None,
+  };
+  let u16_at = |at: usize| {
+    let bytes = [*tiff.get(at)?, *tiff.get(at + 1)?];
+    Some(if big_endian {
+      u16::from_be_bytes(bytes)
+    } else {
+      u16::from_le_bytes(bytes)
+    })
+  };
+  let u32_at = |at: usize| {
+    let bytes = tiff.get(at..at + 4)?.try_into().ok()?;
+    Some(if big_endian {
+      u32::from_be_bytes(bytes)
+    } else {
+      u32::from_le_bytes(bytes)
+    })
+  };
+  let ifd = usize::try_from(u32_at(4)?).ok()?;
+  (0..usize::from(u16_at(ifd)?))
+    .map(|entry| ifd + 2 + entry * 12)
+    .find(|entry| u16_at(*entry) == Some(0x0112))
+    .and_then(|entry| u16_at(entry + 8))
+    .filter(|orientation| (2..=8).contains(orientation))
+}
+
+/// An EXIF segment holding nothing but an orientation.
+fn orientation_segment(orientation: u16) -> Vec<u8> {
+  let mut exif = b"Exif\0\0MM\0\x2a\0\0\0\x08\0\x01".to_vec();
+  // the orientation entry, a single short, and then no next directory
+  exif.extend_from_slice(&[0x01, 0x12, 0x00, 0x03, 0, 0, 0, 1]);
+  exif.extend_from_slice(&orientation.to_be_bytes());
+  exif.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+
+  let length = u16::try_from(exif.len() + 2).expect("the segment is short");
+  let mut segment = vec![0xff, 0xe1];
+  segment.extend_from_slice(&length.to_be_bytes());
+  segment.extend_from_slice(&exif);
+  segment
+}
+
+/// A JPEG without its `APP1` to `APP15` segments, which hold EXIF, XMP and
+/// the like, or `None` if it's malformed. `APP0` is kept, since it's the JFIF
+/// header, and so is the EXIF orientation, without which photos from phones
+/// would be shown on their side.
+fn strip_jpeg(data: &[u8]) -> Option<Vec<u8>> {
+  let mut stripped = data[..2].to_vec();
+  let mut orientation = None;
+  // where the orientation goes, after the JFIF header if there is one
+  let mut header_end = stripped.len();
+  let mut rest = &data[2..];
+  loop {
+    let [0xff, marker, ..] = *rest else {
+      return None;
+    };
+    // markers may be padded with any number of fill bytes
+    if marker == 0xff {
+      rest = &rest[1..];
+      continue;
+    }
+    // everything from the start of the scan on is image data
+    if marker == 0xda {
+      if let Some(orientation) = orientation {
+        stripped
+          .splice(header_end..header_end, orientation_segment(orientation));
+      }
+      stripped.extend_from_slice(rest);
+      return Some(stripped);
+    }
+    let length =
+      usize::from(u16::from_be_bytes([*rest.get(2)?, *rest.get(3)?]));
+    // the length counts its own two bytes
+    if length < 2 {
+      return None;
+    }
+    let segment = rest.get(..2 + length)?;
+    if (0xe1..=0xef).contains(&marker) {
+      if marker == 0xe1 {
+        orientation = orientation.or(exif_orientation(&segment[4..]));
+      }
+    } else {
+      stripped.extend_from_slice(segment);
+      if marker == 0xe0 && header_end == 2 {
+        header_end = stripped.len();
+      }
+    }
+    rest = &rest[segment.len()..];
+  }
+}
+
+/// A PNG without its text and EXIF chunks, or `None` if it's malformed.
+fn strip_png(data: &[u8]) -> Option<Vec<u8>> {
+  let mut stripped = data[..8].to_vec();
+  let mut rest = &data[8..];
+  while !rest.is_empty() {
+    let length = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?);
+    let chunk_type = rest.get(4..8)?;
+    // the length, type, data and CRC
+    let chunk = rest.get(..12 + usize::try_from(length).ok()?)?;
+    if !matches!(chunk_type, b"tEXt" | b"zTXt" | b"iTXt" | b"eXIf" | b"tIME") {
+      stripped.extend_from_slice(chunk);
+    }
+    rest = &rest[chunk.len()..];
+  }
+  Some(stripped)
+}
+
+/// The flags in a WebP's `VP8X` chunk saying it has EXIF and XMP chunks.
+const WEBP_METADATA_FLAGS: u8 = 0x08 | 0x04;
+
+/// A WebP without its EXIF and XMP chunks, or `None` if it's malformed.
+fn strip_webp(data: &[u8]) -> Option<Vec<u8>> {
+  let mut stripped = data.get(..12)?.to_vec();
+  let mut rest = &data[12..];
+  while !rest.is_empty() {
+    let length = u32::from_le_bytes(rest.get(4..8)?.try_into().ok()?);
+    let length = usize::try_from(length).ok()?;
+    // the type, length and data, then a byte of padding if the data's length
+    // is odd, which some encoders leave off the last chunk
+    rest.get(..8 + length)?;
+    let chunk = &rest[..(8 + length + length % 2).min(rest.len())];
+    match &chunk[..4] {
+      b"EXIF" | b"XMP " => {}
+      b"VP8X" => {
+        let flags = stripped.len() + 8;
+        stripped.extend_from_slice(chunk);
+        *stripped.get_mut(flags)? &= !WEBP_METADATA_FLAGS;
+      }
+      _ => stripped.extend_from_slice(chunk),
+    }
+    rest = &rest[chunk.len()..];
+  }
+  let riff_length = u32::try_from(stripped.len() - 8).ok()?;
+  stripped[4..8].copy_from_slice(&riff_length.to_le_bytes());
+  Some(stripped)
+}
+
+/// The boxes an ISO base media file, or a box of one, is made of, as their
+/// types and contents, or `None` if they're malformed.
+fn bmff_boxes(mut data: &[u8]) -> Option<Vec<(&[u8], &[u8])>> {
+  let mut boxes = Vec::new();
+  while !data.is_empty() {
+    let box_type = data.get(4..8)?;
+    let (header, size) =
+      match u32::from_be_bytes(data.get(..4)?.try_into().ok()?) {
+        // the box runs to the end of the file
+        0 => (8, data.len()),
+        1 => {
+          let size = u64::from_be_bytes(data.get(8..16)?.try_into().ok()?);
+          (16, usize::try_from(size).ok()?)
+        }
+        size => (8, usize::try_from(size).ok()?),
+      };
+    boxes.push((box_type, data.get(header..size)?));
+    data = &data[size..];
+  }
+  Some(boxes)
+}
+
+/// Whether an AVIF has EXIF or XMP metadata items, or `None` if it's
+/// malformed.
+fn avif_has_metadata(data: &[u8]) -> Option<bool> {
+  let boxes = bmff_boxes(data)?;
+  let Some((_, meta)) = boxes.iter().find(|(t, _)| *t == b"meta") else {
+    return Some(false);
+  };
+  // `meta`, `iinf` and `infe` are full boxes, starting with a version and flags
+  let meta = bmff_boxes(meta.get(4..)?)?;
+  let Some((_, iinf)) = meta.iter().find(|(t, _)| *t == b"iinf") else {
+    return Some(false);
+  };
+  // then the number of entries, in two bytes before version 1 and four after
+  let entries = iinf.get(if *iinf.first()? == 0 { 6 } else { 8 }..)?;
+
+  for (_, infe) in bmff_boxes(entries)?.iter().filter(|(t, _)| *t == b"infe") {
+    // the item's ID, in two bytes in version 2 and four in version 3, and its
+    // protection index come before its type. Earlier versions have no type.
+    let item_type = match infe.first()? {
+      2 => infe.get(8..12)?,
+      3 => infe.get(10..14)?,
+      _ => continue,
+    };
+    let is_xmp = || {
+      // the type is followed by the item's name and its content type
+      let mut strings = infe
+        .get(if infe[0] == 2 { 12 } else { 14 }..)?
+        .split(|b| *b == 0);
+      Some(strings.nth(1)? == b"application/rdf+xml")
+    };
+    if item_type == b"Exif" || (item_type == b"mime" && is_xmp()?) {
+      return Some(true);
+    }
+  }
+  Some(false)
+}
+
+/// An image as it's stored, without its metadata.
+fn strip_metadata(kind: ImageKind, data: Vec<u8>) -> Result<Vec<u8>, String> {
+  let stripped = match kind {
+    ImageKind::Jpeg => strip_jpeg(&data),
+    ImageKind::Png => strip_png(&data),
+    ImageKind::Webp => strip_webp(&data),
+    ImageKind::Avif => match avif_has_metadata(&data) {
+      Some(true) => {
+        return Err(
+          "has EXIF or XMP metadata, which can't be stripped from AVIF images"
+            .to_string(),
+        )
+      }
+      Some(false) => Some(data),
+      None => None,
+    },
+    ImageKind::Gif => return Ok(data),
+  };
+  stripped.ok_or_else(|| format!("isn't a valid {}", kind.extension()))
+}
+
+/// The directory images for `post` are stored in, or shared images if there's
+/// no post.
+fn images_dir(config: &SiteConfig, post: Option<&str>) -> PathBuf {
+  let dir = config
+    .content_dir
+    .join(IMAGES_PATH_PREFIX.trim_matches('/'));
+  match post {
+    Some(post) => dir.join(post),
+    None => dir,
+  }
+}
+
+/// Stores an image, returning the path it's served at.
+async fn store(
+  config: &SiteConfig,
+  post: Option<&str>,
+  file_name: &str,
+  data: Vec<u8>,
+) -> Result<String, String> {
+  let kind = ImageKind::sniff(&data)
+    .ok_or_else(|| "isn't a PNG, JPEG, GIF, WebP or AVIF image".to_string())?;
+  let data = tokio::task::spawn_blocking(move || strip_metadata(kind, data))
+    .await
+    .map_err(|e| e.to_string())??;
+
+  let stem = Path::new(file_name)
+    .file_stem()
+    .and_then(|stem| stem.to_str())
+    .map(slug_of)
+    .filter(|stem| !stem.is_empty())
+    .unwrap_or_else(|| "image".to_string());
+  let hash = format!("{:x}", Sha256::digest(&data));
+  let name = format!("{stem}-{}.{}", &hash[..12], kind.extension());
+
+  let dir = images_dir(config, post);
+  tokio::fs::create_dir_all(&dir)
+    .await
+    .map_err(|e| format!("can't create `{}`: {e}", dir.display()))?;
+  let file = dir.join(&name);
+  tokio::fs::write(&file, &data)
+    .await
+    .map_err(|e| format!("can't write `{}`: {e}", file.display()))?;
+  log::info!("uploaded `{}`", file.display());
+
+  Ok(match post {
+    Some(post) => format!("{IMAGES_PATH_PREFIX}{post}/{name}"),
+    None => format!("{IMAGES_PATH_PREFIX}{name}"),
+  })
+}
+
+/// The markdown for an image, with alt text to fill in unless it was given.
+fn snippet(path: &str, file_name: &str, alt: &str) -> String {
+  let alt = match alt.trim() {
+    "" => Path::new(file_name)
+      .file_stem()
+      .and_then(|stem| stem.to_str())
+      .unwrap_or_default()
+      .replace(['-', '_'], " "),
+    alt => alt.to_string(),
+  };
+  format!("![{}]({path})", alt.replace(['[', ']'], ""))
+}
+
+async fn field_text(field: Field<'_>) -> Result<String, String> {
+  field.text().await.map_err(|e| e.to_string())
+}
+
+/// Reads the upload form: an optional `post` to store the images with, an
+/// optional `alt` text, and any number of `file`s.
+async fn read_upload(
+  config: &SiteConfig,
+  index: &PostIndex,
+  mut multipart: Multipart,
+) -> Result<Vec<String>, String> {
+  let mut post = None;
+  let mut alt = String::new();
+  let mut snippets = Vec::new();
+  while let Some(field) =
+    multipart.next_field().await.map_err(|e| e.to_string())?
+  {
+    match field.name().unwrap_or_default() {
+      "post" => {
+        let path = field_text(field).await?;
+        if path.trim().is_empty() {
+          continue;
+        }
+        let path = normalize_post_path(&path)
+          .filter(|path| index.source(path).is_some())
+          .ok_or_else(|| format!("there's no post `{path}`"))?;
+        post = Some(path);
+      }
+      "alt" => alt = field_text(field).await?,
+      "file" => {
+        let file_name = field.file_name().unwrap_or_default().to_string();
+        let data = field.bytes().await.map_err(|e| e.to_string())?;
+        // a form submitted without choosing a file sends an empty one
+        if data.is_empty() && file_name.is_empty() {
+          continue;
+        }
+        let path = store(config, post.as_deref(), &file_name, data.to_vec())
+          .await
+          .map_err(|e| format!("`{file_name}` {e}"))?;
+        snippets.push(snippet(&path, &file_name, &alt));
+      }
+      _ => {}
+    }
+  }
+  if snippets.is_empty() {
+    return Err("no images were uploaded".to_string());
+  }
+  Ok(snippets)
+}
+
+/// Stores uploaded images, answering with the markdown for them. Uploads from
+/// the admin area's form go back to it, with the markdown to copy. The route
+/// is guarded by [`require_admin`](crate::auth::require_admin).
+pub async fn upload_images(
+  State(state): State<AppState>,
+  headers: HeaderMap,
+  multipart: Multipart,
+) -> Response {
+  let from_form = headers
+    .get(header::ACCEPT)
+    .and_then(|v| v.to_str().ok())
+    .is_some_and(|v| v.contains("text/html"));
+
+  match read_upload(&state.config, &state.index, multipart).await {
+    Ok(snippets) if from_form => {
+      let snippets = snippets.join("\n");
+      let query = percent_encoding::utf8_percent_encode(
+        &snippets,
+        percent_encoding::NON_ALPHANUMERIC,
+      );
+      Redirect::to(&format!("/admin/upload?uploaded={query}")).into_response()
+    }
+    Ok(snippets) => snippets.join("\n").into_response(),
+    Err(e) => {
+      log::warn!("failed upload: {e}");
+      (StatusCode::BAD_REQUEST, format!("The upload failed: {e}."))
+        .into_response()
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn jpeg_metadata_is_stripped_but_orientation_kept() {
+    let mut jpeg = vec![0xff, 0xd8];
+    jpeg.extend_from_slice(&[0xff, 0xe0, 0x00, 0x04, b'J', b'F']);
+    jpeg.extend_from_slice(&orientation_segment(6));
+    jpeg.extend_from_slice(&[0xff, 0xe2, 0x00, 0x04, b'X', b'X']);
+    jpeg.extend_from_slice(&[0xff, 0xda, 0x01, 0x02]);
+
+    let mut expected = vec![0xff, 0xd8];
+    expected.extend_from_slice(&[0xff, 0xe0, 0x00, 0x04, b'J', b'F']);
+    expected.extend_from_slice(&orientation_segment(6));
+    expected.extend_from_slice(&[0xff, 0xda, 0x01, 0x02]);
+    assert_eq!(strip_jpeg(&jpeg), Some(expected));
+  }
+
+  /// A RIFF chunk, or an ISO BMFF box, with its type and contents.
+  fn chunk(chunk_type: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut chunk = chunk_type.to_vec();
+    chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+      chunk.push(0);
+    }
+    chunk
+  }
+
+  fn webp(chunks: &[Vec<u8>]) -> Vec<u8> {
+    let chunks = chunks.concat();
+    let mut webp = b"RIFF".to_vec();
+    webp.extend_from_slice(&(chunks.len() as u32 + 4).to_le_bytes());
+    webp.extend_from_slice(b"WEBP");
+    webp.extend_from_slice(&chunks);
+    webp
+  }
+
+  fn bmff_box(box_type: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut bmff_box = (data.len() as u32 + 8).to_be_bytes().to_vec();
+    bmff_box.extend_from_slice(box_type);
+    bmff_box.extend_from_slice(data);
+    bmff_box
+  }
+
+  /// An AVIF with an item of each type, and content type for `mime` items.
+  fn avif(items: &[(&[u8], &str)]) -> Vec<u8> {
+    let entries = items
+      .iter()
+      .enumerate()
+      .map(|(id, (item_type, content_type))| {
+        let mut infe = vec![2, 0, 0, 0, 0, id as u8 + 1, 0, 0];
+        infe.extend_from_slice(item_type);
+        infe.extend_from_slice(b"\0");
+        if *item_type == b"mime" {
+          infe.extend_from_slice(content_type.as_bytes());
+          infe.push(0);
+        }
+        bmff_box(b"infe", &infe)
+      })
+      .collect::<Vec<_>>()
+      .concat();
+    let mut iinf = vec![0, 0, 0, 0, 0, items.len() as u8];
+    iinf.extend_from_slice(&entries);
+    let mut meta = vec![0, 0, 0, 0];
+    meta.extend_from_slice(&bmff_box(b"hdlr", &[0; 24]));
+    meta.extend_from_slice(&bmff_box(b"iinf", &iinf));
+
+    let mut avif = bmff_box(b"ftyp", b"avif\0\0\0\0");
+    avif.extend_from_slice(&bmff_box(b"meta", &meta));
+    avif.extend_from_slice(&bmff_box(b"mdat", b"pixels"));
+    avif
+  }
+
+  #[test]
+  fn webp_metadata_chunks_are_stripped() {
+    let vp8x = |flags: u8| chunk(b"VP8X", &[flags, 0, 0, 0, 9, 0, 0, 9, 0, 0]);
+    let image = chunk(b"VP8 ", b"pixels!");
+    let webp_with = webp(&[
+      vp8x(0x10 | WEBP_METADATA_FLAGS),
+      chunk(b"ICCP", b"profile"),
+      image.clone(),
+      chunk(b"EXIF", b"where it was taken"),
+      chunk(b"XMP ", b"<x:xmpmeta/>"),
+    ]);
+    let webp_without =
+      webp(&[vp8x(0x10), chunk(b"ICCP", b"profile"), image.clone()]);
+    assert_eq!(
+      ImageKind::sniff(&webp_with).map(ImageKind::extension),
+      Some("webp")
+    );
+    assert_eq!(strip_webp(&webp_with), Some(webp_without.clone()));
+    assert_eq!(strip_webp(&webp_without), Some(webp_without));
+
+    // the last chunk's padding byte is optional, and the RIFF length is made
+    // to match
+    let padded = webp(&[image]);
+    let mut unpadded = padded[..padded.len() - 1].to_vec();
+    assert_eq!(
+      strip_webp(&unpadded).map(|webp| webp.len()),
+      Some(unpadded.len())
+    );
+    let riff_length = unpadded.len() as u32 - 8;
+    unpadded[4..8].copy_from_slice(&riff_length.to_le_bytes());
+    assert_eq!(strip_webp(&unpadded), Some(unpadded.clone()));
+    // but a chunk longer than the file isn't
+    assert_eq!(strip_webp(&padded[..padded.len() - 2]), None);
+  }
+
+  #[test]
+  fn avifs_with_metadata_items_are_refused() {
+    let plain = avif(&[(b"av01", "")]);
+    assert_eq!(
+      ImageKind::sniff(&plain).map(ImageKind::extension),
+      Some("avif")
+    );
+    assert_eq!(avif_has_metadata(&plain), Some(false));
+    assert_eq!(strip_metadata(ImageKind::Avif, plain.clone()), Ok(plain));
+
+    let exif = avif(&[(b"av01", ""), (b"Exif", "")]);
+    assert_eq!(avif_has_metadata(&exif), Some(true));
+    assert!(strip_metadata(ImageKind::Avif, exif).is_err());
+    let xmp = avif(&[(b"av01", ""), (b"mime", "application/rdf+xml")]);
+    assert_eq!(avif_has_metadata(&xmp), Some(true));
+    let other = avif(&[(b"av01", ""), (b"mime", "text/plain")]);
+    assert_eq!(avif_has_metadata(&other), Some(false));
+
+    let truncated = avif(&[(b"av01", "")]);
+    assert_eq!(avif_has_metadata(&truncated[..truncated.len() - 3]), None);
+  }
+
+  #[test]
+  fn truncated_app1_segments_are_malformed() {
+    for length in [0, 1] {
+      let jpeg = [0xff, 0xd8, 0xff, 0xe1, 0x00, length, 0xff, 0xda];
+      assert_eq!(strip_jpeg(&jpeg), None);
+    }
+    // a segment too short for its EXIF header
+    let jpeg = [0xff, 0xd8, 0xff, 0xe1, 0x00, 0x02, 0xff, 0xda, 0x00];
+    assert_eq!(strip_jpeg(&jpeg), Some(vec![0xff, 0xd8, 0xff, 0xda, 0x00]));
+  }
+}
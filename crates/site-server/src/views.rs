@@ -0,0 +1,147 @@
+//! Counts views of post pages, see
+//! [`ViewsConfig`](site_app::config::ViewsConfig).
+//!
+//! A reader is only counted once per post every [`DEDUP_WINDOW`], telling
+//! readers apart by a hash of their address, browser and a secret made when
+//! the server starts. The hashes are only kept in memory, and only for as
+//! long as they're needed. Requests from the author, bots, prefetches and the
+//! server itself, like exports, aren't counted.
+
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+use axum::{
+  extract::{Request, State},
+  http::{header, HeaderMap, Method, StatusCode},
+  middleware::Next,
+  response::Response,
+};
+use sha2::{Digest, Sha256};
+use site_app::{auth::has_admin_session, posts::normalize_post_path};
+use site_db::storage::AnalyticsStore;
+
+use crate::state::AppState;
+
+/// How long a reader's view of a post keeps them from being counted again.
+const DEDUP_WINDOW: Duration = Duration::from_secs(30 * 60);
+/// How many recent views are remembered before those that have left the
+/// window are dropped.
+const MAX_RECENT: usize = 10_000;
+/// What the user agents of crawlers and the like tend to call themselves.
+const BOT_MARKERS: &[&str] = &["bot", "crawler", "spider", "preview", "curl"];
+
+/// The views counted lately, by the hash of who viewed which post.
+#[derive(Clone)]
+pub struct ViewCounter {
+  state:  AppState,
+  secret: String,
+  recent: Arc<Mutex<HashMap<[u8; 32], Instant>>>,
+}
+
+impl ViewCounter {
+  pub fn new(state: AppState) -> Self {
+    ViewCounter {
+      state,
+      secret: uuid::Uuid::new_v4().to_string(),
+      recent: Default::default(),
+    }
+  }
+
+  /// Whether a reader's view of `post` is new, remembering it if so.
+  fn is_new(&self, client: &str, user_agent: &str, post: &str) -> bool {
+    let hash: [u8; 32] = Sha256::new()
+      .chain_update(&self.secret)
+      .chain_update([0])
+      .chain_update(client)
+      .chain_update([0])
+      .chain_update(user_agent)
+      .chain_update([0])
+      .chain_update(post)
+      .finalize()
+      .into();
+    let now = Instant::now();
+
+    let mut recent = self.recent.lock().unwrap();
+    if recent.len() >= MAX_RECENT {
+      recent.retain(|_, viewed| now.duration_since(*viewed) < DEDUP_WINDOW);
+    }
+    match recent.get(&hash) {
+      Some(viewed) if now.duration_since(*viewed) < DEDUP_WINDOW => false,
+      _ => {
+        recent.insert(hash, now);
+        true
+      }
+    }
+  }
+}
+
+/// Whether a request is a person reading the page, rather than a bot or the
+/// browser fetching it ahead of time.
+pub(crate) fn is_reader(headers: &HeaderMap) -> bool {
+  let is_prefetch = ["sec-purpose", "purpose"].iter().any(|name| {
+    headers
+      .get(*name)
+      .and_then(|v| v.to_str().ok())
+      .is_some_and(|v| v.contains("prefetch"))
+  });
+  let is_bot = headers
+    .get(header::USER_AGENT)
+    .and_then(|v| v.to_str().ok())
+    .map(str::to_ascii_lowercase)
+    .map_or(true, |agent| {
+      BOT_MARKERS.iter().any(|marker| agent.contains(marker))
+    });
+  !is_prefetch && !is_bot
+}
+
+/// Counts the view of a post page once it's been served.
+pub async fn count_views(
+  State(counter): State<ViewCounter>,
+  request: Request,
+  next: Next,
+) -> Response {
+  let state = &counter.state;
+  let post = request
+    .uri()
+    .path()
+    .strip_prefix("/post/")
+    .filter(|post| !post.contains('/'))
+    .map(|post| percent_encoding::percent_decode_str(post).decode_utf8_lossy())
+    .and_then(|post| normalize_post_path(&post));
+  let is_counted = request.method() == Method::GET
+    && is_reader(request.headers())
+    && !has_admin_session(&state.config, &state.db, request.headers()).await;
+  let client = state
+    .config
+    .rate_limit
+    .client_ip(request.headers(), request.extensions());
+  let user_agent = request
+    .headers()
+    .get(header::USER_AGENT)
+    .and_then(|v| v.to_str().ok())
+    .unwrap_or_default()
+    .to_string();
+
+  let response = next.run(request).await;
+
+  // requests made from within the server have no client
+  let (Some(post), Some(client), true) = (post, client, is_counted) else {
+    return response;
+  };
+  if response.status() != StatusCode::OK
+    || !state.index.is_public(&post)
+    || !counter.is_new(&client.to_string(), &user_agent, &post)
+  {
+    return response;
+  }
+  let db = state.db.clone();
+  tokio::spawn(async move {
+    if let Err(e) = db.record_page_view(&format!("/post/{post}")).await {
+      log::error!("failed to count a view of `{post}`: {e}");
+    }
+  });
+  response
+}
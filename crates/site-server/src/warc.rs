@@ -0,0 +1,229 @@
+//! The `export-warc` command, which crawls the rendered site and writes a WARC
+//! archive of it, so that each milestone of the site can be preserved and
+//! replayed.
+//!
+//! Each crawled response becomes a WARC `response` record, and a CDX index of
+//! the records is written next to the archive for replay tools.
+
+use std::{
+  fmt,
+  io::{self, Write},
+  path::{Path, PathBuf},
+};
+
+use axum::{
+  http::{header, response::Parts},
+  Router,
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::crawl::Crawler;
+
+/// The WARC version the archive is written in.
+const WARC_VERSION: &str = "WARC/1.1";
+/// The directory archives are written to by default.
+const ARCHIVE_DIR: &str = "archive";
+
+/// The default archive path, timestamped so milestones don't overwrite each
+/// other.
+pub fn default_archive_path() -> PathBuf {
+  let now = OffsetDateTime::now_utc();
+  Path::new(ARCHIVE_DIR).join(format!("site-{}.warc", cdx_timestamp(now)))
+}
+
+/// What was written to an archive.
+pub struct ExportSummary {
+  archive: PathBuf,
+  index:   PathBuf,
+  /// The number of responses archived, by whether they were successful.
+  ok:      usize,
+  failed:  usize,
+  /// The total size of the archive in bytes.
+  size:    u64,
+}
+
+impl fmt::Display for ExportSummary {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "archived {} responses ({} unsuccessful) to `{}` ({} bytes), indexed in \
+       `{}`",
+      self.ok + self.failed,
+      self.failed,
+      self.archive.display(),
+      self.size,
+      self.index.display()
+    )
+  }
+}
+
+/// A line of the CDX index.
+struct IndexEntry {
+  url:       String,
+  timestamp: String,
+  mime:      String,
+  status:    u16,
+  length:    u64,
+  offset:    u64,
+}
+
+fn cdx_timestamp(date: OffsetDateTime) -> String {
+  format!(
+    "{:04}{:02}{:02}{:02}{:02}{:02}",
+    date.year(),
+    u8::from(date.month()),
+    date.day(),
+    date.hour(),
+    date.minute(),
+    date.second()
+  )
+}
+
+fn warc_date(date: OffsetDateTime) -> String {
+  date
+    .replace_nanosecond(0)
+    .unwrap_or(date)
+    .format(&Rfc3339)
+    .expect("UTC dates always format as RFC 3339")
+}
+
+/// Writes a single WARC record, returning its length in bytes.
+fn write_record(
+  out: &mut impl Write,
+  headers: &[(&str, &str)],
+  block: &[u8],
+) -> io::Result<u64> {
+  let mut head = format!("{WARC_VERSION}\r\n");
+  let record_id = format!("<urn:uuid:{}>", uuid::Uuid::new_v4());
+  for (name, value) in [("WARC-Record-ID", record_id.as_str())]
+    .into_iter()
+    .chain(headers.iter().copied())
+  {
+    head.push_str(&format!("{name}: {value}\r\n"));
+  }
+  head.push_str(&format!("Content-Length: {}\r\n\r\n", block.len()));
+
+  out.write_all(head.as_bytes())?;
+  out.write_all(block)?;
+  out.write_all(b"\r\n\r\n")?;
+  Ok((head.len() + block.len() + 4) as u64)
+}
+
+/// Serializes a response as it would have been sent over HTTP/1.1. The body
+/// has already been read in full, so it's sent with a plain `Content-Length`.
+fn http_response_block(parts: &Parts, body: &[u8]) -> Vec<u8> {
+  let mut block = format!(
+    "HTTP/1.1 {} {}\r\n",
+    parts.status.as_u16(),
+    parts.status.canonical_reason().unwrap_or_default()
+  );
+  for (name, value) in parts.headers.iter() {
+    if name == header::CONTENT_LENGTH || name == header::TRANSFER_ENCODING {
+      continue;
+    }
+    block.push_str(&format!(
+      "{name}: {}\r\n",
+      String::from_utf8_lossy(value.as_bytes())
+    ));
+  }
+  block.push_str(&format!("content-length: {}\r\n\r\n", body.len()));
+
+  let mut block = block.into_bytes();
+  block.extend_from_slice(body);
+  block
+}
+
+/// Crawls the site behind `app` and writes the archive to `out`, with its
+/// CDX index next to it.
+pub async fn export(
+  app: Router,
+  base_url: &str,
+  out: &Path,
+) -> io::Result<ExportSummary> {
+  let mut crawler = Crawler::new(app, base_url);
+  let base_url = crawler.base_url().to_string();
+
+  if let Some(parent) = out.parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+  let mut archive = io::BufWriter::new(std::fs::File::create(out)?);
+  let mut offset = 0;
+
+  let info = format!(
+    "software: site-server/{}\r\nformat: WARC File Format 1.1\r\nisPartOf: \
+     {base_url}\r\n",
+    env!("CARGO_PKG_VERSION")
+  );
+  offset += write_record(
+    &mut archive,
+    &[
+      ("WARC-Type", "warcinfo"),
+      ("WARC-Date", &warc_date(OffsetDateTime::now_utc())),
+      ("Content-Type", "application/warc-fields"),
+    ],
+    info.as_bytes(),
+  )?;
+
+  let mut index = Vec::new();
+  let (mut ok, mut failed) = (0, 0);
+
+  while let Some(crawled) = crawler.next().await {
+    let crawled = crawled.map_err(io::Error::other)?;
+    if crawled.is_ok() {
+      ok += 1;
+    } else {
+      failed += 1;
+    }
+
+    let url = format!("{base_url}{}", crawled.path);
+    let length = write_record(
+      &mut archive,
+      &[
+        ("WARC-Type", "response"),
+        ("WARC-Target-URI", &url),
+        ("WARC-Date", &warc_date(crawled.fetched)),
+        ("Content-Type", "application/http;msgtype=response"),
+      ],
+      &http_response_block(&crawled.parts, &crawled.body),
+    )?;
+    index.push(IndexEntry {
+      url,
+      timestamp: cdx_timestamp(crawled.fetched),
+      mime: crawled.mime,
+      status: crawled.parts.status.as_u16(),
+      length,
+      offset,
+    });
+    offset += length;
+  }
+  archive.flush()?;
+
+  let index_path = out.with_extension("warc.cdx");
+  let file_name = out
+    .file_name()
+    .map(|name| name.to_string_lossy().into_owned())
+    .unwrap_or_default();
+  let mut index_file = io::BufWriter::new(std::fs::File::create(&index_path)?);
+  writeln!(index_file, " CDX a b m s S V g")?;
+  for entry in index.iter() {
+    writeln!(
+      index_file,
+      "{} {} {} {} {} {} {file_name}",
+      entry.url,
+      entry.timestamp,
+      entry.mime,
+      entry.status,
+      entry.length,
+      entry.offset
+    )?;
+  }
+  index_file.flush()?;
+
+  Ok(ExportSummary {
+    archive: out.to_path_buf(),
+    index: index_path,
+    ok,
+    failed,
+    size: offset,
+  })
+}
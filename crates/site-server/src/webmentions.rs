@@ -0,0 +1,313 @@
+//! Sends webmentions for the links in each post as it's published or changed,
+//! see [`site_app::webmentions`], and records how each went.
+//!
+//! Posts are often saved several times in a row, so a post's webmentions are
+//! only sent once it's gone unchanged for a little while, and a change while
+//! they're being sent starts them over. Targets which accepted a webmention
+//! aren't sent another unless the post's links have changed since. Endpoints
+//! which fail, or ask to be tried later, are retried with a growing delay until
+//! they've been tried [`MAX_ATTEMPTS`] times.
+
+use std::{
+  collections::{BTreeSet, HashMap},
+  time::Duration,
+};
+
+use site_app::{
+  config::site_config, live::LiveEvent, post_index::PostIndex, posts::blocking,
+  webmentions,
+};
+use site_db::{
+  storage::{SentWebmention, WebmentionStatus, WebmentionStore},
+  Database,
+};
+use tokio::{
+  sync::broadcast::error::RecvError,
+  task::{JoinHandle, JoinSet},
+};
+
+use crate::live::LiveEvents;
+
+/// How long a post has to go unchanged before its webmentions are sent.
+const SETTLE_DELAY: Duration = Duration::from_secs(30);
+/// How many times a webmention is sent before it's given up on.
+const MAX_ATTEMPTS: i64 = 5;
+/// How long to wait before sending a webmention again, doubling after each
+/// attempt.
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Sends the webmentions of each post published or changed on `events`.
+pub async fn send_for_changed_posts(
+  events: LiveEvents,
+  db: Database,
+  index: PostIndex,
+) {
+  let mut receiver = events.subscribe();
+  let mut sending = HashMap::<String, JoinHandle<()>>::new();
+
+  loop {
+    let path = match receiver.recv().await {
+      Ok(LiveEvent::NewPost { path, .. } | LiveEvent::PostUpdated { path }) => {
+        path
+      }
+      Ok(_) => continue,
+      Err(RecvError::Lagged(skipped)) => {
+        log::warn!("webmention sender missed {skipped} live events");
+        continue;
+      }
+      Err(RecvError::Closed) => return,
+    };
+
+    if let Some(task) = sending.remove(&path) {
+      task.abort();
+    }
+    sending.retain(|_, task| !task.is_finished());
+    let task =
+      tokio::spawn(send_for_post(db.clone(), index.clone(), path.clone()));
+    sending.insert(path, task);
+  }
+}
+
+/// The targets of a post's webmentions: each page it links to, and each it
+/// linked to before, so that those pages can drop their mention of it. If the
+/// links are the same as when they were last sent, the targets which accepted
+/// them are left out.
+fn targets(links: Vec<String>, sent: &[SentWebmention]) -> Vec<String> {
+  let was_linked = sent
+    .iter()
+    .filter(|webmention| webmention.linked)
+    .map(|webmention| &webmention.target)
+    .collect::<BTreeSet<_>>();
+  let links_changed = was_linked != links.iter().collect();
+
+  let mut targets = links;
+  for webmention in sent {
+    if !targets.contains(&webmention.target) {
+      targets.push(webmention.target.clone());
+    }
+  }
+  if !links_changed {
+    targets.retain(|target| {
+      !sent.iter().any(|webmention| {
+        webmention.target == *target
+          && webmention.status == WebmentionStatus::Accepted
+      })
+    });
+  }
+  targets
+}
+
+/// Sends the webmentions of a public post, see [`targets`].
+async fn send_for_post(db: Database, index: PostIndex, path: String) {
+  tokio::time::sleep(SETTLE_DELAY).await;
+  let post = {
+    let path = path.clone();
+    blocking(move || index.post(&path)).await
+  };
+  let Ok(Some(Ok(post))) = post else {
+    return;
+  };
+  if !post.metadata.public {
+    return;
+  }
+
+  let links = webmentions::external_links(&post.html_content);
+  let sent = db.webmentions_for_post(&path).await.unwrap_or_else(|e| {
+    log::error!("failed to fetch the webmentions of `{path}`: {e}");
+    Vec::new()
+  });
+  let targets = targets(links.clone(), &sent);
+  if targets.is_empty() {
+    return;
+  }
+  log::info!("sending {} webmentions for `{path}`", targets.len());
+
+  let source = site_config().url(&format!("/post/{path}"));
+  // dropped, and so aborted, if the post changes again
+  let mut sends = JoinSet::new();
+  for target in targets {
+    let linked = links.contains(&target);
+    sends.spawn(send_with_retries(
+      db.clone(),
+      path.clone(),
+      source.clone(),
+      target,
+      linked,
+    ));
+  }
+  while sends.join_next().await.is_some() {}
+}
+
+/// Where an attempt at sending a webmention got to.
+enum Attempt {
+  NoEndpoint,
+  /// The endpoint answered with a status.
+  Answered {
+    endpoint: String,
+    status:   u16,
+  },
+  /// The target or the endpoint couldn't be reached, or answered with
+  /// nonsense.
+  Failed {
+    endpoint: Option<String>,
+    error:    String,
+  },
+}
+
+async fn attempt(source: &str, target: &str) -> Attempt {
+  let endpoint = match webmentions::discover_endpoint(target).await {
+    Ok(Some(endpoint)) => endpoint,
+    Ok(None) => return Attempt::NoEndpoint,
+    Err(error) => {
+      return Attempt::Failed {
+        endpoint: None,
+        error,
+      }
+    }
+  };
+  match webmentions::send(&endpoint, source, target).await {
+    Ok(status) => Attempt::Answered { endpoint, status },
+    Err(error) => Attempt::Failed {
+      endpoint: Some(endpoint),
+      error,
+    },
+  }
+}
+
+/// Sends a webmention until the endpoint accepts or rejects it, recording
+/// each attempt.
+async fn send_with_retries(
+  db: Database,
+  post_path: String,
+  source: String,
+  target: String,
+  linked: bool,
+) {
+  let mut webmention = SentWebmention {
+    post_path,
+    target,
+    endpoint: None,
+    status: WebmentionStatus::Sending,
+    detail: None,
+    attempts: 0,
+    linked,
+    updated_at: 0,
+  };
+  let mut retry_delay = FIRST_RETRY_DELAY;
+
+  loop {
+    webmention.attempts += 1;
+    let (status, endpoint, detail) =
+      match attempt(&source, &webmention.target).await {
+        Attempt::NoEndpoint => (WebmentionStatus::NoEndpoint, None, None),
+        Attempt::Answered { endpoint, status } => {
+          let detail = Some(format!("answered {status}"));
+          match status {
+            200..=299 => (WebmentionStatus::Accepted, Some(endpoint), detail),
+            // asked to be tried later, or failed on the endpoint's side
+            429 | 500..=599 => {
+              (WebmentionStatus::Sending, Some(endpoint), detail)
+            }
+            _ => (WebmentionStatus::Failed, Some(endpoint), detail),
+          }
+        }
+        Attempt::Failed { endpoint, error } => {
+          (WebmentionStatus::Sending, endpoint, Some(error))
+        }
+      };
+    webmention.status = match status {
+      WebmentionStatus::Sending if webmention.attempts >= MAX_ATTEMPTS => {
+        WebmentionStatus::Failed
+      }
+      status => status,
+    };
+    webmention.endpoint = endpoint.or(webmention.endpoint.take());
+    webmention.detail = detail;
+
+    match webmention.status {
+      WebmentionStatus::Accepted => log::info!(
+        "webmention for `{}` was accepted by {}",
+        webmention.target,
+        webmention.endpoint.as_deref().unwrap_or_default()
+      ),
+      WebmentionStatus::Failed => log::warn!(
+        "gave up sending a webmention for `{}`: {}",
+        webmention.target,
+        webmention.detail.as_deref().unwrap_or_default()
+      ),
+      WebmentionStatus::Sending | WebmentionStatus::NoEndpoint => {}
+    }
+    if let Err(e) = db.record_webmention(&webmention).await {
+      log::error!("failed to record a webmention: {e}");
+    }
+
+    if webmention.status != WebmentionStatus::Sending {
+      return;
+    }
+    tokio::time::sleep(retry_delay).await;
+    retry_delay *= 2;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn sent(
+    target: &str,
+    status: WebmentionStatus,
+    linked: bool,
+  ) -> SentWebmention {
+    SentWebmention {
+      post_path: "post".to_string(),
+      target: target.to_string(),
+      endpoint: None,
+      status,
+      detail: None,
+      attempts: 1,
+      linked,
+      updated_at: 0,
+    }
+  }
+
+  fn links(links: &[&str]) -> Vec<String> {
+    links.iter().map(ToString::to_string).collect()
+  }
+
+  #[test]
+  fn accepted_targets_are_skipped_while_the_links_are_the_same() {
+    let sent = [
+      sent("https://a.example/", WebmentionStatus::Accepted, true),
+      sent("https://b.example/", WebmentionStatus::Failed, true),
+      sent("https://gone.example/", WebmentionStatus::Accepted, false),
+    ];
+    assert_eq!(
+      targets(links(&["https://a.example/", "https://b.example/"]), &sent),
+      ["https://b.example/"]
+    );
+  }
+
+  #[test]
+  fn every_target_is_sent_again_once_the_links_change() {
+    let sent = [
+      sent("https://a.example/", WebmentionStatus::Accepted, true),
+      sent("https://gone.example/", WebmentionStatus::Accepted, false),
+    ];
+    assert_eq!(targets(links(&["https://new.example/"]), &sent), [
+      "https://new.example/",
+      "https://a.example/",
+      "https://gone.example/",
+    ]);
+    assert_eq!(
+      targets(
+        links(&["https://a.example/", "https://new.example/"]),
+        &sent
+      ),
+      [
+        "https://a.example/",
+        "https://new.example/",
+        "https://gone.example/",
+      ]
+    );
+  }
+}
@@ -0,0 +1,117 @@
+//! Serving the site without scripts, see [`site_app::zero_js`]. Leptos always
+//! adds the hydration script and its own inline scripts to rendered pages, so
+//! they're stripped from each page as it streams out. Scripts marked with
+//! [`KEEP_SCRIPT_ATTRIBUTE`] are left in.
+
+use axum::{
+  body::{Body, Bytes},
+  extract::Request,
+  http::header,
+  middleware::Next,
+  response::Response,
+};
+use futures::StreamExt;
+use site_app::zero_js::KEEP_SCRIPT_ATTRIBUTE;
+
+/// The environment variable which turns on the zero-JS mode when set to `1`
+/// or `true`.
+pub const ZERO_JS_VAR: &str = "SITE_ZERO_JS";
+
+/// Whether the zero-JS mode is turned on in the environment.
+pub fn from_env() -> bool {
+  std::env::var(ZERO_JS_VAR).is_ok_and(|v| matches!(v.trim(), "1" | "true"))
+}
+
+const SCRIPT_OPEN: &str = "<script";
+const SCRIPT_CLOSE: &str = "</script>";
+
+/// Whether a `<link>` tag preloads the hydration script or wasm module.
+fn preloads_scripts(tag: &str) -> bool {
+  tag.contains("rel=\"modulepreload\"") || tag.contains("application/wasm")
+}
+
+/// Removes scripts from HTML as it streams. Leptos never splits a tag across
+/// chunks, but a script's contents may be, so whether the stream is inside a
+/// script is kept between chunks.
+#[derive(Default)]
+struct ScriptStripper {
+  in_script:   bool,
+  /// Whether the script the stream is inside is kept.
+  keep_script: bool,
+}
+
+impl ScriptStripper {
+  fn strip(&mut self, mut chunk: &str) -> String {
+    let mut out = String::with_capacity(chunk.len());
+    loop {
+      if self.in_script {
+        let Some(end) = chunk.find(SCRIPT_CLOSE) else {
+          if self.keep_script {
+            out.push_str(chunk);
+          }
+          return out;
+        };
+        let end = end + SCRIPT_CLOSE.len();
+        if self.keep_script {
+          out.push_str(&chunk[..end]);
+        }
+        chunk = &chunk[end..];
+        self.in_script = false;
+      }
+
+      let script = chunk.find(SCRIPT_OPEN);
+      let link =
+        chunk
+          .match_indices("<link")
+          .map(|(start, _)| start)
+          .find(|start| {
+            let tag = &chunk[*start..];
+            preloads_scripts(&tag[..tag.find('>').unwrap_or(tag.len())])
+          });
+      match (script, link) {
+        (Some(script), link) if link.map_or(true, |link| script < link) => {
+          out.push_str(&chunk[..script]);
+          chunk = &chunk[script..];
+          let tag = &chunk[..chunk.find('>').unwrap_or(chunk.len())];
+          self.in_script = true;
+          self.keep_script = tag.contains(KEEP_SCRIPT_ATTRIBUTE);
+        }
+        (_, Some(link)) => {
+          out.push_str(&chunk[..link]);
+          let end = chunk[link..]
+            .find('>')
+            .map_or(chunk.len(), |end| link + end + 1);
+          chunk = &chunk[end..];
+        }
+        _ => {
+          out.push_str(chunk);
+          return out;
+        }
+      }
+    }
+  }
+}
+
+/// Strips the scripts from rendered pages.
+pub async fn strip_scripts(request: Request, next: Next) -> Response {
+  let response = next.run(request).await;
+  let is_html = response
+    .headers()
+    .get(header::CONTENT_TYPE)
+    .and_then(|v| v.to_str().ok())
+    .is_some_and(|v| v.starts_with("text/html"));
+  if !is_html {
+    return response;
+  }
+
+  let (mut parts, body) = response.into_parts();
+  parts.headers.remove(header::CONTENT_LENGTH);
+  let mut stripper = ScriptStripper::default();
+  let body = body.into_data_stream().map(move |chunk| {
+    chunk.map(|chunk| match std::str::from_utf8(&chunk) {
+      Ok(html) => Bytes::from(stripper.strip(html)),
+      Err(_) => chunk,
+    })
+  });
+  Response::from_parts(parts, Body::from_stream(body))
+}
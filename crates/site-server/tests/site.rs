@@ -0,0 +1,110 @@
+//! End-to-end checks of the site against the fixture content, see
+//! [`site_server::testing`].
+
+use axum::http::{header, StatusCode};
+use site_server::testing::TestSite;
+
+async fn start() -> TestSite {
+  TestSite::start()
+    .await
+    .unwrap_or_else(|e| panic!("the site didn't start: {e}"))
+}
+
+#[tokio::test]
+async fn home_lists_public_posts() {
+  let site = start().await;
+  site
+    .get("/")
+    .await
+    .assert_status(StatusCode::OK)
+    .assert_contains("Kitchen Sink")
+    .assert_contains("Series, Part One")
+    .assert_not_contains("An Unfinished Draft");
+}
+
+#[tokio::test]
+async fn posts_render_every_content_feature() {
+  let site = start().await;
+  site
+    .get("/post/kitchen-sink")
+    .await
+    .assert_status(StatusCode::OK)
+    .assert_header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+    .assert_contains("<title>Kitchen Sink</title>")
+    .assert_contains("footnote-definition")
+    .assert_contains("<table>")
+    .assert_contains("task-list-item-checked")
+    .assert_contains("class=\"spoiler\"")
+    .assert_contains("href=\"/post/series-part-two\"")
+    .assert_not_contains("wikilink-missing");
+
+  site
+    .get("/post/series-part-one")
+    .await
+    .assert_status(StatusCode::OK)
+    .assert_contains("href=\"/post/series-part-two\"");
+}
+
+#[tokio::test]
+async fn edits_show_up_once_refreshed() {
+  let site = start().await;
+  let kitchen_sink = site.dir().join("content/posts/kitchen-sink.md");
+  let input = std::fs::read_to_string(&kitchen_sink).unwrap();
+  std::fs::write(&kitchen_sink, input.replace("forty-two", "forty-three"))
+    .unwrap();
+  site.refresh();
+  site
+    .get("/post/kitchen-sink")
+    .await
+    .assert_contains("forty-three")
+    .assert_not_contains("forty-two");
+}
+
+#[tokio::test]
+async fn pages_with_signed_forms_are_never_revalidated() {
+  let site = start().await;
+  let request = axum::http::Request::get("/post/kitchen-sink")
+    .header(header::HOST, "localhost")
+    .header(header::IF_MODIFIED_SINCE, "Fri, 01 Jan 2100 00:00:00 GMT")
+    .body(axum::body::Body::empty())
+    .unwrap();
+  let page = site.send(request).await;
+  page
+    .assert_status(StatusCode::OK)
+    .assert_header(header::CACHE_CONTROL, "no-cache")
+    .assert_contains("name=\"rendered_at\"");
+  assert!(!page.headers.contains_key(header::LAST_MODIFIED));
+  assert!(!page.headers.contains_key(header::ETAG));
+}
+
+#[tokio::test]
+async fn drafts_and_missing_posts_are_not_found() {
+  let site = start().await;
+  site
+    .get("/post/draft")
+    .await
+    .assert_status(StatusCode::NOT_FOUND)
+    .assert_not_contains("never be served");
+  site
+    .get("/post/no-such-post")
+    .await
+    .assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn exporting_again_changes_nothing() {
+  let site = TestSite::start_for_export()
+    .await
+    .unwrap_or_else(|e| panic!("the site didn't start: {e}"));
+  let out = site.dir().join("export");
+  let export = || {
+    site_server::export::export(site.router(), "http://localhost", &out, false)
+  };
+  let first = export().await.unwrap().to_string();
+  assert!(!first.starts_with("0 added"), "{first}");
+  let second = export().await.unwrap().to_string();
+  assert!(
+    second.starts_with("0 added, 0 updated, 0 removed"),
+    "{second}"
+  );
+}
@@ -95,20 +95,39 @@
           # add inputs needed for leptos build
           nativeBuildInputs = common-args.nativeBuildInputs ++ [
             pkgs.cargo-leptos
+            # builds the lazily loaded bundle, and has to match the
+            #   `wasm-bindgen` version in `Cargo.toml`
+            pkgs.wasm-bindgen-cli
             # used by cargo-leptos for styling
             pkgs.dart-sass
             pkgs.tailwindcss
+            # used to precompress the static assets
+            pkgs.brotli
+            pkgs.gzip
          ];
         
-          # enable hash_files again
+          # enable hash_files again, then build the lazily loaded bundle,
+          #   which cargo-leptos doesn't know about
           buildPhaseCargoCommand = ''
             RUST_BACKTRACE=1 LEPTOS_HASH_FILES=true cargo leptos build --release -vvv
+            cargo build -p site-frontend-lazy --lib \
+              --target wasm32-unknown-unknown --profile wasm-release
+            wasm-bindgen --target web --no-typescript \
+              --out-dir target/site/pkg --out-name site-lazy \
+              target/wasm32-unknown-unknown/wasm-release/site_frontend_lazy.wasm
+            wasm-opt -Oz target/site/pkg/site-lazy_bg.wasm \
+              -o target/site/pkg/site-lazy_bg.wasm
           '';
 
           installPhaseCommand = ''
             mkdir -p $out/bin
             cp target/release/site-server $out/bin/
             cp target/release/hash.txt $out/bin/
+            # precompress the compressible assets, so the server doesn't
+            # compress the bundle on every request
+            find target/site -type f \( -name '*.wasm' -o -name '*.js' \
+              -o -name '*.css' -o -name '*.svg' -o -name '*.ttf' \
+              -o -name '*.ico' \) -exec gzip -9 -k {} \; -exec brotli -k {} \;
             cp -r target/site $out/bin/
             cp -r content $out/bin/
           '';
@@ -152,17 +171,29 @@
             cargoArtifacts = site-server-deps;
             cargoClippyExtraArgs = "-p site-app --features hydrate -- --deny warnings";
           });
+          app-lazy-islands-clippy = craneLib.cargoClippy (common-args // {
+            cargoArtifacts = site-server-deps;
+            cargoClippyExtraArgs = "-p site-app --features lazy-islands -- --deny warnings";
+          });
           app-ssr-clippy = craneLib.cargoClippy (common-args // {
             cargoArtifacts = site-server-deps;
             cargoClippyExtraArgs = "-p site-app --features ssr -- --deny warnings";
           });
+          markdown-clippy = craneLib.cargoClippy (common-args // {
+            cargoArtifacts = site-server-deps;
+            cargoClippyExtraArgs = "-p site-markdown -- --deny warnings";
+          });
           site-server-clippy = craneLib.cargoClippy (common-args // {
             cargoArtifacts = site-server-deps;
             cargoClippyExtraArgs = "-p site-server -- --deny warnings";
           });
           site-frontend-clippy = craneLib.cargoClippy (common-args // {
             cargoArtifacts = site-server-deps;
-            cargoClippyExtraArgs = "-p site-frontend -- --deny warnings";
+            cargoClippyExtraArgs = "-p site-frontend --target wasm32-unknown-unknown -- --deny warnings";
+          });
+          site-frontend-lazy-clippy = craneLib.cargoClippy (common-args // {
+            cargoArtifacts = site-server-deps;
+            cargoClippyExtraArgs = "-p site-frontend-lazy --target wasm32-unknown-unknown -- --deny warnings";
           });
 
           # make sure the final binary builds
@@ -202,6 +233,7 @@
             harper
 
             cargo-leptos # main leptos build tool
+            wasm-bindgen-cli # for the lazily loaded bundle
             # used by cargo-leptos for styling
             dart-sass
             tailwindcss